@@ -0,0 +1,192 @@
+use core::fmt;
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    png::Png,
+    raster::{self, ColorType, ImageHeader},
+    Result,
+};
+
+/// The contents of Apple's iDOT chunk, which splits IDAT into segments so they can be decoded
+/// on several cores. The layout is undocumented; the field names follow what has been
+/// reverse engineered from iOS and macOS output.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Idot {
+    /// Number of segments the image is split into, usually 2
+    pub segments: u32,
+    /// Rows in the first segment
+    pub first_height: u32,
+    /// Rows in the second segment
+    pub second_height: u32,
+    /// Offset of the second segment's IDAT, from the start of the iDOT chunk
+    pub second_idat_offset: u32,
+}
+
+impl Idot {
+    pub fn parse(data: &[u8]) -> Result<Idot> {
+        if data.len() != 28 {
+            return Err(format!("iDOT chunk must be 28 bytes long, got {}", data.len()).into());
+        }
+        let field = |index: usize| {
+            u32::from_be_bytes([
+                data[index * 4],
+                data[index * 4 + 1],
+                data[index * 4 + 2],
+                data[index * 4 + 3],
+            ])
+        };
+        Ok(Idot {
+            segments: field(0),
+            first_height: field(4),
+            second_height: field(5),
+            second_idat_offset: field(6),
+        })
+    }
+}
+
+impl fmt::Display for Idot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Apple parallel decoding hint: {} segments of {} and {} rows, second IDAT at +{}",
+            self.segments, self.first_height, self.second_height, self.second_idat_offset
+        )
+    }
+}
+
+/// Explains the Apple-specific chunks, or returns `None` for every other chunk
+pub fn describe(chunk: &Chunk) -> Option<String> {
+    match chunk.chunk_type().to_string().as_str() {
+        "iDOT" => Some(match Idot::parse(chunk.data()) {
+            Ok(idot) => idot.to_string(),
+            Err(e) => format!("malformed iDOT chunk: {}", e),
+        }),
+        "CgBI" => Some(
+            "Apple-optimized PNG: raw deflate image data, BGRA samples and premultiplied alpha; \
+             convert it with decgbi"
+                .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// Whether `png` is an Apple-optimized PNG, marked by a CgBI chunk ahead of IHDR
+pub fn is_cgbi(png: &Png) -> bool {
+    png.chunks()
+        .first()
+        .is_some_and(|c| c.chunk_type().to_string() == "CgBI")
+}
+
+/// Converts an Apple-optimized PNG back into a standard one. The image data is re-wrapped in
+/// zlib, samples are swapped from BGR(A) to RGB(A) and alpha is un-premultiplied. The CgBI
+/// chunk is dropped, as is iDOT, whose offsets no longer hold once IDAT is rewritten.
+pub fn decgbi(png: &Png) -> Result<Png> {
+    if !is_cgbi(png) {
+        return Err("Not an Apple-optimized PNG: no CgBI chunk found".into());
+    }
+    let chunks: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .filter(|c| !matches!(c.chunk_type().to_string().as_str(), "CgBI" | "iDOT"))
+        .cloned()
+        .collect();
+    let standard = Png::from_chunks(chunks);
+
+    let header = ImageHeader::from_png(&standard)?;
+    if header.bit_depth != 8 || !matches!(header.color_type, ColorType::Rgb | ColorType::Rgba) {
+        return Err("Only 8-bit RGB and RGBA Apple-optimized PNGs are supported".into());
+    }
+
+    let raw_deflate: Vec<u8> = standard
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type().to_string() == "IDAT")
+        .flat_map(|c| c.data().iter().copied())
+        .collect();
+    let mut filtered = vec![];
+    DeflateDecoder::new(&raw_deflate[..])
+        .take(header.filtered_size()? as u64)
+        .read_to_end(&mut filtered)?;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&filtered)?;
+
+    let mut chunks = vec![];
+    let mut idat_written = false;
+    for chunk in standard.chunks() {
+        if chunk.chunk_type().to_string() != "IDAT" {
+            chunks.push(chunk.clone());
+        } else if !idat_written {
+            chunks.push(Chunk::new(ChunkType::new(*b"IDAT"), encoder.finish()?)?);
+            encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            idat_written = true;
+        }
+    }
+    let wrapped = Png::from_chunks(chunks);
+
+    let mut image = raster::decode(&wrapped)?;
+    let channels = header.color_type.channels();
+    for pixel in image.samples.chunks_mut(channels) {
+        pixel.swap(0, 2);
+        if channels == 4 {
+            let alpha = pixel[3] as u32;
+            for sample in &mut pixel[..3] {
+                *sample = match alpha {
+                    0 => 0,
+                    _ => ((*sample as u32 * 255 + alpha / 2) / alpha).min(255) as u16,
+                };
+            }
+        }
+    }
+    raster::reencode(&wrapped, &image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+    use flate2::write::DeflateEncoder;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_idot_parse() {
+        let fields: [u32; 7] = [2, 0, 50, 40, 50, 50, 8192];
+        let data: Vec<u8> = fields.iter().flat_map(|f| f.to_be_bytes()).collect();
+        let idot = Idot::parse(&data).unwrap();
+        assert_eq!(idot.segments, 2);
+        assert_eq!((idot.first_height, idot.second_height), (50, 50));
+        assert_eq!(idot.second_idat_offset, 8192);
+        assert!(describe(&chunk("iDOT", &data))
+            .unwrap()
+            .contains("2 segments"));
+
+        assert!(Idot::parse(&[0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_decgbi() {
+        // One premultiplied BGRA pixel: 50% alpha red, then an opaque blue one
+        let header = ImageHeader::from_str("width=2,height=1,color=rgba8").unwrap();
+        let filtered = [0, 0, 0, 128, 128, 255, 0, 0, 255];
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered).unwrap();
+        let png = Png::from_chunks(vec![
+            chunk("CgBI", &[0x50, 0, 0x20, 0x02]),
+            chunk("IHDR", &header.as_bytes()),
+            chunk("iDOT", &[0; 28]),
+            chunk("IDAT", &encoder.finish().unwrap()),
+            chunk("IEND", &[]),
+        ]);
+
+        let converted = decgbi(&png).unwrap();
+        assert!(!is_cgbi(&converted));
+        assert!(converted.chunk_by_type("iDOT").is_none());
+        let image = raster::decode(&converted).unwrap();
+        assert_eq!(image.samples, vec![255, 0, 0, 128, 0, 0, 255, 255]);
+
+        assert!(decgbi(&converted).is_err());
+    }
+}
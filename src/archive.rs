@@ -0,0 +1,131 @@
+use std::io::{Cursor, Read, Write};
+use std::str::FromStr;
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::limits::{self, Limits};
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Result};
+
+/// Name of the file listing the chunks of an archive in order
+pub const MANIFEST: &str = "manifest.txt";
+
+/// Packs every chunk of `png` into a zip archive as `NNN_type.bin`, along with a manifest
+/// listing one `file type` pair per line in chunk order
+pub fn export(png: &Png) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let mut manifest = String::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let name = format!("{:03}_{}.bin", index, chunk.chunk_type());
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(chunk.data())?;
+        manifest.push_str(&format!("{} {}\n", name, chunk.chunk_type()));
+    }
+
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(manifest.as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Rebuilds a PNG from an archive written by `export`. CRCs are recomputed, so the chunk files
+/// may have been edited in the meantime.
+pub fn import(bytes: &[u8]) -> Result<Png> {
+    import_with(bytes, &limits::get())
+}
+
+/// `import` with the given limits instead of the global ones. The archive is compressed, so
+/// what its entries unpack to is held to the file size limit.
+pub fn import_with(bytes: &[u8], limits: &Limits) -> Result<Png> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+    let mut total = 0;
+
+    let manifest = read_entry(zip.by_name(MANIFEST)?, limits, &mut total)?;
+    let manifest = String::from_utf8(manifest).map_err(|_| "Manifest is not valid UTF-8")?;
+
+    let mut chunks = vec![];
+    for line in manifest.lines().filter(|l| !l.trim().is_empty()) {
+        let (name, chunk_type) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("Malformed manifest line: {}", line))?;
+
+        let data = read_entry(zip.by_name(name)?, limits, &mut total)?;
+        chunks.push(Chunk::new(ChunkType::from_str(chunk_type.trim())?, data)?);
+    }
+
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Reads an entry, failing as soon as everything unpacked so far passes the file size limit
+fn read_entry<R: Read>(entry: R, limits: &Limits, total: &mut usize) -> Result<Vec<u8>> {
+    let cap = limits
+        .max_file_size
+        .map_or(u64::MAX, |max| max.saturating_sub(*total) as u64 + 1);
+    let mut data = vec![];
+    entry.take(cap).read_to_end(&mut data)?;
+    *total += data.len();
+    limits.check_file_size(*total)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn testing_png() -> Png {
+        testing::png_from(&[
+            ("IHDR", &[0; 13]),
+            ("IDAT", &[1; 32]),
+            ("ruSt", b"payload"),
+            ("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_export_import() {
+        let png = testing_png();
+        let archive = export(&png).unwrap();
+        let imported = import(&archive).unwrap();
+
+        assert_eq!(imported.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_export_layout() {
+        let archive = export(&testing_png()).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(&archive[..])).unwrap();
+
+        let mut data = String::new();
+        zip.by_name("002_ruSt.bin")
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "payload");
+
+        let mut manifest = String::new();
+        zip.by_name(MANIFEST)
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        assert_eq!(manifest.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_import_invalid() {
+        assert!(import(b"not a zip").is_err());
+    }
+
+    #[test]
+    fn test_import_limit() {
+        let archive = export(&testing_png()).unwrap();
+        let limits = |max| Limits {
+            max_file_size: Some(max),
+            ..Default::default()
+        };
+        assert!(import_with(&archive, &limits(1024)).is_ok());
+        let error = import_with(&archive, &limits(40)).unwrap_err();
+        assert!(error.to_string().contains("more than the limit of 40"));
+    }
+}
@@ -0,0 +1,172 @@
+#![allow(dead_code)]
+
+use bumpalo::{collections::Vec as BumpVec, Bump};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, PngChunkError, Result};
+
+/// A chunk whose data lives in a `Bump` arena rather than its own heap allocation
+#[derive(Debug)]
+pub struct ArenaChunk<'bump> {
+    chunk_type: ChunkType,
+    data: &'bump [u8],
+    crc: u32,
+}
+
+impl<'bump> ArenaChunk<'bump> {
+    /// The `ChunkType` of this chunk
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// The raw data contained in this chunk in bytes
+    pub fn data(&self) -> &'bump [u8] {
+        self.data
+    }
+
+    /// The CRC of this chunk
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Copies this chunk out of the arena into a regular `Chunk`
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        Chunk::new(self.chunk_type.clone(), self.data.to_vec())
+    }
+}
+
+/// A PNG parsed by `Png::parse_in`, with every allocation made in the arena it was parsed into.
+/// Dropping the arena frees the whole file at once.
+#[derive(Debug)]
+pub struct ArenaPng<'bump> {
+    chunks: BumpVec<'bump, ArenaChunk<'bump>>,
+}
+
+impl<'bump> ArenaPng<'bump> {
+    pub fn chunks(&self) -> &[ArenaChunk<'bump>] {
+        &self.chunks
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ArenaChunk<'bump>> {
+        self.chunks
+            .iter()
+            .find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+    }
+
+    /// Copies this file out of the arena into a regular `Png`
+    pub fn to_png(&self) -> Result<Png> {
+        let chunks = self
+            .chunks
+            .iter()
+            .map(ArenaChunk::to_chunk)
+            .collect::<Result<Vec<Chunk>>>()?;
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+pub(crate) fn parse<'bump>(bump: &'bump Bump, bytes: &[u8]) -> Result<ArenaPng<'bump>> {
+    Png::check_signature(bytes)?;
+
+    let mut chunks = BumpVec::new_in(bump);
+    let mut i: usize = 8;
+    while i < bytes.len() {
+        let locate = |e: PngChunkError| e.in_chunk(bytes, 0, i, chunks.len());
+        if bytes.len() - i < Chunk::MIN_CHUNK_LENGTH {
+            return Err(locate(PngChunkError::Parse("Truncated chunk".to_string())));
+        }
+
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&bytes[i + 4..i + 8]);
+        let chunk_type = ChunkType::try_from(buf).map_err(locate)?;
+        let length = Chunk::size_at(bytes, i).map_err(locate)? - Chunk::MIN_CHUNK_LENGTH;
+
+        let data = &bytes[i + 8..i + 8 + length];
+        buf.copy_from_slice(&bytes[i + 8 + length..i + 12 + length]);
+        let crc = u32::from_be_bytes(buf);
+        let expected = Chunk::calculate_crc(&chunk_type.bytes(), data);
+        if crc != expected {
+            return Err(locate(PngChunkError::Crc {
+                chunk_type: chunk_type.to_string(),
+                expected,
+                actual: crc,
+            }));
+        }
+
+        chunks.push(ArenaChunk {
+            chunk_type,
+            data: bump.alloc_slice_copy(data),
+            crc,
+        });
+        i += Chunk::MIN_CHUNK_LENGTH + length;
+    }
+
+    Ok(ArenaPng { chunks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+    use std::time::Instant;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", b"arena"),
+            chunk("IDAT", &[1; 64]),
+            chunk("IEND", &[]),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_parse_in() {
+        let bytes = testing_bytes();
+        let bump = Bump::new();
+        let png = Png::parse_in(&bump, &bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"arena");
+        assert_eq!(png.to_png().unwrap().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_parse_in_invalid_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let bump = Bump::new();
+        assert!(Png::parse_in(&bump, &bytes).is_err());
+        assert!(Png::parse_in(&bump, &bytes[..20]).is_err());
+    }
+
+    /// Compares throughput against the regular parser. Run with
+    /// `cargo test --release --features arena -- --ignored --nocapture bench_parse_in`.
+    #[test]
+    #[ignore]
+    fn bench_parse_in() {
+        const ITERATIONS: usize = 1_000_000;
+        let bytes = testing_bytes();
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let png = Png::try_from(&bytes[..]).unwrap();
+            assert_eq!(png.chunks().len(), 4);
+        }
+        let heap = start.elapsed();
+
+        let mut bump = Bump::new();
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let png = Png::parse_in(&bump, &bytes).unwrap();
+            assert_eq!(png.chunks().len(), 4);
+            drop(png);
+            bump.reset();
+        }
+        let arena = start.elapsed();
+
+        let per_second = |elapsed: std::time::Duration| ITERATIONS as f64 / elapsed.as_secs_f64();
+        println!("Png::try_from: {:.0} files/s", per_second(heap));
+        println!("Png::parse_in: {:.0} files/s", per_second(arena));
+    }
+}
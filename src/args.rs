@@ -1,38 +1,1403 @@
 #![allow(dead_code)]
 
+use crate::bookmarks::Editor;
 use crate::chunk_type::ChunkType;
+use crate::known_chunks::{Srgb, TimeChunk};
+use crate::merge::MergePolicy;
+use crate::profile::Selector;
+use crate::raster::{ImageHeader, PixelFormat};
+use crate::redact::{Fill, Rect};
+use crate::stego::Transform;
+use crate::text::{OnConflict, TextKind};
+use crate::{Error, Result};
+use std::fmt;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use structopt::StructOpt;
 
+/// How commands that report results should print them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format '{}', expected text, json or csv", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A `--format` value: how a report is printed, or for `pixels` the layout of the dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Report(OutputFormat),
+    Pixels(PixelFormat),
+}
+
+impl FromStr for Format {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        OutputFormat::from_str(s)
+            .map(Format::Report)
+            .or_else(|_| PixelFormat::from_str(s).map(Format::Pixels))
+            .map_err(|_| {
+                format!(
+                    "Unknown format '{}', expected text, json or csv, or for pixels rgba8, rgb8 \
+                     or gray8",
+                    s
+                )
+                .into()
+            })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Report(format) => write!(f, "{}", format),
+            Format::Pixels(format) => write!(f, "{}", format),
+        }
+    }
+}
+
+/// A duration such as `30s`, `500ms` or `2m`; a bare number is in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout(pub Duration);
+
+impl FromStr for Timeout {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: u64 = value
+            .parse()
+            .map_err(|_| format!("Invalid duration '{}'", s))?;
+        let duration = match unit {
+            "ms" => Some(Duration::from_millis(value)),
+            "" | "s" => Some(Duration::from_secs(value)),
+            "m" => value.checked_mul(60).map(Duration::from_secs),
+            "h" => value.checked_mul(60 * 60).map(Duration::from_secs),
+            _ => {
+                return Err(
+                    format!("Unknown duration unit '{}', expected ms, s, m or h", unit).into(),
+                )
+            }
+        };
+        duration
+            .map(Timeout)
+            .ok_or_else(|| format!("Duration '{}' is too long", s).into())
+    }
+}
+
+/// A size in bytes such as `512K`, `64M` or `1G` (powers of 1024); a bare number is in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub usize);
+
+impl FromStr for ByteSize {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: usize = value.parse().map_err(|_| format!("Invalid size '{}'", s))?;
+        let shift = match unit {
+            "" => 0,
+            "K" => 10,
+            "M" => 20,
+            "G" => 30,
+            _ => return Err(format!("Unknown size unit '{}', expected K, M or G", unit).into()),
+        };
+        value
+            .checked_mul(1 << shift)
+            .map(ByteSize)
+            .ok_or_else(|| format!("Size '{}' is too large", s).into())
+    }
+}
+
+/// A throughput such as `10M/s` or `10MB/s`, in bytes per second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(pub usize);
+
+impl FromStr for Rate {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let size = s.strip_suffix("/s").ok_or_else(|| {
+            format!(
+                "Invalid rate '{}', expected a size per second such as 10M/s",
+                s
+            )
+        })?;
+        let size = size
+            .strip_suffix('B')
+            .filter(|size| size.ends_with(['K', 'M', 'G']))
+            .unwrap_or(size);
+        match ByteSize::from_str(size)? {
+            ByteSize(0) => Err("Rate must be more than 0 bytes per second".into()),
+            ByteSize(bytes) => Ok(Rate(bytes)),
+        }
+    }
+}
+
+/// A byte range such as `16..64`, `16..` or `..64`. The end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataRange {
+    pub start: usize,
+    /// `None` runs to the end of the data
+    pub end: Option<usize>,
+}
+
+impl DataRange {
+    /// The range within data of `length` bytes, failing if it does not fit
+    pub fn resolve(&self, length: usize) -> Result<Range<usize>> {
+        let end = self.end.unwrap_or(length);
+        if self.start > end || end > length {
+            return Err(format!(
+                "Range {}..{} is out of bounds of {} bytes of data",
+                self.start, end, length
+            )
+            .into());
+        }
+        Ok(self.start..end)
+    }
+}
+
+impl FromStr for DataRange {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || format!("Expected a range such as 16..64, got '{}'", s);
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let start = match start {
+            "" => 0,
+            start => start.parse().map_err(|_| invalid())?,
+        };
+        let end = match end {
+            "" => None,
+            end => Some(end.parse().map_err(|_| invalid())?),
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err(invalid().into());
+        }
+        Ok(DataRange { start, end })
+    }
+}
+
+/// The global options that take a value, so the subcommand can be told apart from their values
+/// without parsing the command line
+const GLOBAL_VALUE_OPTIONS: [&str; 8] = [
+    "--work-dir",
+    "--timeout",
+    "--max-inflate",
+    "--max-inflate-ratio",
+    "--max-memory",
+    "--config",
+    "--format",
+    "--trace-output",
+];
+
+#[derive(StructOpt, Debug)]
+pub struct Cli {
+    /// Directory for temporary files, defaults to TMPDIR
+    #[structopt(long, global = true)]
+    pub work_dir: Option<PathBuf>,
+    /// Abort with exit status 124 if the command takes longer than this, e.g. `30s`
+    #[structopt(long, global = true)]
+    pub timeout: Option<Timeout>,
+    /// Apply strict limits on file size, chunk count, image size and decompression, for files
+    /// from untrusted sources
+    #[structopt(long, global = true)]
+    pub untrusted: bool,
+    /// Largest size a compressed text chunk or ICC profile may inflate to, e.g. `64M`
+    #[structopt(long, global = true)]
+    pub max_inflate: Option<ByteSize>,
+    /// Largest ratio of inflated to compressed size for streams over 1M, 100 by default
+    #[structopt(long, global = true)]
+    pub max_inflate_ratio: Option<usize>,
+    /// Keep payloads joined from several chunks or inflated in temporary files once they grow
+    /// past this, e.g. `64M`
+    #[structopt(long, global = true)]
+    pub max_memory: Option<ByteSize>,
+    /// Print PNGs that would be written to a file as `data:image/png;base64,...` URIs instead
+    #[structopt(long, global = true)]
+    pub output_data_uri: bool,
+    /// Config file with chunk schemas, defaults to PNGCHUNK_CONFIG or ./pngchunk.toml
+    #[structopt(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Fail if anything produced a warning, such as a chunk after IEND or a placement that
+    /// trips up decoders
+    #[structopt(long, global = true)]
+    pub deny_warnings: bool,
+    /// Output format for reports: text, json or csv. For `pixels`, the pixel format of the dump:
+    /// rgba8, rgb8 or gray8.
+    #[structopt(long, global = true)]
+    pub format: Option<Format>,
+    /// Put PNGs that would be written to a file on the clipboard instead. Pass `clipboard:` in
+    /// place of an input file to read the image on the clipboard.
+    #[cfg(feature = "clipboard")]
+    #[structopt(long, global = true)]
+    pub to_clipboard: bool,
+    /// Write a Chrome trace of where the time went to this file, e.g. `trace.json`
+    #[cfg(not(target_os = "wasi"))]
+    #[structopt(long, global = true)]
+    pub trace_output: Option<PathBuf>,
+    /// Print the number of allocations, bytes allocated and peak memory of each phase to stderr
+    #[cfg(feature = "mem-stats")]
+    #[structopt(long, global = true)]
+    pub mem_stats: bool,
+    #[structopt(subcommand)]
+    pub command: PngArgs,
+}
+
+impl Cli {
+    /// The name of the subcommand in `args`, which start with the program name. This does not
+    /// parse them, so it works for command lines that fail to parse.
+    pub fn subcommand_name<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Option<String> {
+        let mut args = args
+            .iter()
+            .skip(1)
+            .map(|arg| arg.as_ref().to_string_lossy());
+        while let Some(arg) = args.next() {
+            if GLOBAL_VALUE_OPTIONS.contains(&arg.as_ref()) {
+                args.next();
+            } else if !arg.starts_with('-') {
+                return Some(arg.into_owned());
+            }
+        }
+        None
+    }
+
+    /// Checks `--format` against the command, returning the report format to print with. A pixel
+    /// format is handed on to `pixels`, the one command that takes one.
+    pub fn output_format(&mut self) -> Result<OutputFormat> {
+        match (self.format, &mut self.command) {
+            (None, _) => Ok(OutputFormat::Text),
+            (Some(Format::Pixels(format)), PngArgs::Pixels(args)) => {
+                args.format = format;
+                Ok(OutputFormat::Text)
+            }
+            (Some(Format::Report(format)), command) if command.formats().contains(&format) => {
+                Ok(format)
+            }
+            (Some(format), _) => {
+                Err(format!("--format {} is not supported by this command", format).into())
+            }
+        }
+    }
+
+    /// The exit status for a command line that failed to parse. `exists` exits with 1 when the
+    /// chunk is absent, so its usage errors exit with 2 instead.
+    pub fn usage_error_status<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> i32 {
+        match Cli::subcommand_name(args).as_deref() {
+            Some("exists") => 2,
+            _ => 1,
+        }
+    }
+}
+
 #[derive(StructOpt, Debug)]
 pub enum PngArgs {
     Encode(EncodeArgs),
     Decode(DecodeArgs),
     Remove(RemoveArgs),
     Print(PrintArgs),
+    Textconv(TextconvArgs),
+    MergeDriver(MergeDriverArgs),
+    Compat(CompatArgs),
+    Carve(CarveArgs),
+    Salvage(SalvageArgs),
+    Patch(PatchArgs),
+    Export(ExportArgs),
+    Import(ImportArgs),
+    Compose(ComposeArgs),
+    Pixels(PixelsArgs),
+    Histogram(HistogramArgs),
+    PixelDiff(PixelDiffArgs),
+    Redact(RedactArgs),
+    L10n(L10nArgs),
+    Validate(ValidateArgs),
+    Text(TextArgs),
+    Migrate(MigrateArgs),
+    Note(NoteArgs),
+    Workspace(WorkspaceArgs),
+    Watermark(WatermarkArgs),
+    Provenance(ProvenanceArgs),
+    Stego(StegoArgs),
+    Decgbi(DecgbiArgs),
+    Extract(ExtractArgs),
+    Lock(LockArgs),
+    Corpus(CorpusArgs),
+    Strip(StripArgs),
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    ExecJson(ExecJsonArgs),
+    Info(InfoArgs),
+    Bookmarks(BookmarksArgs),
+    Schema(SchemaArgs),
+    Repair(RepairArgs),
+    Diff(DiffArgs),
+    Icc(IccArgs),
+    Exif(ExifArgs),
+    Dpi(DpiArgs),
+    Count(CountArgs),
+    Exists(ExistsArgs),
+    Time(TimeArgs),
+    Color(ColorArgs),
+}
+
+impl PngArgs {
+    /// The report formats the command can print with `--format`
+    pub fn formats(&self) -> &'static [OutputFormat] {
+        use OutputFormat::*;
+        match self {
+            PngArgs::Print(_)
+            | PngArgs::Histogram(_)
+            | PngArgs::Validate(_)
+            | PngArgs::Corpus(_)
+            | PngArgs::Stego(_)
+            | PngArgs::Info(_)
+            | PngArgs::Diff(_)
+            | PngArgs::Text(TextArgs::List(_))
+            | PngArgs::Dpi(DpiArgs::Get(_)) => &[Text, Json, Csv],
+            PngArgs::Decode(_) | PngArgs::Schema(_) | PngArgs::Color(ColorArgs::Show(_)) => {
+                &[Text, Json]
+            }
+            PngArgs::Pixels(_) => &[],
+            _ => &[Text],
+        }
+    }
 }
 
 #[derive(StructOpt, Debug)]
 pub struct EncodeArgs {
     pub file_path: PathBuf,
     pub chunk_type: ChunkType,
-    pub message: String,
+    #[structopt(required_unless_one = &["input-file", "field"])]
+    pub message: Option<String>,
+    /// Where to save the result, defaults to overwriting the input file
     pub output_file: Option<PathBuf>,
+    /// Where to save the result, the same as the output file positional. Needed with
+    /// --input-file and --field, which take the place of the message.
+    #[structopt(short, long, conflicts_with = "output-file")]
+    pub output: Option<PathBuf>,
+    /// Embed the contents of a file (`-` for stdin) instead of a message
+    #[structopt(long)]
+    pub input_file: Option<PathBuf>,
+    /// Allow encoding into public chunk types such as `IDAT` or `tEXt`
+    #[structopt(long)]
+    pub force: bool,
+    /// Refuse public chunk types unless --force is given. With `false`, only the registered
+    /// standard types such as `IDAT` or `tEXt` need --force.
+    #[structopt(
+        long,
+        value_name = "true|false",
+        default_value = "true",
+        parse(try_from_str)
+    )]
+    pub only_private: bool,
+    /// Store the message as a plain string, readable by other pngme implementations
+    #[structopt(long)]
+    pub legacy: bool,
+    /// Add the chunk after IEND, where most decoders and validators will not look for it
+    #[structopt(long)]
+    pub after_iend: bool,
+    /// Report what would be written without writing anything
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// Split payloads larger than this over several chunks, e.g. `1M`. Payloads are always
+    /// split when they exceed the largest chunk the PNG spec allows.
+    #[structopt(long)]
+    pub max_chunk_size: Option<ByteSize>,
+    /// Compress the message with zlib before embedding it
+    #[structopt(long, conflicts_with = "legacy")]
+    pub compress: bool,
+    /// Encrypt the message with a passphrase, read from PNGCHUNK_PASSPHRASE or prompted for
+    #[structopt(long, conflicts_with = "legacy")]
+    pub encrypt: bool,
+    /// Build the message from the chunk type's schema in pngchunk.toml, e.g. `version=2`
+    #[structopt(long, number_of_values = 1, conflicts_with = "input-file")]
+    pub field: Vec<FieldValue>,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct DecodeArgs {
     pub file_path: PathBuf,
     pub chunk_type: ChunkType,
+    /// Write the payload bytes as they are, even if they are not UTF-8
+    #[structopt(long, conflicts_with_all = &["base64", "hex"])]
+    pub raw: bool,
+    /// Print the payload as base64
+    #[structopt(long, conflicts_with = "hex")]
+    pub base64: bool,
+    /// Print the payload as lowercase hex
+    #[structopt(long)]
+    pub hex: bool,
+    /// Write the payload to a file instead of stdout
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+    /// Print JSON, XML and plist payloads as they are instead of re-indenting them
+    #[structopt(long)]
+    pub no_pretty: bool,
+    /// Like --raw, but always to stdout and regardless of --format, for capturing the payload
+    /// in scripts. Anything else goes to stderr.
+    #[structopt(long, conflicts_with_all = &["raw", "base64", "hex", "output", "no-pretty"])]
+    pub porcelain: bool,
+    /// Decrypt a message written with `encode --encrypt`, using PNGCHUNK_PASSPHRASE or a prompt
+    #[structopt(long)]
+    pub decrypt: bool,
+    /// Match the chunk type regardless of case, e.g. `RUST` finds `ruSt`
+    #[structopt(long)]
+    pub ignore_case: bool,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct RemoveArgs {
     pub file_path: PathBuf,
-    pub chunk_type: ChunkType,
+    /// The type of the chunk to remove. With --type or --type-prefix instead, every matching
+    /// chunk is removed.
+    #[structopt(required_unless_one = &["type-pattern", "type-prefix"])]
+    pub chunk_type: Option<ChunkType>,
+    /// Select chunks whose type matches this regular expression, e.g. `r[a-z][A-Z]t`
+    #[structopt(long = "type", conflicts_with_all = &["chunk-type", "type-prefix"])]
+    pub type_pattern: Option<String>,
+    /// Select chunks whose type starts with these letters, e.g. `tE`
+    #[structopt(long, conflicts_with = "chunk-type")]
+    pub type_prefix: Option<String>,
+    /// Match the chunk type regardless of case, e.g. `RUST` finds `ruSt`
+    #[structopt(long)]
+    pub ignore_case: bool,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct PrintArgs {
     pub file_path: PathBuf,
+    /// Explain the contents of chunks pngchunk knows how to parse, such as the tags of an eXIf
+    /// chunk. `--detailed` does the same.
+    #[structopt(long, visible_alias = "detailed")]
+    pub parsed: bool,
+    /// With --parsed, show JSON, XML and plist payloads as they are instead of re-indenting them
+    #[structopt(long, requires = "parsed")]
+    pub no_pretty: bool,
+    /// Skip over corrupt chunks to the next valid one, showing the bytes skipped as gaps
+    #[structopt(long)]
+    pub resync: bool,
+    /// With --parsed, read chunks of a type with a Kaitai Struct schema, e.g. `ruSt=rust.ksy`
+    #[structopt(long, requires = "parsed", number_of_values = 1)]
+    pub schema: Vec<SchemaBinding>,
+    /// Only print chunks of this type
+    #[structopt(long)]
+    pub chunk_type: Option<ChunkType>,
+    /// Select chunks whose type matches this regular expression, e.g. `r[a-z][A-Z]t`
+    #[structopt(long = "type", conflicts_with_all = &["chunk-type", "type-prefix"])]
+    pub type_pattern: Option<String>,
+    /// Select chunks whose type starts with these letters, e.g. `tE`
+    #[structopt(long, conflicts_with = "chunk-type")]
+    pub type_prefix: Option<String>,
+    /// Match chunk types regardless of case
+    #[structopt(long)]
+    pub ignore_case: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TextconvArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct MergeDriverArgs {
+    pub base: PathBuf,
+    pub ours: PathBuf,
+    pub theirs: PathBuf,
+    #[structopt(short, long)]
+    pub output: PathBuf,
+    /// How to combine ancillary chunks: union, ours or theirs
+    #[structopt(long, default_value = "union")]
+    pub policy: MergePolicy,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CompatArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CarveArgs {
+    pub file_path: PathBuf,
+    /// Directory to write the carved PNGs and index report to
+    #[structopt(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct SalvageArgs {
+    pub file_path: PathBuf,
+    #[structopt(short, long)]
+    pub output: PathBuf,
+    /// Keep the data of a truncated IDAT chunk so the top of the image can still be rendered
+    #[structopt(long)]
+    pub partial_idat: bool,
+    /// Skip over corrupt chunks to the next valid one instead of stopping at the first
+    #[structopt(long, conflicts_with = "partial-idat")]
+    pub resync: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum PatchArgs {
+    /// Writes the metadata changes between two PNGs to stdout
+    Create(PatchCreateArgs),
+    /// Applies a patch created with `patch create` to a PNG
+    Apply(PatchApplyArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct PatchCreateArgs {
+    pub old: PathBuf,
+    pub new: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct PatchApplyArgs {
+    pub patch: PathBuf,
+    pub file_path: PathBuf,
+    /// Where to save the patched PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExportArgs {
+    pub file_path: PathBuf,
+    pub archive: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ImportArgs {
+    pub archive: PathBuf,
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ComposeArgs {
+    /// Image properties, e.g. `width=16,height=16,color=rgba8`
+    #[structopt(long)]
+    pub ihdr: ImageHeader,
+    /// Raw pixel data, row by row without padding
+    #[structopt(long)]
+    pub pixels: PathBuf,
+    #[structopt(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct PixelsArgs {
+    pub file_path: PathBuf,
+    /// Where to write the raw pixels; a JSON descriptor is written next to it
+    #[structopt(short, long)]
+    pub output: PathBuf,
+    /// Pixel format of the dump, given with `--format`
+    #[structopt(skip = PixelFormat::Rgba8)]
+    pub format: PixelFormat,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct HistogramArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct PixelDiffArgs {
+    pub first: PathBuf,
+    pub second: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RedactArgs {
+    pub file_path: PathBuf,
+    /// Region to redact as `x,y,width,height`
+    #[structopt(long)]
+    pub rect: Rect,
+    /// How to fill the region: black or blur
+    #[structopt(long, default_value = "black")]
+    pub fill: Fill,
+    /// Where to save the redacted PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum L10nArgs {
+    /// Writes the localized iTXt strings of a PNG to a JSON bundle
+    Export(L10nExportArgs),
+    /// Replaces the localized iTXt chunks of a PNG with the strings of a JSON bundle
+    Import(L10nImportArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct L10nExportArgs {
+    pub file_path: PathBuf,
+    pub strings: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct L10nImportArgs {
+    pub strings: PathBuf,
+    pub file_path: PathBuf,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Checks PNG files against the spec. With `--format json` each file gets a JSON object on a
+/// line of its own (JSON Lines). With `--format csv` there is one header and then a row per
+/// finding, starting with the file it was found in.
+#[derive(StructOpt, Debug)]
+pub struct ValidateArgs {
+    /// PNG files, directories or glob patterns such as `assets/**/*.png`
+    #[structopt(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Process the PNG files in directories given as paths, including subdirectories
+    #[structopt(short, long)]
+    pub recursive: bool,
+    /// How many files to process at once, 0 for one per CPU
+    #[structopt(short, long, default_value = "1")]
+    pub jobs: usize,
+    /// Record finished files in this JSON file, so an interrupted run resumes where it left off
+    /// when run again. It is removed once every file has been processed.
+    #[structopt(long)]
+    pub state: Option<PathBuf>,
+    /// Read no more than this much per second on average, e.g. `10MB/s`, so a large run does
+    /// not starve other work on a shared disk
+    #[structopt(long)]
+    pub throttle: Option<Rate>,
+    /// Run at a lower CPU priority
+    #[structopt(long)]
+    pub nice: bool,
+    /// Also warn about things that are legal but suspicious
+    #[structopt(long)]
+    pub advisory: bool,
+    /// A JSON file tuning the advisory thresholds
+    #[structopt(long, requires = "advisory")]
+    pub policy: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum TextArgs {
+    /// Lists the keywords and text of every tEXt, zTXt and iTXt chunk
+    List(TextListArgs),
+    /// Sets a text keyword, stored as an iTXt chunk unless --type asks for another
+    Set(TextSetArgs),
+    /// Removes every text chunk with a keyword
+    Remove(TextRemoveArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TextListArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TextSetArgs {
+    pub file_path: PathBuf,
+    pub keyword: String,
+    pub value: String,
+    /// Language tag of the text, e.g. `en-US`
+    #[structopt(long, default_value = "")]
+    pub language: String,
+    /// Chunk type to store the text in: tEXt, zTXt for compressed text, or iTXt. Only iTXt
+    /// holds a language or text outside Latin-1.
+    #[structopt(long = "type", default_value = "iTXt")]
+    pub text_type: TextKind,
+    /// What to do if the keyword is already set: replace, append, error or keep-both
+    #[structopt(long, default_value = "replace")]
+    pub on_conflict: OnConflict,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TextRemoveArgs {
+    pub file_path: PathBuf,
+    pub keyword: String,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct MigrateArgs {
+    /// Files to upgrade in place
+    #[structopt(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Only upgrade chunks of this type, defaults to every private chunk
+    #[structopt(long)]
+    pub chunk_type: Option<ChunkType>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum NoteArgs {
+    /// Adds a note about a chunk
+    Add(NoteAddArgs),
+    /// Lists the notes about a file
+    List(NoteListArgs),
+    /// Removes a note by the number shown in `note list`
+    Remove(NoteRemoveArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct NoteAddArgs {
+    pub file_path: PathBuf,
+    /// Index of the chunk, as shown by `textconv`
+    #[structopt(long)]
+    pub chunk: usize,
+    pub text: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct NoteListArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct NoteRemoveArgs {
+    pub file_path: PathBuf,
+    pub note: usize,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum WorkspaceArgs {
+    /// Creates an empty workspace in a directory
+    Init(WorkspaceInitArgs),
+    /// Starts tracking files in a workspace
+    Add(WorkspaceAddArgs),
+    /// Re-runs the analyses on every tracked file
+    Analyze(WorkspaceDirArgs),
+    /// Prints a consolidated report of every tracked file
+    Report(WorkspaceDirArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WorkspaceInitArgs {
+    pub dir: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WorkspaceAddArgs {
+    pub dir: PathBuf,
+    #[structopt(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WorkspaceDirArgs {
+    pub dir: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum WatermarkArgs {
+    /// Embeds an invisible watermark carrying an ID into the pixel data
+    Embed(WatermarkEmbedArgs),
+    /// Reports whether a watermark made with a key is present, and its ID
+    Detect(WatermarkDetectArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WatermarkEmbedArgs {
+    pub file_path: PathBuf,
+    #[structopt(long)]
+    pub id: u32,
+    #[structopt(long)]
+    pub key: String,
+    /// Average brightness change in 8-bit levels; higher survives more editing but is more visible
+    #[structopt(long, default_value = "2")]
+    pub strength: f64,
+    /// Where to save the watermarked PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WatermarkDetectArgs {
+    pub file_path: PathBuf,
+    #[structopt(long)]
+    pub key: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ProvenanceArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum StegoArgs {
+    /// Embeds a known payload with each method, applies common transformations and reports
+    /// which methods the payload survived
+    Test(StegoTestArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct StegoTestArgs {
+    pub file_path: PathBuf,
+    /// Transformations to try, from strip, optimize and recompress
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "strip,optimize,recompress"
+    )]
+    pub transforms: Vec<Transform>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DecgbiArgs {
+    pub file_path: PathBuf,
+    /// Where to write the standard PNG, defaults to overwriting the input
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExtractArgs {
+    pub file_path: PathBuf,
+    #[structopt(required_unless_one = &["type-pattern", "type-prefix"])]
+    pub chunk_type: Option<ChunkType>,
+    /// Select chunks whose type matches this regular expression, e.g. `r[a-z][A-Z]t`
+    #[structopt(long = "type", conflicts_with_all = &["chunk-type", "type-prefix"])]
+    pub type_pattern: Option<String>,
+    /// Select chunks whose type starts with these letters, e.g. `tE`
+    #[structopt(long, conflicts_with = "chunk-type")]
+    pub type_prefix: Option<String>,
+    /// Match the chunk type regardless of case
+    #[structopt(long)]
+    pub ignore_case: bool,
+    /// Where to write the chunk data, defaults to stdout
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+    /// Which chunk of this type to extract, counting from 0, when there is more than one
+    #[structopt(long)]
+    pub index: Option<usize>,
+    /// Extract only these bytes of the chunk data, e.g. `16..64`. The file is streamed and
+    /// only the requested bytes are held in memory.
+    #[structopt(long)]
+    pub range: Option<DataRange>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum LockArgs {
+    /// Prints a lockfile describing every PNG under a directory
+    Write(LockWriteArgs),
+    /// Fails if the PNGs under a directory no longer match the lockfile
+    Verify(LockVerifyArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct LockWriteArgs {
+    pub dir: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct LockVerifyArgs {
+    pub dir: PathBuf,
+    #[structopt(long, default_value = "pngchunk.lock")]
+    pub lockfile: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum CorpusArgs {
+    /// Aggregates chunk type statistics over every PNG under a directory
+    Stats(CorpusStatsArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CorpusStatsArgs {
+    pub dir: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct StripArgs {
+    /// PNG files, directories or glob patterns such as `assets/**/*.png`
+    #[structopt(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Process the PNG files in directories given as paths, including subdirectories
+    #[structopt(short, long)]
+    pub recursive: bool,
+    /// How many files to process at once, 0 for one per CPU
+    #[structopt(short, long, default_value = "1")]
+    pub jobs: usize,
+    /// Record finished files in this JSON file, so an interrupted run resumes where it left off
+    /// when run again. It is removed once every file has been processed.
+    #[structopt(long)]
+    pub state: Option<PathBuf>,
+    /// Read no more than this much per second on average, e.g. `10MB/s`, so a large run does
+    /// not starve other work on a shared disk
+    #[structopt(long)]
+    pub throttle: Option<Rate>,
+    /// Run at a lower CPU priority
+    #[structopt(long)]
+    pub nice: bool,
+    /// A built-in profile (web, archive or social) or a JSON profile file. Without one, every
+    /// ancillary chunk is removed.
+    #[structopt(long)]
+    pub profile: Option<String>,
+    /// Chunk types or groups to keep on top of the profile, e.g. `tEXt,pHYs` or `text,color`
+    #[structopt(long, use_delimiter = true)]
+    pub keep: Vec<Selector>,
+    /// Only remove private chunks, the same as `--profile archive`
+    #[structopt(long, conflicts_with = "profile")]
+    pub private_only: bool,
+    /// Where to save the stripped PNG, defaults to overwriting the input file. Only allowed with
+    /// a single file.
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+    /// Only write the result if it is strictly smaller than the input
+    #[structopt(long)]
+    pub only_if_smaller: bool,
+}
+
+/// Reads one JSON command from stdin and prints a JSON result, for integration tests
+#[derive(StructOpt, Debug)]
+pub struct ExecJsonArgs {}
+
+#[derive(StructOpt, Debug)]
+pub struct InfoArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct BookmarksArgs {
+    pub file_path: PathBuf,
+    /// Hex editor to export for: 010editor, imhex or hexpat
+    #[structopt(long)]
+    pub editor: Editor,
+    /// Write the bookmarks to a file instead of stdout
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum SchemaArgs {
+    /// Parses the chunks of one type with a Kaitai Struct (.ksy) schema and prints their fields
+    Apply(SchemaApplyArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct SchemaApplyArgs {
+    pub schema: PathBuf,
+    pub file_path: PathBuf,
+    /// The chunk type the schema describes
+    #[structopt(long = "type")]
+    pub chunk_type: ChunkType,
+}
+
+/// A schema file applied to one chunk type, written as `TYPE=FILE`
+#[derive(Debug, Clone)]
+pub struct SchemaBinding {
+    pub chunk_type: ChunkType,
+    pub path: PathBuf,
+}
+
+impl FromStr for SchemaBinding {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (chunk_type, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected TYPE=FILE, got '{}'", s))?;
+        Ok(SchemaBinding {
+            chunk_type: ChunkType::from_str(chunk_type)?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// A value for one schema field, written as `NAME=VALUE`
+#[derive(Debug, Clone)]
+pub struct FieldValue {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for FieldValue {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected NAME=VALUE, got '{}'", s))?;
+        Ok(FieldValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RepairArgs {
+    /// PNG files, directories or glob patterns such as `assets/**/*.png`
+    #[structopt(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Process the PNG files in directories given as paths, including subdirectories
+    #[structopt(short, long)]
+    pub recursive: bool,
+    /// How many files to process at once, 0 for one per CPU
+    #[structopt(short, long, default_value = "1")]
+    pub jobs: usize,
+    /// Record finished files in this JSON file, so an interrupted run resumes where it left off
+    /// when run again. It is removed once every file has been processed.
+    #[structopt(long)]
+    pub state: Option<PathBuf>,
+    /// Read no more than this much per second on average, e.g. `10MB/s`, so a large run does
+    /// not starve other work on a shared disk
+    #[structopt(long)]
+    pub throttle: Option<Rate>,
+    /// Run at a lower CPU priority
+    #[structopt(long)]
+    pub nice: bool,
+    /// Where to save the repaired file, defaults to overwriting the input file. Only allowed with
+    /// a single file.
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+    /// Add an IEND chunk if the file does not end with one
+    #[structopt(long)]
+    pub add_iend: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DiffArgs {
+    pub old: PathBuf,
+    pub new: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum IccArgs {
+    /// Writes the ICC profile embedded in a PNG to a file
+    Extract(IccExtractArgs),
+    /// Embeds an ICC profile in a PNG, replacing any existing one
+    Embed(IccEmbedArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct IccExtractArgs {
+    pub file_path: PathBuf,
+    /// Where to save the profile, defaults to stdout
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct IccEmbedArgs {
+    pub file_path: PathBuf,
+    /// The ICC profile to embed, e.g. a `.icc` or `.icm` file
+    pub profile: PathBuf,
+    /// Name stored with the profile, 1 to 79 Latin-1 characters
+    #[structopt(long, default_value = "ICC Profile")]
+    pub name: String,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ExifArgs {
+    /// Writes the raw TIFF/EXIF data of the eXIf chunk to a file
+    Dump(ExifDumpArgs),
+    /// Embeds TIFF/EXIF data from a file, replacing any existing eXIf chunk
+    Inject(ExifInjectArgs),
+    /// Removes the eXIf chunk
+    Strip(ExifStripArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExifDumpArgs {
+    pub file_path: PathBuf,
+    /// Where to save the EXIF data, defaults to stdout
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExifInjectArgs {
+    pub file_path: PathBuf,
+    /// The EXIF data, a TIFF structure optionally preceded by the JPEG `Exif` prefix
+    pub exif: PathBuf,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExifStripArgs {
+    pub file_path: PathBuf,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum DpiArgs {
+    /// Prints the resolution stored in the pHYs chunk
+    Get(DpiGetArgs),
+    /// Stores a resolution in the pHYs chunk, adding one if needed
+    Set(DpiSetArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DpiGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DpiSetArgs {
+    pub file_path: PathBuf,
+    /// Dots per inch, horizontally and vertically unless --vertical is given
+    pub dpi: f64,
+    /// Dots per inch vertically, for non-square pixels
+    #[structopt(long)]
+    pub vertical: Option<f64>,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CountArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Match the chunk type regardless of case
+    #[structopt(long)]
+    pub ignore_case: bool,
+}
+
+/// Exits with status 0 if the file has a chunk of the type, 1 if it does not and 2 if the file
+/// cannot be read
+#[derive(StructOpt, Debug)]
+pub struct ExistsArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Match the chunk type regardless of case
+    #[structopt(long)]
+    pub ignore_case: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum TimeArgs {
+    /// Prints the last-modification time stored in the tIME chunk
+    Get(TimeGetArgs),
+    /// Stores a last-modification time in the tIME chunk, e.g. `2024-01-02T03:04:05Z`
+    Set(TimeSetArgs),
+    /// Stores the current time in the tIME chunk
+    Touch(TimeTouchArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TimeGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TimeSetArgs {
+    pub file_path: PathBuf,
+    /// A UTC time like `2024-01-02T03:04:05Z`
+    pub time: TimeChunk,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TimeTouchArgs {
+    pub file_path: PathBuf,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ColorArgs {
+    /// Prints the gamma, chromaticities, sRGB intent and ICC profile of a PNG
+    Show(ColorShowArgs),
+    /// Sets the gAMA, cHRM or sRGB chunk
+    Set(ColorSetArgs),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ColorShowArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ColorSetArgs {
+    pub file_path: PathBuf,
+    /// The gamma the image was encoded with, e.g. `0.45455` for 1/2.2
+    #[structopt(long)]
+    pub gamma: Option<f64>,
+    /// White point and primaries as 8 comma-separated values: white x,y then red, green and
+    /// blue x,y
+    #[structopt(long)]
+    pub chromaticities: Option<String>,
+    /// Mark the image as sRGB with this rendering intent: perceptual, relative, saturation or
+    /// absolute. Removes any ICC profile.
+    #[structopt(long)]
+    pub srgb: Option<Srgb>,
+    /// Where to save the updated PNG, defaults to overwriting the input file
+    #[structopt(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_range_from_str() {
+        let range = DataRange::from_str("16..64").unwrap();
+        assert_eq!(range.resolve(100).unwrap(), 16..64);
+        assert!(range.resolve(50).is_err());
+        assert_eq!(
+            DataRange::from_str("16..").unwrap().resolve(20).unwrap(),
+            16..20
+        );
+        assert_eq!(
+            DataRange::from_str("..8").unwrap().resolve(20).unwrap(),
+            0..8
+        );
+        assert!(DataRange::from_str("16..").unwrap().resolve(10).is_err());
+        assert!(DataRange::from_str("64..16").is_err());
+        assert!(DataRange::from_str("16").is_err());
+        assert!(DataRange::from_str("a..b").is_err());
+    }
+
+    #[test]
+    fn test_byte_size_from_str() {
+        assert_eq!(ByteSize::from_str("4096").unwrap().0, 4096);
+        assert_eq!(ByteSize::from_str("512K").unwrap().0, 512 << 10);
+        assert_eq!(ByteSize::from_str("64M").unwrap().0, 64 << 20);
+        assert!(ByteSize::from_str("64MB").is_err());
+        assert!(ByteSize::from_str("M").is_err());
+    }
+
+    #[test]
+    fn test_rate_from_str() {
+        assert_eq!(Rate::from_str("10MB/s").unwrap().0, 10 << 20);
+        assert_eq!(Rate::from_str("512K/s").unwrap().0, 512 << 10);
+        assert_eq!(Rate::from_str("4096/s").unwrap().0, 4096);
+        assert!(Rate::from_str("10M").is_err());
+        assert!(Rate::from_str("0/s").is_err());
+        assert!(Rate::from_str("B/s").is_err());
+    }
+
+    #[test]
+    fn test_timeout_from_str() {
+        assert_eq!(Timeout::from_str("30s").unwrap().0, Duration::from_secs(30));
+        assert_eq!(Timeout::from_str("45").unwrap().0, Duration::from_secs(45));
+        assert_eq!(
+            Timeout::from_str("500ms").unwrap().0,
+            Duration::from_millis(500)
+        );
+        assert_eq!(Timeout::from_str("2m").unwrap().0, Duration::from_secs(120));
+        assert!(Timeout::from_str("s").is_err());
+        assert!(Timeout::from_str("10 days").is_err());
+        assert!(Timeout::from_str(&format!("{}h", u64::MAX / 60)).is_err());
+        assert!(Timeout::from_str(&format!("{}m", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_encode_output() {
+        let encode = |args: &[&str]| {
+            let args = ["pngchunk", "encode", "in.png", "ruSt"].iter().chain(args);
+            match Cli::from_iter_safe(args).unwrap().command {
+                PngArgs::Encode(args) => args,
+                command => panic!("unexpected {:?}", command),
+            }
+        };
+        let args = encode(&["hello", "out.png"]);
+        assert_eq!(args.message.as_deref(), Some("hello"));
+        assert_eq!(args.output_file, Some(PathBuf::from("out.png")));
+
+        // The message positional is never taken for the output file
+        let args = encode(&["--input-file", "data.bin", "-o", "out.png"]);
+        assert_eq!(
+            (args.message, args.output),
+            (None, Some(PathBuf::from("out.png")))
+        );
+        let args = encode(&["--input-file", "data.bin", "out.png"]);
+        assert_eq!(args.message.as_deref(), Some("out.png"));
+        assert!(args.output.is_none());
+
+        assert!(encode(&["hello"]).only_private);
+        assert!(!encode(&["--only-private", "false", "hello"]).only_private);
+    }
+
+    #[test]
+    fn test_output_format() {
+        let output_format = |args: &[&str]| Cli::from_iter(args).output_format();
+        assert_eq!(
+            output_format(&["pngchunk", "print", "f.png", "--format", "csv"]).unwrap(),
+            OutputFormat::Csv
+        );
+        assert_eq!(
+            output_format(&["pngchunk", "compat", "f.png"]).unwrap(),
+            OutputFormat::Text
+        );
+        let error = output_format(&["pngchunk", "--format", "json", "compat", "f.png"]);
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("not supported by this command"));
+        assert!(output_format(&["pngchunk", "print", "f.png", "--format", "rgb8"]).is_err());
+
+        let mut cli = Cli::from_iter([
+            "pngchunk", "pixels", "f.png", "-o", "f.raw", "--format", "gray8",
+        ]);
+        assert_eq!(cli.output_format().unwrap(), OutputFormat::Text);
+        let PngArgs::Pixels(args) = cli.command else {
+            panic!("expected pixels");
+        };
+        assert_eq!(args.format, PixelFormat::Gray8);
+        assert!(
+            output_format(&["pngchunk", "pixels", "f.png", "-o", "f.raw", "--format", "json"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_usage_error_status() {
+        let status = |args: &[&str]| Cli::usage_error_status(args);
+        assert_eq!(status(&["pngchunk", "exists", "f.png", "ab1c"]), 2);
+        assert_eq!(status(&["pngchunk", "--format", "json", "exists"]), 2);
+        assert_eq!(
+            status(&["pngchunk", "--deny-warnings", "exists", "f.png"]),
+            2
+        );
+        assert_eq!(status(&["pngchunk", "--config", "exists", "count"]), 1);
+        assert_eq!(status(&["pngchunk", "count", "exists"]), 1);
+        assert_eq!(status(&["pngchunk"]), 1);
+
+        // Every global option that takes a value has to be listed to be skipped over
+        let mut help = vec![];
+        Cli::clap().write_help(&mut help).unwrap();
+        let help = String::from_utf8(help).unwrap();
+        let options = help
+            .lines()
+            .filter_map(|line| line.trim().split_once(" <"))
+            .map(|(option, _)| option)
+            .filter(|option| option.starts_with("--"));
+        for option in options {
+            assert!(GLOBAL_VALUE_OPTIONS.contains(&option), "{}", option);
+        }
+    }
 }
@@ -0,0 +1,439 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde_json::{json, Value};
+
+use crate::temp::TempFile;
+use crate::{cancel, diagnostics, lock, stdio, Result};
+
+/// Expands the paths given on the command line into the files to process. Directories are
+/// searched for `.png` files when `recursive` is set, and paths containing `*`, `?` or `[` are
+/// glob patterns, with `**` matching any number of directories, for shells that do not expand
+/// them. Anything else is passed through as is.
+pub fn expand(paths: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        let pattern = path.to_string_lossy();
+        if path.is_dir() {
+            if !recursive {
+                return Err(format!(
+                    "{} is a directory, pass --recursive to process the PNG files in it",
+                    path.display()
+                )
+                .into());
+            }
+            files.extend(lock::png_files(path)?);
+        } else if !path.exists() && pattern.contains(['*', '?', '[']) {
+            let matches = glob::glob(&pattern)
+                .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| format!("Cannot read {}", e.path().display()))?;
+            let matches: Vec<PathBuf> = matches.into_iter().filter(|m| m.is_file()).collect();
+            if matches.is_empty() {
+                return Err(format!("No files match '{}'", pattern).into());
+            }
+            files.extend(matches);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// What processing one file printed, held back until the file is done so the output of files
+/// handled in parallel does not interleave
+#[derive(Debug, Default)]
+pub struct Output {
+    lines: Vec<(bool, String)>,
+}
+
+impl Output {
+    /// Queues a line for stdout
+    pub fn println(&mut self, line: impl Display) {
+        self.lines.push((false, line.to_string()));
+    }
+
+    /// Queues a line for stderr
+    pub fn eprintln(&mut self, line: impl Display) {
+        self.lines.push((true, line.to_string()));
+    }
+
+    /// Queues a warning for stderr, counting it like `diagnostics::warn`
+    pub fn warn(&mut self, message: impl Display) {
+        self.eprintln(format!("Warning: {}", message));
+        diagnostics::record();
+    }
+
+    fn flush(&self) {
+        let (mut stdout, mut stderr) = (io::stdout().lock(), io::stderr().lock());
+        for (is_error, line) in self.lines.iter() {
+            // Once a file has gone to stdout, everything else belongs on stderr
+            let _ = match *is_error || stdio::stdout_taken() {
+                true => writeln!(stderr, "{}", line),
+                false => writeln!(stdout, "{}", line),
+            };
+        }
+    }
+}
+
+/// How a batch of files is run
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// How many files to process at once, 0 for one per CPU
+    pub jobs: usize,
+    /// A file recording which files are done, so an interrupted run can resume
+    pub state: Option<PathBuf>,
+    /// The most bytes of input to read per second, on average
+    pub throttle: Option<usize>,
+    /// Whether to run at a lower priority
+    pub nice: bool,
+}
+
+/// Holds back each file until the files before it fit in the allowed rate, so a run reads no
+/// more than `rate` bytes per second on average
+#[derive(Debug)]
+struct Throttle {
+    rate: usize,
+    start: Instant,
+    /// Bytes in the files started so far
+    bytes: Mutex<u64>,
+}
+
+impl Throttle {
+    fn new(rate: usize) -> Throttle {
+        Throttle {
+            rate,
+            start: Instant::now(),
+            bytes: Mutex::new(0),
+        }
+    }
+
+    /// Waits until `file` may be read, stopping early if the run is cancelled
+    fn wait(&self, file: &Path) {
+        let size = fs::metadata(file).map_or(0, |metadata| metadata.len());
+        let before = {
+            let mut bytes = self.bytes.lock().unwrap_or_else(|e| e.into_inner());
+            *bytes += size;
+            *bytes - size
+        };
+        let due = self.start + Duration::from_secs_f64(before as f64 / self.rate as f64);
+        while !cancel::is_cancelled() {
+            match due.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => {
+                    thread::sleep(left.min(Duration::from_millis(100)))
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Lowers the CPU priority of this thread and the threads it starts. Raising the nice value
+/// needs no privileges, so this cannot fail in practice.
+#[cfg(unix)]
+fn lower_priority() {
+    // SAFETY: nice only changes the scheduling priority and touches no memory
+    unsafe {
+        libc::nice(10);
+    }
+}
+
+#[cfg(not(unix))]
+fn lower_priority() {
+    diagnostics::warn("--nice is not supported on this platform");
+}
+
+/// The files a batch run has finished, saved after each one so a run that is interrupted or
+/// crashes can pick up where it left off. The file is a JSON object listing them under `done`.
+#[derive(Debug)]
+pub struct State {
+    path: PathBuf,
+    done: Mutex<BTreeSet<String>>,
+}
+
+impl State {
+    /// Reads the state in `path`, starting afresh if it does not exist yet
+    pub fn load(path: &Path) -> Result<State> {
+        let done = match fs::read(path) {
+            Ok(bytes) => {
+                let value: Value = serde_json::from_slice(&bytes)
+                    .map_err(|e| format!("Invalid state file {}: {}", path.display(), e))?;
+                value["done"]
+                    .as_array()
+                    .ok_or_else(|| format!("State file {} has no done list", path.display()))?
+                    .iter()
+                    .filter_map(|file| file.as_str().map(String::from))
+                    .collect()
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e).into()),
+        };
+        Ok(State {
+            path: path.to_path_buf(),
+            done: Mutex::new(done),
+        })
+    }
+
+    pub fn is_done(&self, file: &Path) -> bool {
+        self.done().contains(&*file.to_string_lossy())
+    }
+
+    /// Records `file` as done. The state is replaced atomically, so it is never left half
+    /// written, and is saved even once the run has been cancelled.
+    pub fn mark_done(&self, file: &Path) -> Result<()> {
+        let mut done = self.done();
+        done.insert(file.to_string_lossy().into_owned());
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut temp = TempFile::new_in(dir)?;
+        temp.write_all(json!({ "done": *done }).to_string().as_bytes())?;
+        temp.persist(&self.path)
+    }
+
+    /// Removes the state once every file is done, so the next run starts afresh
+    pub fn finish(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn done(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.done.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Runs `f` on every file, `options.jobs` at a time. A single file is handled exactly as if it
+/// had been given on its own. With several, each file's name is printed to stderr before its
+/// output, a failure is reported and the rest are still processed, then a summary is printed and
+/// the result is an error if any file failed.
+///
+/// With a state file, files it lists as done are skipped and each file that succeeds is added
+/// to it. It is removed once every file has succeeded.
+pub fn each<F>(files: &[PathBuf], options: &Options, f: F) -> Result<()>
+where
+    F: Fn(&Path, &mut Output) -> Result<()> + Sync,
+{
+    let state = options.state.as_deref().map(State::load).transpose()?;
+    let files: Vec<PathBuf> = match &state {
+        Some(state) => {
+            let pending: Vec<PathBuf> = files
+                .iter()
+                .filter(|file| !state.is_done(file))
+                .cloned()
+                .collect();
+            if pending.len() < files.len() {
+                eprintln!(
+                    "Resuming: skipping {} file(s) already done according to {}",
+                    files.len() - pending.len(),
+                    state.path.display()
+                );
+            }
+            pending
+        }
+        None => files.to_vec(),
+    };
+    if options.nice {
+        lower_priority();
+    }
+    let throttle = options.throttle.map(Throttle::new);
+    // A file is only recorded once it has been handled in full, including any write
+    let f = |file: &Path, output: &mut Output| -> Result<()> {
+        if let Some(throttle) = &throttle {
+            throttle.wait(file);
+        }
+        f(file, output)?;
+        match &state {
+            Some(state) => state.mark_done(file),
+            None => Ok(()),
+        }
+    };
+    let result = run(&files, options.jobs, f);
+    match state {
+        Some(state) if result.is_ok() => state.finish().and(result),
+        _ => result,
+    }
+}
+
+fn run<F>(files: &[PathBuf], jobs: usize, f: F) -> Result<()>
+where
+    F: Fn(&Path, &mut Output) -> Result<()> + Sync,
+{
+    if let [file] = files {
+        let mut output = Output::default();
+        let result = f(file, &mut output);
+        output.flush();
+        return result;
+    }
+    let _span = tracing::info_span!("batch", files = files.len()).entered();
+    let printed = Mutex::new(());
+    let run = |file: &PathBuf| -> bool {
+        let mut output = Output::default();
+        let result =
+            tracing::info_span!("file", path = %file.display()).in_scope(|| f(file, &mut output));
+        let _guard = printed.lock().unwrap_or_else(|e| e.into_inner());
+        eprintln!("{}:", file.display());
+        output.flush();
+        match result {
+            Ok(()) => false,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                if let Some(location) = e.location() {
+                    eprint!("{}", location.snippet());
+                }
+                true
+            }
+        }
+    };
+    let failed = match jobs {
+        1 => files.iter().filter(|file| run(file)).count(),
+        _ => ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .map_err(|e| format!("Cannot start {} jobs: {}", jobs, e))?
+            .install(|| files.par_iter().filter(|file| run(file)).count()),
+    };
+    eprintln!(
+        "Processed {} files: {} succeeded, {} failed",
+        files.len(),
+        files.len() - failed,
+        failed
+    );
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} files failed", failed, files.len()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_expand() {
+        let dir =
+            crate::temp::work_dir().join(format!("pngchunk-batch-{}", crate::temp::process_id()));
+        fs::create_dir_all(dir.join("icons")).unwrap();
+        for name in ["a.png", "b.txt", "icons/c.png", "icons/d.PNG"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| {
+                    f.strip_prefix(&dir)
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect()
+        };
+
+        let all = expand(std::slice::from_ref(&dir), true).unwrap();
+        assert_eq!(names(all), ["a.png", "icons/c.png", "icons/d.PNG"]);
+        assert!(expand(std::slice::from_ref(&dir), false).is_err());
+
+        let pattern = dir.join("**").join("*.png");
+        assert_eq!(
+            names(expand(&[pattern], false).unwrap()),
+            ["a.png", "icons/c.png"]
+        );
+        assert!(expand(&[dir.join("*.gif")], false).is_err());
+
+        let missing = dir.join("missing.png");
+        assert_eq!(
+            expand(std::slice::from_ref(&missing), false).unwrap(),
+            [missing]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_each() {
+        let files: Vec<PathBuf> = ["a", "b", "c"].iter().map(PathBuf::from).collect();
+        for jobs in [1, 2, 0] {
+            let seen = Mutex::new(vec![]);
+            let options = Options {
+                jobs,
+                ..Options::default()
+            };
+            let result = each(&files, &options, |file, _| {
+                seen.lock().unwrap().push(file.to_path_buf());
+                match file.to_str() {
+                    Some("b") => Err("broken".into()),
+                    _ => Ok(()),
+                }
+            });
+            let mut seen = seen.into_inner().unwrap();
+            seen.sort();
+            assert_eq!(seen, files);
+            assert_eq!(result.unwrap_err().to_string(), "1 of 3 files failed");
+        }
+
+        assert_eq!(
+            each(&files[1..2], &Options::default(), |_, _| Err(
+                "broken".into()
+            ))
+            .unwrap_err()
+            .to_string(),
+            "broken"
+        );
+    }
+
+    #[test]
+    fn test_each_resumes_from_state() {
+        let dir =
+            crate::temp::work_dir().join(format!("pngchunk-state-{}", crate::temp::process_id()));
+        fs::create_dir_all(&dir).unwrap();
+        let options = Options {
+            jobs: 1,
+            state: Some(dir.join("state.json")),
+            ..Options::default()
+        };
+        let files: Vec<PathBuf> = ["a", "b", "c"].iter().map(PathBuf::from).collect();
+        let run = |fail: &'static str| {
+            let seen = Mutex::new(vec![]);
+            let result = each(&files, &options, |file, _| {
+                seen.lock().unwrap().push(file.to_path_buf());
+                match file.to_str() {
+                    Some(name) if name == fail => Err("broken".into()),
+                    _ => Ok(()),
+                }
+            });
+            (result.is_ok(), seen.into_inner().unwrap())
+        };
+
+        assert_eq!(run("b"), (false, files.clone()));
+        let state = State::load(&dir.join("state.json")).unwrap();
+        assert!(state.is_done(Path::new("a")) && !state.is_done(Path::new("b")));
+
+        // Only the file that failed is tried again, and the state goes once it succeeds
+        assert_eq!(run(""), (true, vec![PathBuf::from("b")]));
+        assert!(!dir.join("state.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_throttle() {
+        let mut file = TempFile::new().unwrap();
+        file.write_all(&[0; 100]).unwrap();
+
+        let throttle = Throttle::new(1000);
+        let start = Instant::now();
+        throttle.wait(file.path());
+        // The first 100 bytes take 100ms at 1000 bytes per second
+        throttle.wait(file.path());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
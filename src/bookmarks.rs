@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use serde_json::json;
+
+use crate::{chunk::Chunk, png::Png, Error, Result};
+
+/// The hex editors `export` can write bookmarks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    /// A 010 Editor binary template (`.bt`) placing a struct at every chunk
+    Editor010,
+    /// An ImHex bookmarks file (`.hexbm`)
+    ImHex,
+    /// An ImHex pattern (`.hexpat`) placing a struct at every chunk
+    HexPat,
+}
+
+impl FromStr for Editor {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "010editor" => Ok(Editor::Editor010),
+            "imhex" => Ok(Editor::ImHex),
+            "hexpat" => Ok(Editor::HexPat),
+            _ => Err(format!(
+                "Unknown editor '{}', expected 010editor, imhex or hexpat",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+/// A named byte range of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// The signature, then every chunk followed by its length, type, data and CRC fields
+pub fn regions(png: &Png) -> Vec<Region> {
+    let mut regions = vec![Region {
+        name: "signature".to_string(),
+        offset: 0,
+        size: Png::STANDARD_HEADER.len(),
+    }];
+    let mut offset = Png::STANDARD_HEADER.len();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let name = chunk_name(index, chunk);
+        let length = chunk.data().len();
+        let region = |field: &str, start: usize, size: usize| Region {
+            name: format!("{}.{}", name, field),
+            offset: start,
+            size,
+        };
+        regions.push(Region {
+            name: name.clone(),
+            offset,
+            size: Chunk::MIN_CHUNK_LENGTH + length,
+        });
+        regions.push(region("length", offset, 4));
+        regions.push(region("type", offset + 4, 4));
+        if length > 0 {
+            regions.push(region("data", offset + 8, length));
+        }
+        regions.push(region("crc", offset + 8 + length, 4));
+        offset += Chunk::MIN_CHUNK_LENGTH + length;
+    }
+    regions
+}
+
+/// Writes bookmarks or a template for `editor` covering every chunk of `png`
+pub fn export(png: &Png, editor: Editor) -> String {
+    match editor {
+        Editor::Editor010 => template_010(png),
+        Editor::ImHex => imhex_bookmarks(png),
+        Editor::HexPat => hexpat(png),
+    }
+}
+
+/// Chunk names are unique and usable as identifiers, e.g. `chunk_2_tEXt`
+fn chunk_name(index: usize, chunk: &Chunk) -> String {
+    format!("chunk_{}_{}", index, chunk.chunk_type())
+}
+
+fn template_010(png: &Png) -> String {
+    let mut template = String::from(
+        "// 010 Editor template generated by pngchunk\n\
+         BigEndian();\n\
+         \n\
+         typedef struct (uint size) {\n    \
+             uint32 length;\n    \
+             char type[4];\n    \
+             if (size > 0) uchar data[size];\n    \
+             uint32 crc <format=hex>;\n\
+         } CHUNK;\n\
+         \n\
+         uchar signature[8];\n",
+    );
+    let mut offset = Png::STANDARD_HEADER.len();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        template.push_str(&format!(
+            "FSeek({:#x});\nCHUNK {}({}) <comment=\"{}\">;\n",
+            offset,
+            chunk_name(index, chunk),
+            chunk.data().len(),
+            chunk.chunk_type()
+        ));
+        offset += Chunk::MIN_CHUNK_LENGTH + chunk.data().len();
+    }
+    template
+}
+
+fn imhex_bookmarks(png: &Png) -> String {
+    let bookmarks: serde_json::Map<String, serde_json::Value> = regions(png)
+        .into_iter()
+        .enumerate()
+        .map(|(id, region)| {
+            let bookmark = json!({
+                "id": id,
+                "name": region.name,
+                "comment": "",
+                "color": if region.name.contains('.') { 0x60_80_80_80u32 } else { 0x60_c0_80_40 },
+                "locked": true,
+                "region": { "address": region.offset, "size": region.size },
+            });
+            (id.to_string(), bookmark)
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({ "bookmarks": bookmarks })).unwrap_or_default()
+}
+
+fn hexpat(png: &Png) -> String {
+    let mut pattern = String::from(
+        "// ImHex pattern generated by pngchunk\n\
+         #pragma endian big\n\
+         \n\
+         struct Chunk {\n    \
+             u32 length;\n    \
+             char type[4];\n    \
+             u8 data[length];\n    \
+             u32 crc;\n\
+         };\n\
+         \n\
+         u8 signature[8] @ 0x00;\n",
+    );
+    let mut offset = Png::STANDARD_HEADER.len();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        pattern.push_str(&format!(
+            "Chunk {} @ {:#x};\n",
+            chunk_name(index, chunk),
+            offset
+        ));
+        offset += Chunk::MIN_CHUNK_LENGTH + chunk.data().len();
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn testing_png() -> Png {
+        testing::png_from(&[("IHDR", &[0; 13]), ("IEND", &[])])
+    }
+
+    #[test]
+    fn test_regions() {
+        let regions = regions(&testing_png());
+        let find = |name: &str| regions.iter().find(|r| r.name == name).unwrap();
+        assert_eq!(find("signature").size, 8);
+        assert_eq!(find("chunk_0_IHDR").size, 25);
+        assert_eq!(find("chunk_0_IHDR.data").offset, 16);
+        assert_eq!(find("chunk_0_IHDR.crc").offset, 29);
+        assert_eq!(find("chunk_1_IEND").offset, 33);
+        assert!(regions.iter().all(|r| r.name != "chunk_1_IEND.data"));
+    }
+
+    #[test]
+    fn test_export() {
+        let png = testing_png();
+        let template = export(&png, Editor::Editor010);
+        assert!(template.contains("FSeek(0x21);\nCHUNK chunk_1_IEND(0)"));
+
+        let pattern = export(&png, Editor::HexPat);
+        assert!(pattern.contains("Chunk chunk_0_IHDR @ 0x8;"));
+
+        let bookmarks: serde_json::Value =
+            serde_json::from_str(&export(&png, Editor::ImHex)).unwrap();
+        assert_eq!(bookmarks["bookmarks"]["1"]["name"], "chunk_0_IHDR");
+        assert_eq!(bookmarks["bookmarks"]["1"]["region"]["size"], 25);
+    }
+
+    #[test]
+    fn test_editor_from_str() {
+        assert_eq!(Editor::from_str("imhex").unwrap(), Editor::ImHex);
+        assert!(Editor::from_str("hexfiend").is_err());
+    }
+}
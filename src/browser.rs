@@ -0,0 +1,82 @@
+//! Metadata left by web browsers in screenshots and canvas exports. Browsers write no chunk
+//! types of their own, so they are recognised by the names and text they put in standard chunks.
+
+use crate::text::{self, latin1, split_null};
+use crate::Chunk;
+
+/// The iCCP profile name written by Skia, the graphics library Chrome encodes PNGs with
+pub const SKIA_PROFILE_NAME: &str = "Skia";
+
+/// Text keywords whose value names the program that wrote the file
+const TOOL_KEYWORDS: [&str; 3] = ["Software", "Comment", "Title"];
+
+/// Reduces a value such as "Mozilla Firefox 121.0" to the browser it names
+pub fn browser_name(value: &str) -> Option<&'static str> {
+    if value.contains("Firefox") {
+        Some("Firefox")
+    } else if value.contains("Chrome") || value.contains("Chromium") {
+        Some("Chrome")
+    } else {
+        None
+    }
+}
+
+/// The browser whose encoder wrote `chunk`, with the evidence, for chunks a browser leaves
+/// behind
+pub fn writer(chunk: &Chunk) -> Option<(&'static str, String)> {
+    match chunk.chunk_type().to_string().as_str() {
+        "iCCP" => {
+            let (name, _) = split_null(chunk.data())?;
+            (latin1(name) == SKIA_PROFILE_NAME).then(|| {
+                (
+                    "Chrome",
+                    format!("ICC profile named '{}'", SKIA_PROFILE_NAME),
+                )
+            })
+        }
+        _ => {
+            let (keyword, value) = text::keyword_text(chunk).ok()??;
+            if !TOOL_KEYWORDS.contains(&keyword.as_str()) {
+                return None;
+            }
+            let browser = browser_name(&value)?;
+            Some((browser, format!("{} is '{}'", keyword, value)))
+        }
+    }
+}
+
+/// Explains chunks written by a browser, or returns `None` for every other chunk
+pub fn describe(chunk: &Chunk) -> Option<String> {
+    let (browser, evidence) = writer(chunk)?;
+    Some(match browser {
+        "Chrome" if chunk.chunk_type().to_string() == "iCCP" => format!(
+            "{}, as written by Skia when Chrome saves a screenshot or canvas with a color profile",
+            evidence
+        ),
+        _ => format!("{}, as written by {} screenshots", evidence, browser),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    #[test]
+    fn test_describe() {
+        let skia = chunk("iCCP", b"Skia\0\0\x78\x9c\x03\0\0\0\0\x01");
+        assert!(describe(&skia)
+            .unwrap()
+            .starts_with("ICC profile named 'Skia'"));
+        assert_eq!(describe(&chunk("iCCP", b"sRGB\0\0")), None);
+
+        let firefox = chunk("tEXt", b"Software\0Mozilla Firefox 121.0");
+        assert_eq!(
+            describe(&firefox).unwrap(),
+            "Software is 'Mozilla Firefox 121.0', as written by Firefox screenshots"
+        );
+        assert_eq!(writer(&firefox).unwrap().0, "Firefox");
+        assert_eq!(describe(&chunk("tEXt", b"Author\0Firefox fan")), None);
+        assert_eq!(describe(&chunk("tEXt", b"Software\0GIMP 2.10")), None);
+    }
+}
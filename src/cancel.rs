@@ -0,0 +1,121 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "wasi")]
+use std::sync::OnceLock;
+#[cfg(not(target_os = "wasi"))]
+use std::thread;
+use std::time::Duration;
+#[cfg(target_os = "wasi")]
+use std::time::Instant;
+
+use crate::Result;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+/// WASI has no threads to fire the timeout, so it is checked against this at every safe point
+#[cfg(target_os = "wasi")]
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// Exit status used when a second Ctrl-C forces the process to stop, as shells do for SIGINT
+#[cfg(not(target_os = "wasi"))]
+const INTERRUPTED_STATUS: i32 = 130;
+/// Exit status used when `--timeout` expires, matching coreutils `timeout`
+pub const TIMEOUT_STATUS: i32 = 124;
+/// How long an operation gets to reach a safe point after its timeout before it is killed
+#[cfg(not(target_os = "wasi"))]
+const TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Installs a Ctrl-C handler that asks running operations to stop at their next safe point.
+/// Pressing Ctrl-C a second time exits immediately.
+#[cfg(not(target_os = "wasi"))]
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            process::exit(INTERRUPTED_STATUS);
+        }
+        eprintln!("Cancelling, press Ctrl-C again to stop immediately");
+    })?;
+    Ok(())
+}
+
+/// WASI delivers no signals, so there is nothing to install
+#[cfg(target_os = "wasi")]
+pub fn install() -> Result<()> {
+    Ok(())
+}
+
+/// Cancels the running operation once `timeout` has passed. If it has not stopped at a safe
+/// point within a short grace period the process exits with `TIMEOUT_STATUS`.
+#[cfg(not(target_os = "wasi"))]
+pub fn start_timeout(timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        TIMED_OUT.store(true, Ordering::SeqCst);
+        CANCELLED.store(true, Ordering::SeqCst);
+        thread::sleep(TIMEOUT_GRACE);
+        eprintln!("Error: timed out after {:?}", timeout);
+        process::exit(TIMEOUT_STATUS);
+    });
+}
+
+/// Cancels the running operation at its first safe point after `timeout` has passed. Without
+/// threads an operation that never reaches one cannot be stopped.
+#[cfg(target_os = "wasi")]
+pub fn start_timeout(timeout: Duration) {
+    // A deadline too far off to represent is never reached
+    if let Some(deadline) = Instant::now().checked_add(timeout) {
+        let _ = DEADLINE.set(deadline);
+    }
+}
+
+pub fn timed_out() -> bool {
+    #[cfg(target_os = "wasi")]
+    if DEADLINE
+        .get()
+        .is_some_and(|deadline| Instant::now() >= *deadline)
+    {
+        TIMED_OUT.store(true, Ordering::SeqCst);
+        CANCELLED.store(true, Ordering::SeqCst);
+    }
+    TIMED_OUT.load(Ordering::SeqCst)
+}
+
+pub fn is_cancelled() -> bool {
+    #[cfg(target_os = "wasi")]
+    timed_out();
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Fails if cancellation has been requested. Call this before committing to an irreversible step.
+pub fn check() -> Result<()> {
+    check_state(timed_out(), is_cancelled())
+}
+
+fn check_state(timed_out: bool, cancelled: bool) -> Result<()> {
+    if timed_out {
+        return Err("Timed out".into());
+    }
+    if cancelled {
+        return Err("Cancelled".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        // Other tests run alongside this one, so the global flags are left alone
+        assert!(check_state(false, false).is_ok());
+        assert_eq!(
+            check_state(false, true).unwrap_err().to_string(),
+            "Cancelled"
+        );
+        assert_eq!(
+            check_state(true, true).unwrap_err().to_string(),
+            "Timed out"
+        );
+    }
+}
@@ -1,31 +1,58 @@
 use core::fmt;
+use std::ops::Range;
+use std::sync::Arc;
 
-use crate::{chunk_type::ChunkType, Error, Result};
+use crate::{chunk_type::ChunkType, Error, PngChunkError, Result};
 use crc::{Crc, CRC_32_ISO_HDLC};
+use sha2::{Digest, Sha256};
 
-#[derive(Debug)]
+/// A single PNG chunk. The chunk data is reference counted, so cloning a `Chunk` is cheap and
+/// the data is only copied when a shared chunk is modified.
+#[derive(Debug, Clone)]
 pub struct Chunk {
     m_length: u32,
     m_type: ChunkType,
-    m_chunk_data: Vec<u8>,
+    m_chunk_data: Arc<[u8]>,
     m_crc: u32,
+    /// The CRC as it was read from the file, which may differ from `m_crc` for chunks read
+    /// leniently
+    m_stored_crc: u32,
 }
 
 impl Chunk {
     pub const MIN_CHUNK_LENGTH: usize = 12;
-
-    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+    /// The largest amount of data a chunk may hold, 2^31-1 bytes as required by the PNG spec
+    pub const MAX_DATA_LENGTH: usize = (1 << 31) - 1;
+
+    /// Creates a chunk, failing if `data` is longer than `MAX_DATA_LENGTH`
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        if data.len() > Chunk::MAX_DATA_LENGTH {
+            return Err(PngChunkError::Parse(format!(
+                "{} chunk data is {} bytes, more than the {} allowed by the PNG spec",
+                chunk_type,
+                data.len(),
+                Chunk::MAX_DATA_LENGTH
+            )));
+        }
         let m_length = data.len() as u32;
 
-        let combined = [&chunk_type.bytes()[..], &data[..]].concat();
-        let m_crc = Chunk::calculate_crc(combined);
+        let m_crc = Chunk::calculate_crc(&chunk_type.bytes(), &data);
 
-        Self {
+        Ok(Self {
             m_length,
             m_type: chunk_type,
-            m_chunk_data: data,
+            m_chunk_data: data.into(),
             m_crc,
-        }
+            m_stored_crc: m_crc,
+        })
+    }
+
+    /// Creates a chunk that keeps `stored_crc` as its CRC even if it does not match the data,
+    /// so a damaged chunk can be examined and written back unchanged
+    pub fn with_stored_crc(chunk_type: ChunkType, data: Vec<u8>, stored_crc: u32) -> Result<Chunk> {
+        let mut chunk = Chunk::new(chunk_type, data)?;
+        chunk.m_stored_crc = stored_crc;
+        Ok(chunk)
     }
 
     pub fn length(&self) -> u32 {
@@ -42,20 +69,154 @@ impl Chunk {
         &self.m_chunk_data
     }
 
-    /// The CRC of this chunk
+    /// The CRC of this chunk's type and data, as computed from them
     pub fn crc(&self) -> u32 {
         self.m_crc
     }
 
+    /// The same as `crc`
+    pub fn computed_crc(&self) -> u32 {
+        self.m_crc
+    }
+
+    /// The CRC read from the file, which `as_bytes` writes back. For chunks that were not read
+    /// leniently this is always the computed CRC.
+    pub fn stored_crc(&self) -> u32 {
+        self.m_stored_crc
+    }
+
+    /// Whether the stored CRC matches the chunk's type and data
+    pub fn crc_is_valid(&self) -> bool {
+        self.m_stored_crc == self.m_crc
+    }
+
+    /// Replaces the stored CRC with the computed one
+    pub fn fix_crc(&mut self) {
+        self.m_stored_crc = self.m_crc;
+    }
+
+    /// Replaces the data stored in this chunk, updating its length and CRC
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        *self = Chunk::new(self.m_type.clone(), data)?;
+        Ok(())
+    }
+
+    /// Modifies the data stored in this chunk in place and updates its CRC. If the data is
+    /// shared with clones of this chunk it is copied first, leaving the clones untouched.
+    pub fn modify_data<F: FnOnce(&mut [u8])>(&mut self, f: F) {
+        f(Arc::make_mut(&mut self.m_chunk_data));
+        self.m_crc = Chunk::calculate_crc(&self.m_type.bytes(), &self.m_chunk_data);
+        self.m_stored_crc = self.m_crc;
+    }
+
+    /// The bytes of the chunk data in `range`, failing if the range is out of bounds
+    pub fn data_slice(&self, range: Range<usize>) -> Result<&[u8]> {
+        self.m_chunk_data.get(range.clone()).ok_or_else(|| {
+            format!(
+                "Range {}..{} is out of bounds of the {} byte {} chunk",
+                range.start,
+                range.end,
+                self.m_chunk_data.len(),
+                self.m_type
+            )
+            .into()
+        })
+    }
+
+    /// Reads the byte at `offset` in the chunk data
+    pub fn read_u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.field::<1>(offset)?[0])
+    }
+
+    /// Reads a big-endian `u16` at `offset` in the chunk data
+    pub fn read_u16_be(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.field(offset)?))
+    }
+
+    /// Reads a big-endian `u32` at `offset` in the chunk data
+    pub fn read_u32_be(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.field(offset)?))
+    }
+
+    /// Reads a little-endian `u16` at `offset` in the chunk data
+    pub fn read_u16_le(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.field(offset)?))
+    }
+
+    /// Reads a little-endian `u32` at `offset` in the chunk data
+    pub fn read_u32_le(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.field(offset)?))
+    }
+
+    /// Overwrites the byte at `offset` in the chunk data and updates the CRC
+    pub fn write_u8(&mut self, offset: usize, value: u8) -> Result<()> {
+        self.set_field(offset, [value])
+    }
+
+    /// Overwrites a big-endian `u16` at `offset` in the chunk data and updates the CRC
+    pub fn write_u16_be(&mut self, offset: usize, value: u16) -> Result<()> {
+        self.set_field(offset, value.to_be_bytes())
+    }
+
+    /// Overwrites a big-endian `u32` at `offset` in the chunk data and updates the CRC
+    pub fn write_u32_be(&mut self, offset: usize, value: u32) -> Result<()> {
+        self.set_field(offset, value.to_be_bytes())
+    }
+
+    /// Overwrites a little-endian `u16` at `offset` in the chunk data and updates the CRC
+    pub fn write_u16_le(&mut self, offset: usize, value: u16) -> Result<()> {
+        self.set_field(offset, value.to_le_bytes())
+    }
+
+    /// Overwrites a little-endian `u32` at `offset` in the chunk data and updates the CRC
+    pub fn write_u32_le(&mut self, offset: usize, value: u32) -> Result<()> {
+        self.set_field(offset, value.to_le_bytes())
+    }
+
+    fn field<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        self.check_field(offset, N)?;
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.m_chunk_data[offset..offset + N]);
+        Ok(bytes)
+    }
+
+    fn set_field<const N: usize>(&mut self, offset: usize, bytes: [u8; N]) -> Result<()> {
+        self.check_field(offset, N)?;
+        self.modify_data(|data| data[offset..offset + N].copy_from_slice(&bytes));
+        Ok(())
+    }
+
+    fn check_field(&self, offset: usize, size: usize) -> Result<()> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.m_chunk_data.len() => Ok(()),
+            _ => Err(format!(
+                "{} bytes at offset {} are out of range of the {} byte {} chunk",
+                size,
+                offset,
+                self.m_chunk_data.len(),
+                self.m_type
+            )
+            .into()),
+        }
+    }
+
     /// Returns the data stored in this chunk as a `String`. This function will return an error
     /// if the stored data is not valid UTF-8.
     pub fn data_as_string(&self) -> Result<String> {
-        if let Ok(s) = String::from_utf8(self.m_chunk_data.clone()) {
+        if let Ok(s) = String::from_utf8(self.m_chunk_data.to_vec()) {
             return Ok(s);
         }
         Err("String is not valid utf-8.".into())
     }
 
+    /// Returns the SHA-256 digest of the data stored in this chunk as a lowercase hex string
+    pub fn data_digest(&self) -> String {
+        Sha256::digest(&self.m_chunk_data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
     /// Returns this chunk as a byte sequences described by the PNG spec.
     /// The following data is included in this byte sequence in order:
     /// 1. Length of the data *(4 bytes)*
@@ -67,27 +228,65 @@ impl Chunk {
             .m_length
             .to_be_bytes()
             .into_iter()
-            .chain(self.m_type.bytes().into_iter())
+            .chain(self.m_type.bytes())
             .chain(self.data().iter().cloned())
-            .chain(self.m_crc.to_be_bytes().into_iter())
+            .chain(self.m_stored_crc.to_be_bytes())
             .collect();
 
         bytes
     }
 
-    fn calculate_crc(bytes: Vec<u8>) -> u32 {
+    /// Returns the total size of the chunk starting at `offset` in `bytes` as given by its length
+    /// field, failing if the chunk does not fit. The size is compared against the bytes that
+    /// remain rather than added to `offset`, so a huge declared length cannot overflow `usize`.
+    pub(crate) fn size_at(bytes: &[u8], offset: usize) -> Result<usize> {
+        let remaining = bytes.len().saturating_sub(offset);
+        if remaining < Chunk::MIN_CHUNK_LENGTH {
+            return Err(PngChunkError::Parse("Truncated chunk".to_string()));
+        }
+
+        let length = u32::from_be_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ]);
+        match usize::try_from(length) {
+            Ok(length) if length > Chunk::MAX_DATA_LENGTH => Err(PngChunkError::Parse(format!(
+                "Chunk declares {} bytes of data, more than the {} allowed by the PNG spec",
+                length,
+                Chunk::MAX_DATA_LENGTH
+            ))),
+            Ok(length) if length <= remaining - Chunk::MIN_CHUNK_LENGTH => {
+                Ok(Chunk::MIN_CHUNK_LENGTH + length)
+            }
+            _ => Err(PngChunkError::Parse(format!(
+                "Chunk declares {} bytes of data but only {} remain",
+                length,
+                remaining - Chunk::MIN_CHUNK_LENGTH
+            ))),
+        }
+    }
+
+    /// Calculates the CRC of a chunk from its type and data
+    pub(crate) fn calculate_crc(chunk_type: &[u8], data: &[u8]) -> u32 {
         let crc: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
         let mut digest = crc.digest();
-        digest.update(&bytes);
+        digest.update(chunk_type);
+        digest.update(data);
         digest.finalize()
     }
 }
 
+/// The alternate form, `{:#}`, follows the type with its description when it is a known chunk
 impl fmt::Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Chunk {{",)?;
         writeln!(f, "  Length: {}", self.length())?;
-        writeln!(f, "  Type: {}", self.chunk_type())?;
+        match self.chunk_type().description().filter(|_| f.alternate()) {
+            Some(description) => writeln!(f, "  Type: {} — {}", self.chunk_type(), description)?,
+            None => writeln!(f, "  Type: {}", self.chunk_type())?,
+        }
         writeln!(f, "  Data: {} bytes", self.data().len())?;
         writeln!(f, "  Crc: {}", self.crc())?;
         writeln!(f, "}}",)?;
@@ -99,12 +298,21 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
     fn try_from(value: &[u8]) -> Result<Self> {
         if value.len() < Chunk::MIN_CHUNK_LENGTH {
-            return Err("Chunk must contain atleast 12 bytes.".into());
+            return Err(PngChunkError::Parse(
+                "Chunk must contain atleast 12 bytes.".to_string(),
+            ));
         }
 
         let mut buf: [u8; 4] = [0; 4];
         buf.copy_from_slice(&value[0..4]);
         let m_length = u32::from_be_bytes(buf);
+        if m_length as usize > Chunk::MAX_DATA_LENGTH {
+            return Err(PngChunkError::Parse(format!(
+                "Chunk declares {} bytes of data, more than the {} allowed by the PNG spec",
+                m_length,
+                Chunk::MAX_DATA_LENGTH
+            )));
+        }
 
         buf.fill(0);
         buf.copy_from_slice(&value[4..8]);
@@ -112,10 +320,17 @@ impl TryFrom<&[u8]> for Chunk {
 
         let m_chunk_data: Vec<u8> = match value.len() {
             Chunk::MIN_CHUNK_LENGTH => vec![], // empty chunk data field
-            _ => value[8..value.len() - 4].into_iter().cloned().collect(),
+            _ => value[8..value.len() - 4].to_vec(),
         };
+        if u32::try_from(m_chunk_data.len()) != Ok(m_length) {
+            return Err(PngChunkError::Parse(format!(
+                "Chunk declares {} bytes of data but holds {}",
+                m_length,
+                m_chunk_data.len()
+            )));
+        }
 
-        let m_crc = Chunk::calculate_crc([&m_type.bytes()[..], &m_chunk_data].concat());
+        let m_crc = Chunk::calculate_crc(&m_type.bytes(), &m_chunk_data);
 
         let crc_to_test = &value[8 + m_chunk_data.len()..];
         if crc_to_test.len() != 4 {
@@ -130,14 +345,19 @@ impl TryFrom<&[u8]> for Chunk {
         let crc_to_test = u32::from_be_bytes(buf);
 
         if crc_to_test != m_crc {
-            return Err(format!("CRC invalid: Got {}, should be {}", crc_to_test, m_crc).into());
+            return Err(PngChunkError::Crc {
+                chunk_type: m_type.to_string(),
+                expected: m_crc,
+                actual: crc_to_test,
+            });
         }
 
         Ok(Chunk {
             m_length,
             m_type,
-            m_chunk_data,
+            m_chunk_data: m_chunk_data.into(),
             m_crc,
+            m_stored_crc: m_crc,
         })
     }
 }
@@ -172,7 +392,7 @@ mod tests {
         let data = "This is where your secret message will be!"
             .as_bytes()
             .to_vec();
-        let chunk = Chunk::new(chunk_type, data);
+        let chunk = Chunk::new(chunk_type, data).unwrap();
         assert_eq!(chunk.length(), 42);
         assert_eq!(chunk.crc(), 2882656334);
     }
@@ -197,6 +417,44 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_data_digest() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"abc".to_vec()).unwrap();
+        assert_eq!(
+            chunk.data_digest(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_chunk_copy_on_write() {
+        let mut chunk = testing_chunk();
+        let shared = chunk.clone();
+        assert!(std::ptr::eq(chunk.data(), shared.data()));
+
+        chunk.modify_data(|data| data[0] = b't');
+        assert_eq!(&chunk.data()[..4], b"this");
+        assert_eq!(&shared.data()[..4], b"This");
+        assert_ne!(chunk.crc(), shared.crc());
+
+        let mut expected = shared.data().to_vec();
+        expected[0] = b't';
+        assert_eq!(
+            chunk.crc(),
+            Chunk::new(shared.chunk_type().clone(), expected)
+                .unwrap()
+                .crc()
+        );
+    }
+
+    #[test]
+    fn test_chunk_set_data() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"short".to_vec()).unwrap();
+        assert_eq!(chunk.length(), 5);
+        assert_eq!(chunk.data(), b"short");
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -251,6 +509,94 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_length_mismatch() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[3] += 1;
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_over_max_length() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[..4].copy_from_slice(&(1u32 << 31).to_be_bytes());
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+        assert!(Chunk::size_at(&bytes, 0)
+            .unwrap_err()
+            .to_string()
+            .contains("allowed by the PNG spec"));
+    }
+
+    #[test]
+    fn test_stored_crc() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let mut chunk = Chunk::with_stored_crc(chunk_type, b"abc".to_vec(), 1).unwrap();
+        assert!(!chunk.crc_is_valid());
+        assert_eq!(chunk.stored_crc(), 1);
+        assert_ne!(chunk.computed_crc(), 1);
+        assert_eq!(chunk.as_bytes()[11..], [0, 0, 0, 1]);
+        assert!(Chunk::try_from(&chunk.as_bytes()[..]).is_err());
+
+        chunk.fix_crc();
+        assert!(chunk.crc_is_valid());
+        assert!(Chunk::try_from(&chunk.as_bytes()[..]).is_ok());
+
+        let chunk = testing_chunk();
+        assert!(chunk.crc_is_valid());
+        assert_eq!(chunk.stored_crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_data_slice() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.data_slice(8..13).unwrap(), b"where");
+        assert_eq!(chunk.data_slice(42..42).unwrap(), b"");
+        assert!(chunk.data_slice(40..43).is_err());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = chunk.data_slice(5..4);
+        assert!(reversed.is_err());
+    }
+
+    #[test]
+    fn test_integer_fields() {
+        let mut chunk =
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(chunk.read_u8(4).unwrap(), 5);
+        assert_eq!(chunk.read_u16_be(0).unwrap(), 0x0102);
+        assert_eq!(chunk.read_u16_le(0).unwrap(), 0x0201);
+        assert_eq!(chunk.read_u32_be(1).unwrap(), 0x02030405);
+        assert_eq!(chunk.read_u32_le(1).unwrap(), 0x05040302);
+        assert!(chunk.read_u32_be(2).is_err());
+        assert!(chunk.read_u8(usize::MAX).is_err());
+
+        chunk.write_u32_be(0, 0xdeadbeef).unwrap();
+        chunk.write_u16_le(3, 0x0a0b).unwrap();
+        chunk.write_u8(2, 0).unwrap();
+        assert_eq!(chunk.data(), &[0xde, 0xad, 0, 0x0b, 0x0a]);
+        assert_eq!(
+            chunk.crc(),
+            Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec())
+                .unwrap()
+                .crc()
+        );
+        assert!(chunk.write_u16_be(4, 0).is_err());
+        assert_eq!(chunk.data()[4], 0x0a);
+    }
+
+    #[test]
+    fn test_chunk_size_at() {
+        let bytes = testing_chunk().as_bytes();
+        assert_eq!(Chunk::size_at(&bytes, 0).unwrap(), bytes.len());
+        assert!(Chunk::size_at(&bytes[..bytes.len() - 1], 0).is_err());
+        assert!(Chunk::size_at(&bytes, 1).is_err());
+        assert!(Chunk::size_at(&bytes, bytes.len() + 10).is_err());
+
+        // A declared length close to u32::MAX must not overflow the size calculation
+        let mut huge = bytes.clone();
+        huge[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Chunk::size_at(&huge, 0).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
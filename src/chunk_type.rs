@@ -1,10 +1,51 @@
 use std::fmt;
 use std::str::FromStr;
 
-use crate::Error;
 use crate::Result;
-
-#[derive(Debug)]
+use crate::{Error, PngChunkError};
+
+/// Chunk types defined by the PNG spec, its registered extensions, APNG and a few widely used
+/// private chunks, with a short description of each
+pub const KNOWN_CHUNKS: [(&str, &str); 36] = [
+    ("IHDR", "image header"),
+    ("PLTE", "palette"),
+    ("IDAT", "image data"),
+    ("IEND", "image end"),
+    ("tRNS", "transparency"),
+    ("cHRM", "primary chromaticities and white point"),
+    ("gAMA", "image gamma"),
+    ("iCCP", "embedded ICC profile"),
+    ("sBIT", "significant bits"),
+    ("sRGB", "standard RGB color space"),
+    ("cICP", "coding-independent code points"),
+    ("mDCv", "mastering display color volume"),
+    ("cLLi", "content light level"),
+    ("tEXt", "textual metadata"),
+    ("zTXt", "compressed textual metadata"),
+    ("iTXt", "international textual metadata"),
+    ("bKGD", "background color"),
+    ("hIST", "palette histogram"),
+    ("pHYs", "physical pixel dimensions"),
+    ("sPLT", "suggested palette"),
+    ("eXIf", "Exif metadata"),
+    ("tIME", "last modification time"),
+    ("acTL", "animation control"),
+    ("fcTL", "frame control"),
+    ("fdAT", "frame data"),
+    ("oFFs", "image offset"),
+    ("pCAL", "pixel calibration"),
+    ("sCAL", "physical scale"),
+    ("sTER", "stereo image indicator"),
+    ("gIFg", "GIF graphic control extension"),
+    ("gIFx", "GIF application extension"),
+    ("gIFt", "GIF plain text extension"),
+    ("fRAc", "fractal image parameters"),
+    ("dSIG", "digital signature"),
+    ("iDOT", "Apple parallel decoding offsets"),
+    ("CgBI", "Apple optimized PNG marker"),
+];
+
+#[derive(Debug, Clone)]
 pub struct ChunkType {
     data: [u8; 4],
 }
@@ -23,19 +64,36 @@ impl ChunkType {
     }
 
     pub fn is_critical(&self) -> bool {
-        ChunkType::get_bit_at(self.data[0], 5).unwrap() == false
+        !ChunkType::get_bit_at(self.data[0], 5).unwrap()
     }
 
     pub fn is_public(&self) -> bool {
-        ChunkType::get_bit_at(self.data[1], 5).unwrap() == false
+        !ChunkType::get_bit_at(self.data[1], 5).unwrap()
     }
 
     pub fn is_reserved_bit_valid(&self) -> bool {
-        ChunkType::get_bit_at(self.data[2], 5).unwrap() == false
+        !ChunkType::get_bit_at(self.data[2], 5).unwrap()
     }
 
     pub fn is_safe_to_copy(&self) -> bool {
-        ChunkType::get_bit_at(self.data[3], 5).unwrap() == true
+        ChunkType::get_bit_at(self.data[3], 5).unwrap()
+    }
+
+    /// Compares with `other`, optionally ignoring case. The case of each letter carries one of
+    /// the property bits, so chunk types that differ only in case are different chunk types.
+    pub fn matches(&self, other: &ChunkType, ignore_case: bool) -> bool {
+        match ignore_case {
+            true => self.data.eq_ignore_ascii_case(&other.data),
+            false => self == other,
+        }
+    }
+
+    /// A short description of this chunk type if it is in `KNOWN_CHUNKS`
+    pub fn description(&self) -> Option<&'static str> {
+        KNOWN_CHUNKS
+            .iter()
+            .find(|(chunk_type, _)| chunk_type.as_bytes() == self.data)
+            .map(|(_, description)| *description)
     }
 
     fn get_bit_at(byte: u8, n: u8) -> Result<bool> {
@@ -58,7 +116,9 @@ impl FromStr for ChunkType {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
         if s.len() != 4 {
-            return Err("String must be 4 bytes long.".into());
+            return Err(PngChunkError::Parse(
+                "String must be 4 bytes long.".to_string(),
+            ));
         }
 
         let bytes: Vec<u8> = s.bytes().collect();
@@ -67,7 +127,9 @@ impl FromStr for ChunkType {
         if chunk.data.iter().all(|x| x.is_ascii_alphabetic()) {
             Ok(chunk)
         } else {
-            Err("Chunk type can only contain alphabetic ascii".into())
+            Err(PngChunkError::Parse(
+                "Chunk type can only contain alphabetic ascii".to_string(),
+            ))
         }
     }
 }
@@ -176,6 +238,28 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_matches() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let upper = ChunkType::from_str("RUST").unwrap();
+        assert!(!chunk_type.matches(&upper, false));
+        assert!(chunk_type.matches(&upper, true));
+        assert!(chunk_type.matches(&chunk_type, false));
+        assert!(!chunk_type.matches(&ChunkType::from_str("ruSx").unwrap(), true));
+    }
+
+    #[test]
+    pub fn test_chunk_type_description() {
+        let description = |s: &str| ChunkType::from_str(s).unwrap().description();
+        assert_eq!(description("IDAT"), Some("image data"));
+        assert_eq!(description("tEXt"), Some("textual metadata"));
+        assert_eq!(description("TEXT"), None);
+        assert_eq!(description("ruSt"), None);
+        assert!(KNOWN_CHUNKS
+            .iter()
+            .all(|(chunk_type, _)| ChunkType::from_str(chunk_type).is_ok()));
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -0,0 +1,58 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use arboard::{Clipboard, ImageData};
+
+use crate::{
+    png::Png,
+    raster::{self, ColorType, ImageHeader},
+    Result,
+};
+
+/// The pseudo-path that names the clipboard wherever a command takes an input file
+pub const PATH: &str = "clipboard:";
+
+static OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Makes commands that write a PNG put its image on the clipboard instead
+pub fn set_output(enabled: bool) {
+    OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn output() -> bool {
+    OUTPUT.load(Ordering::Relaxed)
+}
+
+pub fn is_clipboard(path: &str) -> bool {
+    path == PATH
+}
+
+/// Reads the image on the clipboard as a PNG. Clipboards hold pixels rather than files, so the
+/// result has no metadata chunks.
+pub fn read() -> Result<Vec<u8>> {
+    let image = Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_image())
+        .map_err(|e| format!("Could not read an image from the clipboard: {}", e))?;
+    let header = ImageHeader {
+        width: image.width as u32,
+        height: image.height as u32,
+        bit_depth: 8,
+        color_type: ColorType::Rgba,
+        interlaced: false,
+    };
+    Ok(raster::compose(&header, &image.bytes)?.as_bytes())
+}
+
+/// Puts the pixels of `png` on the clipboard
+pub fn write(png: &Png) -> Result<()> {
+    let image = raster::decode(png)?;
+    let data = ImageData {
+        width: image.header.width as usize,
+        height: image.header.height as usize,
+        bytes: Cow::Owned(image.to_rgba8()),
+    };
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_image(data))
+        .map_err(|e| format!("Could not put the image on the clipboard: {}", e))?;
+    Ok(())
+}
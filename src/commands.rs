@@ -1,65 +1,2107 @@
 #![allow(dead_code)]
 
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
+use crate::apple;
+use crate::archive;
+use crate::args::{
+    BookmarksArgs, CarveArgs, ColorArgs, CompatArgs, ComposeArgs, CorpusArgs, CountArgs,
+    DecgbiArgs, DecodeArgs, DiffArgs, DpiArgs, EncodeArgs, ExecJsonArgs, ExifArgs, ExistsArgs,
+    ExportArgs, ExtractArgs, HistogramArgs, IccArgs, ImportArgs, InfoArgs, L10nArgs, LockArgs,
+    MergeDriverArgs, MigrateArgs, NoteArgs, OutputFormat, PatchArgs, PixelDiffArgs, PixelsArgs,
+    PrintArgs, ProvenanceArgs, RedactArgs, RemoveArgs, RepairArgs, SalvageArgs, SchemaArgs,
+    StegoArgs, StripArgs, TextArgs, TextconvArgs, TimeArgs, ValidateArgs, WatermarkArgs,
+    WorkspaceArgs,
+};
+use crate::batch;
+use crate::bookmarks;
+use crate::browser;
+use crate::cancel;
 use crate::chunk::Chunk;
-use crate::png::Png;
-use crate::Result;
-use std::fs;
-use std::path::Path;
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "clipboard")]
+use crate::clipboard;
+use crate::compat;
+use crate::config;
+use crate::corpus::{csv_field, CorpusStats};
+use crate::crypto;
+use crate::datauri;
+use crate::diagnostics;
+use crate::diff::{self, Change};
+use crate::exif;
+use crate::icc::{self, IccProfile};
+use crate::known_chunks::{self, Chrm, Gama, Ihdr, Phys, Srgb, TimeChunk};
+use crate::l10n;
+use crate::limits;
+use crate::lock::{self, Lockfile};
+use crate::merge;
+use crate::notes::Notes;
+use crate::patch::Patch;
+use crate::payload;
+use crate::png::{ParseMode, ParseWarning, Png};
+use crate::pretty;
+use crate::profile::{self, Profile};
+use crate::protocol;
+use crate::provenance;
+use crate::raster::{self, Histogram, PixelDiff};
+use crate::reader::ChunkReader;
+use crate::redact;
+use crate::salvage::{self, Segment};
+use crate::scan;
+use crate::schema::{self, Schema};
+use crate::spill::Spill;
+use crate::stdio;
+use crate::stego;
+use crate::temp::{self, TempFile};
+use crate::text::{self, InternationalTextChunk};
+use crate::type_filter::TypeFilter;
+use crate::validate;
+use crate::watermark;
+use crate::workspace::{self, Workspace};
+use crate::{PngChunkError, Result};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Prints a status line to stdout, or to stderr once a file has been written to stdout
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if stdio::stdout_taken() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Sets the output format for reports, from the global `--format` option
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Runs a single JSON command read from stdin and prints the JSON response
+pub fn exec_json(_args: ExecJsonArgs) -> Result<()> {
+    let mut request = vec![];
+    io::stdin().read_to_end(&mut request)?;
+    println!("{}", protocol::execute(&request));
+    Ok(())
+}
+
+/// Prints the image properties stored in the IHDR chunk of a PNG file
+pub fn info(args: InfoArgs) -> Result<()> {
+    let mut chunks = chunks_from_file(&args.file_path)?;
+    let chunk = chunks
+        .find(|chunk| {
+            chunk
+                .as_ref()
+                .map_or(true, |c| c.chunk_type().to_string() == "IHDR")
+        })
+        .ok_or_else(|| PngChunkError::ChunkNotFound("IHDR".to_string()))??;
+    // Read on to the end, so the layout is checked as it is by every other command
+    chunks.try_for_each(|chunk| chunk.map(drop))?;
+    let ihdr = Ihdr::parse(chunk.data())?;
+    match format() {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "width": ihdr.width,
+                "height": ihdr.height,
+                "bit_depth": ihdr.bit_depth,
+                "color_type": ihdr.color_type.as_byte(),
+                "compression_method": ihdr.compression_method,
+                "filter_method": ihdr.filter_method,
+                "interlace_method": ihdr.interlace_method,
+            })
+        ),
+        OutputFormat::Csv => {
+            println!("width,height,bit_depth,color_type,compression_method,filter_method,interlace_method");
+            println!(
+                "{},{},{},{},{},{},{}",
+                ihdr.width,
+                ihdr.height,
+                ihdr.bit_depth,
+                ihdr.color_type.as_byte(),
+                ihdr.compression_method,
+                ihdr.filter_method,
+                ihdr.interlace_method
+            );
+        }
+        OutputFormat::Text => println!("{}", ihdr),
+    }
+    Ok(())
+}
+
+/// Exports the chunk layout of a PNG file as bookmarks or a template for a hex editor
+pub fn bookmarks(args: BookmarksArgs) -> Result<()> {
+    let png = parse_png(&from_file(&args.file_path)?)?;
+    let exported = bookmarks::export(&png, args.editor);
+    match args.output {
+        Some(file) => to_file(file, exported.as_bytes()),
+        None => {
+            println!("{}", exported);
+            Ok(())
+        }
+    }
+}
+
+/// Interprets private chunks with user-supplied schemas
+pub fn schema(args: SchemaArgs) -> Result<()> {
+    match args {
+        SchemaArgs::Apply(args) => {
+            let schema = Schema::from_ksy(&from_file(&args.schema)?)?;
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let mut json = vec![];
+            let mut found = false;
+            for (index, chunk) in png.chunks().iter().enumerate() {
+                if chunk.chunk_type() != &args.chunk_type {
+                    continue;
+                }
+                found = true;
+                let values = schema.parse(&schema_data(chunk))?;
+                if format() == OutputFormat::Json {
+                    let fields: serde_json::Map<String, serde_json::Value> =
+                        values.into_iter().collect();
+                    json.push(serde_json::json!({ "index": index, "fields": fields }));
+                } else {
+                    println!("{} chunk at index {}:", chunk.chunk_type(), index);
+                    for (name, value) in values {
+                        println!("  {}: {}", name, value);
+                    }
+                }
+            }
+            if !found {
+                return Err(PngChunkError::ChunkNotFound(args.chunk_type.to_string()));
+            }
+            if format() == OutputFormat::Json {
+                println!("{}", serde_json::Value::from(json));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or(OutputFormat::Text)
+}
 
 /// Encodes a message into a PNG file and saves the result
 pub fn encode(args: EncodeArgs) -> Result<()> {
-    let contents = from_file(args.file_path)?;
-    let mut png = Png::try_from(&contents[..])?;
-    png.append_chunk(Chunk::new(args.chunk_type, args.message.into_bytes()));
+    check_encodable(
+        &args.chunk_type,
+        args.only_private,
+        args.force,
+        ("pass --force", "pass --only-private false"),
+    )?;
 
-    match args.output_file {
-        Some(output_file) => {
-            to_file(output_file, &png.as_bytes())?;
+    let supplied = match &args.input_file {
+        Some(input) => Some(from_file(input)?),
+        None if !args.field.is_empty() => {
+            let schema = config::get().schema(&args.chunk_type).ok_or_else(|| {
+                format!(
+                    "No schema for {} in the config, --field needs one",
+                    args.chunk_type
+                )
+            })?;
+            let values: Vec<(String, String)> = args
+                .field
+                .into_iter()
+                .map(|field| (field.name, field.value))
+                .collect();
+            Some(schema.build(&values)?)
         }
-        None => {}
+        None => None,
     };
+    let message = match (supplied, args.message) {
+        (Some(_), Some(message)) => {
+            return Err(format!(
+                "Pass either a message or --input-file/--field, not both (got message '{}', use \
+                 -o to name the output file)",
+                message
+            )
+            .into());
+        }
+        (Some(data), None) => data,
+        (None, Some(message)) => message.into_bytes(),
+        (None, None) => return Err("A message, --input-file or --field is required".into()),
+    };
+    let output_file = args.output.or(args.output_file);
 
+    let contents = from_file(&args.file_path)?;
+    let mut png = parse_png(&contents)?;
+    let data = build_payload(message, args.compress, args.encrypt, args.legacy)?;
+    let max_length = args
+        .max_chunk_size
+        .map_or(Chunk::MAX_DATA_LENGTH, |size| size.0);
+    let parts = payload::split(&data, max_length.min(Chunk::MAX_DATA_LENGTH))?;
+    let (chunk_type, length) = (args.chunk_type.to_string(), data.len());
+    let parts_note = match parts.len() {
+        1 => String::new(),
+        count => format!(" in {} parts", count),
+    };
+    let mut index = None;
+    for part in parts {
+        let chunk = Chunk::new(args.chunk_type.clone(), part)?;
+        let at = if args.after_iend {
+            png.push_chunk(chunk)
+        } else {
+            png.insert_before_iend(chunk)
+        };
+        index.get_or_insert(at);
+    }
+    let index = index.unwrap_or_default();
+    for warning in png.placement_warnings(index) {
+        diagnostics::warn(warning);
+    }
+
+    let output = output_file.unwrap_or(args.file_path);
+    if args.dry_run {
+        eprintln!(
+            "Would write {} chunk ({} bytes{}) at index {} to {}",
+            chunk_type,
+            length,
+            parts_note,
+            index,
+            output.display()
+        );
+        return Ok(());
+    }
+    write_png(&output, &png)?;
+    eprintln!(
+        "Wrote {} chunk ({} bytes{}) at index {} to {}",
+        chunk_type,
+        length,
+        parts_note,
+        index,
+        output.display()
+    );
     Ok(())
 }
 
 /// Searches for a message hidden in a PNG file and prints the message if one is found
 pub fn decode(args: DecodeArgs) -> Result<()> {
     let contents = from_file(args.file_path)?;
-    let png = Png::try_from(&contents[..])?;
-    match png.chunk_by_type(&args.chunk_type.to_string()) {
-        Some(chunk) => {
-            println!("{}", chunk.data_as_string()?);
-            Ok(())
-        }
-        None => Err("Chunk not found.".into()),
+    let png = parse_png(&contents)?;
+    let found = read_message(&png, &args.chunk_type, args.ignore_case, args.decrypt)?;
+    // --porcelain is --raw to stdout whatever --format says. Writing to stdout marks it taken,
+    // so anything else goes to stderr.
+    if args.porcelain || (args.raw && format() != OutputFormat::Json) {
+        // Raw output is streamed, so a payload that spilled to disk is never read back in full
+        return write_message(args.output, &found);
     }
+    let message = &found.bytes()?;
+    let schema = config::get().schema(&found.chunk_type);
+    let output = if format() == OutputFormat::Json {
+        let mut json = found.to_json()?;
+        if let Some(schema) = schema {
+            json["fields"] = match schema.parse(message) {
+                Ok(values) => values.into_iter().collect(),
+                Err(_) => serde_json::Value::Null,
+            };
+        }
+        format!("{}\n", json).into_bytes()
+    } else if args.base64 {
+        format!("{}\n", datauri::base64_encode(message)).into_bytes()
+    } else if args.hex {
+        let hex: String = message.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}\n", hex).into_bytes()
+    } else {
+        if crypto::is_encrypted(message) {
+            return Err("Payload is encrypted, pass --decrypt to read it".into());
+        }
+        if let Some(schema) = schema {
+            let values = schema.parse(message).map_err(|e| {
+                format!(
+                    "Payload does not match the schema for {}: {}",
+                    found.chunk_type, e
+                )
+            })?;
+            let lines: String = values
+                .iter()
+                .map(|(name, value)| format!("{}: {}\n", name, value))
+                .collect();
+            return write_output(args.output, lines.into_bytes());
+        }
+        let mut text = String::from_utf8(message.clone()).map_err(|_| {
+            "Payload is not valid UTF-8, use --raw, --base64 or --hex to retrieve it"
+        })?;
+        if !args.no_pretty {
+            if let Some((_, formatted)) = pretty::pretty(&text) {
+                text = formatted;
+            }
+        }
+        text.push('\n');
+        text.into_bytes()
+    };
+    write_output(args.output, output)
 }
 
 /// Removes a chunk from a PNG file and saves the result
 pub fn remove(args: RemoveArgs) -> Result<()> {
     let contents = from_file(&args.file_path)?;
-    let mut png = Png::try_from(&contents[..])?;
-    if png.remove_chunk(&args.chunk_type.to_string()).is_err() {
-        return Err("Chunk not found".into());
+    let filter = type_filter(
+        args.chunk_type.as_ref(),
+        args.type_pattern.as_deref(),
+        args.type_prefix.as_deref(),
+        args.ignore_case,
+    )?;
+    let png = parse_png(&contents)?;
+    let mut matching = (0..png.chunks().len())
+        .filter(|&index| filter.matches(png.chunks()[index].chunk_type()))
+        .collect::<Vec<usize>>();
+    if filter.is_exact() {
+        matching.truncate(1);
     }
+    if matching.is_empty() {
+        return Err(PngChunkError::ChunkNotFound(filter.to_string()));
+    }
+    let chunks = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !matching.contains(index))
+        .map(|(_, chunk)| chunk.clone())
+        .collect();
+    let png = Png::from_chunks(chunks);
 
-    to_file(&args.file_path, &png.as_bytes())?;
+    write_png(&args.file_path, &png)?;
     Ok(())
 }
 
 /// Prints all of the chunks in a PNG file
 pub fn print_chunks(args: PrintArgs) -> Result<()> {
+    let notes = load_notes(&args.file_path)?;
+    let format = format();
+    let mut json = vec![];
+    let mut offset = Png::STANDARD_HEADER.len();
+    if format == OutputFormat::Csv {
+        println!("offset,type,length,crc");
+    }
+    let segments: Box<dyn Iterator<Item = Result<Segment>>> = if args.resync {
+        Box::new(
+            salvage::resync(&from_file(&args.file_path)?)?
+                .into_iter()
+                .map(Ok),
+        )
+    } else {
+        Box::new(chunks_from_file(&args.file_path)?.map(|chunk| chunk.map(Segment::Chunk)))
+    };
+    let filter = TypeFilter::from_args(
+        args.chunk_type.as_ref(),
+        args.type_pattern.as_deref(),
+        args.type_prefix.as_deref(),
+        args.ignore_case,
+    )?;
+    let schemas = args
+        .schema
+        .iter()
+        .map(|binding| {
+            Ok((
+                binding.chunk_type.clone(),
+                Schema::from_ksy(&from_file(&binding.path)?)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut index = 0;
+    for segment in segments {
+        let chunk = match segment? {
+            Segment::Chunk(chunk) => chunk,
+            Segment::Gap(length) => {
+                match format {
+                    OutputFormat::Json => json.push(serde_json::json!({
+                        "type": "gap",
+                        "length": length,
+                        "offset": offset,
+                    })),
+                    OutputFormat::Csv => println!("{},gap,{},", offset, length),
+                    OutputFormat::Text => {
+                        println!("Gap {{\n  Offset: {}\n  Length: {}\n}}\n", offset, length)
+                    }
+                }
+                offset += length;
+                continue;
+            }
+        };
+        let chunk = &chunk;
+        if filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(chunk.chunk_type()))
+        {
+            offset += Chunk::MIN_CHUNK_LENGTH + chunk.data().len();
+            index += 1;
+            continue;
+        }
+        let parsed = if args.parsed {
+            // Schemas given on the command line take precedence over the config
+            let schema = schemas
+                .iter()
+                .find(|(chunk_type, _)| chunk_type == chunk.chunk_type())
+                .map(|(_, schema)| schema)
+                .or_else(|| config::get().schema(chunk.chunk_type()));
+            match schema {
+                Some(schema) => Some(match schema.parse(&schema_data(chunk)) {
+                    Ok(values) => schema::describe(&values),
+                    Err(e) => format!("does not match schema: {}", e),
+                }),
+                None => apple::describe(chunk)
+                    .or_else(|| browser::describe(chunk))
+                    .or_else(|| exif::describe(chunk))
+                    .or_else(|| known_chunks::describe(chunk))
+                    .or_else(|| structured_text(chunk, !args.no_pretty)),
+            }
+        } else {
+            None
+        };
+        match format {
+            OutputFormat::Json => {
+                let mut entry = chunk_json(chunk, offset);
+                if let Some(parsed) = parsed {
+                    entry["parsed"] = parsed.into();
+                }
+                let notes: Vec<&str> = notes.for_chunk(index).map(|n| n.text.as_str()).collect();
+                if !notes.is_empty() {
+                    entry["notes"] = notes.into();
+                }
+                json.push(entry);
+            }
+            OutputFormat::Csv => println!(
+                "{},{},{},{}",
+                offset,
+                chunk.chunk_type(),
+                chunk.length(),
+                chunk.crc()
+            ),
+            OutputFormat::Text => {
+                print!("{:#}", chunk);
+                if let Some(parsed) = parsed {
+                    println!("  Parsed: {}", parsed.replace('\n', "\n    "));
+                }
+                for note in notes.for_chunk(index) {
+                    println!("  Note: {}", note.text);
+                }
+                println!();
+            }
+        }
+        offset += Chunk::MIN_CHUNK_LENGTH + chunk.data().len();
+        index += 1;
+    }
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::Value::from(json));
+    }
+    Ok(())
+}
+
+/// Prints a stable, line-oriented description of a PNG file for use as a Git `textconv` driver
+pub fn textconv(args: TextconvArgs) -> Result<()> {
+    for (index, chunk) in chunks_from_file(&args.file_path)?.enumerate() {
+        let chunk = &chunk?;
+        println!(
+            "{:>4} {} length={} crc={:08x} sha256={}",
+            index,
+            chunk.chunk_type(),
+            chunk.length(),
+            chunk.crc(),
+            chunk.data_digest()
+        );
+        if let Some(metadata) = chunk_metadata(chunk) {
+            println!("       {}", metadata);
+        }
+    }
+    Ok(())
+}
+
+/// Merges two PNGs with a common ancestor whose pixel data is identical and saves the result
+pub fn merge_driver(args: MergeDriverArgs) -> Result<()> {
+    let base = parse_png(&from_file(&args.base)?)?;
+    let ours = parse_png(&from_file(&args.ours)?)?;
+    let theirs = parse_png(&from_file(&args.theirs)?)?;
+
+    let merged = merge::merge(&base, &ours, &theirs, args.policy)?;
+    write_png(&args.output, &merged)
+}
+
+/// Reports known interoperability hazards in a PNG file and the consumers affected by them
+pub fn compat(args: CompatArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let png = parse_png(&contents)?;
+    let issues = compat::check(&png);
+    if issues.is_empty() {
+        println!("No known compatibility issues found.");
+    }
+    issues.iter().for_each(|issue| println!("{}", issue));
+    Ok(())
+}
+
+/// Finds PNGs embedded in an arbitrary file and writes every valid one to the output directory,
+/// along with an index report describing each candidate
+pub fn carve(args: CarveArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    fs::create_dir_all(&args.output)?;
+
+    let mut report = String::new();
+    let mut carved = 0;
+    let spans = scan::find_pngs(&contents);
+    for (index, span) in spans.iter().enumerate() {
+        if cancel::is_cancelled() {
+            eprint!("{}", report);
+            eprintln!(
+                "Cancelled after {} of {} candidate(s); {} was not written",
+                index,
+                spans.len(),
+                args.output.join("index.txt").display()
+            );
+            return Err("Cancelled".into());
+        }
+        let name = format!("{:04}.png", index);
+        let status = match Png::try_from(&contents[span.clone()]) {
+            Ok(_) => {
+                to_file(args.output.join(&name), &contents[span.clone()])?;
+                carved += 1;
+                "valid".to_string()
+            }
+            Err(e) => format!("invalid ({})", e),
+        };
+        report.push_str(&format!(
+            "{} offset={} length={} {}\n",
+            name,
+            span.start,
+            span.len(),
+            status
+        ));
+    }
+
+    to_file(args.output.join("index.txt"), report.as_bytes())?;
+    println!(
+        "Carved {} PNG(s), see {} for details",
+        carved,
+        args.output.join("index.txt").display()
+    );
+    Ok(())
+}
+
+/// Recovers the complete chunks of a truncated or damaged PNG file and saves the result
+pub fn salvage(args: SalvageArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let salvaged = if args.resync {
+        salvage::salvage_resync(&contents)?
+    } else {
+        salvage::salvage(&contents, args.partial_idat, true)?
+    };
+    write_png(&args.output, &salvaged.png)?;
+
+    status!("Kept {} chunk(s)", salvaged.png.chunks().len());
+    if salvaged.partial_idat {
+        status!("Kept a truncated IDAT chunk");
+    }
+    if salvaged.synthesized_iend {
+        status!("Added a missing IEND chunk");
+    }
+    if salvaged.dropped_bytes > 0 {
+        status!(
+            "Dropped {} unrecoverable byte(s) in {} gap(s)",
+            salvaged.dropped_bytes,
+            salvaged.gaps
+        );
+    }
+    Ok(())
+}
+
+/// Fixes wrong CRCs and drops unreadable trailing data, keeping every chunk that can be read.
+/// This is `salvage` for files that are mostly intact, reporting each fix as it goes.
+pub fn repair(args: RepairArgs) -> Result<()> {
+    let files = batch_files(&args.files, args.recursive, args.output.is_some())?;
+    let options = batch::Options {
+        jobs: args.jobs,
+        state: args.state.clone(),
+        throttle: args.throttle.map(|rate| rate.0),
+        nice: args.nice,
+    };
+    batch::each(&files, &options, |file, output| {
+        let salvaged = salvage::salvage(&from_file(file)?, false, args.add_iend)?;
+        for warning in salvaged.warnings.iter() {
+            match warning {
+                ParseWarning::BadCrc {
+                    offset, chunk_type, ..
+                } => output.println(format!(
+                    "Recomputed the CRC of the {} chunk at offset {}",
+                    chunk_type, offset
+                )),
+                // Reported below, along with how to fix it
+                ParseWarning::MissingIend => {}
+                _ if !warning.is_damage() => output.warn(warning),
+                _ => output.println(format!("Dropped unreadable data: {}", warning)),
+            }
+        }
+        if salvaged.synthesized_iend {
+            output.println("Added a missing IEND chunk");
+        } else if salvaged
+            .png
+            .chunks()
+            .last()
+            .is_none_or(|chunk| chunk.chunk_type().to_string() != "IEND")
+        {
+            output.warn("file does not end with IEND, pass --add-iend to add one");
+        }
+        if !salvaged.warnings.iter().any(ParseWarning::is_damage) && !salvaged.synthesized_iend {
+            output.println("Nothing to repair");
+        }
+        write_png(args.output.as_deref().unwrap_or(file), &salvaged.png)
+    })
+}
+
+/// Creates or applies a patch of metadata changes
+pub fn patch(args: PatchArgs) -> Result<()> {
+    match args {
+        PatchArgs::Create(args) => {
+            let old = parse_png(&from_file(&args.old)?)?;
+            let new = parse_png(&from_file(&args.new)?)?;
+            let patch = Patch::create(&old, &new);
+            io::stdout().write_all(&patch.as_bytes())?;
+            eprintln!("Wrote patch with {} change(s)", patch.ops().len());
+        }
+        PatchArgs::Apply(args) => {
+            let patch = Patch::try_from(&from_file(&args.patch)?[..])?;
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let patched = patch.apply(&png)?;
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &patched)?;
+            status!("Applied {} change(s)", patch.ops().len());
+        }
+    }
+    Ok(())
+}
+
+/// Writes every chunk of a PNG file into a zip archive along with a manifest
+pub fn export(args: ExportArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let png = parse_png(&contents)?;
+    to_file(&args.archive, &archive::export(&png)?)
+}
+
+/// Rebuilds a PNG file from a zip archive written by `export`
+pub fn import(args: ImportArgs) -> Result<()> {
+    let png = archive::import(&from_file(&args.archive)?)?;
+    write_png(&args.file_path, &png)
+}
+
+/// Builds a PNG file from an IHDR template and a raw pixel dump
+pub fn compose(args: ComposeArgs) -> Result<()> {
+    let pixels = from_file(&args.pixels)?;
+    let png = raster::compose(&args.ihdr, &pixels)?;
+    write_png(&args.output, &png)
+}
+
+/// Decodes the pixel data of a PNG file into a raw pixel dump plus a JSON descriptor
+pub fn pixels(args: PixelsArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let png = parse_png(&contents)?;
+    let image = raster::decode(&png)?;
+    to_file(&args.output, &image.convert(args.format))?;
+
+    let descriptor = serde_json::json!({
+        "width": image.header.width,
+        "height": image.header.height,
+        "format": args.format.to_string(),
+        "channels": args.format.channels(),
+        "source": {
+            "bit_depth": image.header.bit_depth,
+            "color_type": image.header.color_type.as_byte(),
+            "interlaced": image.header.interlaced,
+        },
+    });
+    let mut descriptor_path = args.output.into_os_string();
+    descriptor_path.push(".json");
+    to_file(
+        descriptor_path,
+        serde_json::to_string_pretty(&descriptor)?.as_bytes(),
+    )
+}
+
+/// Prints per-channel histograms of the decoded pixel data of a PNG file
+pub fn histogram(args: HistogramArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let png = parse_png(&contents)?;
+    let histogram = Histogram::from_image(&raster::decode(&png)?);
+
+    match format() {
+        OutputFormat::Json => {
+            let channels: serde_json::Map<String, serde_json::Value> = histogram
+                .channels
+                .iter()
+                .map(|(name, counts)| (name.to_string(), serde_json::json!(counts)))
+                .collect();
+            let json = serde_json::json!({
+                "bit_depth": histogram.bit_depth,
+                "channels": channels,
+            });
+            println!("{}", json);
+        }
+        OutputFormat::Csv => {
+            let names: Vec<&str> = histogram.channels.iter().map(|(name, _)| *name).collect();
+            println!("value,{}", names.join(","));
+            let levels = histogram.channels.first().map_or(0, |(_, c)| c.len());
+            for value in 0..levels {
+                let counts: Vec<String> = histogram
+                    .channels
+                    .iter()
+                    .map(|(_, counts)| counts[value].to_string())
+                    .collect();
+                println!("{},{}", value, counts.join(","));
+            }
+        }
+        OutputFormat::Text => {
+            for (name, counts) in histogram.channels.iter() {
+                let total: u64 = counts.iter().sum();
+                let sum: u64 = counts
+                    .iter()
+                    .enumerate()
+                    .map(|(value, &count)| value as u64 * count)
+                    .sum();
+                let min = counts.iter().position(|&c| c > 0).unwrap_or(0);
+                let max = counts.iter().rposition(|&c| c > 0).unwrap_or(0);
+                println!(
+                    "{}: min={} max={} mean={:.2} distinct={}",
+                    name,
+                    min,
+                    max,
+                    sum as f64 / total.max(1) as f64,
+                    counts.iter().filter(|&&c| c > 0).count()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compares the decoded pixel data of two PNG files, ignoring how it is stored
+pub fn pixel_diff(args: PixelDiffArgs) -> Result<()> {
+    let first = parse_png(&from_file(&args.first)?)?;
+    let second = parse_png(&from_file(&args.second)?)?;
+    let diff = PixelDiff::between(&raster::decode(&first)?, &raster::decode(&second)?)?;
+
+    match diff.bounds {
+        None => {
+            println!("Pixel data is identical");
+            Ok(())
+        }
+        Some((left, top, right, bottom)) => {
+            println!(
+                "{} pixel(s) differ within ({}, {}) - ({}, {})",
+                diff.differing, left, top, right, bottom
+            );
+            Err("Pixel data differs".into())
+        }
+    }
+}
+
+/// Blanks or pixelates a region of a PNG file and re-encodes its image data
+pub fn redact(args: RedactArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let png = parse_png(&contents)?;
+    let mut image = raster::decode(&png)?;
+    redact::redact(&mut image, args.rect, args.fill)?;
+
+    let output = args.output.unwrap_or(args.file_path);
+    write_png(output, &raster::reencode(&png, &image)?)
+}
+
+/// Syncs localized iTXt strings between a PNG file and a JSON bundle
+pub fn l10n(args: L10nArgs) -> Result<()> {
+    match args {
+        L10nArgs::Export(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let strings = l10n::collect(&png)?;
+            let bundle = serde_json::to_string_pretty(&l10n::export(&strings))?;
+            to_file(&args.strings, bundle.as_bytes())?;
+            status!("Exported {} keyword(s)", strings.len());
+        }
+        L10nArgs::Import(args) => {
+            let bundle: serde_json::Value = serde_json::from_slice(&from_file(&args.strings)?)?;
+            let strings = l10n::parse(&bundle)?;
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            write_png(
+                args.output.as_ref().unwrap_or(&args.file_path),
+                &l10n::apply(&png, &strings)?,
+            )?;
+            status!("Imported {} keyword(s)", strings.len());
+        }
+    }
+    Ok(())
+}
+
+/// Checks a PNG file against the validation rules, failing if anything is found
+pub fn validate(args: ValidateArgs) -> Result<()> {
+    let policy = match &args.policy {
+        Some(path) => validate::Policy::parse(&from_file(path)?)?,
+        None => validate::Policy::default(),
+    };
+    let files = batch_files(&args.files, args.recursive, false)?;
+    if format() == OutputFormat::Csv {
+        println!("file,offset,severity,message");
+    }
+    let options = batch::Options {
+        jobs: args.jobs,
+        state: args.state.clone(),
+        throttle: args.throttle.map(|rate| rate.0),
+        nice: args.nice,
+    };
+    batch::each(&files, &options, |file, output| {
+        let contents = from_file(file)?;
+        let findings = validate::report(&contents);
+        let advisories = match (args.advisory, Png::try_from(&contents[..])) {
+            (true, Ok(png)) => validate::advise(&png, &policy),
+            _ => vec![],
+        };
+        advisories.iter().for_each(|_| diagnostics::record());
+
+        match format() {
+            OutputFormat::Json => {
+                let entries = |findings: &[validate::Finding]| -> Vec<serde_json::Value> {
+                    findings
+                        .iter()
+                        .map(|f| serde_json::json!({ "offset": f.offset, "message": f.message }))
+                        .collect()
+                };
+                output.println(serde_json::json!({
+                    "file": file.display().to_string(),
+                    "valid": findings.is_empty(),
+                    "findings": entries(&findings),
+                    "advisories": entries(&advisories),
+                }));
+            }
+            OutputFormat::Csv => {
+                for (severity, findings) in [("error", &findings), ("advisory", &advisories)] {
+                    for finding in findings.iter() {
+                        output.println(format!(
+                            "{},{},{},{}",
+                            csv_field(&file.display().to_string()),
+                            finding.offset,
+                            severity,
+                            csv_field(&finding.message)
+                        ));
+                    }
+                }
+            }
+            OutputFormat::Text => {
+                for advisory in advisories.iter() {
+                    output.println(format!("{} (advisory)", advisory));
+                }
+                if findings.is_empty() {
+                    output.println("No problems found.");
+                }
+                findings.iter().for_each(|finding| output.println(finding));
+            }
+        }
+        if findings.is_empty() {
+            return Ok(());
+        }
+        Err(format!("{} problem(s) found", findings.len()).into())
+    })
+}
+
+/// Reads and writes textual metadata chunks
+pub fn text(args: TextArgs) -> Result<()> {
+    match args {
+        TextArgs::List(args) => {
+            let mut json = vec![];
+            if format() == OutputFormat::Csv {
+                println!("type,keyword,language,text");
+            }
+            for chunk in chunks_from_file(&args.file_path)? {
+                let chunk = chunk?;
+                let Some((keyword, value)) = text::keyword_text(&chunk)? else {
+                    continue;
+                };
+                let language = InternationalTextChunk::try_from(&chunk)
+                    .map(|itxt| itxt.language)
+                    .unwrap_or_default();
+                match format() {
+                    OutputFormat::Json => json.push(serde_json::json!({
+                        "type": chunk.chunk_type().to_string(),
+                        "keyword": keyword,
+                        "language": language,
+                        "text": value,
+                    })),
+                    OutputFormat::Csv => println!(
+                        "{},{},{},{}",
+                        chunk.chunk_type(),
+                        csv_field(&keyword),
+                        csv_field(&language),
+                        csv_field(&value)
+                    ),
+                    OutputFormat::Text if language.is_empty() => {
+                        println!("{} {}: {}", chunk.chunk_type(), keyword, value)
+                    }
+                    OutputFormat::Text => {
+                        println!(
+                            "{} {} ({}): {}",
+                            chunk.chunk_type(),
+                            keyword,
+                            language,
+                            value
+                        )
+                    }
+                }
+            }
+            if format() == OutputFormat::Json {
+                println!("{}", serde_json::Value::from(json));
+            }
+        }
+        TextArgs::Set(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let itxt = InternationalTextChunk {
+                keyword: args.keyword,
+                language: args.language,
+                translated_keyword: String::new(),
+                text: args.value,
+            };
+            if let Some(warning) = itxt.language_warning() {
+                diagnostics::warn(warning);
+            }
+            let png = text::set_text_as(&png, itxt, args.text_type, args.on_conflict)?;
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+        }
+        TextArgs::Remove(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let (png, removed) = text::remove_text(&png, &args.keyword);
+            if removed == 0 {
+                return Err(format!("No text chunk with keyword '{}'", args.keyword).into());
+            }
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+            eprintln!(
+                "Removed {} chunk(s) with keyword '{}'",
+                removed, args.keyword
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Upgrades plain string payloads written by older versions to the current envelope format
+pub fn migrate(args: MigrateArgs) -> Result<()> {
+    for file in args.files.iter() {
+        let png = parse_png(&from_file(file)?)?;
+        let mut migrated = 0;
+        let mut chunks = vec![];
+        for chunk in png.chunks() {
+            let selected = match &args.chunk_type {
+                Some(chunk_type) => chunk.chunk_type() == chunk_type,
+                None => !chunk.chunk_type().is_public(),
+            };
+            if selected && payload::is_legacy(chunk.data()) {
+                chunks.push(Chunk::new(
+                    chunk.chunk_type().clone(),
+                    payload::seal(chunk.data()),
+                )?);
+                migrated += 1;
+            } else {
+                chunks.push(chunk.clone());
+            }
+        }
+
+        if migrated > 0 {
+            write_png(file, &Png::from_chunks(chunks))?;
+        }
+        status!("{}: migrated {} chunk(s)", file.display(), migrated);
+    }
+    Ok(())
+}
+
+/// Manages analyst notes about the chunks of a file, stored in a sidecar next to it
+pub fn note(args: NoteArgs) -> Result<()> {
+    match args {
+        NoteArgs::Add(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let mut notes = load_notes(&args.file_path)?;
+            notes.add(&png, args.chunk, args.text)?;
+            to_file(Notes::sidecar(&args.file_path), &notes.as_bytes()?)?;
+        }
+        NoteArgs::List(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            for (index, note) in load_notes(&args.file_path)?.entries.iter().enumerate() {
+                let stale = if Notes::is_stale(note, &png) {
+                    " (stale)"
+                } else {
+                    ""
+                };
+                println!(
+                    "{:>3} chunk {} {}{}: {}",
+                    index, note.chunk, note.chunk_type, stale, note.text
+                );
+            }
+        }
+        NoteArgs::Remove(args) => {
+            let mut notes = load_notes(&args.file_path)?;
+            let removed = notes.remove(args.note)?;
+            to_file(Notes::sidecar(&args.file_path), &notes.as_bytes()?)?;
+            println!("Removed note on chunk {}: {}", removed.chunk, removed.text);
+        }
+    }
+    Ok(())
+}
+
+/// Tracks a set of files under investigation along with their analysis results
+pub fn workspace(args: WorkspaceArgs) -> Result<()> {
+    match args {
+        WorkspaceArgs::Init(args) => {
+            let database = Workspace::database(&args.dir);
+            if database.exists() {
+                return Err(format!("{} already exists", database.display()).into());
+            }
+            fs::create_dir_all(&args.dir)?;
+            to_file(database, &Workspace::default().as_bytes()?)?;
+        }
+        WorkspaceArgs::Add(args) => {
+            let database = Workspace::database(&args.dir);
+            let mut workspace = Workspace::parse(&from_file(&database)?)?;
+            for file in args.files {
+                let contents = from_file(&file)?;
+                let path = fs::canonicalize(&file)?;
+                if !workspace.add(path, &contents) {
+                    eprintln!("{} is already tracked", file.display());
+                }
+            }
+            to_file(database, &workspace.as_bytes()?)?;
+        }
+        WorkspaceArgs::Analyze(args) => {
+            let database = Workspace::database(&args.dir);
+            let mut workspace = Workspace::parse(&from_file(&database)?)?;
+            for entry in workspace.entries.iter_mut() {
+                let contents = from_file(&entry.path)?;
+                let sha256 = workspace::sha256_hex(&contents);
+                if sha256 != entry.sha256 {
+                    println!(
+                        "{} has changed since it was last analyzed",
+                        entry.path.display()
+                    );
+                    entry.sha256 = sha256;
+                }
+                entry.analysis = Some(match Png::try_from(&contents[..]) {
+                    Ok(png) => workspace::analyze(&png),
+                    Err(e) => serde_json::json!({ "error": e.to_string() }),
+                });
+            }
+            to_file(database, &workspace.as_bytes()?)?;
+            println!("Analyzed {} file(s)", workspace.entries.len());
+        }
+        WorkspaceArgs::Report(args) => {
+            let workspace = Workspace::parse(&from_file(Workspace::database(&args.dir))?)?;
+            for entry in workspace.entries.iter() {
+                println!("{}", entry.path.display());
+                println!("  sha256: {}", entry.sha256);
+                let Some(analysis) = &entry.analysis else {
+                    println!("  not analyzed yet");
+                    continue;
+                };
+                if let Some(error) = analysis["error"].as_str() {
+                    println!("  error: {}", error);
+                }
+                if let Some(chunks) = analysis["chunks"].as_array() {
+                    println!("  chunks: {}", chunks.len());
+                }
+                for key in ["compat", "findings"] {
+                    for item in analysis[key].as_array().into_iter().flatten() {
+                        println!("  {}: {}", key, item.as_str().unwrap_or_default());
+                    }
+                }
+                for note in load_notes(&entry.path)?.entries.iter() {
+                    println!("  note on chunk {}: {}", note.chunk, note.text);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Embeds or detects a keyed watermark in the pixel data of a PNG file
+pub fn watermark(args: WatermarkArgs) -> Result<()> {
+    match args {
+        WatermarkArgs::Embed(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let mut image = raster::decode(&png)?;
+            watermark::embed(&mut image, args.id, &args.key, args.strength)?;
+            write_png(
+                args.output.as_ref().unwrap_or(&args.file_path),
+                &raster::reencode(&png, &image)?,
+            )?;
+        }
+        WatermarkArgs::Detect(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let detection = watermark::detect(&raster::decode(&png)?, &args.key)?;
+            if !detection.is_present() {
+                println!("No watermark found (score {:.1})", detection.score);
+                return Err("No watermark found".into());
+            }
+            println!(
+                "Watermark found: id={} (score {:.1})",
+                detection.id, detection.score
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reports the software that likely produced a file, with the evidence for each guess
+pub fn provenance(args: ProvenanceArgs) -> Result<()> {
+    let contents = from_file(&args.file_path)?;
+    let png = parse_png(&contents)?;
+    let clues = provenance::fingerprint(&png);
+    if clues.is_empty() {
+        println!("No producer fingerprints found.");
+        return Ok(());
+    }
+    clues.iter().for_each(|clue| println!("{}", clue));
+    println!(
+        "Likely tool chain: {}",
+        provenance::tool_chain(&clues).join(" -> ")
+    );
+    Ok(())
+}
+
+/// Reports which ways of embedding a payload survive common transformations of a file
+pub fn stego(args: StegoArgs) -> Result<()> {
+    let StegoArgs::Test(args) = args;
+    let png = parse_png(&from_file(&args.file_path)?)?;
+    let results = stego::survival(&png, &args.transforms)?;
+    match format() {
+        OutputFormat::Json => {
+            let methods: serde_json::Map<String, serde_json::Value> = results
+                .iter()
+                .map(|(method, outcomes)| {
+                    let outcomes: serde_json::Map<String, serde_json::Value> = args
+                        .transforms
+                        .iter()
+                        .zip(outcomes)
+                        .map(|(transform, outcome)| {
+                            (transform.to_string(), outcome.to_string().into())
+                        })
+                        .collect();
+                    (method.to_string(), outcomes.into())
+                })
+                .collect();
+            println!("{}", serde_json::Value::from(methods));
+        }
+        OutputFormat::Csv => {
+            println!("method,transform,outcome");
+            for (method, outcomes) in results.iter() {
+                for (transform, outcome) in args.transforms.iter().zip(outcomes) {
+                    println!("{},{},{}", method, transform, outcome);
+                }
+            }
+        }
+        OutputFormat::Text => {
+            for (method, outcomes) in results.iter() {
+                let outcomes: Vec<String> = args
+                    .transforms
+                    .iter()
+                    .zip(outcomes)
+                    .map(|(transform, outcome)| format!("{} {}", transform, outcome))
+                    .collect();
+                println!("{}: {}", method, outcomes.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Converts an Apple-optimized (CgBI) PNG back to a standard PNG. Chunks are read with their
+/// CRCs recomputed, since some optimizers leave them wrong.
+pub fn decgbi(args: DecgbiArgs) -> Result<()> {
     let contents = from_file(&args.file_path)?;
-    let png = Png::try_from(&contents[..])?;
-    png.chunks().iter().for_each(|chunk| println!("{}", chunk));
+    let png = salvage::salvage(&contents, false, true)?.png;
+    let png = apple::decgbi(&png)?;
+    write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+    Ok(())
+}
+
+/// Writes the data field of a chunk verbatim to a file or stdout
+pub fn extract(args: ExtractArgs) -> Result<()> {
+    let filter = type_filter(
+        args.chunk_type.as_ref(),
+        args.type_pattern.as_deref(),
+        args.type_prefix.as_deref(),
+        args.ignore_case,
+    )?;
+    let chunk_type = filter.to_string();
+    let data = match args.range {
+        // Stream the file so only the requested bytes are read into memory
+        Some(range) => {
+            let end = range.end.unwrap_or(usize::MAX);
+            let matching = chunks_from_file(&args.file_path)?
+                .data_ranges_matching(|t| filter.matches(t), range.start..end)?;
+            let (length, bytes) = pick_chunk(matching, args.index, &chunk_type)?;
+            range.resolve(length)?;
+            bytes
+        }
+        None => {
+            let contents = from_file(&args.file_path)?;
+            let png = parse_png(&contents)?;
+            let matching: Vec<&Chunk> = png
+                .chunks()
+                .iter()
+                .filter(|c| filter.matches(c.chunk_type()))
+                .collect();
+            pick_chunk(matching, args.index, &chunk_type)?
+                .data()
+                .to_vec()
+        }
+    };
+
+    match args.output {
+        Some(output) => to_file(output, &data)?,
+        None => io::stdout().write_all(&data)?,
+    }
+    Ok(())
+}
+
+/// Records or checks the expected chunk inventory of a directory of assets
+pub fn lock(args: LockArgs) -> Result<()> {
+    match args {
+        LockArgs::Write(args) => {
+            io::stdout().write_all(&Lockfile::scan(&args.dir)?.as_bytes()?)?;
+        }
+        LockArgs::Verify(args) => {
+            let expected = Lockfile::parse(&from_file(&args.lockfile)?)?;
+            let differences = expected.verify(&Lockfile::scan(&args.dir)?);
+            if differences.is_empty() {
+                println!("All {} asset(s) match the lockfile.", expected.assets.len());
+                return Ok(());
+            }
+            differences.iter().for_each(|d| println!("{}", d));
+            return Err(format!("{} difference(s) from the lockfile", differences.len()).into());
+        }
+    }
+    Ok(())
+}
+
+/// Studies the metadata of a collection of PNG files
+pub fn corpus(args: CorpusArgs) -> Result<()> {
+    match args {
+        CorpusArgs::Stats(args) => {
+            let mut stats = CorpusStats::default();
+            for file in lock::png_files(&args.dir)? {
+                cancel::check()?;
+                match from_file(&file).and_then(|contents| Png::try_from(&contents[..])) {
+                    Ok(png) => stats.add(&png),
+                    Err(_) => stats.add_unreadable(),
+                }
+            }
+
+            match format() {
+                OutputFormat::Json => println!("{}", stats.to_json()),
+                OutputFormat::Csv => print!("{}", stats.to_csv()),
+                OutputFormat::Text => {
+                    println!("{} file(s), {} unreadable", stats.files, stats.unreadable);
+                    let mut types: Vec<_> = stats.types.iter().collect();
+                    types.sort_by(|a, b| b.1.files.cmp(&a.1.files).then(a.0.cmp(b.0)));
+                    for (chunk_type, type_stats) in types {
+                        println!(
+                            "{}: in {} file(s), {} chunk(s), {:.1} bytes on average",
+                            chunk_type,
+                            type_stats.files,
+                            type_stats.occurrences,
+                            type_stats.average_size()
+                        );
+                    }
+                    for (tool, files) in stats.top_producers() {
+                        println!("Producer {}: {} file(s)", tool, files);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes metadata chunks from a PNG file according to a profile
+pub fn strip(args: StripArgs) -> Result<()> {
+    let mut profile = match &args.profile {
+        None if args.private_only => Profile::built_in("archive").unwrap_or_default(),
+        None => Profile::default(),
+        Some(name) => match Profile::built_in(name) {
+            Some(profile) => profile,
+            None if Path::new(name).exists() => Profile::parse(&from_file(name)?)?,
+            None => {
+                return Err(format!(
+                    "Unknown profile '{}', expected one of {} or a profile file",
+                    name,
+                    profile::BUILT_IN.join(", ")
+                )
+                .into())
+            }
+        },
+    };
+    profile.keep.extend(args.keep);
+
+    let files = batch_files(&args.files, args.recursive, args.output.is_some())?;
+    let options = batch::Options {
+        jobs: args.jobs,
+        state: args.state.clone(),
+        throttle: args.throttle.map(|rate| rate.0),
+        nice: args.nice,
+    };
+    batch::each(&files, &options, |file, output| {
+        let bytes = from_file(file)?;
+        let (png, warnings) = Png::parse(&bytes, ParseMode::Strict)?;
+        warnings.iter().for_each(|warning| output.warn(warning));
+        let (stripped, removed) = profile.strip(&png);
+        let size = stripped.as_bytes().len();
+        if args.only_if_smaller && size >= bytes.len() {
+            output.eprintln(format!(
+                "Left {} untouched: stripped size {} is not smaller than {} bytes",
+                file.display(),
+                size,
+                bytes.len()
+            ));
+            return Ok(());
+        }
+        for chunk in removed.iter() {
+            output.eprintln(format!(
+                "Removed {} ({} bytes)",
+                chunk.chunk_type(),
+                chunk.data().len()
+            ));
+        }
+        write_png(args.output.as_deref().unwrap_or(file), &stripped)
+    })
+}
+
+/// Lists the chunks added, removed and modified between two PNG files, failing if there are any
+pub fn diff(args: DiffArgs) -> Result<()> {
+    let old = parse_png(&from_file(&args.old)?)?;
+    let new = parse_png(&from_file(&args.new)?)?;
+    let changes = diff::diff(&old, &new);
+
+    match format() {
+        OutputFormat::Json => {
+            let entries: Vec<serde_json::Value> = changes
+                .iter()
+                .map(|change| match change {
+                    Change::Added(chunk) | Change::Removed(chunk) => {
+                        let mut entry = chunk.to_json();
+                        entry["change"] = change.name().into();
+                        entry
+                    }
+                    Change::Modified { old, new } => serde_json::json!({
+                        "change": change.name(),
+                        "type": new.chunk_type,
+                        "old": old.to_json(),
+                        "new": new.to_json(),
+                    }),
+                })
+                .collect();
+            println!("{}", serde_json::json!({ "changes": entries }));
+        }
+        OutputFormat::Csv => {
+            println!("change,type,old_index,new_index,old_length,new_length,old_crc,new_crc");
+            for change in changes.iter() {
+                let (old, new) = match change {
+                    Change::Added(chunk) => (None, Some(chunk)),
+                    Change::Removed(chunk) => (Some(chunk), None),
+                    Change::Modified { old, new } => (Some(old), Some(new)),
+                };
+                let fields = |chunk: Option<&diff::ChunkSummary>| match chunk {
+                    Some(c) => [
+                        c.index.to_string(),
+                        c.length.to_string(),
+                        format!("{:08x}", c.crc),
+                    ],
+                    None => Default::default(),
+                };
+                let (old, new) = (fields(old), fields(new));
+                println!(
+                    "{},{},{},{},{},{},{},{}",
+                    change.name(),
+                    csv_field(change.chunk_type()),
+                    old[0],
+                    new[0],
+                    old[1],
+                    new[1],
+                    old[2],
+                    new[2]
+                );
+            }
+        }
+        OutputFormat::Text => {
+            let line = |sign: char, chunk: &diff::ChunkSummary| {
+                format!(
+                    "{} {} #{}: {} bytes, crc {:08x}, sha256 {}",
+                    sign,
+                    chunk.chunk_type,
+                    chunk.index,
+                    chunk.length,
+                    chunk.crc,
+                    &chunk.digest[..16]
+                )
+            };
+            for change in changes.iter() {
+                match change {
+                    Change::Added(chunk) => println!("{}", line('+', chunk)),
+                    Change::Removed(chunk) => println!("{}", line('-', chunk)),
+                    Change::Modified { old, new } => {
+                        println!("{}", line('-', old));
+                        println!("{}", line('+', new));
+                    }
+                }
+            }
+            if changes.is_empty() {
+                println!("Chunk structure is identical");
+            }
+        }
+    }
+    match changes.len() {
+        0 => Ok(()),
+        count => Err(format!("{} chunk(s) differ", count).into()),
+    }
+}
+
+/// Moves ICC profiles in and out of the iCCP chunk
+pub fn icc(args: IccArgs) -> Result<()> {
+    match args {
+        IccArgs::Extract(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let profile = icc::extract(&png)?
+                .ok_or_else(|| format!("{} has no ICC profile", args.file_path.display()))?;
+            write_output(args.output, profile.profile)?;
+            status!("Extracted ICC profile '{}'", profile.name);
+        }
+        IccArgs::Embed(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let profile = IccProfile {
+                name: args.name,
+                profile: from_file(&args.profile)?,
+            };
+            if png.chunk_by_type("sRGB").is_some() {
+                diagnostics::warn("removing the sRGB chunk, which the profile replaces");
+            }
+            let png = icc::embed(&png, &profile)?;
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+            status!("Embedded ICC profile '{}'", profile.name);
+        }
+    }
+    Ok(())
+}
+
+/// Reads and writes the EXIF metadata in the eXIf chunk
+pub fn exif(args: ExifArgs) -> Result<()> {
+    match args {
+        ExifArgs::Dump(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let chunk = png
+                .chunk_by_type("eXIf")
+                .ok_or_else(|| PngChunkError::ChunkNotFound("eXIf".to_string()))?;
+            write_output(args.output, chunk.data().to_vec())?;
+        }
+        ExifArgs::Inject(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let png = exif::embed(&png, &from_file(&args.exif)?)?;
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+            status!("Embedded EXIF data from {}", args.exif.display());
+        }
+        ExifArgs::Strip(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let (png, removed) = exif::strip(&png);
+            if removed == 0 {
+                return Err(PngChunkError::ChunkNotFound("eXIf".to_string()));
+            }
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+            status!("Removed {} eXIf chunk(s)", removed);
+        }
+    }
+    Ok(())
+}
+
+/// Reads and writes the resolution in the pHYs chunk
+pub fn dpi(args: DpiArgs) -> Result<()> {
+    match args {
+        DpiArgs::Get(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let chunk = png
+                .chunk_by_type("pHYs")
+                .ok_or_else(|| PngChunkError::ChunkNotFound("pHYs".to_string()))?;
+            let phys = Phys::parse(chunk.data())?;
+            match format() {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "pixels_per_unit_x": phys.pixels_per_unit_x,
+                        "pixels_per_unit_y": phys.pixels_per_unit_y,
+                        "unit": if phys.unit == 1 { "meter" } else { "unknown" },
+                        "dpi": phys.dpi().map(|(x, y)| [x, y]),
+                    })
+                ),
+                OutputFormat::Csv => {
+                    let (x, y) = phys.dpi().map_or((String::new(), String::new()), |(x, y)| {
+                        (format!("{:.2}", x), format!("{:.2}", y))
+                    });
+                    println!("pixels_per_unit_x,pixels_per_unit_y,unit,dpi_x,dpi_y");
+                    println!(
+                        "{},{},{},{},{}",
+                        phys.pixels_per_unit_x, phys.pixels_per_unit_y, phys.unit, x, y
+                    );
+                }
+                OutputFormat::Text => println!("{}", phys),
+            }
+        }
+        DpiArgs::Set(args) => {
+            let mut png = parse_png(&from_file(&args.file_path)?)?;
+            let phys = Phys::from_dpi(args.dpi, args.vertical.unwrap_or(args.dpi))?;
+            let chunk = Chunk::new(ChunkType::from_str("pHYs")?, phys.to_bytes())?;
+            let mut edit = png.edit();
+            edit.set(chunk);
+            edit.commit()?;
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+            status!("Set the resolution to {}", phys);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the number of chunks of a type, for scripts
+pub fn count(args: CountArgs) -> Result<()> {
+    let mut count = 0;
+    for chunk in chunks_from_file(&args.file_path)? {
+        if chunk?
+            .chunk_type()
+            .matches(&args.chunk_type, args.ignore_case)
+        {
+            count += 1;
+        }
+    }
+    println!("{}", count);
+    Ok(())
+}
+
+/// Reports whether a file has a chunk of a type through the exit status alone, for scripts.
+/// The rest of the file is still read after a match, one chunk at a time, so that it is checked
+/// like it is by every other command.
+pub fn exists(args: ExistsArgs) -> Result<()> {
+    let found = || -> Result<bool> {
+        let mut found = false;
+        for chunk in chunks_from_file(&args.file_path)? {
+            found |= chunk?
+                .chunk_type()
+                .matches(&args.chunk_type, args.ignore_case);
+        }
+        // Denied warnings have to be an error here, or they would read as absence
+        diagnostics::check()?;
+        Ok(found)
+    };
+    match found() {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(PngChunkError::Status(1)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(PngChunkError::Status(2))
+        }
+    }
+}
+
+/// Reads and writes the last-modification time in the tIME chunk
+pub fn time(args: TimeArgs) -> Result<()> {
+    let (file_path, output, time) = match args {
+        TimeArgs::Get(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let chunk = png
+                .chunk_by_type("tIME")
+                .ok_or_else(|| PngChunkError::ChunkNotFound("tIME".to_string()))?;
+            println!("{}", TimeChunk::parse(chunk.data())?);
+            return Ok(());
+        }
+        TimeArgs::Set(args) => (args.file_path, args.output, args.time),
+        TimeArgs::Touch(args) => (args.file_path, args.output, TimeChunk::now()?),
+    };
+    let mut png = parse_png(&from_file(&file_path)?)?;
+    let mut edit = png.edit();
+    edit.set(Chunk::new(ChunkType::from_str("tIME")?, time.to_bytes())?);
+    edit.commit()?;
+    write_png(output.as_ref().unwrap_or(&file_path), &png)?;
+    status!("Set the modification time to {}", time);
+    Ok(())
+}
+
+/// Shows and sets the color space information in the gAMA, cHRM, sRGB and iCCP chunks
+pub fn color(args: ColorArgs) -> Result<()> {
+    match args {
+        ColorArgs::Show(args) => {
+            let png = parse_png(&from_file(&args.file_path)?)?;
+            let chunk = |chunk_type: &str| png.chunk_by_type(chunk_type).map(Chunk::data);
+            let gama = chunk("gAMA").map(Gama::parse).transpose()?;
+            let chrm = chunk("cHRM").map(Chrm::parse).transpose()?;
+            let srgb = chunk("sRGB").map(Srgb::parse).transpose()?;
+            let profile = icc::extract(&png)?;
+            if format() == OutputFormat::Json {
+                let point = |(x, y): (u32, u32)| [x as f64 / 100000.0, y as f64 / 100000.0];
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "gamma": gama.map(|gama| gama.gamma()),
+                        "chromaticities": chrm.map(|chrm| serde_json::json!({
+                            "white": point(chrm.white),
+                            "red": point(chrm.red),
+                            "green": point(chrm.green),
+                            "blue": point(chrm.blue),
+                        })),
+                        "srgb": srgb.map(|srgb| srgb.intent_name()),
+                        "icc_profile": profile.as_ref().map(|profile| &profile.name),
+                    })
+                );
+                return Ok(());
+            }
+            if let Some(gama) = gama {
+                println!("gAMA: {}", gama);
+            }
+            if let Some(chrm) = chrm {
+                println!("cHRM: {}", chrm);
+            }
+            if let Some(srgb) = srgb {
+                println!("sRGB: {}", srgb);
+            }
+            if let Some(profile) = &profile {
+                println!(
+                    "iCCP: ICC profile '{}' ({} bytes)",
+                    profile.name,
+                    profile.profile.len()
+                );
+            }
+            if (gama, chrm, srgb, &profile) == (None, None, None, &None) {
+                println!("No color space information");
+            }
+        }
+        ColorArgs::Set(args) => {
+            let mut chunks = vec![];
+            if let Some(gamma) = args.gamma {
+                chunks.push(("gAMA", Gama::from_gamma(gamma)?.to_bytes()));
+            }
+            if let Some(values) = &args.chromaticities {
+                let values = values
+                    .split(',')
+                    .map(|value| {
+                        value
+                            .trim()
+                            .parse::<f64>()
+                            .map_err(|_| format!("Invalid chromaticity '{}'", value))
+                    })
+                    .collect::<std::result::Result<Vec<f64>, String>>()?;
+                chunks.push(("cHRM", Chrm::from_values(&values)?.to_bytes()));
+            }
+            if let Some(srgb) = args.srgb {
+                chunks.push(("sRGB", srgb.to_bytes()));
+            }
+            if chunks.is_empty() {
+                return Err("Nothing to set, pass --gamma, --chromaticities or --srgb".into());
+            }
+
+            let mut png = parse_png(&from_file(&args.file_path)?)?;
+            let has_profile = png.chunk_by_type("iCCP").is_some();
+            let mut edit = png.edit();
+            if args.srgb.is_some() && has_profile {
+                diagnostics::warn("removing the ICC profile, which sRGB replaces");
+                edit.remove("iCCP");
+            }
+            for (chunk_type, data) in chunks {
+                edit.set(Chunk::new(ChunkType::from_str(chunk_type)?, data)?);
+            }
+            edit.commit()?;
+            write_png(args.output.as_ref().unwrap_or(&args.file_path), &png)?;
+        }
+    }
     Ok(())
 }
 
-fn from_file<P: AsRef<Path>>(file: P) -> Result<Vec<u8>> {
-    fs::read(file.as_ref()).map_err(|e| e.into())
+/// Describes the contents of chunks whose layout is simple enough to print on a single line
+fn chunk_metadata(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    match chunk.chunk_type().to_string().as_str() {
+        "IHDR" if data.len() == 13 => Some(format!(
+            "width={} height={} bit_depth={} color_type={} interlace={}",
+            u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            data[8],
+            data[9],
+            data[12]
+        )),
+        "tEXt" => {
+            let (keyword, text) = data.split_at(data.iter().position(|&b| b == 0)?);
+            Some(format!(
+                "{}: {}",
+                String::from_utf8_lossy(keyword),
+                String::from_utf8_lossy(&text[1..]).escape_debug()
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Describes the text of a chunk holding JSON, XML or a plist, either on its own or in a text
+/// chunk, re-indented if `pretty` is set
+fn structured_text(chunk: &Chunk, pretty: bool) -> Option<String> {
+    let text = match text::keyword_text(chunk) {
+        Ok(Some((_, text))) => text,
+        Ok(None) => String::from_utf8(payload::open(chunk.data()).ok()?.message().to_vec()).ok()?,
+        Err(_) => return None,
+    };
+    let syntax = pretty::detect(&text)?;
+    let text = match pretty::pretty(&text) {
+        Some((_, formatted)) if pretty => formatted,
+        _ => text,
+    };
+    Some(format!("{}\n{}", syntax, text))
+}
+
+/// The files to process for paths given on the command line, which must come down to a single
+/// file if the command takes an output path
+fn batch_files(paths: &[PathBuf], recursive: bool, has_output: bool) -> Result<Vec<PathBuf>> {
+    let files = batch::expand(paths, recursive)?;
+    if has_output && files.len() > 1 {
+        return Err("--output can only be used with a single file".into());
+    }
+    Ok(files)
+}
+
+/// The chunk type filter of a command that needs one; the argument parser makes sure one of the
+/// ways of giving it was used
+fn type_filter(
+    chunk_type: Option<&ChunkType>,
+    pattern: Option<&str>,
+    prefix: Option<&str>,
+    ignore_case: bool,
+) -> Result<TypeFilter> {
+    TypeFilter::from_args(chunk_type, pattern, prefix, ignore_case)?
+        .ok_or_else(|| "A chunk type, --type or --type-prefix is required".into())
+}
+
+/// The chunk at `index` among those matching `chunk_type`, which must be given if there is
+/// more than one
+fn pick_chunk<T>(matching: Vec<T>, index: Option<usize>, chunk_type: &str) -> Result<T> {
+    let count = matching.len();
+    match (index, count) {
+        (_, 0) => Err(PngChunkError::ChunkNotFound(chunk_type.to_string())),
+        (None, 1) => Ok(matching
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| unreachable!())),
+        (None, count) => Err(format!(
+            "Found {} {} chunks, use --index to pick one",
+            count, chunk_type
+        )
+        .into()),
+        (index, count) => matching.into_iter().nth(index.unwrap_or(0)).ok_or_else(|| {
+            format!(
+                "Index {} is out of range, there are {} {} chunks",
+                index.unwrap_or(0),
+                count,
+                chunk_type
+            )
+            .into()
+        }),
+    }
+}
+
+/// Writes `output` to `file`, or to stdout if there is none
+fn write_output(file: Option<PathBuf>, output: Vec<u8>) -> Result<()> {
+    match file {
+        Some(file) => to_file(file, &output),
+        None => stdio::write_stdout(&output),
+    }
+}
+
+/// The data a schema describes: the message inside a pngchunk envelope, or else the chunk data
+fn schema_data(chunk: &Chunk) -> Vec<u8> {
+    match payload::open(chunk.data()) {
+        Ok(payload::Payload::Enveloped { message, .. }) => message.to_vec(),
+        _ => chunk.data().to_vec(),
+    }
+}
+
+/// Loads the notes about `file`, which is empty if no notes have been taken yet
+fn load_notes(file: &Path) -> Result<Notes> {
+    let sidecar = Notes::sidecar(file);
+    if !sidecar.exists() {
+        return Ok(Notes::default());
+    }
+    Notes::parse(&from_file(sidecar)?)
+}
+
+/// Whether `file` names stdio, a data URI or the clipboard rather than a file on disk
+fn is_pseudo_path(file: &Path) -> bool {
+    if stdio::is_stdio(file) {
+        return true;
+    }
+    let Some(file) = file.to_str() else {
+        return false;
+    };
+    #[cfg(feature = "clipboard")]
+    if clipboard::is_clipboard(file) {
+        return true;
+    }
+    datauri::is_data_uri(file)
+}
+
+/// Refuses to encode into a public chunk type unless forced, as decoders may act on those.
+/// Without `only_private` that is narrowed to the registered standard types, which decoders
+/// are sure to act on. The hints tell the user how to force it and how to turn off
+/// `only_private`.
+pub(crate) fn check_encodable(
+    chunk_type: &ChunkType,
+    only_private: bool,
+    force: bool,
+    (how_to_force, how_to_allow): (&str, &str),
+) -> Result<()> {
+    if force || !chunk_type.is_public() {
+        return Ok(());
+    }
+    match chunk_type.description() {
+        Some(description) => Err(format!(
+            "Refusing to encode into standard chunk type {} ({}), use a private type \
+             (lowercase second letter) or {}",
+            chunk_type, description, how_to_force
+        )
+        .into()),
+        None if only_private => Err(format!(
+            "Refusing to encode into public chunk type {}, use a private type (lowercase second \
+             letter), {} or {}",
+            chunk_type, how_to_allow, how_to_force
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Builds the chunk data for `message`, compressing, encrypting and sealing it as asked
+pub(crate) fn build_payload(
+    message: Vec<u8>,
+    compress: bool,
+    encrypt: bool,
+    legacy: bool,
+) -> Result<Vec<u8>> {
+    // Compress first, as encrypted data does not compress
+    let message = if compress {
+        payload::compress(&message)?
+    } else {
+        message
+    };
+    let message = if encrypt {
+        crypto::encrypt(&message, &crypto::new_passphrase()?)?
+    } else {
+        message
+    };
+    Ok(if legacy {
+        message
+    } else {
+        payload::seal(&message)
+    })
+}
+
+/// A message read back from a PNG by `read_message`. It may have spilled to disk, see
+/// `spill`, so it is read through the methods here.
+pub(crate) struct Message {
+    pub chunk_type: ChunkType,
+    /// The envelope version, or `None` for a plain payload
+    pub envelope: Option<u8>,
+    pub compressed: bool,
+    data: Spill,
+    /// Where the message starts in `data`, after any envelope header
+    start: usize,
+}
+
+impl Message {
+    pub fn len(&self) -> usize {
+        self.data.len() - self.start
+    }
+
+    /// The message, read into memory
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        self.data.to_vec_at(self.start)
+    }
+
+    pub fn reader(&self) -> Result<Box<dyn Read + '_>> {
+        self.data.reader_at(self.start)
+    }
+
+    pub fn is_encrypted(&self) -> Result<bool> {
+        let magic = self.data.read_at(self.start, crypto::MAGIC.len())?;
+        Ok(crypto::is_encrypted(&magic))
+    }
+
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        let message = self.bytes()?;
+        Ok(serde_json::json!({
+            "type": self.chunk_type.to_string(),
+            "envelope": self.envelope,
+            "encrypted": crypto::is_encrypted(&message),
+            "compressed": self.compressed,
+            "length": message.len(),
+            "text": std::str::from_utf8(&message).ok(),
+            "base64": datauri::base64_encode(&message),
+        }))
+    }
+}
+
+/// Reads the message in the first chunk matching `chunk_type`, joining split payloads,
+/// decrypting it if asked to and inflating it if it was compressed. Joined and inflated
+/// payloads spill to disk once they pass `--max-memory`.
+pub(crate) fn read_message(
+    png: &Png,
+    chunk_type: &ChunkType,
+    ignore_case: bool,
+    decrypt: bool,
+) -> Result<Message> {
+    let chunk = png
+        .chunks()
+        .iter()
+        .find(|c| c.chunk_type().matches(chunk_type, ignore_case))
+        .ok_or_else(|| PngChunkError::ChunkNotFound(chunk_type.to_string()))?;
+    let mut data = Spill::default();
+    if payload::is_part(chunk.data()) {
+        let parts: Vec<&[u8]> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type() == chunk.chunk_type() && payload::is_part(c.data()))
+            .map(Chunk::data)
+            .collect();
+        payload::join_into(&parts, &mut data)?;
+    } else {
+        data.write_all(chunk.data())?;
+    }
+    // Plain string payloads, as written by pngme and older versions, are read as is
+    let header = data.read_at(0, payload::MAGIC.len() + 1)?;
+    let (envelope, start) = match payload::open(&header)? {
+        payload::Payload::Enveloped { version, message } => {
+            (Some(version), header.len() - message.len())
+        }
+        payload::Payload::Legacy(_) => (None, 0),
+    };
+    let (data, start) = if decrypt {
+        let message = crypto::decrypt(&data.to_vec_at(start)?, &crypto::passphrase()?)?;
+        let mut decrypted = Spill::default();
+        decrypted.write_all(&message)?;
+        (decrypted, 0)
+    } else {
+        (data, start)
+    };
+    let magic = payload::COMPRESSED_MAGIC.len();
+    let compressed = payload::is_compressed(&data.read_at(start, magic)?);
+    let (data, start) = if compressed {
+        let mut inflated = Spill::default();
+        limits::get().inflate_into(
+            data.reader_at(start + magic)?,
+            data.len() - start - magic,
+            &mut inflated,
+        )?;
+        (inflated, 0)
+    } else {
+        (data, start)
+    };
+    Ok(Message {
+        chunk_type: chunk.chunk_type().clone(),
+        envelope,
+        compressed,
+        data,
+        start,
+    })
 }
 
+/// Writes a message to `file` atomically, or to stdout if there is none, without reading it
+/// into memory
+fn write_message(file: Option<PathBuf>, message: &Message) -> Result<()> {
+    let _span = tracing::info_span!("write", bytes = message.len()).entered();
+    match file {
+        Some(file) if !stdio::is_stdio(&file) => {
+            let mut temp = TempFile::new()?;
+            io::copy(&mut message.reader()?, &mut temp)?;
+            cancel::check()?;
+            temp.persist(&file)
+        }
+        _ => stdio::copy_to_stdout(message.reader()?),
+    }
+}
+
+/// Describes a chunk at `offset` the way `print` does in JSON
+pub(crate) fn chunk_json(chunk: &Chunk, offset: usize) -> serde_json::Value {
+    let mut entry = serde_json::json!({
+        "type": chunk.chunk_type().to_string(),
+        "length": chunk.length(),
+        "crc": chunk.crc(),
+        "offset": offset,
+    });
+    if let Some(description) = chunk.chunk_type().description() {
+        entry["description"] = description.into();
+    }
+    entry
+}
+
+/// Parses a PNG, reporting any oddities in its chunk layout as warnings
+pub(crate) fn parse_png(contents: &[u8]) -> Result<Png> {
+    let (png, warnings) = Png::parse(contents, ParseMode::Strict)?;
+    warnings.iter().for_each(diagnostics::warn);
+    Ok(png)
+}
+
+/// Reads a file, stdin if `file` is `-`, or decodes `file` itself if it is a data URI or names
+/// the clipboard
+pub(crate) fn from_file<P: AsRef<Path>>(file: P) -> Result<Vec<u8>> {
+    let file = file.as_ref();
+    let _span = tracing::info_span!("read", path = %file.display()).entered();
+    #[cfg(feature = "clipboard")]
+    if file.to_str().is_some_and(clipboard::is_clipboard) {
+        return clipboard::read();
+    }
+    if let Some(uri) = file.to_str().filter(|f| datauri::is_data_uri(f)) {
+        let contents = datauri::decode(uri)?;
+        limits::get().check_file_size(contents.len())?;
+        return Ok(contents);
+    }
+    if stdio::is_stdio(file) {
+        return stdio::read_stdin();
+    }
+    check_file_size(file)?;
+    fs::read(file).map_err(|e| e.into())
+}
+
+/// Checks the size of a file on disk against the file size limit. Sizes too large to address
+/// saturate rather than wrap, so they still fail the check.
+fn check_file_size(file: &Path) -> Result<()> {
+    let len = fs::metadata(file)?.len();
+    limits::get().check_file_size(usize::try_from(len).unwrap_or(usize::MAX))
+}
+
+/// Streams the chunks of a file, for commands that look at one chunk at a time
+fn chunks_from_file<P: AsRef<Path>>(file: P) -> Result<ChunkReader<Box<dyn Read>>> {
+    let file = file.as_ref();
+    let reader: Box<dyn Read> = if file.to_str().is_some_and(datauri::is_data_uri) {
+        Box::new(io::Cursor::new(from_file(file)?))
+    } else if stdio::is_stdio(file) {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        check_file_size(file)?;
+        Box::new(BufReader::new(File::open(file)?))
+    };
+    // Layout warnings go through diagnostics, as they do for commands that use `parse_png`
+    Ok(ChunkReader::new(reader).warn_on_layout())
+}
+
+/// Writes a PNG to `file`, or prints it as a data URI if asked to with `--output-data-uri` or
+/// if `file` is itself a data URI because the input was one. The clipboard is handled the same
+/// way when that feature is enabled.
+fn write_png<P: AsRef<Path>>(file: P, png: &Png) -> Result<()> {
+    let file = file.as_ref();
+    #[cfg(feature = "clipboard")]
+    if clipboard::output() || file.to_str().is_some_and(clipboard::is_clipboard) {
+        return clipboard::write(png);
+    }
+    if datauri::output() || file.to_str().is_some_and(datauri::is_data_uri) {
+        println!("{}", datauri::encode(&png.as_bytes()));
+        return Ok(());
+    }
+    to_file(file, &png.as_bytes())
+}
+
+/// Writes `contents` to `file` atomically, or to stdout if `file` is `-`
 fn to_file<P: AsRef<Path>>(file: P, contents: &[u8]) -> Result<()> {
-    fs::write(file.as_ref(), contents).map_err(|e| e.into())
+    let _span = tracing::info_span!("write", bytes = contents.len()).entered();
+    if stdio::is_stdio(file.as_ref()) {
+        return stdio::write_stdout(contents);
+    }
+    temp::write_atomic(file.as_ref(), contents)
 }
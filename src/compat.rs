@@ -0,0 +1,115 @@
+use core::fmt;
+
+use crate::png::Png;
+
+/// Files with more chunks than this are rejected or truncated by some decoders
+pub const MAX_CHUNK_COUNT: usize = 10_000;
+/// Uncompressed iTXt text larger than this is rejected by some decoders
+pub const MAX_UNCOMPRESSED_TEXT: usize = 1 << 20;
+
+/// An interoperability hazard along with the consumers known to struggle with it
+#[derive(Debug)]
+pub struct CompatIssue {
+    pub consumers: &'static [&'static str],
+    pub message: String,
+}
+
+impl fmt::Display for CompatIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.consumers.join(", "), self.message)
+    }
+}
+
+/// Checks a PNG for known decoder quirks, returning one issue per hazard found
+pub fn check(png: &Png) -> Vec<CompatIssue> {
+    let mut issues = vec![];
+
+    if png.chunks().len() > MAX_CHUNK_COUNT {
+        issues.push(CompatIssue {
+            consumers: &["libpng", "browsers"],
+            message: format!(
+                "file contains {} chunks, more than the {} some decoders will read",
+                png.chunks().len(),
+                MAX_CHUNK_COUNT
+            ),
+        });
+    }
+
+    for chunk in png.chunks() {
+        let data = chunk.data();
+        match chunk.chunk_type().to_string().as_str() {
+            "IHDR" if data.len() > 8 && data[8] == 16 => issues.push(CompatIssue {
+                consumers: &["browsers"],
+                message: "image uses 16-bit samples, which browsers reduce to 8 bits; consider 8-bit for web delivery".to_string(),
+            }),
+            "acTL" => issues.push(CompatIssue {
+                consumers: &["libpng", "stb_image"],
+                message: "image is an APNG; consumers without APNG support only show the default image".to_string(),
+            }),
+            "iTXt" => {
+                let compressed = data
+                    .iter()
+                    .position(|&b| b == 0)
+                    .and_then(|keyword_end| data.get(keyword_end + 1))
+                    .is_some_and(|&flag| flag != 0);
+                if !compressed && data.len() > MAX_UNCOMPRESSED_TEXT {
+                    issues.push(CompatIssue {
+                        consumers: &["libpng"],
+                        message: format!(
+                            "iTXt chunk holds {} bytes of uncompressed text; compress it or move it out of the image",
+                            data.len()
+                        ),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::testing::chunk;
+
+    fn ihdr(bit_depth: u8) -> Chunk {
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, bit_depth, 6, 0, 0, 0])
+    }
+
+    #[test]
+    fn test_compat_clean() {
+        let png = Png::from_chunks(vec![ihdr(8), chunk("IEND", &[])]);
+        assert!(check(&png).is_empty());
+    }
+
+    #[test]
+    fn test_compat_16_bit_apng() {
+        let png = Png::from_chunks(vec![
+            ihdr(16),
+            chunk("acTL", &[0, 0, 0, 1, 0, 0, 0, 0]),
+            chunk("IEND", &[]),
+        ]);
+        assert_eq!(check(&png).len(), 2);
+    }
+
+    #[test]
+    fn test_compat_uncompressed_itxt() {
+        let mut data = b"Comment\0\0\0\0\0".to_vec();
+        data.extend(vec![b'a'; MAX_UNCOMPRESSED_TEXT]);
+        let png = Png::from_chunks(vec![ihdr(8), chunk("iTXt", &data), chunk("IEND", &[])]);
+
+        let issues = check(&png);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].consumers, &["libpng"]);
+    }
+
+    #[test]
+    fn test_compat_chunk_count() {
+        let chunks = (0..=MAX_CHUNK_COUNT).map(|_| chunk("ruSt", &[])).collect();
+        let png = Png::from_chunks(chunks);
+        assert_eq!(check(&png).len(), 1);
+    }
+}
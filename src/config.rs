@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use serde_json::Value;
+
+use crate::{chunk_type::ChunkType, schema::Schema, Result};
+
+/// Config file picked up from the working directory when no other is given
+pub const FILE_NAME: &str = "pngchunk.toml";
+/// Environment variable naming the config file
+pub const CONFIG_VAR: &str = "PNGCHUNK_CONFIG";
+
+static ACTIVE: OnceLock<Config> = OnceLock::new();
+
+/// Settings read from `pngchunk.toml`. Chunk schemas are registered per chunk type:
+///
+/// ```toml
+/// [schemas.ruSt]
+/// fields = [
+///     { name = "version", type = "u16" },
+///     { name = "flags", type = "u8" },
+///     { name = "label", type = "str", terminator = 0 },
+/// ]
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub schemas: BTreeMap<String, Schema>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config> {
+        let toml: Value = toml::from_str(text).map_err(|e| format!("Invalid config: {}", e))?;
+        let mut config = Config::default();
+        match &toml["schemas"] {
+            Value::Null => {}
+            Value::Object(schemas) => {
+                for (chunk_type, table) in schemas {
+                    ChunkType::from_str(chunk_type)?;
+                    let schema = Schema::from_toml(table)
+                        .map_err(|e| format!("Schema for {}: {}", chunk_type, e))?;
+                    config.schemas.insert(chunk_type.clone(), schema);
+                }
+            }
+            _ => return Err("schemas must be a table of chunk types".into()),
+        }
+        Ok(config)
+    }
+
+    /// Reads the config from `path`, else the file named by `PNGCHUNK_CONFIG`, else
+    /// `pngchunk.toml` in the working directory if there is one
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match std::env::var_os(CONFIG_VAR) {
+                Some(path) => PathBuf::from(path),
+                None if Path::new(FILE_NAME).is_file() => PathBuf::from(FILE_NAME),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read config {}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// The schema registered for `chunk_type`, if any
+    pub fn schema(&self, chunk_type: &ChunkType) -> Option<&Schema> {
+        self.schemas.get(&chunk_type.to_string())
+    }
+}
+
+/// Makes `config` the one used by every command. Only the first call has an effect.
+pub fn set(config: Config) {
+    let _ = ACTIVE.set(config);
+}
+
+/// The active config, empty if none was loaded
+pub fn get() -> &'static Config {
+    ACTIVE.get_or_init(Config::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FieldType;
+
+    const CONFIG: &str = r#"
+[schemas.ruSt]
+endian = "little"
+fields = [
+    { name = "version", type = "u16" },
+    { name = "flags", type = "u8", endian = "big" },
+    { name = "label_length", type = "u8" },
+    { name = "label", type = "str", size = "label_length" },
+]
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(CONFIG).unwrap();
+        let schema = config
+            .schema(&ChunkType::from_str("ruSt").unwrap())
+            .unwrap();
+        assert_eq!(schema.fields.len(), 4);
+        assert_eq!(schema.fields[0].field_type, FieldType::Unsigned(2));
+        assert!(config
+            .schema(&ChunkType::from_str("teSt").unwrap())
+            .is_none());
+        assert!(Config::parse("").unwrap().schemas.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(Config::parse("schemas = 1").is_err());
+        assert!(Config::parse("[schemas.ab]\nfields = []").is_err());
+        let error = Config::parse("[schemas.ruSt]\nfields = [{ name = \"a\", type = \"u12\" }]")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("ruSt") && error.contains("u12"));
+    }
+}
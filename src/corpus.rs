@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{json, Value};
+
+use crate::{png::Png, provenance};
+
+/// How often one chunk type appears across a corpus
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TypeStats {
+    /// Files containing at least one chunk of this type
+    pub files: usize,
+    /// Chunks of this type across every file
+    pub occurrences: usize,
+    /// Total data size of those chunks, in bytes
+    pub total_size: u64,
+}
+
+impl TypeStats {
+    pub fn average_size(&self) -> f64 {
+        self.total_size as f64 / self.occurrences.max(1) as f64
+    }
+}
+
+/// Chunk type statistics aggregated over many files
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CorpusStats {
+    pub files: usize,
+    /// Files that could not be parsed, which are otherwise left out
+    pub unreadable: usize,
+    pub types: BTreeMap<String, TypeStats>,
+    /// Files containing both types of each pair, with the pair in sorted order
+    pub co_occurrence: BTreeMap<(String, String), usize>,
+    /// Files each producer was detected in, as fingerprinted by `provenance`
+    pub producers: BTreeMap<String, usize>,
+}
+
+impl CorpusStats {
+    pub fn add(&mut self, png: &Png) {
+        self.files += 1;
+
+        let mut present = BTreeSet::new();
+        for chunk in png.chunks() {
+            let chunk_type = chunk.chunk_type().to_string();
+            let stats = self.types.entry(chunk_type.clone()).or_default();
+            stats.occurrences += 1;
+            stats.total_size += chunk.data().len() as u64;
+            present.insert(chunk_type);
+        }
+        for chunk_type in present.iter() {
+            self.types.entry(chunk_type.clone()).or_default().files += 1;
+        }
+        for (i, a) in present.iter().enumerate() {
+            for b in present.iter().skip(i + 1) {
+                *self
+                    .co_occurrence
+                    .entry((a.clone(), b.clone()))
+                    .or_default() += 1;
+            }
+        }
+
+        let clues = provenance::fingerprint(png);
+        for tool in provenance::tool_chain(&clues) {
+            *self.producers.entry(tool.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn add_unreadable(&mut self) {
+        self.unreadable += 1;
+    }
+
+    /// Producers ordered from most to least common
+    pub fn top_producers(&self) -> Vec<(&str, usize)> {
+        let mut producers: Vec<(&str, usize)> = self
+            .producers
+            .iter()
+            .map(|(tool, &count)| (tool.as_str(), count))
+            .collect();
+        producers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        producers
+    }
+
+    pub fn to_json(&self) -> Value {
+        let types: serde_json::Map<String, Value> = self
+            .types
+            .iter()
+            .map(|(chunk_type, stats)| {
+                (
+                    chunk_type.clone(),
+                    json!({
+                        "files": stats.files,
+                        "occurrences": stats.occurrences,
+                        "average_size": stats.average_size(),
+                    }),
+                )
+            })
+            .collect();
+        let co_occurrence: Vec<Value> = self
+            .co_occurrence
+            .iter()
+            .map(|((a, b), files)| json!({"types": [a, b], "files": files}))
+            .collect();
+        let producers: Vec<Value> = self
+            .top_producers()
+            .iter()
+            .map(|(tool, files)| json!({"tool": tool, "files": files}))
+            .collect();
+        json!({
+            "files": self.files,
+            "unreadable": self.unreadable,
+            "types": types,
+            "co_occurrence": co_occurrence,
+            "producers": producers,
+        })
+    }
+
+    /// Writes the statistics as a single long-format table with `metric,a,b,value` columns, so
+    /// every statistic can be loaded with one CSV reader
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("metric,a,b,value\n");
+        csv.push_str(&format!("files,,,{}\n", self.files));
+        csv.push_str(&format!("unreadable,,,{}\n", self.unreadable));
+        for (chunk_type, stats) in self.types.iter() {
+            csv.push_str(&format!("type_files,{},,{}\n", chunk_type, stats.files));
+            csv.push_str(&format!(
+                "type_occurrences,{},,{}\n",
+                chunk_type, stats.occurrences
+            ));
+            csv.push_str(&format!(
+                "type_average_size,{},,{:.2}\n",
+                chunk_type,
+                stats.average_size()
+            ));
+        }
+        for ((a, b), files) in self.co_occurrence.iter() {
+            csv.push_str(&format!("co_occurrence,{},{},{}\n", a, b, files));
+        }
+        for (tool, files) in self.top_producers() {
+            csv.push_str(&format!("producer,{},,{}\n", csv_field(tool), files));
+        }
+        csv
+    }
+}
+
+/// Quotes a field if it contains a comma, quote or newline
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks(
+            chunks
+                .iter()
+                .map(|(t, data)| {
+                    Chunk::new(ChunkType::from_str(t).unwrap(), data.to_vec()).unwrap()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_corpus_stats() {
+        let mut stats = CorpusStats::default();
+        stats.add(&png(&[
+            ("IHDR", &[0; 13]),
+            ("tEXt", b"Software\0GIMP 2.10"),
+            ("tEXt", b"Comment\0hi"),
+            ("IEND", &[]),
+        ]));
+        stats.add(&png(&[("IHDR", &[0; 13]), ("IEND", &[])]));
+        stats.add_unreadable();
+
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.unreadable, 1);
+        let text = &stats.types["tEXt"];
+        assert_eq!((text.files, text.occurrences), (1, 2));
+        assert_eq!(text.average_size(), 14.0);
+        assert_eq!(stats.types["IHDR"].files, 2);
+        assert_eq!(
+            stats.co_occurrence[&("IEND".to_string(), "IHDR".to_string())],
+            2
+        );
+        assert_eq!(stats.top_producers(), vec![("GIMP", 1)]);
+    }
+
+    #[test]
+    fn test_corpus_output() {
+        let mut stats = CorpusStats::default();
+        stats.add(&png(&[("IHDR", &[0; 13]), ("IEND", &[])]));
+
+        let json = stats.to_json();
+        assert_eq!(json["types"]["IHDR"]["average_size"], 13.0);
+        let csv = stats.to_csv();
+        assert!(csv.starts_with("metric,a,b,value\nfiles,,,1\n"));
+        assert!(csv.contains("co_occurrence,IEND,IHDR,1\n"));
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+    }
+}
@@ -0,0 +1,191 @@
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+use crate::Result;
+
+/// Marks a message encrypted by `encrypt`. Like the payload envelope, the leading 0x89 keeps it
+/// from being mistaken for a plain string.
+pub const MAGIC: [u8; 4] = [0x89, b'p', b'c', b'e'];
+/// Current encryption header version
+pub const VERSION: u8 = 1;
+/// Environment variable holding the passphrase, so scripts need not answer a prompt
+pub const PASSPHRASE_VAR: &str = "PNGCHUNK_PASSPHRASE";
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Whether `message` was written by `encrypt`
+pub fn is_encrypted(message: &[u8]) -> bool {
+    message.starts_with(&MAGIC)
+}
+
+/// Encrypts `message` with ChaCha20-Poly1305 under a key derived from `passphrase` with
+/// Argon2id. The result is the magic, version, salt and nonce followed by the ciphertext. The
+/// magic, version and salt are authenticated along with the message, so they cannot be
+/// swapped out without decryption failing.
+pub fn encrypt(message: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let salt: [u8; SALT_LENGTH] = rand_bytes()?;
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut data = MAGIC.to_vec();
+    data.push(VERSION);
+    data.extend(salt);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: message,
+                aad: &data,
+            },
+        )
+        .map_err(|_| "Encryption failed")?;
+    data.extend(nonce);
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+/// Decrypts a message written by `encrypt`, failing if the passphrase is wrong or the data has
+/// been tampered with
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if !is_encrypted(data) {
+        return Err("Payload is not encrypted".into());
+    }
+    if data.len() < HEADER_LENGTH {
+        return Err("Encryption header is truncated".into());
+    }
+    let version = data[MAGIC.len()];
+    if version > VERSION {
+        return Err(format!(
+            "Payload uses encryption version {}, this build supports up to {}",
+            version, VERSION
+        )
+        .into());
+    }
+    if version != VERSION {
+        return Err(format!("Unknown encryption version {}", version).into());
+    }
+    let (associated, salt) = (
+        &data[..HEADER_LENGTH - NONCE_LENGTH],
+        &data[MAGIC.len() + 1..HEADER_LENGTH - NONCE_LENGTH],
+    );
+    let nonce = Nonce::from_slice(&data[HEADER_LENGTH - NONCE_LENGTH..HEADER_LENGTH]);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    Ok(cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: &data[HEADER_LENGTH..],
+                aad: associated,
+            },
+        )
+        .map_err(|_| "Wrong passphrase or corrupted payload")?)
+}
+
+/// Reads the passphrase from `PNGCHUNK_PASSPHRASE`, or prompts for it on the terminal
+#[cfg(not(target_os = "wasi"))]
+pub fn passphrase() -> Result<String> {
+    match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => Ok(rpassword::prompt_password("Passphrase: ")?),
+    }
+}
+
+/// Reads the passphrase from `PNGCHUNK_PASSPHRASE`. WASI has no terminal to prompt on.
+#[cfg(target_os = "wasi")]
+pub fn passphrase() -> Result<String> {
+    std::env::var(PASSPHRASE_VAR)
+        .map_err(|_| format!("Set {} to give the passphrase", PASSPHRASE_VAR).into())
+}
+
+/// Reads the passphrase to encrypt with like `passphrase`, but asks for it twice when prompting,
+/// since a mistyped one would leave the payload unreadable
+#[cfg(not(target_os = "wasi"))]
+pub fn new_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".into());
+    }
+    Ok(passphrase)
+}
+
+/// Reads the passphrase to encrypt with from `PNGCHUNK_PASSPHRASE`
+#[cfg(target_os = "wasi")]
+pub fn new_passphrase() -> Result<String> {
+    passphrase()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn rand_bytes<const N: usize>() -> Result<[u8; N]> {
+    use chacha20poly1305::aead::rand_core::RngCore;
+    let mut bytes = [0; N];
+    OsRng
+        .try_fill_bytes(&mut bytes)
+        .map_err(|e| format!("No randomness available: {}", e))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = encrypt(b"hidden message", "correct horse").unwrap();
+        assert!(is_encrypted(&data));
+        assert_eq!(data[4], VERSION);
+        assert_eq!(data.len(), HEADER_LENGTH + 14 + 16);
+        assert_eq!(decrypt(&data, "correct horse").unwrap(), b"hidden message");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let data = encrypt(b"hidden message", "correct horse").unwrap();
+        assert!(decrypt(&data, "battery staple").is_err());
+
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&tampered, "correct horse").is_err());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(decrypt(b"plain", "pass").is_err());
+        assert!(decrypt(&MAGIC, "pass").is_err());
+        let mut future = encrypt(b"", "pass").unwrap();
+        future[4] = VERSION + 1;
+        assert!(decrypt(&future, "pass")
+            .unwrap_err()
+            .to_string()
+            .contains("version"));
+        future[4] = 0;
+        assert!(decrypt(&future, "pass")
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown encryption version 0"));
+    }
+
+    #[test]
+    fn test_header_is_authenticated() {
+        let data = encrypt(b"hidden message", "correct horse").unwrap();
+        let salt = &data[MAGIC.len() + 1..HEADER_LENGTH - NONCE_LENGTH];
+        let cipher = ChaCha20Poly1305::new(&derive_key("correct horse", salt).unwrap());
+        let nonce = Nonce::from_slice(&data[HEADER_LENGTH - NONCE_LENGTH..HEADER_LENGTH]);
+        // Without the header as associated data the tag does not verify
+        assert!(cipher.decrypt(nonce, &data[HEADER_LENGTH..]).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Result;
+
+/// The prefix of every data URI written by pngchunk
+pub const PREFIX: &str = "data:image/png;base64,";
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+static OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Makes commands that write a PNG print it as a data URI instead
+pub fn set_output(enabled: bool) {
+    OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn output() -> bool {
+    OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Whether `s` looks like a data URI rather than a file path
+pub fn is_data_uri(s: &str) -> bool {
+    trim(s)
+        .get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Decodes a base64 data URI. Surrounding quotes and a CSS `url(...)` wrapper are ignored, as
+/// is whitespace inside the data, so values can be pasted straight from CSS or HTML.
+pub fn decode(uri: &str) -> Result<Vec<u8>> {
+    let uri = trim(uri);
+    if !is_data_uri(uri) {
+        return Err("Not a data URI".into());
+    }
+    let (header, data) = uri[5..]
+        .split_once(',')
+        .ok_or("Data URI is missing its comma")?;
+    let mut params = header.split(';');
+    let media_type = params.next().unwrap_or_default();
+    if !params.any(|p| p.eq_ignore_ascii_case("base64")) {
+        return Err("Only base64 data URIs are supported".into());
+    }
+    if !media_type.is_empty() && !media_type.eq_ignore_ascii_case("image/png") {
+        return Err(format!("Expected an image/png data URI, got {}", media_type).into());
+    }
+    base64_decode(data)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    format!("{}{}", PREFIX, base64_encode(bytes))
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let n = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard or URL-safe base64, with or without padding
+pub fn base64_decode(data: &str) -> Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    for c in data.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return Err(format!("Invalid base64 character '{}'", c as char).into()),
+        };
+        n = n << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((n >> bits) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+fn trim(s: &str) -> &str {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("url(")
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(s)
+        .trim();
+    s.trim_matches(|c| c == '"' || c == '\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_round_trip() {
+        for len in 0..10 {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37) as u8).collect();
+            assert_eq!(base64_decode(&base64_encode(&bytes)).unwrap(), bytes);
+        }
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_decode("TW\nE").unwrap(), b"Ma");
+        assert!(base64_decode("TW*u").is_err());
+    }
+
+    #[test]
+    fn test_data_uri() {
+        let uri = encode(&[0x89, b'P', b'N', b'G']);
+        assert_eq!(uri, "data:image/png;base64,iVBORw==");
+        assert_eq!(decode(&uri).unwrap(), vec![0x89, b'P', b'N', b'G']);
+        assert_eq!(
+            decode("url(\"data:image/png;base64,iVBORw==\")").unwrap(),
+            vec![0x89, b'P', b'N', b'G']
+        );
+
+        assert!(is_data_uri("DATA:image/png;base64,"));
+        assert!(!is_data_uri("image.png"));
+        assert!(decode("data:image/png,plain").is_err());
+        assert!(decode("data:image/jpeg;base64,AAAA").is_err());
+    }
+}
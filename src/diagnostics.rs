@@ -0,0 +1,63 @@
+//! Warnings about things that are odd but not wrong enough to fail a command. Each one is
+//! printed to stderr as it is found and counted, so that `--deny-warnings` can turn a run that
+//! warned into a failure, for CI jobs that should not let oddities slip through.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::Result;
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static DENY: AtomicBool = AtomicBool::new(false);
+
+/// Makes `check` fail if anything warned
+pub fn set_deny(deny: bool) {
+    DENY.store(deny, Ordering::SeqCst);
+}
+
+/// Prints a warning to stderr and counts it
+pub fn warn(message: impl Display) {
+    eprintln!("Warning: {}", message);
+    record();
+}
+
+/// Counts a warning that was printed some other way, e.g. queued in a `batch::Output`
+pub fn record() {
+    WARNINGS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// The number of warnings so far
+pub fn count() -> usize {
+    WARNINGS.load(Ordering::SeqCst)
+}
+
+/// Fails if warnings are denied and there were any
+pub fn check() -> Result<()> {
+    check_count(count(), DENY.load(Ordering::SeqCst))
+}
+
+fn check_count(count: usize, deny: bool) -> Result<()> {
+    match count {
+        0 => Ok(()),
+        _ if !deny => Ok(()),
+        count => Err(format!("{} warning(s), denied by --deny-warnings", count).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        // Other tests run alongside this one, so DENY is left alone
+        record();
+        assert!(count() > 0);
+        assert!(check_count(0, true).is_ok());
+        assert!(check_count(2, false).is_ok());
+        assert_eq!(
+            check_count(2, true).unwrap_err().to_string(),
+            "2 warning(s), denied by --deny-warnings"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde_json::json;
+
+use crate::{chunk::Chunk, png::Png};
+
+/// A chunk as it appears in one of the files being compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub index: usize,
+    pub chunk_type: String,
+    pub length: usize,
+    pub crc: u32,
+    pub digest: String,
+}
+
+impl ChunkSummary {
+    fn new(index: usize, chunk: &Chunk) -> ChunkSummary {
+        ChunkSummary {
+            index,
+            chunk_type: chunk.chunk_type().to_string(),
+            length: chunk.data().len(),
+            crc: chunk.crc(),
+            digest: chunk.data_digest(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "index": self.index,
+            "type": self.chunk_type,
+            "length": self.length,
+            "crc": format!("{:08x}", self.crc),
+            "sha256": self.digest,
+        })
+    }
+}
+
+/// One difference between the chunks of two files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(ChunkSummary),
+    Removed(ChunkSummary),
+    Modified {
+        old: ChunkSummary,
+        new: ChunkSummary,
+    },
+}
+
+impl Change {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::Added(_) => "added",
+            Change::Removed(_) => "removed",
+            Change::Modified { .. } => "modified",
+        }
+    }
+
+    pub fn chunk_type(&self) -> &str {
+        match self {
+            Change::Added(chunk) | Change::Removed(chunk) => &chunk.chunk_type,
+            Change::Modified { new, .. } => &new.chunk_type,
+        }
+    }
+}
+
+/// The chunks added, removed and modified going from `old` to `new`. Chunks with the same type
+/// and data are matched first wherever they are, so reordering alone is not a change. The
+/// remaining chunks of each type are paired up in order as modified, and any left over are
+/// added or removed. Changes are listed in file order, removed chunks by their old position.
+pub fn diff(old: &Png, new: &Png) -> Vec<Change> {
+    let old: Vec<ChunkSummary> = summaries(old);
+    let new: Vec<ChunkSummary> = summaries(new);
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+
+    for (i, chunk) in new.iter().enumerate() {
+        let same = (0..old.len()).find(|&j| {
+            !old_matched[j]
+                && old[j].chunk_type == chunk.chunk_type
+                && old[j].digest == chunk.digest
+        });
+        if let Some(j) = same {
+            old_matched[j] = true;
+            new_matched[i] = true;
+        }
+    }
+
+    let mut changes = vec![];
+    for (chunk, _) in new.iter().zip(new_matched).filter(|(_, matched)| !matched) {
+        let paired =
+            (0..old.len()).find(|&j| !old_matched[j] && old[j].chunk_type == chunk.chunk_type);
+        let change = match paired {
+            Some(j) => {
+                old_matched[j] = true;
+                Change::Modified {
+                    old: old[j].clone(),
+                    new: chunk.clone(),
+                }
+            }
+            None => Change::Added(chunk.clone()),
+        };
+        changes.push(change);
+    }
+    changes.extend(
+        old.iter()
+            .zip(old_matched)
+            .filter(|(_, matched)| !matched)
+            .map(|(chunk, _)| Change::Removed(chunk.clone())),
+    );
+    // A removed chunk goes before whatever took its place
+    changes.sort_by_key(|change| match change {
+        Change::Removed(chunk) => (chunk.index, 0),
+        Change::Added(chunk) | Change::Modified { new: chunk, .. } => (chunk.index, 1),
+    });
+    changes
+}
+
+fn summaries(png: &Png) -> Vec<ChunkSummary> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| ChunkSummary::new(index, chunk))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    #[test]
+    fn test_diff() {
+        let old = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"a\0one"),
+            chunk("tEXt", b"b\0two"),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("IDAT", &[1, 2, 3]),
+            chunk("IEND", &[]),
+        ]);
+        let new = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"b\0two"),
+            chunk("tEXt", b"a\0uno"),
+            chunk("IDAT", &[1, 2]),
+            chunk("pHYs", &[0; 9]),
+            chunk("IEND", &[]),
+        ]);
+        let changes = diff(&old, &new);
+        let names: Vec<(&str, &str)> = changes.iter().map(|c| (c.name(), c.chunk_type())).collect();
+        assert_eq!(
+            names,
+            [
+                ("modified", "tEXt"),
+                ("removed", "gAMA"),
+                ("modified", "IDAT"),
+                ("added", "pHYs"),
+            ]
+        );
+        match &changes[2] {
+            Change::Modified { old, new } => {
+                assert_eq!((old.index, new.index), (4, 3));
+                assert_eq!((old.length, new.length), (3, 2));
+                assert_ne!(old.crc, new.crc);
+            }
+            change => panic!("unexpected {:?}", change),
+        }
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+#![allow(dead_code)]
+
+use crate::{chunk::Chunk, png::Png, Error, Result};
+
+/// Chunks that may appear at most once, along with the chunks they must precede
+pub(crate) const SINGLE_CHUNKS: [(&str, &[&str]); 14] = [
+    ("IHDR", &[]),
+    ("PLTE", &["IDAT"]),
+    ("IEND", &[]),
+    ("cHRM", &["PLTE", "IDAT"]),
+    ("gAMA", &["PLTE", "IDAT"]),
+    ("iCCP", &["PLTE", "IDAT"]),
+    ("sBIT", &["PLTE", "IDAT"]),
+    ("sRGB", &["PLTE", "IDAT"]),
+    ("bKGD", &["IDAT"]),
+    ("hIST", &["IDAT"]),
+    ("tRNS", &["IDAT"]),
+    ("pHYs", &["IDAT"]),
+    ("tIME", &[]),
+    ("eXIf", &["IDAT"]),
+];
+
+/// A set of staged changes to a `Png`. Changes are made against a copy of the chunk list and
+/// only written back by `commit`, once the result has been checked against the ordering and
+/// multiplicity rules of the PNG spec. Dropping the edit without committing discards it.
+pub struct PngEdit<'a> {
+    png: &'a mut Png,
+    staged: Vec<Chunk>,
+    error: Option<Error>,
+}
+
+impl<'a> PngEdit<'a> {
+    pub fn new(png: &'a mut Png) -> Self {
+        let staged = png.chunks().to_vec();
+        Self {
+            png,
+            staged,
+            error: None,
+        }
+    }
+
+    /// The chunk list as it will look once committed
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.staged
+    }
+
+    /// Stages inserting `chunk` at `index`
+    pub fn insert(&mut self, index: usize, chunk: Chunk) -> &mut Self {
+        if index > self.staged.len() {
+            self.fail(format!(
+                "Cannot insert {} at index {}",
+                chunk.chunk_type(),
+                index
+            ));
+        } else {
+            self.staged.insert(index, chunk);
+        }
+        self
+    }
+
+    /// Stages inserting `chunk` just before the final chunk, like `Png::append_chunk`
+    pub fn append(&mut self, chunk: Chunk) -> &mut Self {
+        let index = self.staged.len().saturating_sub(1);
+        self.insert(index, chunk)
+    }
+
+    /// Stages removing the first chunk of type `chunk_type`
+    pub fn remove(&mut self, chunk_type: &str) -> &mut Self {
+        match self.position(chunk_type) {
+            Some(index) => {
+                self.staged.remove(index);
+            }
+            None => self.fail(format!("No {} chunk to remove", chunk_type)),
+        }
+        self
+    }
+
+    /// Stages replacing the first chunk of type `chunk_type` with `chunk`
+    pub fn replace(&mut self, chunk_type: &str, chunk: Chunk) -> &mut Self {
+        match self.position(chunk_type) {
+            Some(index) => self.staged[index] = chunk,
+            None => self.fail(format!("No {} chunk to replace", chunk_type)),
+        }
+        self
+    }
+
+    /// Stages setting a chunk that may appear at most once: an existing chunk of its type is
+    /// replaced, otherwise it is inserted ahead of the first chunk it must precede, or else
+    /// just before the final chunk
+    pub fn set(&mut self, chunk: Chunk) -> &mut Self {
+        let chunk_type = chunk.chunk_type().to_string();
+        if let Some(index) = self.position(&chunk_type) {
+            self.staged[index] = chunk;
+            return self;
+        }
+        let before = SINGLE_CHUNKS
+            .iter()
+            .find(|(single, _)| *single == chunk_type)
+            .map_or(&[][..], |(_, before)| *before);
+        let index = self
+            .staged
+            .iter()
+            .position(|c| before.contains(&c.chunk_type().to_string().as_str()));
+        match index {
+            Some(index) => self.insert(index, chunk),
+            None => self.append(chunk),
+        }
+    }
+
+    /// Checks the staged chunk list and applies it to the `Png`. If any staged change failed
+    /// or the result breaks the spec, the `Png` is left untouched.
+    pub fn commit(self) -> Result<()> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        check_order(&self.staged)?;
+        *self.png = Png::from_chunks(self.staged);
+        Ok(())
+    }
+
+    fn position(&self, chunk_type: &str) -> Option<usize> {
+        self.staged
+            .iter()
+            .position(|c| c.chunk_type().to_string() == chunk_type)
+    }
+
+    fn fail(&mut self, message: String) {
+        if self.error.is_none() {
+            self.error = Some(message.into());
+        }
+    }
+}
+
+/// Checks a chunk list against the ordering and multiplicity rules of the PNG spec
+pub fn check_order(chunks: &[Chunk]) -> Result<()> {
+    let types: Vec<String> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+    let position = |chunk_type: &str| types.iter().position(|t| t == chunk_type);
+
+    if types.first().map(String::as_str) != Some("IHDR") {
+        return Err("IHDR must be the first chunk".into());
+    }
+    if types.last().map(String::as_str) != Some("IEND") {
+        return Err("IEND must be the last chunk".into());
+    }
+
+    let idats: Vec<usize> = (0..types.len()).filter(|&i| types[i] == "IDAT").collect();
+    match (idats.first(), idats.last()) {
+        (Some(first), Some(last)) if last - first + 1 == idats.len() => {}
+        (Some(_), Some(_)) => return Err("IDAT chunks must be consecutive".into()),
+        _ => return Err("At least one IDAT chunk is required".into()),
+    }
+
+    for (chunk_type, before) in SINGLE_CHUNKS.iter() {
+        if types.iter().filter(|t| t == chunk_type).count() > 1 {
+            return Err(format!("Only one {} chunk is allowed", chunk_type).into());
+        }
+        let index = match position(chunk_type) {
+            Some(index) => index,
+            None => continue,
+        };
+        for other in before.iter() {
+            if position(other).is_some_and(|other_index| other_index < index) {
+                return Err(format!("{} must come before {}", chunk_type, other).into());
+            }
+        }
+    }
+
+    if let Some(plte) = position("PLTE") {
+        for after in ["bKGD", "hIST", "tRNS"] {
+            if position(after).is_some_and(|index| index < plte) {
+                return Err(format!("{} must come after PLTE", after).into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, chunk};
+
+    fn testing_png() -> Png {
+        testing::png_from(&[("IHDR", &[]), ("IDAT", &[]), ("IEND", &[])])
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_commit() {
+        let mut png = testing_png();
+        let mut edit = png.edit();
+        edit.insert(1, chunk("gAMA", &[]))
+            .append(chunk("tEXt", &[]))
+            .replace("tEXt", chunk("zTXt", &[]));
+        edit.commit().unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "gAMA", "IDAT", "zTXt", "IEND"]);
+    }
+
+    #[test]
+    fn test_edit_set() {
+        let mut png = testing_png();
+        let mut edit = png.edit();
+        edit.set(chunk("pHYs", &[]))
+            .set(chunk("tIME", &[]))
+            .set(chunk("pHYs", &[]));
+        edit.commit().unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "pHYs", "IDAT", "tIME", "IEND"]);
+    }
+
+    #[test]
+    fn test_edit_rollback_on_invalid_order() {
+        let mut png = testing_png();
+        let mut edit = png.edit();
+        edit.append(chunk("gAMA", &[]));
+        assert!(edit.commit().is_err());
+
+        assert_eq!(types(&png), vec!["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_edit_rollback_on_failed_step() {
+        let mut png = testing_png();
+        let mut edit = png.edit();
+        edit.append(chunk("tEXt", &[])).remove("PLTE");
+        assert!(edit.commit().is_err());
+
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_check_order() {
+        let valid = vec![
+            chunk("IHDR", &[]),
+            chunk("sRGB", &[]),
+            chunk("PLTE", &[]),
+            chunk("tRNS", &[]),
+            chunk("IDAT", &[]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ];
+        assert!(check_order(&valid).is_ok());
+
+        let duplicate = vec![
+            chunk("IHDR", &[]),
+            chunk("IHDR", &[]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ];
+        assert!(check_order(&duplicate).is_err());
+
+        let split = vec![
+            chunk("IHDR", &[]),
+            chunk("IDAT", &[]),
+            chunk("tEXt", &[]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ];
+        assert!(check_order(&split).is_err());
+
+        let trns_first = vec![
+            chunk("IHDR", &[]),
+            chunk("tRNS", &[]),
+            chunk("PLTE", &[]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ];
+        assert!(check_order(&trns_first).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use core::fmt;
+use std::io;
+
+/// Everything that can go wrong in pngchunk. Parsing, CRC and lookup failures have their own
+/// variants so callers can tell them apart; other failures carry a message or the source error.
+#[derive(Debug)]
+pub enum PngChunkError {
+    /// The input is not a well-formed PNG or chunk
+    Parse(String),
+    /// A chunk's stored CRC does not match its contents
+    Crc {
+        chunk_type: String,
+        expected: u32,
+        actual: u32,
+    },
+    /// No chunk of the requested type exists
+    ChunkNotFound(String),
+    Io(io::Error),
+    /// Any other failure, described by its message
+    Message(String),
+    /// A failure from one of our dependencies
+    Other(Box<dyn std::error::Error + Send + Sync>),
+    /// Ends the process with this exit status without printing anything, for commands whose
+    /// result is the status itself
+    Status(i32),
+    /// A parse or CRC failure along with where in the file it was found
+    At {
+        error: Box<PngChunkError>,
+        location: Location,
+    },
+}
+
+/// Where in a file an error was found, with the bytes around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Absolute offset of the offending byte
+    pub offset: usize,
+    /// Index of the chunk it belongs to, if it belongs to one
+    pub chunk_index: Option<usize>,
+    /// Absolute offset of the first byte of `context`
+    pub context_start: usize,
+    /// Up to `Location::CONTEXT` bytes either side of `offset`
+    pub context: Vec<u8>,
+}
+
+impl Location {
+    pub const CONTEXT: usize = 16;
+
+    /// The location of `offset` in a file of which `bytes` is the part starting at `base`
+    pub fn new(bytes: &[u8], base: usize, offset: usize, chunk_index: Option<usize>) -> Location {
+        let relative = offset.saturating_sub(base).min(bytes.len());
+        let start = relative.saturating_sub(Location::CONTEXT);
+        let end = (relative + Location::CONTEXT).min(bytes.len());
+        Location {
+            offset,
+            chunk_index,
+            context_start: base + start,
+            context: bytes[start..end].to_vec(),
+        }
+    }
+
+    /// A hexdump of the context in rows of 16 bytes, with the offending byte marked. Empty if
+    /// there is no context, as when the file ends at `offset`.
+    pub fn snippet(&self) -> String {
+        let mut snippet = String::new();
+        if self.context.is_empty() {
+            return snippet;
+        }
+        let first_row = self.context_start / 16 * 16;
+        let end = self.context_start + self.context.len();
+        for row in (first_row..end).step_by(16) {
+            let byte = |offset: usize| {
+                offset
+                    .checked_sub(self.context_start)
+                    .and_then(|i| self.context.get(i))
+            };
+            let hex: Vec<String> = (row..row + 16)
+                .map(|offset| byte(offset).map_or("  ".to_string(), |b| format!("{:02x}", b)))
+                .collect();
+            let ascii: String = (row..row + 16)
+                .map(|offset| match byte(offset) {
+                    Some(b) if b.is_ascii_graphic() => *b as char,
+                    Some(_) => '.',
+                    None => ' ',
+                })
+                .collect();
+            snippet.push_str(&format!(
+                "  {:#010x}  {}  |{}|\n",
+                row,
+                hex.join(" "),
+                ascii
+            ));
+            if (row..row + 16).contains(&self.offset) {
+                let column = 14 + (self.offset - row) * 3;
+                snippet.push_str(&format!("{}^^\n", " ".repeat(column)));
+            }
+        }
+        snippet
+    }
+}
+
+impl PngChunkError {
+    /// Attaches where the error was found, unless it already has a location
+    pub fn at(self, location: Location) -> PngChunkError {
+        match self {
+            PngChunkError::At { .. } => self,
+            error => PngChunkError::At {
+                error: Box::new(error),
+                location,
+            },
+        }
+    }
+
+    /// Locates an error in the chunk starting at `start`: at its CRC field for a CRC mismatch,
+    /// otherwise at the start of the chunk. `bytes` holds the file from offset `base` on.
+    pub fn in_chunk(self, bytes: &[u8], base: usize, start: usize, index: usize) -> PngChunkError {
+        let relative = start.saturating_sub(base);
+        let length = bytes
+            .get(relative..relative + 4)
+            .and_then(|length| <[u8; 4]>::try_from(length).ok())
+            .map(u32::from_be_bytes);
+        let offset = match (self.kind(), length) {
+            (PngChunkError::Crc { .. }, Some(length)) => start + 8 + length as usize,
+            _ => start,
+        };
+        self.at(Location::new(bytes, base, offset, Some(index)))
+    }
+
+    /// The error without its location
+    pub fn kind(&self) -> &PngChunkError {
+        match self {
+            PngChunkError::At { error, .. } => error,
+            error => error,
+        }
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            PngChunkError::At { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PngChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngChunkError::Parse(message) | PngChunkError::Message(message) => {
+                write!(f, "{}", message)
+            }
+            PngChunkError::Crc {
+                chunk_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "CRC invalid for {} chunk: got {:#010x}, should be {:#010x}",
+                chunk_type, actual, expected
+            ),
+            PngChunkError::ChunkNotFound(chunk_type) => {
+                write!(f, "No {} chunk found", chunk_type)
+            }
+            PngChunkError::Io(e) => write!(f, "{}", e),
+            PngChunkError::Other(e) => write!(f, "{}", e),
+            PngChunkError::Status(status) => write!(f, "Exit status {}", status),
+            PngChunkError::At { error, location } => match location.chunk_index {
+                Some(index) => write!(
+                    f,
+                    "{} (chunk {} at offset {})",
+                    error, index, location.offset
+                ),
+                None => write!(f, "{} (at offset {})", error, location.offset),
+            },
+        }
+    }
+}
+
+impl std::error::Error for PngChunkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngChunkError::Io(e) => Some(e),
+            PngChunkError::Other(e) => Some(e.as_ref()),
+            PngChunkError::At { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for PngChunkError {
+    fn from(message: String) -> Self {
+        PngChunkError::Message(message)
+    }
+}
+
+impl From<&str> for PngChunkError {
+    fn from(message: &str) -> Self {
+        PngChunkError::Message(message.to_string())
+    }
+}
+
+impl From<io::Error> for PngChunkError {
+    fn from(e: io::Error) -> Self {
+        PngChunkError::Io(e)
+    }
+}
+
+/// Dependency errors that have no variant of their own are kept as `Other`
+macro_rules! other_from {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for PngChunkError {
+                fn from(e: $error) -> Self {
+                    PngChunkError::Other(Box::new(e))
+                }
+            }
+        )*
+    };
+}
+
+other_from!(
+    serde_json::Error,
+    std::string::FromUtf8Error,
+    std::str::Utf8Error,
+    std::num::ParseIntError,
+    std::num::ParseFloatError,
+    std::array::TryFromSliceError,
+    zip::result::ZipError,
+);
+#[cfg(not(target_os = "wasi"))]
+other_from!(ctrlc::Error);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, png::Png};
+
+    #[test]
+    fn test_error_variants() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend([0, 0, 0, 0, b'I', b'E', b'N', b'D', 0, 0, 0, 0]);
+        assert!(matches!(
+            Png::try_from(&bytes[..]).unwrap_err().kind(),
+            PngChunkError::Crc { .. }
+        ));
+        assert!(matches!(
+            Png::try_from(&bytes[..4]),
+            Err(PngChunkError::Parse(_))
+        ));
+        assert!(matches!(
+            Chunk::try_from(&bytes[8..12]),
+            Err(PngChunkError::Parse(_))
+        ));
+
+        let mut png = Png::from_chunks(vec![]);
+        assert!(matches!(
+            png.remove_chunk("tEXt"),
+            Err(PngChunkError::ChunkNotFound(chunk_type)) if chunk_type == "tEXt"
+        ));
+    }
+
+    #[test]
+    fn test_location() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend([0, 0, 0, 1, b'r', b'u', b'S', b't', b'x', 0, 0, 0, 0]);
+        let error = Png::try_from(&bytes[..]).unwrap_err();
+        let location = error.location().unwrap();
+        assert_eq!((location.offset, location.chunk_index), (17, Some(0)));
+        assert_eq!(location.context_start, 1);
+        assert_eq!(location.context, bytes[1..]);
+        assert!(error.to_string().ends_with("(chunk 0 at offset 17)"));
+
+        let snippet = location.snippet();
+        let lines: Vec<&str> = snippet.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("  0x00000000     50 4e 47"));
+        assert!(lines[1].starts_with("  0x00000010  78 00 00 00 00"));
+        assert_eq!(lines[2], format!("{}^^", " ".repeat(17)));
+
+        let truncated = Png::try_from(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(truncated.location().unwrap().offset, 8);
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        let io = PngChunkError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert!(matches!(io, PngChunkError::Io(_)));
+        assert!(std::error::Error::source(&io).is_some());
+
+        let message: PngChunkError = "bad things".into();
+        assert_eq!(message.to_string(), "bad things");
+    }
+}
@@ -0,0 +1,335 @@
+use core::fmt;
+use std::str::FromStr;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Result};
+
+/// The prefix of EXIF data in JPEG APP1 segments, which must not be repeated in eXIf
+const JPEG_PREFIX: &[u8] = b"Exif\0\0";
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+
+/// The tags of an eXIf chunk that are worth showing, read from the first IFD and the EXIF
+/// sub-IFD it points to. Every other tag is skipped.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExifTags {
+    pub big_endian: bool,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub software: Option<String>,
+    /// How the image should be rotated or flipped for display, 1 to 8
+    pub orientation: Option<u16>,
+    pub date_time: Option<String>,
+    pub date_time_original: Option<String>,
+    pub date_time_digitized: Option<String>,
+}
+
+impl ExifTags {
+    /// Parses the TIFF structure in `data`, the contents of an eXIf chunk
+    pub fn parse(data: &[u8]) -> Result<ExifTags> {
+        let tiff = Tiff::new(data)?;
+        let mut tags = ExifTags {
+            big_endian: tiff.big_endian,
+            ..ExifTags::default()
+        };
+        let mut exif_ifd = None;
+        for entry in tiff.ifd(tiff.u32(4)? as usize)? {
+            match entry.tag {
+                TAG_MAKE => tags.make = tiff.ascii(&entry),
+                TAG_MODEL => tags.model = tiff.ascii(&entry),
+                TAG_SOFTWARE => tags.software = tiff.ascii(&entry),
+                TAG_DATE_TIME => tags.date_time = tiff.ascii(&entry),
+                TAG_ORIENTATION if entry.field_type == 3 => {
+                    tags.orientation = Some(tiff.u16(entry.value_offset)?)
+                }
+                TAG_EXIF_IFD => exif_ifd = Some(tiff.u32(entry.value_offset)? as usize),
+                _ => {}
+            }
+        }
+        if let Some(offset) = exif_ifd {
+            for entry in tiff.ifd(offset)? {
+                match entry.tag {
+                    TAG_DATE_TIME_ORIGINAL => tags.date_time_original = tiff.ascii(&entry),
+                    TAG_DATE_TIME_DIGITIZED => tags.date_time_digitized = tiff.ascii(&entry),
+                    _ => {}
+                }
+            }
+        }
+        Ok(tags)
+    }
+}
+
+impl fmt::Display for ExifTags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EXIF ({})",
+            if self.big_endian {
+                "big-endian"
+            } else {
+                "little-endian"
+            }
+        )?;
+        let fields = [
+            ("Make", &self.make),
+            ("Model", &self.model),
+            ("Software", &self.software),
+            ("Date/time", &self.date_time),
+            ("Taken", &self.date_time_original),
+            ("Digitized", &self.date_time_digitized),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                write!(f, "\n{}: {}", name, value)?;
+            }
+        }
+        if let Some(orientation) = self.orientation {
+            write!(
+                f,
+                "\nOrientation: {} ({})",
+                orientation,
+                orientation_name(orientation)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn orientation_name(orientation: u16) -> &'static str {
+    match orientation {
+        1 => "normal",
+        2 => "flipped horizontally",
+        3 => "rotated 180°",
+        4 => "flipped vertically",
+        5 => "transposed",
+        6 => "rotated 90° clockwise",
+        7 => "transversed",
+        8 => "rotated 90° counterclockwise",
+        _ => "unknown",
+    }
+}
+
+/// An entry of an IFD. `value_offset` is where the value is, which is inside the entry itself
+/// for values of up to 4 bytes.
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value_offset: usize,
+}
+
+/// A TIFF structure, read in its own byte order. Offsets are from the start of the header.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Result<Tiff<'a>> {
+        let big_endian = match data.get(..4) {
+            Some(b"MM\0*") => true,
+            Some(b"II*\0") => false,
+            _ if data.starts_with(JPEG_PREFIX) => {
+                return Err("EXIF data starts with the JPEG 'Exif' prefix, which eXIf omits".into())
+            }
+            _ => return Err("EXIF data does not start with a TIFF header".into()),
+        };
+        Ok(Tiff { data, big_endian })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        offset
+            .checked_add(N)
+            .and_then(|end| self.data.get(offset..end))
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                format!("EXIF data ends before offset {}", offset.saturating_add(N)).into()
+            })
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        let bytes = self.bytes(offset)?;
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        let bytes = self.bytes(offset)?;
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn ifd(&self, offset: usize) -> Result<Vec<Entry>> {
+        let count = self.u16(offset)? as usize;
+        (0..count)
+            .map(|index| {
+                let start = offset + 2 + index * 12;
+                let (field_type, count) = (self.u16(start + 2)?, self.u32(start + 4)?);
+                let size = type_size(field_type).saturating_mul(count as usize);
+                Ok(Entry {
+                    tag: self.u16(start)?,
+                    field_type,
+                    count,
+                    value_offset: match size {
+                        0..=4 => start + 8,
+                        _ => self.u32(start + 8)? as usize,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// The value of an ASCII entry, without its null terminator, or `None` if the entry is of
+    /// another type or runs past the end of the data
+    fn ascii(&self, entry: &Entry) -> Option<String> {
+        if entry.field_type != 2 {
+            return None;
+        }
+        let end = entry.value_offset.checked_add(entry.count as usize)?;
+        let bytes = self.data.get(entry.value_offset..end)?;
+        let text = bytes.split(|&b| b == 0).next().unwrap_or_default();
+        Some(String::from_utf8_lossy(text).trim().to_string())
+    }
+}
+
+/// The size in bytes of one value of a TIFF field type
+fn type_size(field_type: u16) -> usize {
+    match field_type {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+/// Explains an eXIf chunk, or returns `None` for every other chunk
+pub fn describe(chunk: &Chunk) -> Option<String> {
+    if chunk.chunk_type().to_string() != "eXIf" {
+        return None;
+    }
+    Some(match ExifTags::parse(chunk.data()) {
+        Ok(tags) => tags.to_string(),
+        Err(e) => format!("malformed eXIf chunk: {}", e),
+    })
+}
+
+/// Embeds the EXIF data in `data` in `png`, replacing any existing eXIf chunk. A leading JPEG
+/// `Exif` prefix, as found in EXIF data taken from a JPEG, is dropped. The chunk goes just
+/// before IDAT, as the spec requires.
+pub fn embed(png: &Png, data: &[u8]) -> Result<Png> {
+    let data = data.strip_prefix(JPEG_PREFIX).unwrap_or(data);
+    ExifTags::parse(data)?;
+    let (png, _) = strip(png);
+    let mut chunks = png.chunks().to_vec();
+    let index = chunks
+        .iter()
+        .position(|c| c.chunk_type().to_string() == "IDAT")
+        .ok_or("Cannot embed EXIF data without an IDAT chunk")?;
+    chunks.insert(
+        index,
+        Chunk::new(ChunkType::from_str("eXIf")?, data.to_vec())?,
+    );
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Removes every eXIf chunk, returning the number removed
+pub fn strip(png: &Png) -> (Png, usize) {
+    let mut chunks = png.chunks().to_vec();
+    let before = chunks.len();
+    chunks.retain(|chunk| chunk.chunk_type().to_string() != "eXIf");
+    let removed = before - chunks.len();
+    (Png::from_chunks(chunks), removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    /// A little-endian TIFF with make, model and orientation in IFD0, and DateTimeOriginal in
+    /// the EXIF sub-IFD
+    fn testing_exif() -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        let entry = |tag: u16, field_type: u16, count: u32, value: u32| {
+            [
+                &tag.to_le_bytes()[..],
+                &field_type.to_le_bytes(),
+                &count.to_le_bytes(),
+                &value.to_le_bytes(),
+            ]
+            .concat()
+        };
+        // IFD0 at 8 with 4 entries ends at 8 + 2 + 48 + 4 = 62
+        data.extend(4u16.to_le_bytes());
+        data.extend(entry(TAG_MAKE, 2, 4, u32::from_le_bytes(*b"Foo\0")));
+        data.extend(entry(TAG_MODEL, 2, 8, 62));
+        data.extend(entry(TAG_ORIENTATION, 3, 1, 6));
+        data.extend(entry(TAG_EXIF_IFD, 4, 1, 70));
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"Model X\0");
+        // EXIF IFD at 70 with 1 entry ends at 70 + 2 + 12 + 4 = 88
+        data.extend(1u16.to_le_bytes());
+        data.extend(entry(TAG_DATE_TIME_ORIGINAL, 2, 20, 88));
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"2024:05:01 12:00:00\0");
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let tags = ExifTags::parse(&testing_exif()).unwrap();
+        assert_eq!(tags.make.as_deref(), Some("Foo"));
+        assert_eq!(tags.model.as_deref(), Some("Model X"));
+        assert_eq!(tags.orientation, Some(6));
+        assert_eq!(
+            tags.date_time_original.as_deref(),
+            Some("2024:05:01 12:00:00")
+        );
+        assert!(tags
+            .to_string()
+            .contains("Orientation: 6 (rotated 90° clockwise)"));
+
+        assert!(ExifTags::parse(b"II*\0").is_err());
+        assert!(ExifTags::parse(&testing_exif()[..60]).is_err());
+        assert!(ExifTags::parse(b"Exif\0\0II*\0").is_err());
+    }
+
+    #[test]
+    fn test_embed() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[1, 2, 3]),
+            chunk("IEND", &[]),
+        ]);
+        let prefixed = [JPEG_PREFIX, &testing_exif()].concat();
+        let png = embed(&png, &prefixed).unwrap();
+        let png = embed(&png, &testing_exif()).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "eXIf", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[1].data(), testing_exif());
+        assert!(describe(&png.chunks()[1])
+            .unwrap()
+            .contains("Model: Model X"));
+        assert!(embed(&png, b"not exif").is_err());
+
+        let (png, removed) = strip(&png);
+        assert_eq!(removed, 1);
+        assert_eq!(png.chunks().len(), 3);
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::text::{check_keyword, latin1, split_null, to_latin1};
+use crate::{chunk::Chunk, chunk_type::ChunkType, limits, png::Png, Error, Result};
+
+/// The contents of an iCCP chunk: a named ICC profile, stored zlib-compressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    /// Latin-1 name of the profile, following the same rules as text keywords
+    pub name: String,
+    /// The uncompressed ICC profile, as found in a `.icc` file
+    pub profile: Vec<u8>,
+}
+
+impl IccProfile {
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        check_keyword(&self.name)?;
+        let mut data = to_latin1(&self.name)?;
+        // Null separator, then compression method 0 (zlib)
+        data.extend([0, 0]);
+        let mut encoder = ZlibEncoder::new(data, Compression::default());
+        encoder.write_all(&self.profile)?;
+        Chunk::new(ChunkType::from_str("iCCP")?, encoder.finish()?)
+    }
+}
+
+impl TryFrom<&Chunk> for IccProfile {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "iCCP" {
+            return Err(format!("Expected an iCCP chunk, got {}", chunk.chunk_type()).into());
+        }
+        let (name, rest) =
+            split_null(chunk.data()).ok_or("iCCP chunk is missing its profile name")?;
+        match rest {
+            [0, compressed @ ..] => Ok(IccProfile {
+                name: latin1(name),
+                profile: limits::get().inflate(compressed)?,
+            }),
+            [method, ..] => Err(format!("Unsupported iCCP compression method {}", method).into()),
+            [] => Err("iCCP chunk is missing its compression method".into()),
+        }
+    }
+}
+
+/// The ICC profile embedded in `png`, if it has one
+pub fn extract(png: &Png) -> Result<Option<IccProfile>> {
+    png.chunk_by_type("iCCP")
+        .map(IccProfile::try_from)
+        .transpose()
+}
+
+/// Embeds `profile` in `png`, replacing any existing profile. An sRGB chunk would contradict the
+/// profile, so it is removed. The iCCP chunk goes just before PLTE or IDAT, as the spec requires.
+pub fn embed(png: &Png, profile: &IccProfile) -> Result<Png> {
+    let chunk = profile.to_chunk()?;
+    let mut chunks: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .filter(|c| !matches!(c.chunk_type().to_string().as_str(), "iCCP" | "sRGB"))
+        .cloned()
+        .collect();
+    let index = chunks
+        .iter()
+        .position(|c| matches!(c.chunk_type().to_string().as_str(), "PLTE" | "IDAT"))
+        .ok_or("Cannot embed a profile without an IDAT chunk")?;
+    chunks.insert(index, chunk);
+    Ok(Png::from_chunks(chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn testing_profile() -> IccProfile {
+        IccProfile {
+            name: "Display P3".to_string(),
+            profile: b"\0\0\x02\x30appl".repeat(50),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chunk = testing_profile().to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"Display P3\0\0"));
+        assert!(chunk.data().len() < testing_profile().profile.len());
+        assert_eq!(IccProfile::try_from(&chunk).unwrap(), testing_profile());
+
+        let unnamed = IccProfile {
+            name: String::new(),
+            profile: vec![1],
+        };
+        assert!(unnamed.to_chunk().is_err());
+
+        let bad_method = Chunk::new(ChunkType::from_str("iCCP").unwrap(), b"P3\0\x01".to_vec());
+        assert!(IccProfile::try_from(&bad_method.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_embed() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("sRGB", &[0]),
+            chunk("IDAT", &[1, 2, 3]),
+            chunk("IEND", &[]),
+        ]);
+        assert_eq!(extract(&png).unwrap(), None);
+
+        let png = embed(&png, &testing_profile()).unwrap();
+        let other = IccProfile {
+            name: "Other".to_string(),
+            profile: vec![1, 2, 3],
+        };
+        let png = embed(&png, &other).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "iCCP", "IDAT", "IEND"]);
+        assert_eq!(extract(&png).unwrap(), Some(other));
+    }
+}
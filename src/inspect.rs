@@ -0,0 +1,419 @@
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    known_chunks::Ihdr,
+    limits::{self, Limits},
+    png::Png,
+    validate::{self, Finding, Policy},
+};
+
+/// Something `Inspector` has learned about a file, reported as soon as the bytes that decide it
+/// have arrived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The file starts with the PNG signature
+    SignatureOk,
+    /// The IHDR chunk has been read and is valid
+    Header(Ihdr),
+    /// An advisory rule of the policy was broken. The file is still well formed, so it is up to
+    /// the caller whether to reject it.
+    PolicyViolation(Finding),
+    /// The file is malformed or breaks the limits. Nothing more is read after this.
+    Rejected(Finding),
+    /// IEND has been read and every chunk was well formed
+    Complete,
+}
+
+/// Checks a PNG as it arrives, for services that want to turn away bad uploads before they
+/// have been received in full. Bytes are fed in with `push` in pieces of any size, and only the
+/// chunk header being read is buffered, so memory use does not grow with the file. Chunk data
+/// is checked against its CRC but otherwise not kept, except for IHDR.
+#[derive(Debug)]
+pub struct Inspector {
+    policy: Policy,
+    limits: Limits,
+    state: State,
+    /// Bytes of the signature, chunk header or CRC being read
+    pending: Vec<u8>,
+    /// Total bytes pushed
+    offset: usize,
+    chunks: usize,
+    text_chunks: usize,
+    idat_size: usize,
+    /// Offset, type and size of every private chunk
+    private: Vec<(usize, ChunkType, usize)>,
+}
+
+#[derive(Debug)]
+enum State {
+    Signature,
+    Header,
+    Data(Current),
+    Crc(Current),
+    Complete,
+    Rejected,
+}
+
+/// The chunk being read
+#[derive(Debug)]
+struct Current {
+    offset: usize,
+    chunk_type: ChunkType,
+    length: usize,
+    read: usize,
+    crc: flate2::Crc,
+    /// The data, for the chunks that are parsed
+    data: Vec<u8>,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Inspector::new(Policy::default())
+    }
+}
+
+impl Inspector {
+    /// An inspector applying `policy` and the limits in effect
+    pub fn new(policy: Policy) -> Inspector {
+        Inspector {
+            policy,
+            limits: limits::get(),
+            state: State::Signature,
+            pending: vec![],
+            offset: 0,
+            chunks: 0,
+            text_chunks: 0,
+            idat_size: 0,
+            private: vec![],
+        }
+    }
+
+    /// Whether a `Verdict::Rejected` has been given
+    pub fn is_rejected(&self) -> bool {
+        matches!(self.state, State::Rejected)
+    }
+
+    /// Whether IEND has been read
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Complete)
+    }
+
+    /// Reads the next piece of the file, returning the verdicts it settled
+    pub fn push(&mut self, mut bytes: &[u8]) -> Vec<Verdict> {
+        let mut verdicts = vec![];
+        if let Err(finding) = self.limits.check_file_size(self.offset + bytes.len()) {
+            self.reject(&mut verdicts, self.offset, finding.to_string());
+            return verdicts;
+        }
+        while !bytes.is_empty() {
+            match &mut self.state {
+                State::Rejected => break,
+                State::Complete => {
+                    let message = format!("{} bytes of data after IEND", bytes.len());
+                    self.reject(&mut verdicts, self.offset, message);
+                    break;
+                }
+                State::Signature => {
+                    if !self.fill(&mut bytes, Png::STANDARD_HEADER.len()) {
+                        break;
+                    }
+                    if self.pending[..] != Png::STANDARD_HEADER {
+                        self.reject(&mut verdicts, 0, "not a PNG signature".to_string());
+                        break;
+                    }
+                    self.pending.clear();
+                    self.state = State::Header;
+                    verdicts.push(Verdict::SignatureOk);
+                }
+                State::Header => {
+                    if !self.fill(&mut bytes, 8) {
+                        break;
+                    }
+                    let offset = self.offset - 8;
+                    if let Err(message) = self.start_chunk(offset, &mut verdicts) {
+                        self.reject(&mut verdicts, offset, message);
+                    }
+                    self.pending.clear();
+                }
+                State::Data(current) => {
+                    let take = bytes.len().min(current.length - current.read);
+                    current.crc.update(&bytes[..take]);
+                    if current.chunk_type.to_string() == "IHDR" {
+                        current.data.extend(&bytes[..take]);
+                    }
+                    current.read += take;
+                    self.offset += take;
+                    bytes = &bytes[take..];
+                    if current.read == current.length {
+                        if let State::Data(current) =
+                            std::mem::replace(&mut self.state, State::Header)
+                        {
+                            self.state = State::Crc(current);
+                        }
+                    }
+                }
+                State::Crc(_) => {
+                    if !self.fill(&mut bytes, 4) {
+                        break;
+                    }
+                    let stored = u32::from_be_bytes([
+                        self.pending[0],
+                        self.pending[1],
+                        self.pending[2],
+                        self.pending[3],
+                    ]);
+                    self.pending.clear();
+                    if let State::Crc(current) = std::mem::replace(&mut self.state, State::Header) {
+                        let offset = current.offset;
+                        if let Err(message) = self.finish_chunk(current, stored, &mut verdicts) {
+                            self.reject(&mut verdicts, offset, message);
+                        }
+                    }
+                }
+            }
+        }
+        verdicts
+    }
+
+    /// Ends the upload, rejecting the file if it stopped before IEND
+    pub fn finish(&mut self) -> Vec<Verdict> {
+        let mut verdicts = vec![];
+        if !matches!(self.state, State::Complete | State::Rejected) {
+            self.reject(
+                &mut verdicts,
+                self.offset,
+                "file ends before IEND".to_string(),
+            );
+        }
+        verdicts
+    }
+
+    /// Moves bytes into `pending` until it holds `n`, returning whether it does
+    fn fill(&mut self, bytes: &mut &[u8], n: usize) -> bool {
+        let take = bytes.len().min(n - self.pending.len());
+        self.pending.extend(&bytes[..take]);
+        self.offset += take;
+        *bytes = &bytes[take..];
+        self.pending.len() == n
+    }
+
+    fn reject(&mut self, verdicts: &mut Vec<Verdict>, offset: usize, message: String) {
+        self.state = State::Rejected;
+        verdicts.push(Verdict::Rejected(Finding { offset, message }));
+    }
+
+    /// Checks the chunk header in `pending` and starts reading its data
+    fn start_chunk(
+        &mut self,
+        offset: usize,
+        verdicts: &mut Vec<Verdict>,
+    ) -> std::result::Result<(), String> {
+        let length = u32::from_be_bytes([
+            self.pending[0],
+            self.pending[1],
+            self.pending[2],
+            self.pending[3],
+        ]) as usize;
+        let type_bytes = [
+            self.pending[4],
+            self.pending[5],
+            self.pending[6],
+            self.pending[7],
+        ];
+        if !type_bytes.iter().all(u8::is_ascii_alphabetic) {
+            return Err(format!("invalid chunk type {:02x?}", type_bytes));
+        }
+        let chunk_type = ChunkType::new(type_bytes);
+        let name = chunk_type.to_string();
+        if length > Chunk::MAX_DATA_LENGTH {
+            return Err(format!(
+                "{} chunk declares {} bytes of data, more than the PNG spec allows",
+                name, length
+            ));
+        }
+        self.chunks += 1;
+        self.limits
+            .check_chunk_count(self.chunks)
+            .map_err(|e| e.to_string())?;
+        if self.chunks == 1 && name != "IHDR" {
+            return Err(format!("first chunk is {}, not IHDR", name));
+        }
+        if self.chunks > 1 && name == "IHDR" {
+            return Err("more than one IHDR chunk".to_string());
+        }
+        if name == "IHDR" && length != Ihdr::LENGTH {
+            return Err(format!("IHDR must be 13 bytes long, got {}", length));
+        }
+
+        if validate::is_text(&chunk_type) {
+            self.text_chunks += 1;
+        }
+        for message in self
+            .policy
+            .check_chunk(&chunk_type, length, self.text_chunks)
+        {
+            verdicts.push(Verdict::PolicyViolation(Finding { offset, message }));
+        }
+        if name == "IDAT" {
+            self.idat_size += length;
+        }
+        if !chunk_type.is_public() {
+            self.private.push((offset, chunk_type.clone(), length));
+        }
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&type_bytes);
+        self.state = State::Data(Current {
+            offset,
+            chunk_type,
+            length,
+            read: 0,
+            crc,
+            data: vec![],
+        });
+        Ok(())
+    }
+
+    /// Checks the stored CRC of a chunk that has been read in full, and anything else that
+    /// needed its data
+    fn finish_chunk(
+        &mut self,
+        current: Current,
+        stored: u32,
+        verdicts: &mut Vec<Verdict>,
+    ) -> std::result::Result<(), String> {
+        let name = current.chunk_type.to_string();
+        if stored != current.crc.sum() {
+            return Err(format!("CRC of {} chunk does not match its data", name));
+        }
+        match name.as_str() {
+            "IHDR" => {
+                let ihdr = Ihdr::parse(&current.data).map_err(|e| e.to_string())?;
+                self.limits
+                    .check_pixels(ihdr.width, ihdr.height)
+                    .map_err(|e| e.to_string())?;
+                verdicts.push(Verdict::Header(ihdr));
+            }
+            "IEND" => {
+                if self.idat_size == 0 {
+                    return Err("no IDAT chunk".to_string());
+                }
+                for (offset, chunk_type, size) in self.private.iter() {
+                    if let Some(message) =
+                        self.policy.check_private(chunk_type, *size, self.idat_size)
+                    {
+                        verdicts.push(Verdict::PolicyViolation(Finding {
+                            offset: *offset,
+                            message,
+                        }));
+                    }
+                }
+                self.state = State::Complete;
+                verdicts.push(Verdict::Complete);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const IHDR: [u8; 13] = [0, 0, 0, 2, 0, 0, 0, 2, 8, 0, 0, 0, 0];
+
+    fn png(extra: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut chunks = vec![("IHDR", &IHDR[..])];
+        chunks.extend(extra);
+        chunks.extend([("IDAT", &[0; 8][..]), ("IEND", &[])]);
+        testing::png_from(&chunks).as_bytes()
+    }
+
+    /// Pushes `bytes` one at a time, collecting every verdict
+    fn inspect(bytes: &[u8]) -> Vec<Verdict> {
+        let mut inspector = Inspector::default();
+        let mut verdicts: Vec<Verdict> = bytes
+            .iter()
+            .flat_map(|byte| inspector.push(&[*byte]))
+            .collect();
+        verdicts.extend(inspector.finish());
+        verdicts
+    }
+
+    #[test]
+    fn test_inspect_valid() {
+        let bytes = png(&[("tEXt", b"Title\0x")]);
+        let verdicts = inspect(&bytes);
+        assert_eq!(verdicts.len(), 3);
+        assert_eq!(verdicts[0], Verdict::SignatureOk);
+        assert!(matches!(
+            verdicts[1],
+            Verdict::Header(Ihdr { width: 2, .. })
+        ));
+        assert_eq!(verdicts[2], Verdict::Complete);
+
+        let mut inspector = Inspector::default();
+        assert_eq!(inspector.push(&bytes).len(), 3);
+        assert!(inspector.is_complete());
+        assert!(inspector.finish().is_empty());
+    }
+
+    #[test]
+    fn test_inspect_early_rejection() {
+        let bytes = png(&[]);
+        let mut inspector = Inspector::default();
+        let verdicts = inspector.push(&bytes[..40]);
+        assert!(matches!(
+            verdicts[..],
+            [Verdict::SignatureOk, Verdict::Header(_)]
+        ));
+        assert!(!inspector.is_complete());
+
+        let mut corrupt = bytes.clone();
+        corrupt[20] ^= 1;
+        let mut inspector = Inspector::default();
+        let verdicts = inspector.push(&corrupt[..40]);
+        assert!(
+            matches!(&verdicts[..], [Verdict::SignatureOk, Verdict::Rejected(f)] if f.offset == 8)
+        );
+        assert!(inspector.is_rejected());
+        assert!(inspector.push(&corrupt[40..]).is_empty());
+
+        assert!(matches!(
+            Inspector::default().push(b"GIF89a\0\0")[..],
+            [Verdict::Rejected(_)]
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            inspect(&trailing).last(),
+            Some(Verdict::Rejected(_))
+        ));
+        assert!(matches!(
+            inspect(&bytes[..bytes.len() - 1]).last(),
+            Some(Verdict::Rejected(f)) if f.message == "file ends before IEND"
+        ));
+    }
+
+    #[test]
+    fn test_inspect_policy() {
+        let bytes = png(&[("tEXt", b""), ("ruSt", &[0; 9])]);
+        let violations: Vec<String> = inspect(&bytes)
+            .into_iter()
+            .filter_map(|verdict| match verdict {
+                Verdict::PolicyViolation(finding) => Some(finding.message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                "tEXt chunk is empty",
+                "private ruSt chunk (9 bytes) is larger than the image data (8 bytes)"
+            ]
+        );
+    }
+}
@@ -0,0 +1,713 @@
+use core::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{chunk::Chunk, raster::ColorType, Error, Result};
+
+/// The contents of the IHDR chunk, with every field the spec defines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0, deflate
+    pub compression_method: u8,
+    /// Always 0, adaptive filtering with the five basic filter types
+    pub filter_method: u8,
+    /// 0 for none or 1 for Adam7
+    pub interlace_method: u8,
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+    /// The largest width or height the spec allows, so dimensions fit a signed 32-bit integer
+    pub const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+    /// Checks that an image is neither empty nor larger than the spec allows
+    pub fn check_size(width: u32, height: u32) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Err(format!("Image size {}x{} is empty", width, height).into());
+        }
+        if width > Ihdr::MAX_DIMENSION || height > Ihdr::MAX_DIMENSION {
+            return Err(format!(
+                "Image size {}x{} is larger than the {} pixels the spec allows in each direction",
+                width,
+                height,
+                Ihdr::MAX_DIMENSION
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Ihdr> {
+        if data.len() != Ihdr::LENGTH {
+            return Err(format!("IHDR must be 13 bytes long, got {}", data.len()).into());
+        }
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: ColorType::from_byte(data[9])?,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: data[12],
+        };
+
+        Ihdr::check_size(ihdr.width, ihdr.height)?;
+        let valid_depths: &[u8] = match ihdr.color_type {
+            ColorType::Gray => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            _ => &[8, 16],
+        };
+        if !valid_depths.contains(&ihdr.bit_depth) {
+            return Err(format!(
+                "Bit depth {} is not allowed for color type {}",
+                ihdr.bit_depth,
+                ihdr.color_type.as_byte()
+            )
+            .into());
+        }
+        if ihdr.compression_method != 0 {
+            return Err(format!("Unknown compression method {}", ihdr.compression_method).into());
+        }
+        if ihdr.filter_method != 0 {
+            return Err(format!("Unknown filter method {}", ihdr.filter_method).into());
+        }
+        if ihdr.interlace_method > 1 {
+            return Err(format!("Unknown interlace method {}", ihdr.interlace_method).into());
+        }
+        Ok(ihdr)
+    }
+
+    pub fn color_type_name(&self) -> &'static str {
+        match self.color_type {
+            ColorType::Gray => "grayscale",
+            ColorType::Rgb => "truecolor",
+            ColorType::Indexed => "indexed color",
+            ColorType::GrayAlpha => "grayscale with alpha",
+            ColorType::Rgba => "truecolor with alpha",
+        }
+    }
+
+    pub fn interlace_name(&self) -> &'static str {
+        match self.interlace_method {
+            0 => "none",
+            _ => "Adam7",
+        }
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Width:              {}", self.width)?;
+        writeln!(f, "Height:             {}", self.height)?;
+        writeln!(f, "Bit depth:          {}", self.bit_depth)?;
+        writeln!(
+            f,
+            "Color type:         {} ({})",
+            self.color_type.as_byte(),
+            self.color_type_name()
+        )?;
+        writeln!(
+            f,
+            "Compression method: {} (deflate)",
+            self.compression_method
+        )?;
+        writeln!(f, "Filter method:      {} (adaptive)", self.filter_method)?;
+        write!(
+            f,
+            "Interlace method:   {} ({})",
+            self.interlace_method,
+            self.interlace_name()
+        )
+    }
+}
+
+/// The contents of the pHYs chunk: the intended pixel size or aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phys {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    /// 0 if only the aspect ratio is known, 1 for pixels per meter
+    pub unit: u8,
+}
+
+impl Phys {
+    pub const LENGTH: usize = 9;
+    const METERS_PER_INCH: f64 = 0.0254;
+
+    pub fn parse(data: &[u8]) -> Result<Phys> {
+        if data.len() != Phys::LENGTH {
+            return Err(format!("pHYs must be 9 bytes long, got {}", data.len()).into());
+        }
+        let phys = Phys {
+            pixels_per_unit_x: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            pixels_per_unit_y: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            unit: data[8],
+        };
+        if phys.unit > 1 {
+            return Err(format!("Unknown pHYs unit {}", phys.unit).into());
+        }
+        Ok(phys)
+    }
+
+    /// Pixels per meter for the given resolution in dots per inch, rounded to the nearest
+    /// whole number as the chunk cannot store fractions
+    pub fn from_dpi(dpi_x: f64, dpi_y: f64) -> Result<Phys> {
+        let to_meters = |dpi: f64| -> Result<u32> {
+            let pixels = (dpi / Phys::METERS_PER_INCH).round();
+            if !(1.0..=i32::MAX as f64).contains(&pixels) {
+                return Err(format!("{} DPI is out of range", dpi).into());
+            }
+            Ok(pixels as u32)
+        };
+        Ok(Phys {
+            pixels_per_unit_x: to_meters(dpi_x)?,
+            pixels_per_unit_y: to_meters(dpi_y)?,
+            unit: 1,
+        })
+    }
+
+    /// The resolution in dots per inch, or `None` if the unit is unknown
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        let to_dpi = |pixels: u32| pixels as f64 * Phys::METERS_PER_INCH;
+        (self.unit == 1).then(|| {
+            (
+                to_dpi(self.pixels_per_unit_x),
+                to_dpi(self.pixels_per_unit_y),
+            )
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.pixels_per_unit_x.to_be_bytes().to_vec();
+        data.extend(self.pixels_per_unit_y.to_be_bytes());
+        data.push(self.unit);
+        data
+    }
+}
+
+impl fmt::Display for Phys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dpi() {
+            Some((x, _)) if self.pixels_per_unit_x == self.pixels_per_unit_y => {
+                write!(
+                    f,
+                    "{:.0} DPI ({} pixels per meter)",
+                    x, self.pixels_per_unit_x
+                )
+            }
+            Some((x, y)) => write!(
+                f,
+                "{:.0}x{:.0} DPI ({}x{} pixels per meter)",
+                x, y, self.pixels_per_unit_x, self.pixels_per_unit_y
+            ),
+            None => write!(
+                f,
+                "Aspect ratio {}:{}, unit unknown",
+                self.pixels_per_unit_x, self.pixels_per_unit_y
+            ),
+        }
+    }
+}
+
+/// The contents of the tIME chunk: when the image was last modified, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeChunk {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60, to allow for leap seconds
+    pub second: u8,
+}
+
+impl TimeChunk {
+    pub const LENGTH: usize = 7;
+
+    pub fn parse(data: &[u8]) -> Result<TimeChunk> {
+        if data.len() != TimeChunk::LENGTH {
+            return Err(format!("tIME must be 7 bytes long, got {}", data.len()).into());
+        }
+        let time = TimeChunk {
+            year: u16::from_be_bytes([data[0], data[1]]),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            second: data[6],
+        };
+        time.check()?;
+        Ok(time)
+    }
+
+    /// The current time, from the system clock
+    pub fn now() -> Result<TimeChunk> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| "The system clock is set before 1970")?
+            .as_secs();
+        TimeChunk::from_unix(seconds)
+    }
+
+    /// The time `seconds` after the Unix epoch
+    pub fn from_unix(seconds: u64) -> Result<TimeChunk> {
+        let (days, rest) = (seconds / 86400, seconds % 86400);
+        // Howard Hinnant's civil_from_days, shifted so years start in March
+        let days = days as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Ok(TimeChunk {
+            year: u16::try_from(year).map_err(|_| format!("Year {} is out of range", year))?,
+            month: month as u8,
+            day: day as u8,
+            hour: (rest / 3600) as u8,
+            minute: (rest % 3600 / 60) as u8,
+            second: (rest % 60) as u8,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.year.to_be_bytes().to_vec();
+        data.extend([self.month, self.day, self.hour, self.minute, self.second]);
+        data
+    }
+
+    fn check(&self) -> Result<()> {
+        let leap = self.year.is_multiple_of(4)
+            && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400));
+        let days_in_month = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return Err(format!("Month {} is out of range", self.month).into()),
+        };
+        if self.day == 0 || self.day > days_in_month {
+            return Err(
+                format!("Day {} is out of range for month {}", self.day, self.month).into(),
+            );
+        }
+        if self.hour > 23 || self.minute > 59 || self.second > 60 {
+            return Err(format!(
+                "Time {:02}:{:02}:{:02} is out of range",
+                self.hour, self.minute, self.second
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TimeChunk {
+    type Err = Error;
+
+    /// Parses a UTC time like `2024-01-02T03:04:05Z`. A space may separate the date and time,
+    /// and the `Z` may be left out, but other time zones are not accepted.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || -> Error {
+            format!(
+                "Invalid time '{}', expected a UTC time like 2024-01-02T03:04:05Z",
+                s
+            )
+            .into()
+        };
+        let text = s.strip_suffix('Z').unwrap_or(s);
+        let bytes = text.as_bytes();
+        if bytes.len() != 19
+            || !text.is_ascii()
+            || [4, 7].iter().any(|&i| bytes[i] != b'-')
+            || ![b'T', b' '].contains(&bytes[10])
+            || [13, 16].iter().any(|&i| bytes[i] != b':')
+        {
+            return Err(invalid());
+        }
+        let field = |range: std::ops::Range<usize>| -> Result<u16> {
+            let digits = &text[range];
+            match digits.bytes().all(|b| b.is_ascii_digit()) {
+                true => digits.parse().map_err(|_| invalid()),
+                false => Err(invalid()),
+            }
+        };
+        let time = TimeChunk {
+            year: field(0..4)?,
+            month: field(5..7)? as u8,
+            day: field(8..10)? as u8,
+            hour: field(11..13)? as u8,
+            minute: field(14..16)? as u8,
+            second: field(17..19)? as u8,
+        };
+        time.check()?;
+        Ok(time)
+    }
+}
+
+impl fmt::Display for TimeChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Gamma and chromaticity values are stored as integers, 100000 times the actual value
+const COLOR_SCALE: f64 = 100000.0;
+
+fn scaled(value: f64, name: &str) -> Result<u32> {
+    let scaled = (value * COLOR_SCALE).round();
+    if !(0.0..=i32::MAX as f64).contains(&scaled) {
+        return Err(format!("{} {} is out of range", name, value).into());
+    }
+    Ok(scaled as u32)
+}
+
+fn read_u32s<const N: usize>(data: &[u8], chunk_type: &str) -> Result<[u32; N]> {
+    if data.len() != N * 4 {
+        return Err(format!(
+            "{} must be {} bytes long, got {}",
+            chunk_type,
+            N * 4,
+            data.len()
+        )
+        .into());
+    }
+    let mut values = [0; N];
+    for (value, bytes) in values.iter_mut().zip(data.chunks_exact(4)) {
+        *value = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    Ok(values)
+}
+
+/// The contents of the gAMA chunk: the gamma the image was encoded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gama {
+    /// 100000 times the gamma, e.g. 45455 for 1/2.2
+    pub gamma: u32,
+}
+
+impl Gama {
+    pub fn parse(data: &[u8]) -> Result<Gama> {
+        let [gamma] = read_u32s(data, "gAMA")?;
+        if gamma == 0 {
+            return Err("gAMA must not be 0".into());
+        }
+        Ok(Gama { gamma })
+    }
+
+    pub fn from_gamma(gamma: f64) -> Result<Gama> {
+        match scaled(gamma, "Gamma")? {
+            0 => Err(format!("Gamma {} is out of range", gamma).into()),
+            gamma => Ok(Gama { gamma }),
+        }
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma as f64 / COLOR_SCALE
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.gamma.to_be_bytes().to_vec()
+    }
+}
+
+impl fmt::Display for Gama {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gamma {:.5} (1/{:.2})", self.gamma(), 1.0 / self.gamma())
+    }
+}
+
+/// The contents of the cHRM chunk: the CIE 1931 x,y chromaticities of the white point and the
+/// red, green and blue primaries, each 100000 times the actual value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chrm {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+impl Chrm {
+    /// The chromaticities of sRGB, which the spec recommends writing along with an sRGB chunk
+    pub const SRGB: Chrm = Chrm {
+        white: (31270, 32900),
+        red: (64000, 33000),
+        green: (30000, 60000),
+        blue: (15000, 6000),
+    };
+
+    pub fn parse(data: &[u8]) -> Result<Chrm> {
+        let v = read_u32s::<8>(data, "cHRM")?;
+        Ok(Chrm {
+            white: (v[0], v[1]),
+            red: (v[2], v[3]),
+            green: (v[4], v[5]),
+            blue: (v[6], v[7]),
+        })
+    }
+
+    /// Builds the chunk from the actual values: white x and y, then red, green and blue
+    pub fn from_values(values: &[f64]) -> Result<Chrm> {
+        let [wx, wy, rx, ry, gx, gy, bx, by] = values else {
+            return Err(format!("cHRM takes 8 values, got {}", values.len()).into());
+        };
+        let point = |x: f64, y: f64| -> Result<(u32, u32)> {
+            Ok((scaled(x, "Chromaticity")?, scaled(y, "Chromaticity")?))
+        };
+        Ok(Chrm {
+            white: point(*wx, *wy)?,
+            red: point(*rx, *ry)?,
+            green: point(*gx, *gy)?,
+            blue: point(*bx, *by)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flat_map(|(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+            .flatten()
+            .collect()
+    }
+}
+
+impl fmt::Display for Chrm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let point = |(x, y): (u32, u32)| {
+            format!(
+                "({:.5}, {:.5})",
+                x as f64 / COLOR_SCALE,
+                y as f64 / COLOR_SCALE
+            )
+        };
+        write!(
+            f,
+            "White {}, red {}, green {}, blue {}",
+            point(self.white),
+            point(self.red),
+            point(self.green),
+            point(self.blue)
+        )?;
+        if *self == Chrm::SRGB {
+            write!(f, " (sRGB)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of the sRGB chunk: the image is in the sRGB color space, to be rendered with
+/// the given intent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Srgb {
+    /// 0 perceptual, 1 relative colorimetric, 2 saturation or 3 absolute colorimetric
+    pub rendering_intent: u8,
+}
+
+impl Srgb {
+    pub const INTENTS: [&'static str; 4] = ["perceptual", "relative", "saturation", "absolute"];
+
+    pub fn parse(data: &[u8]) -> Result<Srgb> {
+        match data {
+            [intent @ 0..=3] => Ok(Srgb {
+                rendering_intent: *intent,
+            }),
+            [intent] => Err(format!("Unknown sRGB rendering intent {}", intent).into()),
+            _ => Err(format!("sRGB must be 1 byte long, got {}", data.len()).into()),
+        }
+    }
+
+    pub fn intent_name(&self) -> &'static str {
+        Srgb::INTENTS[self.rendering_intent as usize]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![self.rendering_intent]
+    }
+}
+
+impl FromStr for Srgb {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match Srgb::INTENTS.iter().position(|intent| *intent == s) {
+            Some(intent) => Ok(Srgb {
+                rendering_intent: intent as u8,
+            }),
+            None => Err(format!(
+                "Unknown rendering intent '{}', expected perceptual, relative, saturation or absolute",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for Srgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sRGB, {} rendering intent ({})",
+            self.intent_name(),
+            self.rendering_intent
+        )
+    }
+}
+
+/// Explains the chunks with a typed parser here, or returns `None` for every other chunk
+pub fn describe(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    let chunk_type = chunk.chunk_type().to_string();
+    let described = match chunk_type.as_str() {
+        "gAMA" => Gama::parse(data).map(|c| c.to_string()),
+        "cHRM" => Chrm::parse(data).map(|c| c.to_string()),
+        "sRGB" => Srgb::parse(data).map(|c| c.to_string()),
+        "pHYs" => Phys::parse(data).map(|c| c.to_string()),
+        "tIME" => TimeChunk::parse(data).map(|c| c.to_string()),
+        _ => return None,
+    };
+    Some(described.unwrap_or_else(|e| format!("malformed {} chunk: {}", chunk_type, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IHDR: [u8; 13] = [0, 0, 1, 0, 0, 0, 0, 200, 8, 6, 0, 0, 1];
+
+    #[test]
+    fn test_parse_ihdr() {
+        let ihdr = Ihdr::parse(&IHDR).unwrap();
+        assert_eq!(ihdr.width, 256);
+        assert_eq!(ihdr.height, 200);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.interlace_method, 1);
+        assert_eq!(ihdr.color_type_name(), "truecolor with alpha");
+        assert_eq!(ihdr.interlace_name(), "Adam7");
+    }
+
+    #[test]
+    fn test_ihdr_display() {
+        let text = Ihdr::parse(&IHDR).unwrap().to_string();
+        assert!(text.contains("Width:              256\n"));
+        assert!(text.contains("Color type:         6 (truecolor with alpha)\n"));
+        assert!(text.ends_with("Interlace method:   1 (Adam7)"));
+    }
+
+    #[test]
+    fn test_phys() {
+        let phys = Phys::from_dpi(300.0, 300.0).unwrap();
+        assert_eq!(phys.pixels_per_unit_x, 11811);
+        assert_eq!(phys.to_string(), "300 DPI (11811 pixels per meter)");
+        assert_eq!(Phys::parse(&phys.to_bytes()).unwrap(), phys);
+
+        let (x, y) = Phys::from_dpi(72.0, 144.0).unwrap().dpi().unwrap();
+        assert_eq!((x.round(), y.round()), (72.0, 144.0));
+        let ratio = Phys::parse(&[0, 0, 0, 2, 0, 0, 0, 1, 0]).unwrap();
+        assert_eq!(ratio.dpi(), None);
+        assert_eq!(ratio.to_string(), "Aspect ratio 2:1, unit unknown");
+
+        assert!(Phys::from_dpi(0.0, 300.0).is_err());
+        assert!(Phys::parse(&[0; 8]).is_err());
+        assert!(Phys::parse(&[0, 0, 0, 1, 0, 0, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        let time = TimeChunk::from_str("2024-01-02T03:04:05Z").unwrap();
+        assert_eq!(time.to_bytes(), [0x07, 0xe8, 1, 2, 3, 4, 5]);
+        assert_eq!(TimeChunk::parse(&time.to_bytes()).unwrap(), time);
+        assert_eq!(time.to_string(), "2024-01-02T03:04:05Z");
+        assert_eq!(TimeChunk::from_str("2024-01-02 03:04:05").unwrap(), time);
+
+        assert!(TimeChunk::from_str("2024-02-29T00:00:00Z").is_ok());
+        assert!(TimeChunk::from_str("2023-02-29T00:00:00Z").is_err());
+        assert!(TimeChunk::from_str("2024-01-02T24:00:00Z").is_err());
+        assert!(TimeChunk::from_str("2024-01-02T03:04:05+01:00").is_err());
+        assert!(TimeChunk::from_str("2024-1-2T03:04:05Z").is_err());
+        assert!(TimeChunk::parse(&[0x07, 0xe8, 13, 1, 0, 0, 0]).is_err());
+        assert!(TimeChunk::parse(&[0x07, 0xe8, 1, 1, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_time_from_unix() {
+        assert_eq!(
+            TimeChunk::from_unix(0).unwrap().to_string(),
+            "1970-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            TimeChunk::from_unix(1_709_210_096).unwrap().to_string(),
+            "2024-02-29T12:34:56Z"
+        );
+        assert!(TimeChunk::now().unwrap().year >= 2024);
+    }
+
+    #[test]
+    fn test_gama() {
+        let gama = Gama::from_gamma(1.0 / 2.2).unwrap();
+        assert_eq!(gama.gamma, 45455);
+        assert_eq!(gama.to_string(), "Gamma 0.45455 (1/2.20)");
+        assert_eq!(Gama::parse(&gama.to_bytes()).unwrap(), gama);
+        assert!(Gama::parse(&[0; 4]).is_err());
+        assert!(Gama::parse(&[0; 3]).is_err());
+        assert!(Gama::from_gamma(-1.0).is_err());
+    }
+
+    #[test]
+    fn test_chrm() {
+        let values = [0.3127, 0.329, 0.64, 0.33, 0.3, 0.6, 0.15, 0.06];
+        let chrm = Chrm::from_values(&values).unwrap();
+        assert_eq!(chrm, Chrm::SRGB);
+        assert_eq!(Chrm::parse(&chrm.to_bytes()).unwrap(), chrm);
+        assert!(chrm
+            .to_string()
+            .starts_with("White (0.31270, 0.32900), red"));
+        assert!(chrm.to_string().ends_with("(sRGB)"));
+        assert!(Chrm::from_values(&values[..7]).is_err());
+        assert!(Chrm::parse(&[0; 31]).is_err());
+    }
+
+    #[test]
+    fn test_srgb() {
+        let srgb = Srgb::from_str("relative").unwrap();
+        assert_eq!(srgb.to_bytes(), [1]);
+        assert_eq!(
+            Srgb::parse(&[0]).unwrap().to_string(),
+            "sRGB, perceptual rendering intent (0)"
+        );
+        assert!(Srgb::parse(&[4]).is_err());
+        assert!(Srgb::parse(&[]).is_err());
+        assert!(Srgb::from_str("vivid").is_err());
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        assert!(Ihdr::parse(&IHDR[..12]).is_err());
+        let invalid = |index: usize, value: u8| {
+            let mut data = IHDR;
+            data[index] = value;
+            Ihdr::parse(&data).is_err()
+        };
+        assert!(invalid(2, 0));
+        assert!(invalid(8, 4));
+        assert!(invalid(9, 5));
+        assert!(invalid(10, 1));
+        assert!(invalid(11, 1));
+        assert!(invalid(12, 2));
+        assert!(invalid(0, 0x80));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::{png::Png, text::canonicalize_language_tag, text::InternationalTextChunk, Result};
+
+/// Version of the JSON bundle written by `export`
+pub const BUNDLE_VERSION: u64 = 1;
+
+/// Keyword -> language tag -> text
+pub type Strings = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Collects the localized strings of `png` from its iTXt chunks. Chunks without a language tag
+/// are not localized and are skipped. Identical duplicates are merged, conflicting ones are an
+/// error. Language tags are compared in canonical case.
+pub fn collect(png: &Png) -> Result<Strings> {
+    let mut strings = Strings::new();
+    for chunk in png.chunks() {
+        if chunk.chunk_type().to_string() != "iTXt" {
+            continue;
+        }
+        let text = InternationalTextChunk::try_from(chunk)?;
+        if text.language.is_empty() {
+            continue;
+        }
+        insert(&mut strings, text.keyword, text.language, text.text)?;
+    }
+    Ok(strings)
+}
+
+/// Serializes `strings` as a bundle for translators:
+/// `{"version": 1, "strings": {"keyword": {"en-US": "text", ...}, ...}}`
+pub fn export(strings: &Strings) -> Value {
+    json!({
+        "version": BUNDLE_VERSION,
+        "strings": strings,
+    })
+}
+
+/// Parses a bundle written by `export`, validating every language tag
+pub fn parse(bundle: &Value) -> Result<Strings> {
+    if bundle["version"] != json!(BUNDLE_VERSION) {
+        return Err(format!("Unsupported bundle version {}", bundle["version"]).into());
+    }
+    let keywords = bundle["strings"]
+        .as_object()
+        .ok_or("Bundle is missing its strings object")?;
+
+    let mut strings = Strings::new();
+    for (keyword, translations) in keywords.iter() {
+        let translations: &Map<String, Value> = translations
+            .as_object()
+            .ok_or_else(|| format!("Translations of '{}' must be an object", keyword))?;
+        for (language, text) in translations.iter() {
+            let text = text
+                .as_str()
+                .ok_or_else(|| format!("Text of '{}' ({}) must be a string", keyword, language))?;
+            insert(
+                &mut strings,
+                keyword.clone(),
+                language.clone(),
+                text.to_string(),
+            )?;
+        }
+    }
+    Ok(strings)
+}
+
+/// Replaces the localized iTXt chunks of `png` with `strings`. Unlocalized text chunks are kept.
+pub fn apply(png: &Png, strings: &Strings) -> Result<Png> {
+    let mut chunks: Vec<_> = png
+        .chunks()
+        .iter()
+        .filter(|c| {
+            c.chunk_type().to_string() != "iTXt"
+                || InternationalTextChunk::try_from(*c).is_ok_and(|t| t.language.is_empty())
+        })
+        .cloned()
+        .collect();
+
+    let end = chunks
+        .iter()
+        .position(|c| c.chunk_type().to_string() == "IEND")
+        .unwrap_or(chunks.len());
+    let mut localized = vec![];
+    for (keyword, translations) in strings.iter() {
+        for (language, text) in translations.iter() {
+            let itxt = InternationalTextChunk {
+                keyword: keyword.clone(),
+                language: language.clone(),
+                translated_keyword: String::new(),
+                text: text.clone(),
+            };
+            localized.push(itxt.to_chunk()?);
+        }
+    }
+    chunks.splice(end..end, localized);
+
+    Ok(Png::from_chunks(chunks))
+}
+
+fn insert(strings: &mut Strings, keyword: String, language: String, text: String) -> Result<()> {
+    let language = canonicalize_language_tag(&language)
+        .ok_or_else(|| format!("Invalid language tag '{}' for '{}'", language, keyword))?;
+    let translations = strings.entry(keyword.clone()).or_default();
+    match translations.get(&language) {
+        Some(existing) if *existing != text => {
+            Err(format!("Conflicting {} translations of '{}'", language, keyword).into())
+        }
+        _ => {
+            translations.insert(language, text);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::testing::chunk;
+
+    fn itxt(keyword: &str, language: &str, text: &str) -> Chunk {
+        InternationalTextChunk {
+            keyword: keyword.to_string(),
+            language: language.to_string(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+        }
+        .to_chunk()
+        .unwrap()
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            itxt("Comment", "", "not localized"),
+            itxt("greeting", "en", "Hello"),
+            itxt("greeting", "fr", "Bonjour"),
+            itxt("greeting", "en", "Hello"),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_collect_deduplicates() {
+        let strings = collect(&testing_png()).unwrap();
+        assert_eq!(strings.len(), 1);
+        assert_eq!(strings["greeting"].len(), 2);
+        assert_eq!(strings["greeting"]["fr"], "Bonjour");
+    }
+
+    #[test]
+    fn test_collect_conflict() {
+        let png = Png::from_chunks(vec![
+            itxt("greeting", "en", "Hello"),
+            itxt("greeting", "en", "Hi"),
+        ]);
+        assert!(collect(&png).is_err());
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let strings = collect(&testing_png()).unwrap();
+        let bundle = export(&strings);
+        assert_eq!(bundle["strings"]["greeting"]["en"], "Hello");
+        assert_eq!(parse(&bundle).unwrap(), strings);
+    }
+
+    #[test]
+    fn test_parse_canonicalizes_tags() {
+        let bundle = json!({"version": 1, "strings": {"greeting": {"EN-us": "Hi", "en-US": "Hi"}}});
+        let strings = parse(&bundle).unwrap();
+        assert_eq!(
+            strings["greeting"].keys().collect::<Vec<_>>(),
+            vec!["en-US"]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_tag() {
+        let bundle = json!({"version": 1, "strings": {"greeting": {"en_US": "Hello"}}});
+        assert!(parse(&bundle).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut strings = collect(&testing_png()).unwrap();
+        strings
+            .get_mut("greeting")
+            .unwrap()
+            .insert("de".to_string(), "Hallo".to_string());
+
+        let png = apply(&testing_png(), &strings).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            vec!["IHDR", "iTXt", "IDAT", "iTXt", "iTXt", "iTXt", "IEND"]
+        );
+        assert_eq!(collect(&png).unwrap(), strings);
+    }
+}
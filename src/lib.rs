@@ -0,0 +1,130 @@
+//! Reading and editing the chunks of PNG files.
+//!
+//! `Png`, `Chunk` and `ChunkType` cover parsing and writing chunk streams; the `commands`
+//! module holds the logic behind each `pngchunk` subcommand, driven by `run`.
+
+#[cfg(all(feature = "clipboard", target_os = "wasi"))]
+compile_error!("the clipboard feature is not available on WASI");
+
+pub mod apple;
+pub mod archive;
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod args;
+pub mod batch;
+pub mod bookmarks;
+pub mod browser;
+pub mod cancel;
+pub mod chunk;
+pub mod chunk_type;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod commands;
+pub mod compat;
+pub mod config;
+pub mod corpus;
+pub mod crypto;
+pub mod datauri;
+pub mod diagnostics;
+pub mod diff;
+pub mod edit;
+pub mod error;
+pub mod exif;
+pub mod icc;
+pub mod inspect;
+pub mod known_chunks;
+pub mod l10n;
+pub mod limits;
+pub mod lock;
+#[cfg(feature = "mem-stats")]
+pub mod mem_stats;
+pub mod merge;
+pub mod notes;
+pub mod patch;
+pub mod payload;
+pub mod png;
+pub mod pretty;
+pub mod profile;
+pub mod protocol;
+pub mod provenance;
+pub mod raster;
+pub mod reader;
+pub mod redact;
+pub mod salvage;
+pub mod scan;
+pub mod schema;
+pub mod spill;
+pub mod stdio;
+pub mod stego;
+pub mod temp;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod text;
+pub mod trace;
+pub mod type_filter;
+pub mod validate;
+pub mod visit;
+pub mod watermark;
+pub mod workspace;
+
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use png::Png;
+
+pub use error::PngChunkError;
+
+pub type Error = PngChunkError;
+pub type Result<T> = std::result::Result<T, Error>;
+
+use args::PngArgs;
+
+/// Runs a single subcommand
+pub fn run(command: PngArgs) -> Result<()> {
+    match command {
+        PngArgs::Encode(args) => commands::encode(args)?,
+        PngArgs::Decode(args) => commands::decode(args)?,
+        PngArgs::Remove(args) => commands::remove(args)?,
+        PngArgs::Print(args) => commands::print_chunks(args)?,
+        PngArgs::Textconv(args) => commands::textconv(args)?,
+        PngArgs::MergeDriver(args) => commands::merge_driver(args)?,
+        PngArgs::Compat(args) => commands::compat(args)?,
+        PngArgs::Carve(args) => commands::carve(args)?,
+        PngArgs::Salvage(args) => commands::salvage(args)?,
+        PngArgs::Patch(args) => commands::patch(args)?,
+        PngArgs::Export(args) => commands::export(args)?,
+        PngArgs::Import(args) => commands::import(args)?,
+        PngArgs::Compose(args) => commands::compose(args)?,
+        PngArgs::Pixels(args) => commands::pixels(args)?,
+        PngArgs::Histogram(args) => commands::histogram(args)?,
+        PngArgs::PixelDiff(args) => commands::pixel_diff(args)?,
+        PngArgs::Redact(args) => commands::redact(args)?,
+        PngArgs::L10n(args) => commands::l10n(args)?,
+        PngArgs::Validate(args) => commands::validate(args)?,
+        PngArgs::Text(args) => commands::text(args)?,
+        PngArgs::Migrate(args) => commands::migrate(args)?,
+        PngArgs::Note(args) => commands::note(args)?,
+        PngArgs::Workspace(args) => commands::workspace(args)?,
+        PngArgs::Watermark(args) => commands::watermark(args)?,
+        PngArgs::Provenance(args) => commands::provenance(args)?,
+        PngArgs::Stego(args) => commands::stego(args)?,
+        PngArgs::Decgbi(args) => commands::decgbi(args)?,
+        PngArgs::Extract(args) => commands::extract(args)?,
+        PngArgs::Lock(args) => commands::lock(args)?,
+        PngArgs::Corpus(args) => commands::corpus(args)?,
+        PngArgs::Strip(args) => commands::strip(args)?,
+        PngArgs::ExecJson(args) => commands::exec_json(args)?,
+        PngArgs::Info(args) => commands::info(args)?,
+        PngArgs::Bookmarks(args) => commands::bookmarks(args)?,
+        PngArgs::Schema(args) => commands::schema(args)?,
+        PngArgs::Repair(args) => commands::repair(args)?,
+        PngArgs::Diff(args) => commands::diff(args)?,
+        PngArgs::Icc(args) => commands::icc(args)?,
+        PngArgs::Exif(args) => commands::exif(args)?,
+        PngArgs::Dpi(args) => commands::dpi(args)?,
+        PngArgs::Count(args) => commands::count(args)?,
+        PngArgs::Exists(args) => commands::exists(args)?,
+        PngArgs::Time(args) => commands::time(args)?,
+        PngArgs::Color(args) => commands::color(args)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use std::io::{self, Read, Write};
+use std::sync::OnceLock;
+
+use flate2::read::ZlibDecoder;
+
+use crate::Result;
+
+static ACTIVE: OnceLock<Limits> = OnceLock::new();
+
+/// Default ceiling for inflating compressed text and ICC profiles
+pub const DEFAULT_MAX_INFLATED: usize = 256 << 20;
+/// Streams that inflate to less than this are never rejected for their ratio alone
+pub const RATIO_FLOOR: usize = 1 << 20;
+/// Ratio of inflated to compressed size past which a stream above `RATIO_FLOOR` looks like a
+/// decompression bomb. Real text and ICC profiles compress far less than this.
+pub const BOMB_RATIO: usize = 100;
+
+/// Resource limits applied while reading files. `None` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest input file, in bytes
+    pub max_file_size: Option<usize>,
+    /// Most chunks a file may contain
+    pub max_chunks: Option<usize>,
+    /// Most bytes a single zlib stream may inflate to
+    pub max_inflated: Option<usize>,
+    /// Largest ratio of inflated to compressed size, for streams above `RATIO_FLOOR`
+    pub max_ratio: Option<usize>,
+    /// Most pixels a decoded image may have
+    pub max_pixels: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_file_size: None,
+            max_chunks: None,
+            max_inflated: Some(DEFAULT_MAX_INFLATED),
+            max_ratio: Some(BOMB_RATIO),
+            max_pixels: None,
+        }
+    }
+}
+
+impl Limits {
+    /// The strictest limits, for scanning files from untrusted sources. They comfortably fit
+    /// ordinary images but stop oversized inputs and decompression bombs.
+    pub fn untrusted() -> Limits {
+        Limits {
+            max_file_size: Some(64 << 20),
+            max_chunks: Some(10_000),
+            max_inflated: Some(64 << 20),
+            max_ratio: Some(BOMB_RATIO),
+            max_pixels: Some(1 << 26),
+        }
+    }
+
+    pub fn check_file_size(&self, size: usize) -> Result<()> {
+        match self.max_file_size {
+            Some(max) if size > max => {
+                Err(format!("File is {} bytes, more than the limit of {}", size, max).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_chunk_count(&self, count: usize) -> Result<()> {
+        match self.max_chunks {
+            Some(max) if count > max => {
+                Err(format!("File has more than the limit of {} chunks", max).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_pixels(&self, width: u32, height: u32) -> Result<()> {
+        match self.max_pixels {
+            Some(max) if width as u64 * height as u64 > max => Err(format!(
+                "Image is {}x{}, more than the limit of {} pixels",
+                width, height, max
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Inflates a zlib stream, failing as soon as the output passes `max_inflated` or grows
+    /// beyond `max_ratio` times the compressed size
+    pub fn inflate(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut inflated = vec![];
+        self.inflate_into(data, data.len(), &mut inflated)?;
+        Ok(inflated)
+    }
+
+    /// Inflates the zlib stream read from `data`, `length` bytes long, into `out` as it goes,
+    /// with the same limits as `inflate`. Returns the inflated size.
+    pub fn inflate_into<R: Read, W: Write>(
+        &self,
+        data: R,
+        length: usize,
+        out: &mut W,
+    ) -> Result<usize> {
+        let _span = tracing::info_span!("inflate", bytes = length).entered();
+        let by_ratio = self
+            .max_ratio
+            .map(|ratio| length.saturating_mul(ratio).max(RATIO_FLOOR));
+        let cap = [self.max_inflated, by_ratio].into_iter().flatten().min();
+
+        let mut decoder = ZlibDecoder::new(data);
+        let inflated = match cap {
+            Some(cap) => {
+                let inflated = io::copy(&mut (&mut decoder).take(cap as u64 + 1), out)? as usize;
+                if inflated > cap {
+                    return Err(format!(
+                        "Possible decompression bomb: {} compressed bytes inflate to more than {}",
+                        length, cap
+                    )
+                    .into());
+                }
+                inflated
+            }
+            None => io::copy(&mut decoder, out)? as usize,
+        };
+        Ok(inflated)
+    }
+}
+
+/// Sets the limits for the rest of the process. Only the first call has an effect.
+pub fn set(limits: Limits) {
+    let _ = ACTIVE.set(limits);
+}
+
+/// The limits in effect, unlimited unless `set` was called
+pub fn get() -> Limits {
+    ACTIVE.get().copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn compressed_zeros(len: usize) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0; len]).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_inflate_limit() {
+        let bomb = compressed_zeros(1 << 20);
+        assert!(bomb.len() < 2048);
+
+        assert_eq!(Limits::default().inflate(&bomb).unwrap().len(), 1 << 20);
+        let unlimited = Limits {
+            max_inflated: None,
+            ..Default::default()
+        };
+        assert_eq!(unlimited.inflate(&bomb).unwrap().len(), 1 << 20);
+        let strict = Limits {
+            max_inflated: Some(1 << 16),
+            ..Default::default()
+        };
+        assert!(strict.inflate(&bomb).is_err());
+        assert_eq!(
+            strict.inflate(&compressed_zeros(1 << 16)).unwrap().len(),
+            1 << 16
+        );
+    }
+
+    #[test]
+    fn test_inflate_ratio() {
+        let limits = Limits {
+            max_ratio: Some(100),
+            ..Default::default()
+        };
+        // Small outputs are allowed whatever their ratio
+        assert!(limits.inflate(&compressed_zeros(RATIO_FLOOR)).is_ok());
+        assert!(limits.inflate(&compressed_zeros(RATIO_FLOOR * 4)).is_err());
+    }
+
+    #[test]
+    fn test_untrusted_limits() {
+        let limits = Limits::untrusted();
+        assert!(limits.check_file_size(1 << 20).is_ok());
+        assert!(limits.check_file_size(1 << 30).is_err());
+        assert!(limits.check_chunk_count(100_000).is_err());
+        assert!(limits.check_pixels(4096, 4096).is_ok());
+        assert!(limits.check_pixels(100_000, 100_000).is_err());
+    }
+
+    #[test]
+    fn test_default_limits() {
+        let limits = Limits::default();
+        assert!(limits.check_file_size(usize::MAX).is_ok());
+        assert!(limits.check_chunk_count(usize::MAX).is_ok());
+        assert!(limits.check_pixels(u32::MAX, u32::MAX).is_ok());
+        assert_eq!(limits.max_inflated, Some(DEFAULT_MAX_INFLATED));
+        assert_eq!(limits.max_ratio, Some(BOMB_RATIO));
+        assert!(limits.inflate(&compressed_zeros(RATIO_FLOOR * 4)).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::{png::Png, raster, workspace::sha256_hex, Result};
+
+/// Version of the lockfile format written by `Lockfile::as_bytes`
+pub const VERSION: u64 = 1;
+
+/// The chunk inventory expected for one asset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetLock {
+    /// Type and data SHA-256 of each chunk, in file order
+    pub chunks: Vec<(String, String)>,
+    /// SHA-256 of the decoded pixels, or `None` if the image could not be decoded
+    pub pixels: Option<String>,
+}
+
+impl AssetLock {
+    pub fn from_png(png: &Png) -> AssetLock {
+        let chunks = png
+            .chunks()
+            .iter()
+            .map(|c| (c.chunk_type().to_string(), c.data_digest()))
+            .collect();
+        // Hashing decoded pixels means recompressing IDAT without changing the image is not
+        // reported as a change
+        let pixels = raster::decode(png).ok().map(|image| {
+            let bytes: Vec<u8> = image
+                .to_rgba16()
+                .iter()
+                .flat_map(|s| s.to_be_bytes())
+                .collect();
+            sha256_hex(&bytes)
+        });
+        AssetLock { chunks, pixels }
+    }
+}
+
+/// The expected chunk inventory of every PNG under a directory, keyed by path relative to it
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    pub assets: BTreeMap<String, AssetLock>,
+}
+
+impl Lockfile {
+    /// Locks every `.png` file under `dir`, recursively
+    pub fn scan(dir: &Path) -> Result<Lockfile> {
+        let mut assets = BTreeMap::new();
+        for path in png_files(dir)? {
+            let contents = fs::read(&path)?;
+            let png =
+                Png::try_from(&contents[..]).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let name = path
+                .strip_prefix(dir)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            assets.insert(name, AssetLock::from_png(&png));
+        }
+        Ok(Lockfile { assets })
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Lockfile> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        if value["version"].as_u64() != Some(VERSION) {
+            return Err(format!("Unsupported lockfile version, expected {}", VERSION).into());
+        }
+        let mut assets = BTreeMap::new();
+        let entries = value["assets"]
+            .as_object()
+            .ok_or("Lockfile is missing its assets")?;
+        for (name, asset) in entries {
+            let chunks = asset["chunks"]
+                .as_array()
+                .ok_or_else(|| format!("Asset {} is missing its chunks", name))?
+                .iter()
+                .map(
+                    |chunk| match (chunk["type"].as_str(), chunk["sha256"].as_str()) {
+                        (Some(chunk_type), Some(sha256)) => {
+                            Ok((chunk_type.to_string(), sha256.to_string()))
+                        }
+                        _ => Err(format!("Asset {} has a malformed chunk entry", name).into()),
+                    },
+                )
+                .collect::<Result<Vec<(String, String)>>>()?;
+            let pixels = asset["pixels"].as_str().map(str::to_string);
+            assets.insert(name.clone(), AssetLock { chunks, pixels });
+        }
+        Ok(Lockfile { assets })
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let assets: serde_json::Map<String, Value> = self
+            .assets
+            .iter()
+            .map(|(name, asset)| {
+                let chunks: Vec<Value> = asset
+                    .chunks
+                    .iter()
+                    .map(|(chunk_type, sha256)| json!({"type": chunk_type, "sha256": sha256}))
+                    .collect();
+                (
+                    name.clone(),
+                    json!({"chunks": chunks, "pixels": asset.pixels}),
+                )
+            })
+            .collect();
+        let mut bytes = serde_json::to_vec_pretty(&json!({
+            "version": VERSION,
+            "assets": assets,
+        }))?;
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+
+    /// Describes every way `actual` differs from this lockfile, one line per difference
+    pub fn verify(&self, actual: &Lockfile) -> Vec<String> {
+        let mut differences = vec![];
+        for (name, expected) in self.assets.iter() {
+            match actual.assets.get(name) {
+                Some(found) => differences.extend(
+                    diff_asset(expected, found)
+                        .into_iter()
+                        .map(|d| format!("{}: {}", name, d)),
+                ),
+                None => differences.push(format!("{}: missing", name)),
+            }
+        }
+        for name in actual.assets.keys() {
+            if !self.assets.contains_key(name) {
+                differences.push(format!("{}: not in the lockfile", name));
+            }
+        }
+        differences
+    }
+}
+
+/// Compares chunks by type and occurrence, so the second tEXt is compared with the second tEXt
+fn diff_asset(expected: &AssetLock, found: &AssetLock) -> Vec<String> {
+    let mut differences = vec![];
+    let occurrences = |asset: &AssetLock| {
+        let mut by_type: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (chunk_type, sha256) in asset.chunks.iter() {
+            by_type
+                .entry(chunk_type.clone())
+                .or_default()
+                .push(sha256.clone());
+        }
+        by_type
+    };
+    let (expected_types, found_types) = (occurrences(expected), occurrences(found));
+    let pixels_known = expected.pixels.is_some() && found.pixels.is_some();
+
+    let types: std::collections::BTreeSet<&String> =
+        expected_types.keys().chain(found_types.keys()).collect();
+    for chunk_type in types {
+        let empty = vec![];
+        let before = expected_types.get(chunk_type).unwrap_or(&empty);
+        let after = found_types.get(chunk_type).unwrap_or(&empty);
+        if after.len() > before.len() {
+            differences.push(format!(
+                "{} {} chunk(s) added",
+                after.len() - before.len(),
+                chunk_type
+            ));
+        } else if after.len() < before.len() {
+            differences.push(format!(
+                "{} {} chunk(s) removed",
+                before.len() - after.len(),
+                chunk_type
+            ));
+        }
+        // IDAT is covered by the pixel hash when both images could be decoded
+        if chunk_type == "IDAT" && pixels_known {
+            continue;
+        }
+        let changed = before.iter().zip(after).filter(|(b, a)| b != a).count();
+        if changed > 0 {
+            differences.push(format!("{} {} chunk(s) changed", changed, chunk_type));
+        }
+    }
+
+    if expected.pixels != found.pixels {
+        differences.push("pixel data changed".to_string());
+    }
+    if expected.chunks.len() == found.chunks.len()
+        && differences.is_empty()
+        && expected
+            .chunks
+            .iter()
+            .map(|c| &c.0)
+            .ne(found.chunks.iter().map(|c| &c.0))
+    {
+        differences.push("chunks reordered".to_string());
+    }
+    differences
+}
+
+/// Every `.png` file under `dir`, in sorted order
+pub(crate) fn png_files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut files = vec![];
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            files.extend(png_files(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{compose, ImageHeader};
+    use crate::testing::chunk;
+    use std::str::FromStr;
+
+    fn testing_png(pixels: &[u8]) -> Png {
+        let header = ImageHeader::from_str("width=2,height=1,color=gray8").unwrap();
+        compose(&header, pixels).unwrap()
+    }
+
+    fn with_text(png: &Png) -> Png {
+        let mut png = png.clone();
+        png.append_chunk(chunk("tEXt", b"Software\0x"));
+        png
+    }
+
+    fn lockfile(assets: &[(&str, &Png)]) -> Lockfile {
+        Lockfile {
+            assets: assets
+                .iter()
+                .map(|(name, png)| (name.to_string(), AssetLock::from_png(png)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let lock = lockfile(&[("a.png", &testing_png(&[1, 2]))]);
+        assert_eq!(Lockfile::parse(&lock.as_bytes().unwrap()).unwrap(), lock);
+        assert!(Lockfile::parse(br#"{"version": 9, "assets": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let png = testing_png(&[1, 2]);
+        let expected = lockfile(&[("a.png", &png), ("b.png", &png)]);
+        assert!(expected.verify(&expected).is_empty());
+
+        let actual = lockfile(&[
+            ("a.png", &with_text(&png)),
+            ("c.png", &testing_png(&[2, 1])),
+        ]);
+        assert_eq!(
+            expected.verify(&actual),
+            vec![
+                "a.png: 1 tEXt chunk(s) added",
+                "b.png: missing",
+                "c.png: not in the lockfile",
+            ]
+        );
+
+        let changed = lockfile(&[("a.png", &testing_png(&[2, 1])), ("b.png", &png)]);
+        assert_eq!(expected.verify(&changed), vec!["a.png: pixel data changed"]);
+    }
+
+    #[test]
+    fn test_scan() {
+        let dir =
+            crate::temp::work_dir().join(format!("pngchunk-lock-{}", crate::temp::process_id()));
+        fs::create_dir_all(dir.join("icons")).unwrap();
+        fs::write(dir.join("icons/a.png"), testing_png(&[1, 2]).as_bytes()).unwrap();
+        fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+
+        let lock = Lockfile::scan(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(lock.assets.keys().collect::<Vec<_>>(), vec!["icons/a.png"]);
+    }
+}
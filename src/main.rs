@@ -1,22 +1,109 @@
-use args::PngArgs;
+#[cfg(feature = "mem-stats")]
+use pngchunk::mem_stats;
+use pngchunk::{
+    args::Cli, cancel, commands, config, datauri, diagnostics, limits, run, spill, temp, trace,
+    PngChunkError, Result,
+};
+use std::{env, process};
 use structopt::StructOpt;
 
-mod args;
-pub mod chunk;
-pub mod chunk_type;
-mod commands;
-mod png;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+#[cfg(feature = "mem-stats")]
+#[global_allocator]
+static ALLOCATOR: mem_stats::CountingAllocator = mem_stats::CountingAllocator;
 
 fn main() -> Result<()> {
-    let opt = PngArgs::from_args();
-    match opt {
-        PngArgs::Encode(args) => commands::encode(args)?,
-        PngArgs::Decode(args) => commands::decode(args)?,
-        PngArgs::Remove(args) => commands::remove(args)?,
-        PngArgs::Print(args) => commands::print_chunks(args)?,
+    let args: Vec<_> = env::args_os().collect();
+    let mut opt = match Cli::from_iter_safe(&args) {
+        Ok(opt) => opt,
+        // Help and version requests
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            process::exit(Cli::usage_error_status(&args));
+        }
+    };
+    match opt.output_format() {
+        Ok(format) => commands::set_format(format),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(Cli::usage_error_status(&args));
+        }
+    }
+    let mut layers: Vec<trace::BoxedLayer> = vec![];
+    #[cfg(not(target_os = "wasi"))]
+    let trace = match opt.trace_output.as_deref().map(trace::chrome).transpose() {
+        Ok(Some((layer, guard))) => {
+            layers.push(layer);
+            Some(guard)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    #[cfg(feature = "mem-stats")]
+    if opt.mem_stats {
+        layers.push(Box::new(mem_stats::StatsLayer));
+    }
+    trace::install(layers)?;
+    cancel::install()?;
+    if let Some(dir) = opt.work_dir {
+        temp::set_work_dir(dir);
+    }
+    let mut limits = if opt.untrusted {
+        limits::Limits::untrusted()
+    } else {
+        limits::Limits::default()
+    };
+    if let Some(size) = opt.max_inflate {
+        limits.max_inflated = Some(size.0);
+    }
+    if let Some(ratio) = opt.max_inflate_ratio {
+        limits.max_ratio = Some(ratio);
+    }
+    limits::set(limits);
+    if let Some(size) = opt.max_memory {
+        spill::set_max_memory(size.0);
+    }
+    match config::Config::load(opt.config.as_deref()) {
+        Ok(config) => config::set(config),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+    datauri::set_output(opt.output_data_uri);
+    diagnostics::set_deny(opt.deny_warnings);
+    #[cfg(feature = "clipboard")]
+    pngchunk::clipboard::set_output(opt.to_clipboard);
+    if let Some(timeout) = opt.timeout {
+        cancel::start_timeout(timeout.0);
+    }
+
+    let result = tracing::info_span!("command")
+        .in_scope(|| run(opt.command))
+        .and_then(|()| diagnostics::check());
+    // Exiting skips destructors, so the trace has to be flushed first
+    #[cfg(not(target_os = "wasi"))]
+    drop(trace);
+    #[cfg(feature = "mem-stats")]
+    if opt.mem_stats {
+        eprint!("{}", mem_stats::report());
+    }
+    if cancel::timed_out() {
+        eprintln!("Error: timed out");
+        process::exit(cancel::TIMEOUT_STATUS);
+    }
+    if let Err(PngChunkError::Status(status)) = result {
+        process::exit(status);
+    }
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        if let Some(location) = e.location() {
+            eprint!("{}", location.snippet());
+        }
+        process::exit(1);
     }
     Ok(())
 }
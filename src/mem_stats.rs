@@ -0,0 +1,172 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use tracing::span::Id;
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static PHASES: Mutex<BTreeMap<&'static str, Phase>> = Mutex::new(BTreeMap::new());
+
+/// The system allocator, counting every allocation. Register it as the `#[global_allocator]`
+/// for the figures to mean anything.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            freed(layout.size());
+            allocated(new_size);
+        }
+        new_ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        freed(layout.size());
+    }
+}
+
+fn allocated(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED.fetch_add(size, Ordering::Relaxed);
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+fn freed(size: usize) {
+    CURRENT.fetch_sub(size, Ordering::Relaxed);
+}
+
+/// Allocation figures for every span with the same name
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub calls: usize,
+    pub allocations: usize,
+    pub allocated: usize,
+    /// The most memory in use during a single call, above what was in use when it started
+    pub peak: usize,
+}
+
+/// Counters when a span was entered, kept in its extensions until it exits
+struct Start {
+    allocations: usize,
+    allocated: usize,
+    current: usize,
+    /// The peak so far, restored on exit after `PEAK` has tracked the span's own peak
+    outer_peak: usize,
+}
+
+/// Attributes allocations to the tracing span they happen in. Spans running on other threads
+/// at the same time, as with `--jobs`, are counted in each other's figures.
+pub struct StatsLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for StatsLayer {
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let current = CURRENT.load(Ordering::Relaxed);
+        let start = Start {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            allocated: ALLOCATED.load(Ordering::Relaxed),
+            current,
+            outer_peak: PEAK.swap(current, Ordering::Relaxed),
+        };
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(start);
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let Some(start) = span.extensions_mut().remove::<Start>() else {
+            return;
+        };
+        let peak = PEAK.fetch_max(start.outer_peak, Ordering::Relaxed);
+        let mut phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+        let phase = phases.entry(span.name()).or_default();
+        phase.calls += 1;
+        phase.allocations += ALLOCATIONS.load(Ordering::Relaxed) - start.allocations;
+        phase.allocated += ALLOCATED.load(Ordering::Relaxed) - start.allocated;
+        phase.peak = phase.peak.max(peak.saturating_sub(start.current));
+    }
+}
+
+/// The figures gathered so far for each span name
+pub fn phases() -> BTreeMap<&'static str, Phase> {
+    PHASES.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// A table of the figures for each phase, followed by the totals for the whole run
+pub fn report() -> String {
+    let mut report = format!(
+        "{:<10} {:>7} {:>12} {:>14} {:>14}\n",
+        "phase", "calls", "allocations", "allocated", "peak"
+    );
+    for (name, phase) in phases() {
+        let _ = writeln!(
+            report,
+            "{:<10} {:>7} {:>12} {:>14} {:>14}",
+            name, phase.calls, phase.allocations, phase.allocated, phase.peak
+        );
+    }
+    let _ = writeln!(
+        report,
+        "{:<10} {:>7} {:>12} {:>14} {:>14}",
+        "total",
+        "",
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED.load(Ordering::Relaxed),
+        PEAK.load(Ordering::Relaxed)
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_phases() {
+        let subscriber = tracing_subscriber::registry().with(StatsLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("mem-test").entered();
+            allocated(100);
+            allocated(50);
+            freed(150);
+            allocated(20);
+            freed(20);
+        });
+        let phase = phases()["mem-test"].clone();
+        assert_eq!(phase.calls, 1);
+        assert_eq!(phase.allocations, 3);
+        assert_eq!(phase.allocated, 170);
+        assert_eq!(phase.peak, 150);
+        assert!(report().contains("mem-test"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use crate::{chunk::Chunk, edit::SINGLE_CHUNKS, png::Png, Error, Result};
+
+/// How ancillary chunks are combined when both sides of a merge changed them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep every ancillary chunk added by either side and drop those removed by either side
+    Union,
+    /// Take the ancillary chunks from our side
+    Ours,
+    /// Take the ancillary chunks from their side
+    Theirs,
+}
+
+impl FromStr for MergePolicy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "union" => Ok(MergePolicy::Union),
+            "ours" => Ok(MergePolicy::Ours),
+            "theirs" => Ok(MergePolicy::Theirs),
+            _ => Err(format!(
+                "Unknown merge policy '{}', expected union, ours or theirs",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+/// Performs a three-way merge of two PNGs that share a common ancestor. The merge only
+/// succeeds when both sides carry identical critical chunks (header, palette and pixel data),
+/// in which case their ancillary chunks are combined according to `policy`.
+pub fn merge(base: &Png, ours: &Png, theirs: &Png, policy: MergePolicy) -> Result<Png> {
+    if critical_keys(ours) != critical_keys(theirs) {
+        return Err(
+            "Pixel data or other critical chunks differ, cannot merge automatically".into(),
+        );
+    }
+
+    let chunks = match policy {
+        MergePolicy::Ours => ours.chunks().to_vec(),
+        MergePolicy::Theirs => theirs.chunks().to_vec(),
+        MergePolicy::Union => union(base, ours, theirs)?,
+    };
+
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Combines both sides' ancillary chunks. Both sides changing a chunk the spec allows only once,
+/// such as tIME or pHYs, is a conflict.
+fn union(base: &Png, ours: &Png, theirs: &Png) -> Result<Vec<Chunk>> {
+    let base_keys: Vec<(String, String)> = base.chunks().iter().map(chunk_key).collect();
+    let our_keys: Vec<(String, String)> = ours.chunks().iter().map(chunk_key).collect();
+    let their_keys: Vec<(String, String)> = theirs.chunks().iter().map(chunk_key).collect();
+
+    // Start from our side, dropping anything their side removed from the base
+    let mut merged: Vec<Chunk> = ours
+        .chunks()
+        .iter()
+        .filter(|chunk| {
+            let key = chunk_key(chunk);
+            chunk.chunk_type().is_critical()
+                || !base_keys.contains(&key)
+                || their_keys.contains(&key)
+        })
+        .cloned()
+        .collect();
+
+    // Then add anything their side introduced, keeping it on the same side of the image data
+    let first_idat = their_keys.iter().position(|(t, _)| t == "IDAT");
+    for (index, chunk) in theirs.chunks().iter().enumerate() {
+        let key = chunk_key(chunk);
+        if chunk.chunk_type().is_critical() || base_keys.contains(&key) || our_keys.contains(&key) {
+            continue;
+        }
+
+        let anchor = match first_idat {
+            Some(idat) if index < idat => "IDAT",
+            _ => "IEND",
+        };
+        let position = merged
+            .iter()
+            .position(|c| c.chunk_type().to_string() == anchor)
+            .unwrap_or(merged.len());
+        merged.insert(position, chunk.clone());
+    }
+
+    for (single, _) in SINGLE_CHUNKS {
+        let count = merged
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == single)
+            .count();
+        if count > 1 {
+            return Err(format!(
+                "Both sides changed the {} chunk, pass --policy ours or theirs to pick one",
+                single
+            )
+            .into());
+        }
+    }
+    Ok(merged)
+}
+
+fn critical_keys(png: &Png) -> Vec<(String, String)> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().is_critical())
+        .map(chunk_key)
+        .collect()
+}
+
+fn chunk_key(chunk: &Chunk) -> (String, String) {
+    (chunk.chunk_type().to_string(), chunk.data_digest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| format!("{}:{}", c.chunk_type(), c.data_as_string().unwrap()))
+            .collect()
+    }
+
+    fn base() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"base"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_merge_union() {
+        let ours = Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"base"),
+            chunk("IDAT", b"pixels"),
+            chunk("ruSt", b"ours"),
+            chunk("IEND", b""),
+        ]);
+        let theirs = Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("iTXt", b"theirs"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ]);
+
+        let merged = merge(&base(), &ours, &theirs, MergePolicy::Union).unwrap();
+        assert_eq!(
+            types(&merged),
+            vec![
+                "IHDR:header",
+                "iTXt:theirs",
+                "IDAT:pixels",
+                "ruSt:ours",
+                "IEND:"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_prefer_side() {
+        let ours = base();
+        let theirs = Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ]);
+
+        let merged = merge(&base(), &ours, &theirs, MergePolicy::Ours).unwrap();
+        assert_eq!(merged.chunks().len(), 4);
+
+        let merged = merge(&base(), &ours, &theirs, MergePolicy::Theirs).unwrap();
+        assert_eq!(merged.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_merge_single_chunk_conflict() {
+        let edited = |time: &[u8]| {
+            Png::from_chunks(vec![
+                chunk("IHDR", b"header"),
+                chunk("tEXt", b"base"),
+                chunk("IDAT", b"pixels"),
+                chunk("tIME", time),
+                chunk("IEND", b""),
+            ])
+        };
+        let (ours, theirs) = (edited(b"ours"), edited(b"theirs"));
+
+        let error = merge(&base(), &ours, &theirs, MergePolicy::Union).unwrap_err();
+        assert!(error.to_string().contains("tIME"));
+        let merged = merge(&base(), &ours, &theirs, MergePolicy::Theirs).unwrap();
+        assert!(types(&merged).contains(&"tIME:theirs".to_string()));
+        // The same edit on both sides is not a conflict
+        assert!(merge(&base(), &ours, &ours, MergePolicy::Union).is_ok());
+    }
+
+    #[test]
+    fn test_merge_pixel_conflict() {
+        let theirs = Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("IDAT", b"other pixels"),
+            chunk("IEND", b""),
+        ]);
+
+        assert!(merge(&base(), &base(), &theirs, MergePolicy::Union).is_err());
+    }
+
+    #[test]
+    fn test_merge_policy_from_str() {
+        assert_eq!(MergePolicy::from_str("ours").unwrap(), MergePolicy::Ours);
+        assert!(MergePolicy::from_str("both").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::{png::Png, Result};
+
+/// An analyst's remark about one chunk of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    /// Index of the chunk in the file
+    pub chunk: usize,
+    /// Type of the chunk when the note was taken, to spot notes made stale by later edits
+    pub chunk_type: String,
+    pub text: String,
+}
+
+/// The notes about a file, kept in a sidecar next to it so the file itself is never modified
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Notes {
+    pub entries: Vec<Note>,
+}
+
+impl Notes {
+    /// The sidecar holding the notes for `file`: `<file>.notes.json`
+    pub fn sidecar(file: &Path) -> PathBuf {
+        let mut path: OsString = file.as_os_str().to_owned();
+        path.push(".notes.json");
+        PathBuf::from(path)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Notes> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        let entries = value["notes"]
+            .as_array()
+            .ok_or("Notes file is missing its notes array")?
+            .iter()
+            .map(|note| {
+                Ok(Note {
+                    chunk: note["chunk"].as_u64().ok_or("Note is missing its chunk")? as usize,
+                    chunk_type: note["type"].as_str().unwrap_or_default().to_string(),
+                    text: note["text"]
+                        .as_str()
+                        .ok_or("Note is missing its text")?
+                        .to_string(),
+                })
+            })
+            .collect::<Result<Vec<Note>>>()?;
+        Ok(Notes { entries })
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let notes: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|note| json!({"chunk": note.chunk, "type": note.chunk_type, "text": note.text}))
+            .collect();
+        Ok(serde_json::to_vec_pretty(&json!({ "notes": notes }))?)
+    }
+
+    /// Adds a note about chunk `chunk` of `png`
+    pub fn add(&mut self, png: &Png, chunk: usize, text: String) -> Result<()> {
+        let chunk_type = png
+            .chunks()
+            .get(chunk)
+            .ok_or_else(|| {
+                format!(
+                    "No chunk at index {}, the file has {}",
+                    chunk,
+                    png.chunks().len()
+                )
+            })?
+            .chunk_type()
+            .to_string();
+        self.entries.push(Note {
+            chunk,
+            chunk_type,
+            text,
+        });
+        Ok(())
+    }
+
+    /// Removes the note at `index` in `entries`
+    pub fn remove(&mut self, index: usize) -> Result<Note> {
+        if index >= self.entries.len() {
+            return Err(format!("No note {}", index).into());
+        }
+        Ok(self.entries.remove(index))
+    }
+
+    /// The notes about chunk `chunk`
+    pub fn for_chunk(&self, chunk: usize) -> impl Iterator<Item = &Note> {
+        self.entries.iter().filter(move |note| note.chunk == chunk)
+    }
+
+    /// Whether `note` still refers to a chunk of the type it was written about
+    pub fn is_stale(note: &Note, png: &Png) -> bool {
+        png.chunks()
+            .get(note.chunk)
+            .is_none_or(|c| c.chunk_type().to_string() != note.chunk_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn testing_png() -> Png {
+        testing::png_from(&[("IHDR", &[]), ("ruSt", &[]), ("IEND", &[])])
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            Notes::sidecar(Path::new("dir/image.png")),
+            PathBuf::from("dir/image.png.notes.json")
+        );
+    }
+
+    #[test]
+    fn test_notes_round_trip() {
+        let mut notes = Notes::default();
+        notes
+            .add(&testing_png(), 1, "looks like encrypted blob".to_string())
+            .unwrap();
+        let parsed = Notes::parse(&notes.as_bytes().unwrap()).unwrap();
+
+        assert_eq!(parsed, notes);
+        assert_eq!(parsed.entries[0].chunk_type, "ruSt");
+        assert_eq!(parsed.for_chunk(1).count(), 1);
+        assert_eq!(parsed.for_chunk(0).count(), 0);
+    }
+
+    #[test]
+    fn test_notes_add_remove() {
+        let png = testing_png();
+        let mut notes = Notes::default();
+        assert!(notes.add(&png, 3, "missing".to_string()).is_err());
+
+        notes.add(&png, 0, "header".to_string()).unwrap();
+        notes.add(&png, 2, "end".to_string()).unwrap();
+        assert_eq!(notes.remove(0).unwrap().text, "header");
+        assert!(notes.remove(1).is_err());
+        assert_eq!(notes.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_notes_stale() {
+        let mut notes = Notes::default();
+        notes.add(&testing_png(), 1, "blob".to_string()).unwrap();
+
+        let mut edited = testing_png();
+        edited.remove_chunk("ruSt").unwrap();
+        assert!(!Notes::is_stale(&notes.entries[0], &testing_png()));
+        assert!(Notes::is_stale(&notes.entries[0], &edited));
+    }
+}
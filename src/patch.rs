@@ -0,0 +1,223 @@
+use crate::error::Location;
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Error, Result};
+
+/// A single change to the ancillary chunks of a PNG
+#[derive(Debug)]
+pub enum PatchOp {
+    /// Remove the chunk with this type whose data has this SHA-256 digest
+    Remove {
+        chunk_type: ChunkType,
+        digest: String,
+    },
+    /// Add a chunk, either before the image data or at the end of the file
+    Add { before_idat: bool, chunk: Chunk },
+}
+
+/// A set of metadata changes between two versions of a PNG. Patches never touch critical chunks,
+/// so they can be applied to any file carrying the same metadata regardless of its pixel data.
+#[derive(Debug)]
+pub struct Patch {
+    ops: Vec<PatchOp>,
+}
+
+impl Patch {
+    pub const MAGIC: [u8; 8] = *b"PNGPATCH";
+    pub const VERSION: u8 = 1;
+
+    /// Captures the ancillary chunks removed from and added to `old` to produce `new`
+    pub fn create(old: &Png, new: &Png) -> Patch {
+        let old_keys: Vec<(String, String)> = old.chunks().iter().map(ancillary_key).collect();
+        let new_keys: Vec<(String, String)> = new.chunks().iter().map(ancillary_key).collect();
+        let mut ops = vec![];
+
+        for (chunk, key) in old.chunks().iter().zip(old_keys.iter()) {
+            if !chunk.chunk_type().is_critical() && !new_keys.contains(key) {
+                ops.push(PatchOp::Remove {
+                    chunk_type: chunk.chunk_type().clone(),
+                    digest: key.1.clone(),
+                });
+            }
+        }
+
+        let first_idat = new_keys.iter().position(|(t, _)| t == "IDAT");
+        for (index, (chunk, key)) in new.chunks().iter().zip(new_keys.iter()).enumerate() {
+            if !chunk.chunk_type().is_critical() && !old_keys.contains(key) {
+                ops.push(PatchOp::Add {
+                    before_idat: first_idat.is_some_and(|idat| index < idat),
+                    chunk: chunk.clone(),
+                });
+            }
+        }
+
+        Patch { ops }
+    }
+
+    pub fn ops(&self) -> &[PatchOp] {
+        &self.ops
+    }
+
+    /// Applies this patch to `png`, failing if a chunk it removes is not present
+    pub fn apply(&self, png: &Png) -> Result<Png> {
+        let mut chunks = png.chunks().to_vec();
+        for op in self.ops.iter() {
+            match op {
+                PatchOp::Remove { chunk_type, digest } => {
+                    let index = chunks
+                        .iter()
+                        .position(|c| c.chunk_type() == chunk_type && &c.data_digest() == digest)
+                        .ok_or_else(|| {
+                            format!("Patch does not apply: no matching {} chunk", chunk_type)
+                        })?;
+                    chunks.remove(index);
+                }
+                PatchOp::Add { before_idat, chunk } => {
+                    let anchor = if *before_idat { "IDAT" } else { "IEND" };
+                    let index = chunks
+                        .iter()
+                        .position(|c| c.chunk_type().to_string() == anchor)
+                        .unwrap_or(chunks.len());
+                    chunks.insert(index, chunk.clone());
+                }
+            }
+        }
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Serializes this patch. Removals are stored as `-`, the chunk type and the data digest;
+    /// additions as `+`, `<` or `>` for their placement and the chunk as it appears in a PNG.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Patch::MAGIC.to_vec();
+        bytes.push(Patch::VERSION);
+        for op in self.ops.iter() {
+            match op {
+                PatchOp::Remove { chunk_type, digest } => {
+                    bytes.push(b'-');
+                    bytes.extend(chunk_type.bytes());
+                    bytes.extend(digest.as_bytes());
+                }
+                PatchOp::Add { before_idat, chunk } => {
+                    bytes.push(b'+');
+                    bytes.push(if *before_idat { b'<' } else { b'>' });
+                    bytes.extend(chunk.as_bytes());
+                }
+            }
+        }
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Patch {
+    type Error = Error;
+    fn try_from(value: &[u8]) -> Result<Self> {
+        if value.len() < 9 || value[..8] != Patch::MAGIC {
+            return Err("Not a pngchunk patch file.".into());
+        }
+        if value[8] != Patch::VERSION {
+            return Err(format!("Unsupported patch version {}", value[8]).into());
+        }
+
+        let mut ops = vec![];
+        let mut i = 9;
+        while i < value.len() {
+            match value[i] {
+                b'-' if value.len() - i >= 69 => {
+                    let chunk_type = ChunkType::try_from([
+                        value[i + 1],
+                        value[i + 2],
+                        value[i + 3],
+                        value[i + 4],
+                    ])?;
+                    let digest = String::from_utf8(value[i + 5..i + 69].to_vec())?;
+                    ops.push(PatchOp::Remove { chunk_type, digest });
+                    i += 69;
+                }
+                b'+' if value.len() - i >= 2 + Chunk::MIN_CHUNK_LENGTH => {
+                    let before_idat = value[i + 1] == b'<';
+                    let start = i + 2;
+                    let locate = |e: Error| e.at(Location::new(value, 0, start, None));
+                    let end = start + Chunk::size_at(value, start).map_err(locate)?;
+                    let chunk = Chunk::try_from(&value[start..end]).map_err(locate)?;
+                    ops.push(PatchOp::Add { before_idat, chunk });
+                    i = end;
+                }
+                _ => return Err(format!("Malformed patch at offset {}", i).into()),
+            }
+        }
+
+        Ok(Patch { ops })
+    }
+}
+
+fn ancillary_key(chunk: &Chunk) -> (String, String) {
+    (chunk.chunk_type().to_string(), chunk.data_digest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn old() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"Author\0old"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    fn new() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"Author\0new"),
+            chunk("IDAT", b"new pixels"),
+            chunk("ruSt", b"payload"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_patch_create() {
+        let patch = Patch::create(&old(), &new());
+        assert_eq!(patch.ops().len(), 3);
+    }
+
+    #[test]
+    fn test_patch_apply_ignores_pixels() {
+        let patch = Patch::create(&old(), &new());
+        let patched = patch.apply(&old()).unwrap();
+
+        let expected = Png::from_chunks(vec![
+            chunk("IHDR", b"header"),
+            chunk("tEXt", b"Author\0new"),
+            chunk("IDAT", b"pixels"),
+            chunk("ruSt", b"payload"),
+            chunk("IEND", b""),
+        ]);
+        assert_eq!(patched.as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_patch_does_not_apply() {
+        let patch = Patch::create(&old(), &new());
+        assert!(patch.apply(&new()).is_err());
+    }
+
+    #[test]
+    fn test_patch_round_trip() {
+        let patch = Patch::create(&old(), &new());
+        let parsed = Patch::try_from(&patch.as_bytes()[..]).unwrap();
+        assert_eq!(parsed.as_bytes(), patch.as_bytes());
+        assert_eq!(
+            parsed.apply(&old()).unwrap().as_bytes(),
+            patch.apply(&old()).unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_patch_invalid() {
+        assert!(Patch::try_from(&b"PNGPATCH"[..]).is_err());
+        assert!(Patch::try_from(&b"PNGPATCH\x01?"[..]).is_err());
+        assert!(Patch::try_from(&b"PNGPATCH\x02"[..]).is_err());
+    }
+}
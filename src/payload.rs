@@ -0,0 +1,223 @@
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{limits, Result};
+
+/// Marks data written by `seal`. The leading 0x89 can never start valid UTF-8, so plain string
+/// payloads written by older versions are never mistaken for an envelope.
+pub const MAGIC: [u8; 4] = [0x89, b'p', b'c', b'k'];
+/// Current envelope version
+pub const VERSION: u8 = 1;
+/// Marks a message compressed by `compress`
+pub const COMPRESSED_MAGIC: [u8; 4] = [0x89, b'p', b'c', b'z'];
+/// Marks one part of chunk data that `split` spread over several chunks
+pub const PART_MAGIC: [u8; 4] = [0x89, b'p', b'c', b's'];
+/// The magic, then the part's sequence number and the total number of parts
+pub const PART_HEADER_LENGTH: usize = PART_MAGIC.len() + 8;
+
+/// The message stored in a chunk written by pngchunk
+#[derive(Debug, PartialEq, Eq)]
+pub enum Payload<'a> {
+    /// A message in a versioned envelope
+    Enveloped { version: u8, message: &'a [u8] },
+    /// A bare message, as written before envelopes were introduced
+    Legacy(&'a [u8]),
+}
+
+impl Payload<'_> {
+    pub fn message(&self) -> &[u8] {
+        match self {
+            Payload::Enveloped { message, .. } => message,
+            Payload::Legacy(message) => message,
+        }
+    }
+}
+
+/// Wraps `message` in an envelope of the current version
+pub fn seal(message: &[u8]) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    data.push(VERSION);
+    data.extend(message);
+    data
+}
+
+/// Reads the payload stored in chunk data, failing if it uses an envelope version newer than
+/// this build understands
+pub fn open(data: &[u8]) -> Result<Payload<'_>> {
+    match data.strip_prefix(&MAGIC[..]) {
+        Some([version, message @ ..]) if *version <= VERSION => Ok(Payload::Enveloped {
+            version: *version,
+            message,
+        }),
+        Some([version, ..]) => Err(format!(
+            "Payload uses envelope version {}, this build supports up to {}",
+            version, VERSION
+        )
+        .into()),
+        Some([]) => Err("Payload envelope is missing its version".into()),
+        None => Ok(Payload::Legacy(data)),
+    }
+}
+
+/// Compresses `message` with zlib, as PNG does for IDAT and zTXt, behind a marker so `decode`
+/// knows to inflate it
+pub fn compress(message: &[u8]) -> Result<Vec<u8>> {
+    let _span = tracing::info_span!("compress", bytes = message.len()).entered();
+    let mut encoder = ZlibEncoder::new(COMPRESSED_MAGIC.to_vec(), Compression::best());
+    encoder.write_all(message)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn is_compressed(message: &[u8]) -> bool {
+    message.starts_with(&COMPRESSED_MAGIC)
+}
+
+/// Inflates a message written by `compress`, leaving any other message as it is
+pub fn decompress(message: Vec<u8>) -> Result<Vec<u8>> {
+    match message.strip_prefix(&COMPRESSED_MAGIC[..]) {
+        Some(compressed) => limits::get().inflate(compressed),
+        None => Ok(message),
+    }
+}
+
+/// Splits chunk data that is larger than `max_length` into parts of at most that size, each
+/// prefixed with its sequence number and the number of parts. Data that fits is returned as is.
+pub fn split(data: &[u8], max_length: usize) -> Result<Vec<Vec<u8>>> {
+    if data.len() <= max_length {
+        return Ok(vec![data.to_vec()]);
+    }
+    if max_length <= PART_HEADER_LENGTH {
+        return Err(format!(
+            "Chunks must hold more than {} bytes to split a payload",
+            PART_HEADER_LENGTH
+        )
+        .into());
+    }
+    let pieces = data.chunks(max_length - PART_HEADER_LENGTH);
+    let count = u32::try_from(pieces.len()).map_err(|_| "Payload needs too many parts")?;
+    Ok(pieces
+        .enumerate()
+        .map(|(sequence, piece)| {
+            let mut part = PART_MAGIC.to_vec();
+            part.extend((sequence as u32).to_be_bytes());
+            part.extend(count.to_be_bytes());
+            part.extend(piece);
+            part
+        })
+        .collect())
+}
+
+pub fn is_part(data: &[u8]) -> bool {
+    data.starts_with(&PART_MAGIC) && data.len() >= PART_HEADER_LENGTH
+}
+
+/// Reassembles data written by `split` from its parts, in any order. Fails if a part is
+/// missing, repeated or from a split with a different number of parts.
+pub fn join(parts: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    join_into(parts, &mut data)?;
+    Ok(data)
+}
+
+/// Reassembles data written by `split` into `out`, checking every part before writing any
+pub fn join_into<W: Write>(parts: &[&[u8]], out: &mut W) -> Result<()> {
+    let mut pieces: Vec<(u32, &[u8])> = vec![];
+    let mut expected = None;
+    for part in parts {
+        if !is_part(part) {
+            return Err("Chunk is not part of a split payload".into());
+        }
+        let field =
+            |at: usize| u32::from_be_bytes([part[at], part[at + 1], part[at + 2], part[at + 3]]);
+        let (sequence, count) = (field(4), field(8));
+        if *expected.get_or_insert(count) != count {
+            return Err("Split payload parts disagree on the number of parts".into());
+        }
+        pieces.push((sequence, &part[PART_HEADER_LENGTH..]));
+    }
+    pieces.sort_by_key(|&(sequence, _)| sequence);
+    let count = expected.unwrap_or(0);
+    if pieces.len() != count as usize
+        || pieces
+            .iter()
+            .enumerate()
+            .any(|(i, &(sequence, _))| sequence != i as u32)
+    {
+        return Err(format!("Split payload has {} of its {} parts", pieces.len(), count).into());
+    }
+    for (_, piece) in pieces {
+        out.write_all(piece)?;
+    }
+    Ok(())
+}
+
+/// Whether `data` looks like a plain string payload that `migrate` should upgrade
+pub fn is_legacy(data: &[u8]) -> bool {
+    matches!(open(data), Ok(Payload::Legacy(_))) && std::str::from_utf8(data).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let data = seal(b"hidden message");
+        assert_eq!(&data[..5], &[0x89, b'p', b'c', b'k', VERSION]);
+        assert_eq!(
+            open(&data).unwrap(),
+            Payload::Enveloped {
+                version: VERSION,
+                message: b"hidden message"
+            }
+        );
+    }
+
+    #[test]
+    fn test_compress() {
+        let message = "hidden message ".repeat(100).into_bytes();
+        let compressed = compress(&message).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < message.len() / 10);
+        assert_eq!(decompress(compressed).unwrap(), message);
+        assert_eq!(decompress(b"plain".to_vec()).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn test_split_join() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(split(&data, 256).unwrap(), vec![data.clone()]);
+
+        let parts = split(&data, 112).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| p.len() <= 112 && is_part(p)));
+        assert_eq!(&parts[2][4..12], &[0, 0, 0, 2, 0, 0, 0, 3]);
+
+        let mut shuffled: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        shuffled.swap(0, 2);
+        assert_eq!(join(&shuffled).unwrap(), data);
+
+        assert!(join(&shuffled[..2]).is_err());
+        assert!(join(&[&parts[0], &parts[0], &parts[1]]).is_err());
+        assert!(split(&data, PART_HEADER_LENGTH).is_err());
+    }
+
+    #[test]
+    fn test_open_legacy() {
+        let payload = open(b"plain string").unwrap();
+        assert_eq!(payload, Payload::Legacy(b"plain string"));
+        assert_eq!(payload.message(), b"plain string");
+        assert!(is_legacy(b"plain string"));
+        assert!(!is_legacy(&seal(b"plain string")));
+        assert!(!is_legacy(&[0xff, 0xfe]));
+    }
+
+    #[test]
+    fn test_open_future_version() {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION + 1);
+        assert!(open(&data).is_err());
+        assert!(open(&MAGIC).is_err());
+    }
+}
@@ -1,22 +1,390 @@
+#![allow(dead_code)]
+
 use core::fmt;
+use std::io::Read;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    edit::PngEdit,
+    limits,
+    reader::ChunkReader,
+    visit::{ChunkVisitor, VisitAction},
+    Error, PngChunkError, Result,
+};
+
+/// How strictly `Png::parse` treats damage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Fail at the first problem, like `Png::try_from`
+    #[default]
+    Strict,
+    /// Read as much as possible and report each problem as a `ParseWarning`
+    Lenient,
+}
+
+/// A problem `Png::parse` worked around in lenient mode, or an oddity in the chunk layout that
+/// does not stop the file from being read in either mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// The stored CRC of the chunk at `offset` did not match its contents. The chunk keeps the
+    /// stored CRC until `Png::fix_crcs` is called.
+    BadCrc {
+        offset: usize,
+        chunk_type: ChunkType,
+        stored: u32,
+        computed: u32,
+    },
+    /// The chunk at `offset` declares more data than the file holds. Reading stopped there.
+    LengthMismatch {
+        offset: usize,
+        declared: u32,
+        available: usize,
+    },
+    /// Fewer bytes than the smallest possible chunk remain at `offset`. They were dropped.
+    Truncated { offset: usize, length: usize },
+    /// The chunk type at `offset` is not four ASCII letters, so its length and contents cannot
+    /// be trusted either. Reading stopped there.
+    InvalidType { offset: usize, bytes: [u8; 4] },
+    /// Bytes after IEND, which were dropped
+    TrailingData { offset: usize, length: usize },
+    /// The first chunk is not IHDR
+    MissingIhdr,
+    /// There is no IEND chunk
+    MissingIend,
+    /// Chunks after IEND, which most decoders ignore
+    AfterIend { count: usize },
+}
 
-use crate::{chunk::Chunk, Error, Result};
+impl ParseWarning {
+    /// Whether this is damage lenient mode had to work around, rather than an oddity in the
+    /// chunk layout
+    pub fn is_damage(&self) -> bool {
+        !matches!(
+            self,
+            ParseWarning::MissingIhdr | ParseWarning::MissingIend | ParseWarning::AfterIend { .. }
+        )
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::BadCrc {
+                offset,
+                chunk_type,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "CRC of {} chunk at offset {} is {:#010x}, should be {:#010x}",
+                chunk_type, offset, stored, computed
+            ),
+            ParseWarning::LengthMismatch {
+                offset,
+                declared,
+                available,
+            } => write!(
+                f,
+                "Chunk at offset {} declares {} bytes of data but only {} remain",
+                offset, declared, available
+            ),
+            ParseWarning::Truncated { offset, length } => {
+                write!(
+                    f,
+                    "Truncated chunk of {} bytes at offset {}",
+                    length, offset
+                )
+            }
+            ParseWarning::InvalidType { offset, bytes } => write!(
+                f,
+                "Chunk type {:02x?} at offset {} is not four ASCII letters",
+                bytes, offset
+            ),
+            ParseWarning::TrailingData { offset, length } => {
+                write!(f, "{} bytes after IEND at offset {}", length, offset)
+            }
+            ParseWarning::MissingIhdr => write!(f, "The first chunk is not IHDR"),
+            ParseWarning::MissingIend => write!(f, "There is no IEND chunk"),
+            ParseWarning::AfterIend { count } => {
+                write!(
+                    f,
+                    "{} chunk(s) after IEND, which most decoders ignore",
+                    count
+                )
+            }
+        }
+    }
+}
+
+/// Tracks the chunk types of a file as they are read, to find the oddities reported by
+/// `Png::layout_warnings` without keeping the chunks
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Layout {
+    chunks: usize,
+    starts_with_ihdr: bool,
+    /// The number of chunks read up to and including the first IEND
+    iend: Option<usize>,
+}
+
+impl Layout {
+    pub(crate) fn add(&mut self, chunk_type: &ChunkType) {
+        let chunk_type = chunk_type.bytes();
+        if self.chunks == 0 {
+            self.starts_with_ihdr = &chunk_type == b"IHDR";
+        }
+        self.chunks += 1;
+        if &chunk_type == b"IEND" && self.iend.is_none() {
+            self.iend = Some(self.chunks);
+        }
+    }
 
+    pub(crate) fn warnings(&self) -> Vec<ParseWarning> {
+        let mut warnings = vec![];
+        if !self.starts_with_ihdr {
+            warnings.push(ParseWarning::MissingIhdr);
+        }
+        match self.iend {
+            None => warnings.push(ParseWarning::MissingIend),
+            Some(iend) if iend < self.chunks => warnings.push(ParseWarning::AfterIend {
+                count: self.chunks - iend,
+            }),
+            Some(_) => {}
+        }
+        warnings
+    }
+}
+
+/// A parsed PNG file. Chunk data is shared between clones, so a `Png` can be cloned cheaply
+/// and handed to other threads.
+#[derive(Debug, Clone)]
 pub struct Png {
     m_chunks: Vec<Chunk>,
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    /// Ancillary chunks larger than this are rejected by some decoders (libpng among them,
+    /// depending on how it was built)
+    pub const ANCILLARY_SIZE_LIMIT: usize = 1 << 20;
+
+    /// Whether `bytes` starts with the PNG signature
+    pub fn has_valid_signature(bytes: &[u8]) -> bool {
+        bytes.starts_with(&Png::STANDARD_HEADER)
+    }
+
+    /// Checks that `bytes` starts with the PNG signature, explaining what was found instead if
+    /// it does not
+    pub fn check_signature(bytes: &[u8]) -> Result<()> {
+        if Png::has_valid_signature(bytes) {
+            return Ok(());
+        }
+
+        let reason = if bytes.len() < Png::STANDARD_HEADER.len()
+            && Png::STANDARD_HEADER.starts_with(bytes)
+        {
+            format!("file is only {} bytes long", bytes.len())
+        } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+            "this looks like a JPEG file".to_string()
+        } else if bytes.starts_with(b"GIF8") {
+            "this looks like a GIF file".to_string()
+        } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+            "this looks like a WebP file".to_string()
+        } else if bytes.starts_with(&Png::STANDARD_HEADER[..4]) {
+            // The signature's CR LF, SUB and LF bytes exist to catch text-mode transfers
+            "the signature is damaged, likely by a transfer that converted line endings".to_string()
+        } else {
+            let found: Vec<String> = bytes.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+            format!("found {} instead", found.join(" "))
+        };
+        Err(PngChunkError::Parse(format!(
+            "Not a PNG file, the PNG signature is missing: {}",
+            reason
+        )))
+    }
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
         Self { m_chunks: chunks }
     }
 
+    /// Parses a PNG from `reader` a chunk at a time, without first reading the whole file into
+    /// memory. See `ChunkReader` to process chunks without keeping them all.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png> {
+        let chunks = ChunkReader::new(reader).collect::<Result<Vec<Chunk>>>()?;
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Parses `bytes` into `bump`, avoiding a separate heap allocation for every chunk. Intended
+    /// for services that parse large numbers of small files and can reset the arena in between.
+    #[cfg(feature = "arena")]
+    pub fn parse_in<'bump>(
+        bump: &'bump bumpalo::Bump,
+        bytes: &[u8],
+    ) -> Result<crate::arena::ArenaPng<'bump>> {
+        crate::arena::parse(bump, bytes)
+    }
+
+    /// Walks through the chunks in `bytes`, handing each one to `visitor` without building a
+    /// `Png`. Parsing stops early, without error, if the visitor asks it to.
+    pub fn parse_with_visitor<V: ChunkVisitor>(bytes: &[u8], visitor: &mut V) -> Result<()> {
+        Png::check_signature(bytes)?;
+
+        let mut i: usize = 8;
+        let mut index = 0;
+        while i < bytes.len() {
+            let locate = |e: PngChunkError| e.in_chunk(bytes, 0, i, index);
+            if bytes.len() - i < Chunk::MIN_CHUNK_LENGTH {
+                return Err(locate(PngChunkError::Parse("Truncated chunk".to_string())));
+            }
+
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(&bytes[i..i + 4]);
+            let length = u32::from_be_bytes(buf);
+            buf.copy_from_slice(&bytes[i + 4..i + 8]);
+            let chunk_type = ChunkType::try_from(buf).map_err(locate)?;
+            let chunk_size = Chunk::size_at(bytes, i).map_err(locate)?;
+
+            match visitor.visit_header(i, length, &chunk_type) {
+                VisitAction::Stop => return Ok(()),
+                VisitAction::SkipBody => {}
+                VisitAction::Continue => {
+                    let chunk = Chunk::try_from(&bytes[i..i + chunk_size]).map_err(locate)?;
+                    if visitor.visit_body(&chunk) == VisitAction::Stop {
+                        return Ok(());
+                    }
+                }
+            }
+
+            i += chunk_size;
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Parses `bytes` in the given mode. Strict mode fails on damage like `try_from`. Lenient
+    /// mode recovers from damage instead of failing on it: a chunk with a wrong CRC is kept as it
+    /// is, and reading stops after IEND or at the first chunk that has a malformed type or does
+    /// not fit in the file, dropping the bytes from there on. Each problem is returned as
+    /// a warning. Only a missing signature is still an error. In both modes the warnings end
+    /// with any oddities in the chunk layout, see `layout_warnings`.
+    pub fn parse(bytes: &[u8], mode: ParseMode) -> Result<(Png, Vec<ParseWarning>)> {
+        if mode == ParseMode::Strict {
+            let png = Png::try_from(bytes)?;
+            let warnings = png.layout_warnings();
+            return Ok((png, warnings));
+        }
+        let _span = tracing::info_span!("parse", bytes = bytes.len(), lenient = true).entered();
+        Png::check_signature(bytes)?;
+
+        let mut warnings = vec![];
+        let mut chunks = vec![];
+        let limits = limits::get();
+        let mut i = Png::STANDARD_HEADER.len();
+        while i < bytes.len() {
+            let available = bytes.len() - i;
+            if available < Chunk::MIN_CHUNK_LENGTH {
+                warnings.push(ParseWarning::Truncated {
+                    offset: i,
+                    length: available,
+                });
+                break;
+            }
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(&bytes[i..i + 4]);
+            let declared = u32::from_be_bytes(buf);
+            let Ok(chunk_size) = Chunk::size_at(bytes, i) else {
+                warnings.push(ParseWarning::LengthMismatch {
+                    offset: i,
+                    declared,
+                    available: available - Chunk::MIN_CHUNK_LENGTH,
+                });
+                break;
+            };
+            buf.copy_from_slice(&bytes[i + 4..i + 8]);
+            if !buf.iter().all(u8::is_ascii_alphabetic) {
+                warnings.push(ParseWarning::InvalidType {
+                    offset: i,
+                    bytes: buf,
+                });
+                break;
+            }
+            let chunk_type = ChunkType::new(buf);
+            limits.check_chunk_count(chunks.len() + 1)?;
+            let end = i + chunk_size;
+            buf.copy_from_slice(&bytes[end - 4..end]);
+            let stored = u32::from_be_bytes(buf);
+            let chunk =
+                Chunk::with_stored_crc(chunk_type.clone(), bytes[i + 8..end - 4].to_vec(), stored)?;
+            if !chunk.crc_is_valid() {
+                warnings.push(ParseWarning::BadCrc {
+                    offset: i,
+                    chunk_type: chunk_type.clone(),
+                    stored,
+                    computed: chunk.crc(),
+                });
+            }
+            chunks.push(chunk);
+            i = end;
+            if chunk_type.to_string() == "IEND" && i < bytes.len() {
+                warnings.push(ParseWarning::TrailingData {
+                    offset: i,
+                    length: bytes.len() - i,
+                });
+                break;
+            }
+        }
+        let png = Png::from_chunks(chunks);
+        warnings.extend(png.layout_warnings());
+        Ok((png, warnings))
+    }
+
+    /// Oddities in the chunk layout that do not stop the file from being read: a first chunk
+    /// other than IHDR, a missing IEND, and chunks after IEND
+    pub fn layout_warnings(&self) -> Vec<ParseWarning> {
+        let mut layout = Layout::default();
+        self.m_chunks
+            .iter()
+            .for_each(|c| layout.add(c.chunk_type()));
+        layout.warnings()
+    }
+
+    /// Inserts `chunk` before the last chunk, which is IEND in a well-formed file. Use
+    /// `insert_before_iend` for files that may have chunks after IEND.
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.m_chunks.insert(self.m_chunks.len() - 1, chunk);
     }
 
+    /// Inserts `chunk` just before IEND, or at the end if there is no IEND, and returns the
+    /// index it ended up at
+    pub fn insert_before_iend(&mut self, chunk: Chunk) -> usize {
+        let index = self
+            .m_chunks
+            .iter()
+            .position(|c| c.chunk_type().to_string() == "IEND")
+            .unwrap_or(self.m_chunks.len());
+        self.m_chunks.insert(index, chunk);
+        index
+    }
+
+    /// Adds `chunk` after every other chunk, even IEND. Most decoders ignore anything after
+    /// IEND, so this is only useful for hiding data or reproducing such files.
+    pub fn push_chunk(&mut self, chunk: Chunk) -> usize {
+        self.m_chunks.push(chunk);
+        self.m_chunks.len() - 1
+    }
+
+    /// Replaces every stored CRC that does not match its chunk with the computed one,
+    /// returning how many were fixed
+    pub fn fix_crcs(&mut self) -> usize {
+        let mut fixed = 0;
+        for chunk in self.m_chunks.iter_mut().filter(|c| !c.crc_is_valid()) {
+            chunk.fix_crc();
+            fixed += 1;
+        }
+        fixed
+    }
+
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         if let Some(index) = self
             .m_chunks
@@ -25,7 +393,12 @@ impl Png {
         {
             return Ok(self.m_chunks.remove(index));
         }
-        Err("Chunk not found!".into())
+        Err(PngChunkError::ChunkNotFound(chunk_type.to_string()))
+    }
+
+    /// Starts a set of changes that are checked together and either all applied or discarded
+    pub fn edit(&mut self) -> PngEdit<'_> {
+        PngEdit::new(self)
     }
 
     pub fn header(&self) -> [u8; 8] {
@@ -47,12 +420,55 @@ impl Png {
         }
     }
 
+    /// Describes the ways in which the chunk at `index` may trip up common decoders, either
+    /// because of where it is placed or because of its size
+    pub fn placement_warnings(&self, index: usize) -> Vec<String> {
+        let mut warnings = vec![];
+        let chunk = match self.m_chunks.get(index) {
+            Some(chunk) => chunk,
+            None => return warnings,
+        };
+
+        let position = |chunk_type: &str| {
+            self.m_chunks
+                .iter()
+                .position(|c| c.chunk_type().to_string() == chunk_type)
+        };
+
+        if let Some(ihdr) = position("IHDR") {
+            if index < ihdr {
+                warnings.push(format!(
+                    "{} is placed before IHDR, which must be the first chunk; move it after IHDR",
+                    chunk.chunk_type()
+                ));
+            }
+        }
+        if let Some(iend) = position("IEND") {
+            if index > iend {
+                warnings.push(format!(
+                    "{} is placed after IEND and will be ignored by most decoders; move it before IEND",
+                    chunk.chunk_type()
+                ));
+            }
+        }
+        if !chunk.chunk_type().is_critical() && chunk.data().len() > Png::ANCILLARY_SIZE_LIMIT {
+            warnings.push(format!(
+                "{} holds {} bytes, more than the {} bytes some decoders accept in an ancillary chunk; consider a smaller payload",
+                chunk.chunk_type(),
+                chunk.data().len(),
+                Png::ANCILLARY_SIZE_LIMIT
+            ));
+        }
+
+        warnings
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
+        let _span = tracing::info_span!("serialize", chunks = self.m_chunks.len()).entered();
         let chunk_bytes = self
             .m_chunks
             .iter()
-            .map(|chunk| chunk.as_bytes())
-            .flatten()
+            .flat_map(|chunk| chunk.as_bytes())
             .collect::<Vec<u8>>();
 
         self.header()
@@ -75,20 +491,17 @@ impl fmt::Display for Png {
 impl TryFrom<&[u8]> for Png {
     type Error = Error;
     fn try_from(value: &[u8]) -> Result<Self> {
-        if value[..8] != Png::STANDARD_HEADER {
-            return Err("First 8 bytes do not match png signature.".into());
-        }
+        let _span = tracing::info_span!("parse", bytes = value.len()).entered();
+        Png::check_signature(value)?;
 
         let mut i: usize = 8;
         let mut m_chunks: Vec<Chunk> = vec![];
+        let limits = limits::get();
         while i < value.len() {
-            // get length
-            let mut buf = [0u8; 4];
-            buf.copy_from_slice(&value[i..i + 4]);
-            let length = u32::from_be_bytes(buf) as usize;
-            let chunk_size = Chunk::MIN_CHUNK_LENGTH + length;
-
-            let chunk = Chunk::try_from(&value[i..i + chunk_size])?;
+            limits.check_chunk_count(m_chunks.len() + 1)?;
+            let locate = |e: PngChunkError| e.in_chunk(value, 0, i, m_chunks.len());
+            let chunk_size = Chunk::size_at(value, i).map_err(locate)?;
+            let chunk = Chunk::try_from(&value[i..i + chunk_size]).map_err(locate)?;
             m_chunks.push(chunk);
 
             i += chunk_size;
@@ -103,9 +516,11 @@ mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
+    use crate::testing;
     use std::convert::TryFrom;
     use std::str::FromStr;
 
+    #[allow(clippy::vec_init_then_push)]
     fn testing_chunks() -> Vec<Chunk> {
         let mut chunks = Vec::new();
 
@@ -127,7 +542,7 @@ mod tests {
         let chunk_type = ChunkType::from_str(chunk_type)?;
         let data: Vec<u8> = data.bytes().collect();
 
-        Ok(Chunk::new(chunk_type, data))
+        Chunk::new(chunk_type, data)
     }
 
     #[test]
@@ -174,6 +589,31 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_check_signature() {
+        assert!(Png::has_valid_signature(&PNG_FILE));
+        assert!(Png::check_signature(&PNG_FILE).is_ok());
+
+        let message = |bytes: &[u8]| Png::check_signature(bytes).unwrap_err().to_string();
+        assert!(message(&PNG_FILE[..3]).contains("only 3 bytes"));
+        assert!(message(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10]).contains("JPEG"));
+        assert!(message(b"GIF89a").contains("GIF"));
+        assert!(message(b"\x89PNG\n\x1a\n\0\0").contains("line endings"));
+        assert!(message(b"hello world").contains("68 65 6c 6c 6f 20 77 6f"));
+        assert!(!Png::has_valid_signature(b""));
+    }
+
+    #[test]
+    fn test_try_from_explains_signature() {
+        let message = |bytes: &[u8]| Png::try_from(bytes).unwrap_err().to_string();
+        assert!(message(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F']).contains("JPEG"));
+
+        // A text-mode transfer turns the signature's LF into CR LF
+        let mut mangled = b"\x89PNG\r\r\n\x1a\r\n".to_vec();
+        mangled.extend(&PNG_FILE[8..]);
+        assert!(message(&mangled).contains("line endings"));
+    }
+
     #[test]
     fn test_invalid_chunk() {
         let mut chunk_bytes: Vec<u8> = testing_chunks()
@@ -220,6 +660,24 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_before_iend() {
+        let iend = || testing::chunk("IEND", &[]);
+        let mut png = Png::from_chunks(vec![
+            iend(),
+            chunk_from_strings("zzZz", "trailing").unwrap(),
+        ]);
+        let index = png.insert_before_iend(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(index, 0);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "IEND");
+
+        let index = png.push_chunk(chunk_from_strings("LaSt", "Message").unwrap());
+        assert_eq!(index, 3);
+
+        let mut empty = Png::from_chunks(vec![]);
+        assert_eq!(empty.insert_before_iend(iend()), 0);
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -229,6 +687,220 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_placement_warnings() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("ruSt", "too early").unwrap(),
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("ruSt", "fine").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("ruSt", "too late").unwrap(),
+        ]);
+
+        assert_eq!(png.placement_warnings(0).len(), 1);
+        assert!(png.placement_warnings(2).is_empty());
+        assert_eq!(png.placement_warnings(4).len(), 1);
+    }
+
+    #[test]
+    fn test_placement_warnings_size() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let png = Png::from_chunks(vec![Chunk::new(
+            chunk_type,
+            vec![0; Png::ANCILLARY_SIZE_LIMIT + 1],
+        )
+        .unwrap()]);
+
+        assert_eq!(png.placement_warnings(0).len(), 1);
+    }
+
+    #[derive(Default)]
+    struct CountingVisitor {
+        headers: usize,
+        bodies: usize,
+        stop_after: Option<usize>,
+    }
+
+    impl ChunkVisitor for CountingVisitor {
+        fn visit_header(&mut self, _: usize, _: u32, chunk_type: &ChunkType) -> VisitAction {
+            self.headers += 1;
+            if Some(self.headers) == self.stop_after {
+                VisitAction::Stop
+            } else if chunk_type.to_string() == "IDAT" {
+                VisitAction::SkipBody
+            } else {
+                VisitAction::Continue
+            }
+        }
+
+        fn visit_body(&mut self, _: &Chunk) -> VisitAction {
+            self.bodies += 1;
+            VisitAction::Continue
+        }
+    }
+
+    #[test]
+    fn test_parse_with_visitor() {
+        let mut visitor = CountingVisitor::default();
+        Png::parse_with_visitor(&PNG_FILE, &mut visitor).unwrap();
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idats = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == "IDAT")
+            .count();
+        assert_eq!(visitor.headers, png.chunks().len());
+        assert_eq!(visitor.bodies, png.chunks().len() - idats);
+    }
+
+    #[test]
+    fn test_parse_with_visitor_stop() {
+        let mut visitor = CountingVisitor {
+            stop_after: Some(2),
+            ..Default::default()
+        };
+        Png::parse_with_visitor(&PNG_FILE, &mut visitor).unwrap();
+
+        assert_eq!(visitor.headers, 2);
+        assert_eq!(visitor.bodies, 1);
+    }
+
+    #[test]
+    fn test_parse_with_visitor_truncated() {
+        let mut visitor = CountingVisitor::default();
+        assert!(Png::parse_with_visitor(&PNG_FILE[..100], &mut visitor).is_err());
+    }
+
+    #[test]
+    fn test_truncated_from_bytes() {
+        assert!(Png::try_from(&PNG_FILE[..4]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..PNG_FILE.len() - 1]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..PNG_FILE.len() - 12 + 2]).is_err());
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the CRC of the first chunk, then add garbage after IEND
+        bytes[8 + 12 + 13 - 1] ^= 0xff;
+        bytes.extend(b"garbage");
+        assert!(Png::try_from(&bytes[..]).is_err());
+        assert!(Png::parse(&bytes, ParseMode::Strict).is_err());
+
+        let (png, warnings) = Png::parse(&bytes, ParseMode::Lenient).unwrap();
+        assert!(!png.chunks()[0].crc_is_valid());
+        assert_eq!(png.as_bytes(), bytes[..PNG_FILE.len()]);
+        let mut fixed = png.clone();
+        assert_eq!(fixed.fix_crcs(), 1);
+        assert!(fixed.chunks().iter().all(Chunk::crc_is_valid));
+        assert!(matches!(
+            warnings[..],
+            [
+                ParseWarning::BadCrc { offset: 8, .. },
+                ParseWarning::TrailingData { length: 7, .. },
+            ]
+        ));
+        assert_eq!(
+            warnings[1].to_string(),
+            format!("7 bytes after IEND at offset {}", PNG_FILE.len())
+        );
+
+        // Nothing from a chunk with a malformed type on can be trusted, so reading stops there
+        bytes[8 + 25 + 4] = b'1';
+        let (png, warnings) = Png::parse(&bytes, ParseMode::Lenient).unwrap();
+        assert_eq!(png.chunks().len(), 1);
+        assert!(matches!(
+            warnings[..],
+            [
+                ParseWarning::BadCrc { offset: 8, .. },
+                ParseWarning::InvalidType { offset: 33, .. },
+                ParseWarning::MissingIend,
+            ]
+        ));
+
+        let (png, warnings) =
+            Png::parse(&PNG_FILE[..PNG_FILE.len() - 2], ParseMode::Lenient).unwrap();
+        assert_eq!(
+            png.chunks().len(),
+            Png::try_from(&PNG_FILE[..]).unwrap().chunks().len() - 1
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                ParseWarning::Truncated {
+                    offset: PNG_FILE.len() - 12,
+                    length: 10
+                },
+                ParseWarning::MissingIend,
+            ]
+        );
+
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let (png, warnings) = Png::parse(&bytes, ParseMode::Lenient).unwrap();
+        assert!(png.chunks().is_empty());
+        assert!(matches!(
+            warnings[..],
+            [
+                ParseWarning::LengthMismatch {
+                    offset: 8,
+                    declared: u32::MAX,
+                    ..
+                },
+                ParseWarning::MissingIhdr,
+                ParseWarning::MissingIend,
+            ]
+        ));
+
+        assert!(Png::parse(b"not a png", ParseMode::Lenient).is_err());
+        assert!(Png::parse(&PNG_FILE, ParseMode::Lenient)
+            .unwrap()
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn test_layout_warnings() {
+        let (png, warnings) = Png::parse(&PNG_FILE, ParseMode::Strict).unwrap();
+        assert!(warnings.is_empty());
+
+        let mut chunks = png.chunks().to_vec();
+        chunks.push(chunk_from_strings("ruSt", "hidden").unwrap());
+        let bytes = Png::from_chunks(chunks).as_bytes();
+        let (_, warnings) = Png::parse(&bytes, ParseMode::Strict).unwrap();
+        assert_eq!(warnings, [ParseWarning::AfterIend { count: 1 }]);
+        assert!(!warnings[0].is_damage());
+
+        assert_eq!(
+            testing_png().layout_warnings(),
+            [ParseWarning::MissingIhdr, ParseWarning::MissingIend]
+        );
+    }
+
+    #[test]
+    fn test_pathological_length() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Png::try_from(&bytes[..]).is_err());
+
+        let mut visitor = CountingVisitor::default();
+        assert!(Png::parse_with_visitor(&bytes, &mut visitor).is_err());
+    }
+
+    #[test]
+    fn test_png_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Png>();
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let clone = png.clone();
+        assert!(std::ptr::eq(
+            png.chunks()[1].data(),
+            clone.chunks()[1].data()
+        ));
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -236,6 +908,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::iter_cloned_collect)]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
@@ -0,0 +1,184 @@
+use core::fmt;
+
+/// Structured text formats that are commonly stored in text and private chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Json,
+    Xml,
+    /// An XML property list, formatted like any other XML
+    Plist,
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Syntax::Json => "JSON",
+            Syntax::Xml => "XML",
+            Syntax::Plist => "plist",
+        })
+    }
+}
+
+/// Guesses the syntax of `text`, or returns `None` if it does not look structured
+pub fn detect(text: &str) -> Option<Syntax> {
+    let text = text.trim();
+    if text.starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        Some(Syntax::Json)
+    } else if text.starts_with('<') && text.ends_with('>') {
+        if text.contains("<plist") {
+            Some(Syntax::Plist)
+        } else {
+            Some(Syntax::Xml)
+        }
+    } else {
+        None
+    }
+}
+
+/// Re-indents `text` if it is JSON, XML or a plist, returning the syntax and formatted text
+pub fn pretty(text: &str) -> Option<(Syntax, String)> {
+    let syntax = detect(text)?;
+    let formatted = match syntax {
+        Syntax::Json => {
+            let value: serde_json::Value = serde_json::from_str(text.trim()).ok()?;
+            serde_json::to_string_pretty(&value).ok()?
+        }
+        Syntax::Xml | Syntax::Plist => indent_xml(text.trim())?,
+    };
+    Some((syntax, formatted))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    /// Self-closing elements, declarations, comments and processing instructions
+    Empty(&'a str),
+    Text(&'a str),
+}
+
+fn tokenize(xml: &str) -> Option<Vec<Token<'_>>> {
+    let mut tokens = vec![];
+    let mut rest = xml;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let end = if rest.starts_with("<!--") {
+                rest.find("-->")? + 3
+            } else if rest.starts_with("<![CDATA[") {
+                rest.find("]]>")? + 3
+            } else {
+                rest.find('>')? + 1
+            };
+            let tag = &rest[..end];
+            tokens.push(if tag.starts_with("</") {
+                Token::Close(tag)
+            } else if tag.starts_with("<?") || tag.starts_with("<!") || tag.ends_with("/>") {
+                Token::Empty(tag)
+            } else {
+                Token::Open(tag)
+            });
+            rest = &rest[end..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                tokens.push(Token::Text(text));
+            }
+            rest = &rest[end..];
+        }
+    }
+    Some(tokens)
+}
+
+/// Puts every element on its own line, indented by nesting depth. Elements holding only text
+/// stay on one line. Returns `None` if the tags are not balanced.
+fn indent_xml(xml: &str) -> Option<String> {
+    let tokens = tokenize(xml)?;
+    let mut lines = vec![];
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < tokens.len() {
+        let pad = "  ".repeat(depth);
+        match tokens[i..] {
+            [Token::Open(open), Token::Text(text), Token::Close(close), ..] => {
+                lines.push(format!("{}{}{}{}", pad, open, text, close));
+                i += 3;
+                continue;
+            }
+            [Token::Open(open), Token::Close(close), ..] => {
+                lines.push(format!("{}{}{}", pad, open, close));
+                i += 2;
+                continue;
+            }
+            _ => {}
+        }
+        match tokens[i] {
+            Token::Open(tag) => {
+                lines.push(format!("{}{}", pad, tag));
+                depth += 1;
+            }
+            Token::Close(tag) => {
+                depth = depth.checked_sub(1)?;
+                lines.push(format!("{}{}", "  ".repeat(depth), tag));
+            }
+            Token::Empty(tag) | Token::Text(tag) => lines.push(format!("{}{}", pad, tag)),
+        }
+        i += 1;
+    }
+    if depth != 0 {
+        return None;
+    }
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(r#" {"a": 1} "#), Some(Syntax::Json));
+        assert_eq!(
+            detect("<x:xmpmeta><rdf:RDF/></x:xmpmeta>"),
+            Some(Syntax::Xml)
+        );
+        assert_eq!(
+            detect(r#"<?xml version="1.0"?><plist version="1.0"><dict/></plist>"#),
+            Some(Syntax::Plist)
+        );
+        assert_eq!(detect("{not json"), None);
+        assert_eq!(detect("Created with GIMP"), None);
+    }
+
+    #[test]
+    fn test_pretty_json() {
+        let (syntax, text) = pretty(r#"{"a":[1,2]}"#).unwrap();
+        assert_eq!(syntax, Syntax::Json);
+        assert_eq!(text, "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+    }
+
+    #[test]
+    fn test_pretty_xml() {
+        let xml = r#"<?xml version="1.0"?><plist><dict><key>Name</key><string>a &lt; b</string><true/><array></array></dict></plist>"#;
+        let (syntax, text) = pretty(xml).unwrap();
+        assert_eq!(syntax, Syntax::Plist);
+        assert_eq!(
+            text,
+            "<?xml version=\"1.0\"?>\n\
+             <plist>\n  \
+               <dict>\n    \
+                 <key>Name</key>\n    \
+                 <string>a &lt; b</string>\n    \
+                 <true/>\n    \
+                 <array></array>\n  \
+               </dict>\n\
+             </plist>"
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_xml() {
+        assert_eq!(pretty("<a><b></a></b></a>"), None);
+        assert_eq!(pretty("<a><b></a>"), None);
+    }
+}
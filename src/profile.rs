@@ -0,0 +1,262 @@
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::{chunk::Chunk, png::Png, Error, Result};
+
+/// Names of the built-in profiles
+pub const BUILT_IN: [&str; 3] = ["web", "archive", "social"];
+
+/// What a profile does with a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Keep,
+    Remove,
+}
+
+/// Matches chunks either by exact type or by a named group of related types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Type(String),
+    /// tEXt, zTXt and iTXt
+    Text,
+    /// eXIf
+    Exif,
+    /// tIME
+    Time,
+    /// iCCP, sRGB, gAMA, cHRM and sBIT
+    Color,
+    /// acTL, fcTL and fdAT
+    Animation,
+    /// Every private chunk
+    Private,
+}
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Selector> {
+        Ok(match s {
+            "text" => Selector::Text,
+            "exif" => Selector::Exif,
+            "time" => Selector::Time,
+            "color" => Selector::Color,
+            "animation" => Selector::Animation,
+            "private" => Selector::Private,
+            _ if s.len() == 4 && s.bytes().all(|b| b.is_ascii_alphabetic()) => {
+                Selector::Type(s.to_string())
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown chunk selector '{}', expected a chunk type or one of text, exif, time, color, animation or private",
+                    s
+                )
+                .into())
+            }
+        })
+    }
+
+    pub fn matches(&self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type().to_string();
+        let one_of = |types: &[&str]| types.contains(&chunk_type.as_str());
+        match self {
+            Selector::Type(t) => *t == chunk_type,
+            Selector::Text => one_of(&["tEXt", "zTXt", "iTXt"]),
+            Selector::Exif => one_of(&["eXIf"]),
+            Selector::Time => one_of(&["tIME"]),
+            Selector::Color => one_of(&["iCCP", "sRGB", "gAMA", "cHRM", "sBIT"]),
+            Selector::Animation => one_of(&["acTL", "fcTL", "fdAT"]),
+            Selector::Private => !chunk.chunk_type().is_public(),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Selector::parse(s)
+    }
+}
+
+/// Decides which ancillary chunks to keep when stripping metadata. Critical chunks are always
+/// kept; otherwise `keep` wins over `remove`, and chunks matching neither get `default`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub keep: Vec<Selector>,
+    pub remove: Vec<Selector>,
+    pub default: Action,
+}
+
+impl Default for Profile {
+    /// Removes every ancillary chunk
+    fn default() -> Self {
+        Profile {
+            keep: vec![],
+            remove: vec![],
+            default: Action::Remove,
+        }
+    }
+}
+
+impl Profile {
+    /// Looks up a built-in profile:
+    ///
+    /// * `web` keeps what affects how the image looks (color information, transparency and
+    ///   animation) and removes everything else
+    /// * `social` is `web` without ICC profiles, which can identify the device or software that
+    ///   made the image
+    /// * `archive` keeps every public chunk and removes private ones
+    pub fn built_in(name: &str) -> Option<Profile> {
+        let appearance = vec![
+            Selector::Color,
+            Selector::Type("tRNS".to_string()),
+            Selector::Animation,
+        ];
+        match name {
+            "web" => Some(Profile {
+                keep: appearance,
+                ..Default::default()
+            }),
+            "social" => Some(Profile {
+                keep: ["sRGB", "gAMA", "cHRM", "sBIT", "tRNS"]
+                    .iter()
+                    .map(|t| Selector::Type(t.to_string()))
+                    .chain([Selector::Animation])
+                    .collect(),
+                ..Default::default()
+            }),
+            "archive" => Some(Profile {
+                keep: vec![],
+                remove: vec![Selector::Private],
+                default: Action::Keep,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads a profile file, a JSON object such as
+    /// `{"keep": ["color", "pHYs"], "remove": ["text"], "default": "remove"}`
+    pub fn parse(bytes: &[u8]) -> Result<Profile> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        let object = value.as_object().ok_or("Profile must be a JSON object")?;
+        let selectors = |key: &str| -> Result<Vec<Selector>> {
+            match &value[key] {
+                Value::Null => Ok(vec![]),
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| {
+                        Selector::parse(item.as_str().ok_or(format!("{} must list strings", key))?)
+                    })
+                    .collect(),
+                _ => Err(format!("{} must be an array", key).into()),
+            }
+        };
+        if let Some(key) = object
+            .keys()
+            .find(|k| !["keep", "remove", "default"].contains(&k.as_str()))
+        {
+            return Err(format!("Unknown profile setting '{}'", key).into());
+        }
+        let default = match value["default"].as_str() {
+            None | Some("remove") => Action::Remove,
+            Some("keep") => Action::Keep,
+            Some(other) => {
+                return Err(format!("default must be keep or remove, got '{}'", other).into())
+            }
+        };
+        Ok(Profile {
+            keep: selectors("keep")?,
+            remove: selectors("remove")?,
+            default,
+        })
+    }
+
+    pub fn action(&self, chunk: &Chunk) -> Action {
+        if chunk.chunk_type().is_critical() || self.keep.iter().any(|s| s.matches(chunk)) {
+            Action::Keep
+        } else if self.remove.iter().any(|s| s.matches(chunk)) {
+            Action::Remove
+        } else {
+            self.default
+        }
+    }
+
+    /// Applies the profile to `png`, returning the stripped file and the chunks removed
+    pub fn strip(&self, png: &Png) -> (Png, Vec<Chunk>) {
+        let (kept, removed): (Vec<Chunk>, Vec<Chunk>) = png
+            .chunks()
+            .iter()
+            .cloned()
+            .partition(|chunk| self.action(chunk) == Action::Keep);
+        (Png::from_chunks(kept), removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(
+            [
+                "IHDR", "iCCP", "pHYs", "tEXt", "eXIf", "tRNS", "IDAT", "tIME", "prVt", "IEND",
+            ]
+            .iter()
+            .map(|t| chunk(t, &[]))
+            .collect(),
+        )
+    }
+
+    fn kept(profile: &Profile) -> Vec<String> {
+        profile
+            .strip(&testing_png())
+            .0
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_built_in_profiles() {
+        let web = Profile::built_in("web").unwrap();
+        assert_eq!(kept(&web), ["IHDR", "iCCP", "tRNS", "IDAT", "IEND"]);
+        let social = Profile::built_in("social").unwrap();
+        assert_eq!(kept(&social), ["IHDR", "tRNS", "IDAT", "IEND"]);
+        let archive = Profile::built_in("archive").unwrap();
+        assert_eq!(kept(&archive).len(), 9);
+        assert!(BUILT_IN
+            .iter()
+            .all(|name| Profile::built_in(name).is_some()));
+        assert!(Profile::built_in("print").is_none());
+    }
+
+    #[test]
+    fn test_default_profile() {
+        assert_eq!(kept(&Profile::default()), ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let profile = Profile::parse(
+            br#"{"keep": ["pHYs", "time"], "remove": ["private"], "default": "keep"}"#,
+        )
+        .unwrap();
+        assert_eq!(kept(&profile).len(), 9);
+        let (_, removed) = profile.strip(&testing_png());
+        assert_eq!(removed[0].chunk_type().to_string(), "prVt");
+
+        assert!(Profile::parse(br#"{"keep": ["metadata"]}"#).is_err());
+        assert!(Profile::parse(br#"{"default": "maybe"}"#).is_err());
+        assert!(Profile::parse(br#"{"drop": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_selector_from_str() {
+        assert_eq!(
+            Selector::from_str("pHYs").unwrap(),
+            Selector::Type("pHYs".to_string())
+        );
+        assert_eq!(Selector::from_str("color").unwrap(), Selector::Color);
+        assert!(Selector::from_str("pHY").is_err());
+    }
+}
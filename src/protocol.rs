@@ -0,0 +1,233 @@
+//! The `exec-json` protocol, for driving pngchunk from other projects' test suites.
+//!
+//! A request is a single JSON object naming a `command` and its arguments, for example
+//! `{"command": "decode", "file": "a.png", "chunk_type": "ruSt"}`. The PNG is read from `file`,
+//! or from `png` as base64. Commands that change the PNG write it to `output`, falling back to
+//! `file`, or return it base64 encoded as `png` when neither is given.
+//!
+//! The response is always `{"ok": true, "result": ...}` or `{"ok": false, "error": "..."}`.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::commands::{self, build_payload, check_encodable, chunk_json, read_message};
+use crate::{chunk::Chunk, chunk_type::ChunkType, datauri, limits, png::Png, temp, Result};
+
+/// Commands understood by `execute`
+pub const COMMANDS: [&str; 4] = ["print", "encode", "decode", "remove"];
+
+/// Runs one request and returns its response
+pub fn execute(request: &[u8]) -> Value {
+    match serde_json::from_slice(request)
+        .map_err(Into::into)
+        .and_then(|request| dispatch(&request))
+    {
+        Ok(result) => json!({ "ok": true, "result": result }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    }
+}
+
+fn dispatch(request: &Value) -> Result<Value> {
+    let object = request.as_object().ok_or("Request must be a JSON object")?;
+    let command = object
+        .get("command")
+        .and_then(Value::as_str)
+        .ok_or("Request is missing a command")?;
+    match command {
+        "print" => print(request),
+        "encode" => encode(request),
+        "decode" => decode(request),
+        "remove" => remove(request),
+        _ => Err(format!(
+            "Unknown command '{}', expected one of {}",
+            command,
+            COMMANDS.join(", ")
+        )
+        .into()),
+    }
+}
+
+fn print(request: &Value) -> Result<Value> {
+    let png = load(request)?;
+    let mut offset = Png::STANDARD_HEADER.len();
+    let mut chunks = vec![];
+    for chunk in png.chunks() {
+        chunks.push(chunk_json(chunk, offset));
+        offset += Chunk::MIN_CHUNK_LENGTH + chunk.data().len();
+    }
+    Ok(chunks.into())
+}
+
+fn encode(request: &Value) -> Result<Value> {
+    let mut png = load(request)?;
+    let chunk_type = chunk_type(request)?;
+    let only_private = request["only_private"].as_bool().unwrap_or(true);
+    check_encodable(
+        &chunk_type,
+        only_private,
+        flag(request, "force"),
+        ("set force", "set only_private to false"),
+    )?;
+    let message = match (
+        string(request, "message")?,
+        string(request, "message_base64")?,
+    ) {
+        (Some(message), None) => message.as_bytes().to_vec(),
+        (None, Some(encoded)) => datauri::base64_decode(encoded)?,
+        _ => return Err("Pass exactly one of message or message_base64".into()),
+    };
+    let data = build_payload(
+        message,
+        flag(request, "compress"),
+        false,
+        flag(request, "legacy"),
+    )?;
+    let index = png.insert_before_iend(Chunk::new(chunk_type, data)?);
+    let mut result = json!({ "index": index });
+    save(request, &png, &mut result)?;
+    Ok(result)
+}
+
+fn decode(request: &Value) -> Result<Value> {
+    let png = load(request)?;
+    read_message(&png, &chunk_type(request)?, false, false)?.to_json()
+}
+
+fn remove(request: &Value) -> Result<Value> {
+    let mut png = load(request)?;
+    let chunk = png.remove_chunk(&chunk_type(request)?.to_string())?;
+    let mut result = json!({ "length": chunk.length() });
+    save(request, &png, &mut result)?;
+    Ok(result)
+}
+
+fn load(request: &Value) -> Result<Png> {
+    let bytes = match (string(request, "file")?, string(request, "png")?) {
+        (Some(file), None) => commands::from_file(file)?,
+        (None, Some(encoded)) => {
+            let bytes = datauri::base64_decode(encoded)?;
+            limits::get().check_file_size(bytes.len())?;
+            bytes
+        }
+        _ => return Err("Pass exactly one of file or png".into()),
+    };
+    commands::parse_png(&bytes)
+}
+
+/// Writes a changed PNG back, or adds it to `result` if the request has nowhere to write it
+fn save(request: &Value, png: &Png, result: &mut Value) -> Result<()> {
+    match string(request, "output")?.or(string(request, "file")?) {
+        Some(file) => temp::write_atomic(Path::new(file), &png.as_bytes()),
+        None => {
+            result["png"] = datauri::base64_encode(&png.as_bytes()).into();
+            Ok(())
+        }
+    }
+}
+
+fn chunk_type(request: &Value) -> Result<ChunkType> {
+    ChunkType::from_str(string(request, "chunk_type")?.ok_or("Request is missing chunk_type")?)
+}
+
+fn string<'a>(request: &'a Value, key: &str) -> Result<Option<&'a str>> {
+    match &request[key] {
+        Value::Null => Ok(None),
+        Value::String(s) => Ok(Some(s)),
+        _ => Err(format!("{} must be a string", key).into()),
+    }
+}
+
+fn flag(request: &Value, key: &str) -> bool {
+    request[key].as_bool().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn png() -> String {
+        let chunks = vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])];
+        datauri::base64_encode(&Png::from_chunks(chunks).as_bytes())
+    }
+
+    #[test]
+    fn test_encode_then_decode() {
+        let request = json!({
+            "command": "encode",
+            "png": png(),
+            "chunk_type": "ruSt",
+            "message": "hello",
+        });
+        let response = execute(request.to_string().as_bytes());
+        assert_eq!(response["ok"], true);
+        assert_eq!(response["result"]["index"], 1);
+
+        let request = json!({
+            "command": "decode",
+            "png": response["result"]["png"],
+            "chunk_type": "ruSt",
+        });
+        let response = execute(request.to_string().as_bytes());
+        assert_eq!(response["result"]["text"], "hello");
+        assert_eq!(response["result"]["envelope"], crate::payload::VERSION);
+    }
+
+    #[test]
+    fn test_print() {
+        let request = json!({ "command": "print", "png": png() });
+        let result = &execute(request.to_string().as_bytes())["result"];
+        assert_eq!(result[0]["type"], "IHDR");
+        assert_eq!(result[0]["offset"], 8);
+        assert_eq!(result[1]["offset"], 33);
+    }
+
+    #[test]
+    fn test_errors() {
+        let response = execute(b"not json");
+        assert_eq!(response["ok"], false);
+
+        let request = json!({ "command": "decode", "png": png(), "chunk_type": "ruSt" });
+        let response = execute(request.to_string().as_bytes());
+        assert_eq!(response["ok"], false);
+        assert!(response["error"].as_str().unwrap().contains("ruSt"));
+
+        let request = json!({
+            "command": "encode",
+            "png": png(),
+            "chunk_type": "RUST",
+            "message": "hello",
+        });
+        let response = execute(request.to_string().as_bytes());
+        assert!(response["error"].as_str().unwrap().contains("set force"));
+
+        let request = json!({
+            "command": "encode",
+            "png": png(),
+            "chunk_type": "RUST",
+            "message": "hello",
+            "only_private": false,
+        });
+        assert_eq!(execute(request.to_string().as_bytes())["ok"], true);
+        let request = json!({
+            "command": "encode",
+            "png": png(),
+            "chunk_type": "IDAT",
+            "message": "hello",
+            "only_private": false,
+        });
+        let response = execute(request.to_string().as_bytes());
+        assert!(response["error"]
+            .as_str()
+            .unwrap()
+            .contains("standard chunk type IDAT"));
+
+        let response = execute(br#"{"command": "frobnicate"}"#);
+        assert!(response["error"]
+            .as_str()
+            .unwrap()
+            .contains("Unknown command"));
+    }
+}
@@ -0,0 +1,225 @@
+use core::fmt;
+
+use crate::{browser, png::Png, text};
+
+/// Pixels per metre of a 144 DPI Retina display, as written by macOS screenshots
+const RETINA_PPM: u32 = 5669;
+/// libpng's default zlib buffer, which fixes the size of every IDAT chunk but the last
+const LIBPNG_IDAT_SIZE: usize = 8192;
+
+/// One piece of evidence that `tool` had a hand in producing a file
+#[derive(Debug, PartialEq, Eq)]
+pub struct Clue {
+    pub tool: String,
+    pub evidence: String,
+}
+
+impl fmt::Display for Clue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.tool, self.evidence)
+    }
+}
+
+/// Fingerprints the software that produced `png` from its chunk patterns. Clues come in file
+/// order, so tools that wrote metadata early in the file come first.
+pub fn fingerprint(png: &Png) -> Vec<Clue> {
+    let mut clues = vec![];
+    let mut clue = |tool: &str, evidence: String| {
+        clues.push(Clue {
+            tool: tool.to_string(),
+            evidence,
+        })
+    };
+
+    let has = |chunk_type: &str| png.chunk_by_type(chunk_type).is_some();
+    let retina = png.chunk_by_type("pHYs").is_some_and(|phys| {
+        let data = phys.data();
+        data.len() == 9 && data[..4] == RETINA_PPM.to_be_bytes() && data[8] == 1
+    });
+    if has("iDOT") {
+        let detail = if retina { " and a 144 DPI pHYs" } else { "" };
+        clue("macOS", format!("Apple iDOT chunk{}", detail));
+    }
+
+    for chunk in png.chunks() {
+        if chunk.chunk_type().to_string() == "iCCP" {
+            if let Some((tool, evidence)) = browser::writer(chunk) {
+                clue(tool, evidence);
+            }
+        }
+        let Ok(Some((keyword, value))) = text::keyword_text(chunk) else {
+            continue;
+        };
+        match keyword.as_str() {
+            "Software" => clue(software_name(&value), format!("Software is '{}'", value)),
+            "XML:com.adobe.xmp" => {
+                if let Some(tool) = xmp_creator_tool(&value) {
+                    clue(
+                        software_name(&tool),
+                        format!("XMP CreatorTool is '{}'", tool),
+                    );
+                } else if value.contains("photoshop:") {
+                    clue(
+                        "Adobe Photoshop",
+                        "XMP uses the photoshop namespace".to_string(),
+                    );
+                }
+                if value.contains("<exif:UserComment>") && value.contains("Screenshot") {
+                    clue(
+                        "macOS",
+                        "XMP UserComment marks it as a screenshot".to_string(),
+                    );
+                }
+            }
+            "date:create" | "date:modify" | "date:timestamp" => {
+                clue("ImageMagick", format!("tEXt '{}' timestamp", keyword))
+            }
+            "Comment" if value.contains("GIMP") => {
+                clue("GIMP", "comment mentions GIMP".to_string())
+            }
+            _ => {}
+        }
+    }
+
+    let idat_sizes: Vec<usize> = png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type().to_string() == "IDAT")
+        .map(|c| c.data().len())
+        .collect();
+    if idat_sizes.len() > 1
+        && idat_sizes[..idat_sizes.len() - 1]
+            .iter()
+            .all(|&size| size == LIBPNG_IDAT_SIZE)
+    {
+        clue(
+            "libpng",
+            format!("IDAT split into {} byte chunks", LIBPNG_IDAT_SIZE),
+        );
+    }
+
+    clues
+}
+
+/// The tools named by `clues`, each once, in the order they were first seen
+pub fn tool_chain(clues: &[Clue]) -> Vec<&str> {
+    let mut tools: Vec<&str> = vec![];
+    for clue in clues {
+        if !tools.contains(&clue.tool.as_str()) {
+            tools.push(&clue.tool);
+        }
+    }
+    tools
+}
+
+/// Reduces a software string such as "Adobe Photoshop 24.1 (Macintosh)" to the product name
+fn software_name(software: &str) -> &str {
+    const KNOWN: [&str; 6] = [
+        "Adobe Photoshop",
+        "Adobe ImageReady",
+        "ImageMagick",
+        "GIMP",
+        "Paint.NET",
+        "Inkscape",
+    ];
+    KNOWN
+        .iter()
+        .find(|name| software.contains(*name))
+        .copied()
+        .or_else(|| browser::browser_name(software))
+        .unwrap_or(software.trim())
+}
+
+fn xmp_creator_tool(xmp: &str) -> Option<String> {
+    // The tool is either an attribute or an element, depending on how the XMP was serialized
+    let value = if let Some(start) = xmp.find("xmp:CreatorTool=\"") {
+        let rest = &xmp[start + "xmp:CreatorTool=\"".len()..];
+        &rest[..rest.find('"')?]
+    } else {
+        let start = xmp.find("<xmp:CreatorTool>")? + "<xmp:CreatorTool>".len();
+        let rest = &xmp[start..];
+        &rest[..rest.find('<')?]
+    };
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::testing::chunk;
+
+    fn xmp(body: &str) -> Chunk {
+        let mut data = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+        data.extend(body.as_bytes());
+        chunk("iTXt", &data)
+    }
+
+    #[test]
+    fn test_macos_screenshot() {
+        let mut phys = RETINA_PPM.to_be_bytes().repeat(2);
+        phys.push(1);
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("pHYs", &phys),
+            xmp("<exif:UserComment>Screenshot</exif:UserComment>"),
+            chunk("iDOT", &[0; 28]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+
+        let clues = fingerprint(&png);
+        assert_eq!(clues.len(), 2);
+        assert!(clues[0].evidence.contains("144 DPI"));
+        assert_eq!(tool_chain(&clues), vec!["macOS"]);
+    }
+
+    #[test]
+    fn test_tool_chain() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            xmp(r#"<rdf:Description xmp:CreatorTool="Adobe Photoshop 24.1 (Macintosh)"/>"#),
+            chunk("tEXt", b"date:create\x002024-01-01T00:00:00+00:00"),
+            chunk("IDAT", &[0; LIBPNG_IDAT_SIZE]),
+            chunk("IDAT", &[0; 10]),
+            chunk("IEND", &[]),
+        ]);
+
+        let clues = fingerprint(&png);
+        assert!(clues[0].evidence.contains("24.1"));
+        assert_eq!(
+            tool_chain(&clues),
+            vec!["Adobe Photoshop", "ImageMagick", "libpng"]
+        );
+    }
+
+    #[test]
+    fn test_browser_screenshot() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("iCCP", b"Skia\0\0"),
+            chunk("tEXt", b"Software\0Mozilla Firefox 121.0"),
+            chunk("IEND", &[]),
+        ]);
+
+        let clues = fingerprint(&png);
+        assert_eq!(clues[0].evidence, "ICC profile named 'Skia'");
+        assert_eq!(tool_chain(&clues), vec!["Chrome", "Firefox"]);
+    }
+
+    #[test]
+    fn test_no_clues() {
+        let png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        assert!(fingerprint(&png).is_empty());
+    }
+
+    #[test]
+    fn test_software_name() {
+        assert_eq!(software_name("GIMP 2.10.34"), "GIMP");
+        assert_eq!(software_name(" Acorn "), "Acorn");
+        assert_eq!(
+            xmp_creator_tool("<xmp:CreatorTool>Pixelmator</xmp:CreatorTool>").as_deref(),
+            Some("Pixelmator")
+        );
+    }
+}
@@ -0,0 +1,1097 @@
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::known_chunks::Ihdr;
+use crate::{chunk::Chunk, chunk_type::ChunkType, limits, png::Png, Error, Result};
+
+/// The color types defined by the PNG spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Gray,
+    Rgb,
+    Indexed,
+    GrayAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn from_byte(byte: u8) -> Result<ColorType> {
+        match byte {
+            0 => Ok(ColorType::Gray),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(format!("Invalid color type {}", byte).into()),
+        }
+    }
+
+    pub fn as_byte(&self) -> u8 {
+        match self {
+            ColorType::Gray => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// The number of samples making up each pixel
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Gray | ColorType::Indexed => 1,
+            ColorType::GrayAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+}
+
+/// The image properties stored in IHDR that are needed to lay out pixel data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+impl ImageHeader {
+    /// The number of bytes in one unfiltered row of the full image. Fails if that does not fit
+    /// in a `usize`.
+    pub fn row_bytes(&self) -> Result<usize> {
+        self.row_bytes_for(self.width)
+    }
+
+    /// The number of bytes in one unfiltered row that is `width` pixels wide. Fails if that does
+    /// not fit in a `usize`.
+    pub fn row_bytes_for(&self, width: u32) -> Result<usize> {
+        let bits = (width as usize)
+            .checked_mul(self.color_type.channels() * self.bit_depth as usize)
+            .ok_or_else(|| self.too_large())?;
+        Ok(bits.div_ceil(8))
+    }
+
+    /// The number of bytes of raw pixel data in the whole image, laid out row by row without
+    /// padding. Fails if that does not fit in a `usize`.
+    pub fn raw_size(&self) -> Result<usize> {
+        self.row_bytes()?
+            .checked_mul(self.height as usize)
+            .ok_or_else(|| self.too_large())
+    }
+
+    /// The distance in bytes between corresponding bytes of neighbouring pixels, as used by the
+    /// filters (never less than one)
+    pub fn filter_stride(&self) -> usize {
+        (self.color_type.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// The width and height of every interlace pass in order, or of the whole image if it is
+    /// not interlaced, along with the pass origin and spacing as (x0, y0, dx, dy)
+    fn passes(&self) -> Vec<(Pass, (usize, usize))> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let passes: &[Pass] = if self.interlaced {
+            &ADAM7
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+        passes
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let pass_width = (width + dx - 1 - x0.min(width)) / dx;
+                let pass_height = (height + dy - 1 - y0.min(height)) / dy;
+                ((x0, y0, dx, dy), (pass_width, pass_height))
+            })
+            .collect()
+    }
+
+    /// The number of bytes of inflated IDAT data the image needs, including the filter type
+    /// byte at the start of every row of every pass. Fails if that does not fit in a `usize`.
+    pub fn filtered_size(&self) -> Result<usize> {
+        self.passes()
+            .into_iter()
+            .filter(|&(_, (w, h))| w > 0 && h > 0)
+            .try_fold(0usize, |total, (_, (w, h))| {
+                let bits = w.checked_mul(self.color_type.channels() * self.bit_depth as usize)?;
+                (bits.div_ceil(8) + 1)
+                    .checked_mul(h)
+                    .and_then(|size| total.checked_add(size))
+            })
+            .ok_or_else(|| self.too_large())
+    }
+
+    fn too_large(&self) -> Error {
+        format!("Image size {}x{} is too large", self.width, self.height).into()
+    }
+
+    /// Reads the header from the IHDR chunk of `png`
+    pub fn from_png(png: &Png) -> Result<ImageHeader> {
+        let ihdr = png.chunk_by_type("IHDR").ok_or("Missing IHDR chunk")?;
+        Ok(Ihdr::parse(ihdr.data())?.into())
+    }
+
+    /// Serializes this header as the data of an IHDR chunk
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.width
+            .to_be_bytes()
+            .into_iter()
+            .chain(self.height.to_be_bytes())
+            .chain([
+                self.bit_depth,
+                self.color_type.as_byte(),
+                0,
+                0,
+                self.interlaced as u8,
+            ])
+            .collect()
+    }
+}
+
+impl FromStr for ImageHeader {
+    type Err = Error;
+    /// Parses a template such as `width=16,height=16,color=rgba8`. The color is one of `gray`,
+    /// `graya`, `rgb` or `rgba` followed by a bit depth of 8 or 16.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut width = None;
+        let mut height = None;
+        let mut color = None;
+        let mut interlaced = false;
+
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value, got '{}'", field))?;
+            match key.trim() {
+                "width" => width = Some(value.trim().parse::<u32>()?),
+                "height" => height = Some(value.trim().parse::<u32>()?),
+                "color" => color = Some(parse_color(value.trim())?),
+                "interlace" => interlaced = value.trim() == "1",
+                other => return Err(format!("Unknown IHDR field '{}'", other).into()),
+            }
+        }
+
+        let (color_type, bit_depth) = color.ok_or("IHDR template is missing color")?;
+        let header = ImageHeader {
+            width: width.ok_or("IHDR template is missing width")?,
+            height: height.ok_or("IHDR template is missing height")?,
+            bit_depth,
+            color_type,
+            interlaced,
+        };
+        Ihdr::check_size(header.width, header.height)?;
+        Ok(header)
+    }
+}
+
+impl From<Ihdr> for ImageHeader {
+    fn from(ihdr: Ihdr) -> ImageHeader {
+        ImageHeader {
+            width: ihdr.width,
+            height: ihdr.height,
+            bit_depth: ihdr.bit_depth,
+            color_type: ihdr.color_type,
+            interlaced: ihdr.interlace_method == 1,
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Result<(ColorType, u8)> {
+    let split = value
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(|| format!("Color '{}' is missing a bit depth", value))?;
+    let (name, depth) = value.split_at(split);
+    let color_type = match name {
+        "gray" => ColorType::Gray,
+        "graya" => ColorType::GrayAlpha,
+        "rgb" => ColorType::Rgb,
+        "rgba" => ColorType::Rgba,
+        _ => return Err(format!("Unknown color '{}'", name).into()),
+    };
+    match depth {
+        "8" => Ok((color_type, 8)),
+        "16" => Ok((color_type, 16)),
+        _ => Err(format!("Unsupported bit depth '{}', expected 8 or 16", depth).into()),
+    }
+}
+
+/// The formats raw pixel data can be converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8,
+    Rgb8,
+    Gray8,
+}
+
+impl PixelFormat {
+    pub fn channels(&self) -> usize {
+        match self {
+            PixelFormat::Rgba8 => 4,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Gray8 => 1,
+        }
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rgba8" => Ok(PixelFormat::Rgba8),
+            "rgb8" => Ok(PixelFormat::Rgb8),
+            "gray8" => Ok(PixelFormat::Gray8),
+            _ => Err(format!(
+                "Unknown pixel format '{}', expected rgba8, rgb8 or gray8",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+impl std::fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PixelFormat::Rgba8 => write!(f, "rgba8"),
+            PixelFormat::Rgb8 => write!(f, "rgb8"),
+            PixelFormat::Gray8 => write!(f, "gray8"),
+        }
+    }
+}
+
+/// A fully decoded image. Samples are stored unpacked, one `u16` per channel per pixel in row
+/// order, at the bit depth of the source image.
+#[derive(Debug)]
+pub struct Image {
+    pub header: ImageHeader,
+    pub samples: Vec<u16>,
+    /// Palette entries from PLTE, for indexed images
+    pub palette: Vec<[u8; 3]>,
+    /// The raw contents of tRNS, if present
+    pub transparency: Option<Vec<u8>>,
+}
+
+impl Image {
+    /// The samples of the pixel at (`x`, `y`)
+    pub fn pixel(&self, x: u32, y: u32) -> &[u16] {
+        let channels = self.header.color_type.channels();
+        let start = (y as usize * self.header.width as usize + x as usize) * channels;
+        &self.samples[start..start + channels]
+    }
+
+    /// Converts the image to 16-bit RGBA, resolving palettes, transparency and bit depth. This is
+    /// a lossless representation of every image, so it can be used to compare pixel data.
+    pub fn to_rgba16(&self) -> Vec<u16> {
+        let depth = self.header.bit_depth;
+        let max = (1u32 << depth) - 1;
+        let scale = |sample: u16| (sample as u32 * 65535 / max) as u16;
+        let key = |index: usize| {
+            self.transparency.as_ref().and_then(|t| {
+                t.get(index * 2..index * 2 + 2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            })
+        };
+
+        let channels = self.header.color_type.channels();
+        let mut rgba = Vec::with_capacity(self.samples.len() / channels * 4);
+        for pixel in self.samples.chunks(channels) {
+            let converted = match self.header.color_type {
+                ColorType::Gray => {
+                    let v = scale(pixel[0]);
+                    let alpha = if key(0) == Some(pixel[0]) { 0 } else { 65535 };
+                    [v, v, v, alpha]
+                }
+                ColorType::GrayAlpha => {
+                    let v = scale(pixel[0]);
+                    [v, v, v, scale(pixel[1])]
+                }
+                ColorType::Rgb => {
+                    let transparent = (0..3).all(|c| key(c) == Some(pixel[c]));
+                    let alpha = if transparent { 0 } else { 65535 };
+                    [scale(pixel[0]), scale(pixel[1]), scale(pixel[2]), alpha]
+                }
+                ColorType::Rgba => [
+                    scale(pixel[0]),
+                    scale(pixel[1]),
+                    scale(pixel[2]),
+                    scale(pixel[3]),
+                ],
+                ColorType::Indexed => {
+                    let index = pixel[0] as usize;
+                    let [r, g, b] = self.palette.get(index).copied().unwrap_or([0, 0, 0]);
+                    let alpha = self
+                        .transparency
+                        .as_ref()
+                        .and_then(|t| t.get(index).copied())
+                        .unwrap_or(255);
+                    [r, g, b, alpha].map(|v| v as u16 * 257)
+                }
+            };
+            rgba.extend(converted);
+        }
+        rgba
+    }
+
+    /// Converts the image to 8-bit RGBA, resolving palettes, transparency and bit depth
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.to_rgba16()
+            .into_iter()
+            .map(|v| ((v as u32 * 255 + 32767) / 65535) as u8)
+            .collect()
+    }
+
+    /// Packs the samples back into rows at the bit depth of the image, the layout expected by
+    /// `compose`
+    pub fn to_raw(&self) -> Result<Vec<u8>> {
+        let header = ImageHeader {
+            interlaced: false,
+            ..self.header
+        };
+        let row_bytes = header.row_bytes()?;
+        let per_row = header.width as usize * header.color_type.channels();
+        let mut raw = vec![0u8; header.raw_size()?];
+        if per_row == 0 {
+            return Ok(raw);
+        }
+        for (y, row) in self.samples.chunks(per_row).enumerate() {
+            let out = &mut raw[y * row_bytes..(y + 1) * row_bytes];
+            for (index, &sample) in row.iter().enumerate() {
+                write_sample(out, index, header.bit_depth, sample);
+            }
+        }
+        Ok(raw)
+    }
+
+    /// Converts the image to raw pixel data in `format`. Alpha is dropped rather than composited
+    /// and gray values use the Rec. 601 luma weights.
+    pub fn convert(&self, format: PixelFormat) -> Vec<u8> {
+        let rgba = self.to_rgba8();
+        match format {
+            PixelFormat::Rgba8 => rgba,
+            PixelFormat::Rgb8 => rgba.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            PixelFormat::Gray8 => rgba
+                .chunks(4)
+                .map(|p| {
+                    ((p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114 + 500) / 1000) as u8
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Per-channel counts of every possible sample value
+#[derive(Debug)]
+pub struct Histogram {
+    pub bit_depth: u8,
+    pub channels: Vec<(&'static str, Vec<u64>)>,
+}
+
+impl Histogram {
+    /// Counts the samples of `image`. Indexed images are counted after looking colors up in the
+    /// palette, so their channels are 8-bit red, green and blue (plus alpha when tRNS is present).
+    pub fn from_image(image: &Image) -> Histogram {
+        let (names, bit_depth, samples): (&[&'static str], u8, Vec<u16>) =
+            match image.header.color_type {
+                ColorType::Gray => (&["gray"], image.header.bit_depth, image.samples.clone()),
+                ColorType::GrayAlpha => (
+                    &["gray", "alpha"],
+                    image.header.bit_depth,
+                    image.samples.clone(),
+                ),
+                ColorType::Rgb => (
+                    &["red", "green", "blue"],
+                    image.header.bit_depth,
+                    image.samples.clone(),
+                ),
+                ColorType::Rgba => (
+                    &["red", "green", "blue", "alpha"],
+                    image.header.bit_depth,
+                    image.samples.clone(),
+                ),
+                ColorType::Indexed => {
+                    let rgba = image.to_rgba8();
+                    if image.transparency.is_some() {
+                        let samples = rgba.iter().map(|&s| s as u16).collect();
+                        (&["red", "green", "blue", "alpha"], 8, samples)
+                    } else {
+                        let samples = rgba
+                            .chunks(4)
+                            .flat_map(|p| [p[0] as u16, p[1] as u16, p[2] as u16])
+                            .collect();
+                        (&["red", "green", "blue"], 8, samples)
+                    }
+                }
+            };
+
+        let mut channels: Vec<(&'static str, Vec<u64>)> = names
+            .iter()
+            .map(|&name| (name, vec![0; 1 << bit_depth]))
+            .collect();
+        for pixel in samples.chunks(names.len()) {
+            for (channel, &sample) in channels.iter_mut().zip(pixel) {
+                channel.1[sample as usize] += 1;
+            }
+        }
+
+        Histogram {
+            bit_depth,
+            channels,
+        }
+    }
+}
+
+/// The pixels that differ between two images of the same size
+#[derive(Debug, PartialEq, Eq)]
+pub struct PixelDiff {
+    pub differing: u64,
+    /// The smallest rectangle containing every differing pixel as (left, top, right, bottom),
+    /// inclusive
+    pub bounds: Option<(u32, u32, u32, u32)>,
+}
+
+impl PixelDiff {
+    /// Compares the pixel data of two images after converting both to 16-bit RGBA, so images
+    /// that look the same compare equal even if they are stored differently
+    pub fn between(a: &Image, b: &Image) -> Result<PixelDiff> {
+        if (a.header.width, a.header.height) != (b.header.width, b.header.height) {
+            return Err(format!(
+                "Image sizes differ: {}x{} and {}x{}",
+                a.header.width, a.header.height, b.header.width, b.header.height
+            )
+            .into());
+        }
+
+        let width = a.header.width as usize;
+        let mut diff = PixelDiff {
+            differing: 0,
+            bounds: None,
+        };
+        let (a, b) = (a.to_rgba16(), b.to_rgba16());
+        for (index, (pa, pb)) in a.chunks(4).zip(b.chunks(4)).enumerate() {
+            if pa == pb {
+                continue;
+            }
+            let (x, y) = ((index % width) as u32, (index / width) as u32);
+            diff.differing += 1;
+            diff.bounds = Some(match diff.bounds {
+                None => (x, y, x, y),
+                Some((l, t, r, b)) => (l.min(x), t.min(y), r.max(x), b.max(y)),
+            });
+        }
+        Ok(diff)
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.differing == 0
+    }
+}
+
+/// Decodes the pixel data of `png`, undoing compression, filtering and interlacing
+pub fn decode(png: &Png) -> Result<Image> {
+    let header = ImageHeader::from_png(png)?;
+
+    let compressed: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type().to_string() == "IDAT")
+        .flat_map(|c| c.data().iter().copied())
+        .collect();
+    limits::get().check_pixels(header.width, header.height)?;
+    // Never inflate more than the image needs, so IDAT cannot be a decompression bomb
+    let expected = header.filtered_size()?;
+    let mut filtered = vec![];
+    ZlibDecoder::new(&compressed[..])
+        .take(expected as u64)
+        .read_to_end(&mut filtered)?;
+    // The header alone says nothing about how much memory is safe to allocate, the data does
+    if filtered.len() < expected {
+        return Err(format!(
+            "Image data ends before the last row: {} of {} bytes",
+            filtered.len(),
+            expected
+        )
+        .into());
+    }
+
+    let channels = header.color_type.channels();
+    let (width, height) = (header.width as usize, header.height as usize);
+    let len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| header.too_large())?;
+    let mut samples = vec![0u16; len];
+
+    let mut offset = 0;
+    for ((x0, y0, dx, dy), (pass_width, pass_height)) in header.passes() {
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+
+        let row_bytes = header.row_bytes_for(pass_width as u32)?;
+        let size = (row_bytes + 1) * pass_height;
+        let data = filtered
+            .get(offset..offset + size)
+            .ok_or("Image data ends before the last row")?;
+        let rows = unfilter_rows(data, row_bytes, header.filter_stride())?;
+        offset += size;
+
+        for (py, row) in rows.chunks(row_bytes).enumerate() {
+            for px in 0..pass_width {
+                let target = ((y0 + py * dy) * width + x0 + px * dx) * channels;
+                for c in 0..channels {
+                    samples[target + c] = read_sample(row, px * channels + c, header.bit_depth);
+                }
+            }
+        }
+    }
+
+    let palette: Vec<[u8; 3]> = png
+        .chunk_by_type("PLTE")
+        .map(|c| {
+            c.data()
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect()
+        })
+        .unwrap_or_default();
+    if header.color_type == ColorType::Indexed && palette.is_empty() {
+        return Err("Indexed image is missing its PLTE chunk".into());
+    }
+
+    Ok(Image {
+        header,
+        samples,
+        palette,
+        transparency: png.chunk_by_type("tRNS").map(|c| c.data().to_vec()),
+    })
+}
+
+/// The origin and spacing of an interlace pass as (x0, y0, dx, dy)
+type Pass = (usize, usize, usize, usize);
+
+/// Pass origins and spacing for Adam7 interlacing
+const ADAM7: [Pass; 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bits = bit_depth as usize;
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << bits) - 1) as u8) as u16
+        }
+    }
+}
+
+fn write_sample(row: &mut [u8], index: usize, bit_depth: u8, sample: u16) {
+    match bit_depth {
+        16 => row[index * 2..index * 2 + 2].copy_from_slice(&sample.to_be_bytes()),
+        8 => row[index] = sample as u8,
+        _ => {
+            let bits = bit_depth as usize;
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+            row[bit / 8] |= (sample as u8 & ((1 << bits) - 1) as u8) << shift;
+        }
+    }
+}
+
+fn unfilter_rows(data: &[u8], row_bytes: usize, stride: usize) -> Result<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::with_capacity(data.len());
+    for (y, line) in data.chunks(row_bytes + 1).enumerate() {
+        let filter = line[0];
+        let start = output.len();
+        for i in 0..row_bytes {
+            let a = if i >= stride {
+                output[start + i - stride]
+            } else {
+                0
+            };
+            let b = if y > 0 {
+                output[start + i - row_bytes]
+            } else {
+                0
+            };
+            let c = if y > 0 && i >= stride {
+                output[start + i - row_bytes - stride]
+            } else {
+                0
+            };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("Invalid filter type {} in row {}", filter, y).into()),
+            };
+            output.push(line[1 + i].wrapping_add(predictor));
+        }
+    }
+    Ok(output)
+}
+
+/// Builds a PNG from raw pixel data laid out row by row without padding. 16-bit samples are
+/// expected in big-endian order. The image is always written without interlacing.
+pub fn compose(header: &ImageHeader, pixels: &[u8]) -> Result<Png> {
+    let header = ImageHeader {
+        interlaced: false,
+        ..*header
+    };
+    let expected = header.raw_size()?;
+    if pixels.len() != expected {
+        return Err(format!(
+            "Expected {} bytes of pixel data for this header, got {}",
+            expected,
+            pixels.len()
+        )
+        .into());
+    }
+
+    let filtered = filter_rows(&header, pixels)?;
+    let _span = tracing::info_span!("compress", bytes = filtered.len()).entered();
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&filtered)?;
+
+    Ok(Png::from_chunks(vec![
+        Chunk::new(ChunkType::from_str("IHDR")?, header.as_bytes())?,
+        Chunk::new(ChunkType::from_str("IDAT")?, encoder.finish()?)?,
+        Chunk::new(ChunkType::from_str("IEND")?, vec![])?,
+    ]))
+}
+
+/// Replaces the image data of `png` with the pixels of `image`, keeping every other chunk. The
+/// image is written without interlacing, so IHDR is updated accordingly.
+pub fn reencode(png: &Png, image: &Image) -> Result<Png> {
+    let composed = compose(&image.header, &image.to_raw()?)?;
+    let (ihdr, idat) = (&composed.chunks()[0], &composed.chunks()[1]);
+
+    let mut chunks = vec![];
+    for chunk in png.chunks() {
+        match chunk.chunk_type().to_string().as_str() {
+            "IHDR" => chunks.push(ihdr.clone()),
+            "IDAT"
+                if chunks
+                    .iter()
+                    .any(|c: &Chunk| c.chunk_type() == idat.chunk_type()) => {}
+            "IDAT" => chunks.push(idat.clone()),
+            _ => chunks.push(chunk.clone()),
+        }
+    }
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Filters each row with whichever filter gives the smallest sum of absolute differences, the
+/// same heuristic used by libpng
+fn filter_rows(header: &ImageHeader, pixels: &[u8]) -> Result<Vec<u8>> {
+    let row_bytes = header.row_bytes()?;
+    let stride = header.filter_stride();
+    let mut output = Vec::with_capacity(pixels.len() + header.height as usize);
+    let empty = vec![0u8; row_bytes];
+
+    for (y, row) in pixels.chunks(row_bytes).enumerate() {
+        let prior = if y == 0 {
+            &empty[..]
+        } else {
+            &pixels[(y - 1) * row_bytes..y * row_bytes]
+        };
+
+        let best = (0..5u8)
+            .map(|filter| (filter, filter_row(filter, row, prior, stride)))
+            .min_by_key(|(_, filtered)| {
+                filtered
+                    .iter()
+                    .map(|&b| (b as i8).unsigned_abs() as u64)
+                    .sum::<u64>()
+            })
+            .unwrap();
+
+        output.push(best.0);
+        output.extend(best.1);
+    }
+
+    Ok(output)
+}
+
+fn filter_row(filter: u8, row: &[u8], prior: &[u8], stride: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let a = if i >= stride { row[i - stride] } else { 0 };
+            let b = prior[i];
+            let c = if i >= stride { prior[i - stride] } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                _ => paeth(a, b, c),
+            };
+            row[i].wrapping_sub(predictor)
+        })
+        .collect()
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    #[test]
+    fn test_header_from_str() {
+        let header = ImageHeader::from_str("width=16,height=8,color=rgba8").unwrap();
+        assert_eq!(header.width, 16);
+        assert_eq!(header.height, 8);
+        assert_eq!(header.bit_depth, 8);
+        assert_eq!(header.color_type, ColorType::Rgba);
+        assert_eq!(header.row_bytes().unwrap(), 64);
+
+        let header = ImageHeader::from_str("width=3, height=1, color=gray16").unwrap();
+        assert_eq!(header.row_bytes().unwrap(), 6);
+        assert_eq!(header.filter_stride(), 2);
+
+        assert!(ImageHeader::from_str("width=16,height=16").is_err());
+        assert!(ImageHeader::from_str("width=16,height=16,color=rgba4").is_err());
+        assert!(ImageHeader::from_str("width=0,height=16,color=rgb8").is_err());
+    }
+
+    #[test]
+    fn test_header_as_bytes() {
+        let header = ImageHeader::from_str("width=50,height=50,color=rgba8").unwrap();
+        assert_eq!(
+            header.as_bytes(),
+            vec![0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 0, 0]
+        );
+
+        let ihdr = |header: &ImageHeader| {
+            let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), header.as_bytes());
+            Png::from_chunks(vec![chunk.unwrap()])
+        };
+        assert_eq!(ImageHeader::from_png(&ihdr(&header)).unwrap(), header);
+        let oversized = ImageHeader {
+            width: Ihdr::MAX_DIMENSION + 1,
+            ..header
+        };
+        assert!(ImageHeader::from_png(&ihdr(&oversized)).is_err());
+    }
+
+    #[test]
+    fn test_compose() {
+        let header = ImageHeader::from_str("width=4,height=4,color=rgb8").unwrap();
+        let pixels: Vec<u8> = (0..48).collect();
+        let png = compose(&header, &pixels).unwrap();
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[0].data(), &header.as_bytes()[..]);
+    }
+
+    #[test]
+    fn test_compose_wrong_size() {
+        let header = ImageHeader::from_str("width=4,height=4,color=rgb8").unwrap();
+        assert!(compose(&header, &[0; 47]).is_err());
+
+        let huge = ImageHeader {
+            width: Ihdr::MAX_DIMENSION,
+            height: Ihdr::MAX_DIMENSION,
+            bit_depth: 16,
+            ..header
+        };
+        assert!(compose(&huge, &[]).is_err());
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        for template in ["color=rgba8", "color=rgb16", "color=gray8", "color=graya16"] {
+            let header = ImageHeader::from_str(&format!("width=5,height=3,{}", template)).unwrap();
+            let pixels: Vec<u8> = (0..header.row_bytes().unwrap() * 3)
+                .map(|i| (i * 7) as u8)
+                .collect();
+            let png = compose(&header, &pixels).unwrap();
+
+            let image = decode(&png).unwrap();
+            assert_eq!(image.header, header);
+            let expected: Vec<u16> = match header.bit_depth {
+                16 => pixels
+                    .chunks(2)
+                    .map(|s| u16::from_be_bytes([s[0], s[1]]))
+                    .collect(),
+                _ => pixels.iter().map(|&s| s as u16).collect(),
+            };
+            assert_eq!(image.samples, expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_truncated_data() {
+        let header = ImageHeader::from_str("width=60000,height=60000,color=rgba8").unwrap();
+        let mut chunks = compose(
+            &ImageHeader::from_str("width=1,height=1,color=rgba8").unwrap(),
+            &[0; 4],
+        )
+        .unwrap()
+        .chunks()
+        .to_vec();
+        chunks[0] = chunk("IHDR", &header.as_bytes());
+        // Rejected for its missing rows before anything is sized from the header
+        let error = decode(&Png::from_chunks(chunks)).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Image data ends before the last row"));
+    }
+
+    #[test]
+    fn test_decode_image_file() {
+        let png = Png::try_from(&include_bytes!("../test.png")[..]).unwrap();
+        let image = decode(&png).unwrap();
+        assert_eq!(image.header.width, 512);
+        assert_eq!(image.samples.len(), 512 * 512 * 4);
+        assert_eq!(image.convert(PixelFormat::Rgb8).len(), 512 * 512 * 3);
+    }
+
+    #[test]
+    fn test_read_sample_packed() {
+        let row = [0b1011_0001];
+        assert_eq!(read_sample(&row, 0, 1), 1);
+        assert_eq!(read_sample(&row, 1, 1), 0);
+        assert_eq!(read_sample(&row, 1, 2), 0b11);
+        assert_eq!(read_sample(&row, 1, 4), 0b0001);
+    }
+
+    #[test]
+    fn test_decode_interlaced_indexed() {
+        // A 3x3 indexed image with 2-bit samples, Adam7 interlaced and filtered with filter 0
+        let indices = [[0u8, 1, 2], [3, 0, 1], [2, 3, 0]];
+        let pass_pixels: Vec<Vec<(usize, usize)>> = ADAM7
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let mut pixels = vec![];
+                for y in (y0..3).step_by(dy) {
+                    for x in (x0..3).step_by(dx) {
+                        pixels.push((x, y));
+                    }
+                }
+                pixels
+            })
+            .collect();
+
+        let mut filtered = vec![];
+        for (&(x0, _, dx, _), pixels) in ADAM7.iter().zip(pass_pixels.iter()) {
+            let pass_width = if x0 < 3 { (3 - x0).div_ceil(dx) } else { 0 };
+            for row in pixels.chunks(pass_width.max(1)) {
+                if pass_width == 0 {
+                    break;
+                }
+                let mut byte = 0u8;
+                for (i, &(x, y)) in row.iter().enumerate() {
+                    byte |= indices[y][x] << (6 - 2 * i);
+                }
+                filtered.extend([0, byte]);
+            }
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered).unwrap();
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 3, 0, 0, 0, 3, 2, 3, 0, 0, 1]),
+            chunk("PLTE", &[0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255]),
+            chunk("tRNS", &[0]),
+            chunk("IDAT", &encoder.finish().unwrap()),
+            chunk("IEND", &[]),
+        ]);
+
+        let image = decode(&png).unwrap();
+        let expected: Vec<u16> = indices.iter().flatten().map(|&i| i as u16).collect();
+        assert_eq!(image.samples, expected);
+
+        let rgba = image.to_rgba8();
+        assert_eq!(&rgba[..8], &[0, 0, 0, 0, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_convert_gray() {
+        let header = ImageHeader::from_str("width=2,height=1,color=gray16").unwrap();
+        let png = compose(&header, &[0xff, 0xff, 0x80, 0x00]).unwrap();
+        let image = decode(&png).unwrap();
+        assert_eq!(image.convert(PixelFormat::Gray8), vec![255, 128]);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let header = ImageHeader::from_str("width=2,height=2,color=graya8").unwrap();
+        let png = compose(&header, &[0, 255, 0, 255, 10, 128, 255, 255]).unwrap();
+        let histogram = Histogram::from_image(&decode(&png).unwrap());
+
+        assert_eq!(histogram.bit_depth, 8);
+        assert_eq!(histogram.channels.len(), 2);
+        let (name, gray) = &histogram.channels[0];
+        assert_eq!(*name, "gray");
+        assert_eq!((gray[0], gray[10], gray[255]), (2, 1, 1));
+        let (_, alpha) = &histogram.channels[1];
+        assert_eq!((alpha[128], alpha[255]), (1, 3));
+    }
+
+    #[test]
+    fn test_histogram_16_bit() {
+        let header = ImageHeader::from_str("width=1,height=1,color=gray16").unwrap();
+        let png = compose(&header, &[0x12, 0x34]).unwrap();
+        let histogram = Histogram::from_image(&decode(&png).unwrap());
+
+        assert_eq!(histogram.channels[0].1.len(), 65536);
+        assert_eq!(histogram.channels[0].1[0x1234], 1);
+    }
+
+    #[test]
+    fn test_to_raw_round_trip() {
+        for (color_type, bit_depth) in [
+            (ColorType::Gray, 1),
+            (ColorType::Gray, 4),
+            (ColorType::GrayAlpha, 8),
+            (ColorType::Rgb, 16),
+        ] {
+            let header = ImageHeader {
+                width: 5,
+                height: 3,
+                bit_depth,
+                color_type,
+                interlaced: false,
+            };
+            let raw: Vec<u8> = (0..header.row_bytes().unwrap() * 3)
+                .map(|i| (i * 37) as u8)
+                .collect();
+            let image = decode(&compose(&header, &raw).unwrap()).unwrap();
+            let round_trip = decode(&compose(&header, &image.to_raw().unwrap()).unwrap()).unwrap();
+            assert_eq!(round_trip.samples, image.samples);
+        }
+    }
+
+    #[test]
+    fn test_reencode_keeps_metadata() {
+        let header = ImageHeader::from_str("width=2,height=2,color=gray8").unwrap();
+        let composed = compose(&header, &[1, 2, 3, 4]).unwrap();
+        let mut chunks = composed.chunks().to_vec();
+        chunks.insert(1, chunk("tEXt", b"Title\0x"));
+        let png = Png::from_chunks(chunks);
+
+        let mut image = decode(&png).unwrap();
+        image.samples[0] = 9;
+        let reencoded = reencode(&png, &image).unwrap();
+
+        assert!(reencoded.chunk_by_type("tEXt").is_some());
+        assert_eq!(decode(&reencoded).unwrap().samples, vec![9, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_filtered_size() {
+        let header = ImageHeader::from_str("width=5,height=3,color=rgb8").unwrap();
+        assert_eq!(header.filtered_size().unwrap(), (15 + 1) * 3);
+
+        let interlaced = ImageHeader {
+            interlaced: true,
+            ..header
+        };
+        // Pass sizes are 1x1, 1x1, 2x0, 1x1, 3x1, 2x2 and 5x1
+        let expected: usize = [(1, 1), (1, 1), (1, 1), (3, 1), (2, 2), (5, 1)]
+            .iter()
+            .map(|&(w, h)| (w * 3 + 1) * h)
+            .sum();
+        assert_eq!(interlaced.filtered_size().unwrap(), expected);
+
+        let huge = ImageHeader {
+            width: Ihdr::MAX_DIMENSION,
+            height: Ihdr::MAX_DIMENSION,
+            bit_depth: 16,
+            color_type: ColorType::Rgba,
+            interlaced: false,
+        };
+        assert!(huge.filtered_size().is_err());
+    }
+
+    #[test]
+    fn test_pixel_diff() {
+        let header = ImageHeader::from_str("width=3,height=3,color=gray8").unwrap();
+        let a = decode(&compose(&header, &[0; 9]).unwrap()).unwrap();
+        let b = decode(&compose(&header, &[0, 0, 0, 0, 1, 0, 0, 0, 1]).unwrap()).unwrap();
+
+        assert!(PixelDiff::between(&a, &a).unwrap().is_identical());
+        assert_eq!(
+            PixelDiff::between(&a, &b).unwrap(),
+            PixelDiff {
+                differing: 2,
+                bounds: Some((1, 1, 2, 2))
+            }
+        );
+    }
+
+    #[test]
+    fn test_pixel_diff_across_formats() {
+        let gray8 = ImageHeader::from_str("width=2,height=1,color=gray8").unwrap();
+        let rgb16 = ImageHeader::from_str("width=2,height=1,color=rgb16").unwrap();
+        let a = decode(&compose(&gray8, &[0x12, 0xff]).unwrap()).unwrap();
+        let b = decode(
+            &compose(
+                &rgb16,
+                &[
+                    0x12, 0x12, 0x12, 0x12, 0x12, 0x12, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                ],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        assert!(PixelDiff::between(&a, &b).unwrap().is_identical());
+    }
+
+    #[test]
+    fn test_pixel_diff_size_mismatch() {
+        let small = ImageHeader::from_str("width=1,height=1,color=gray8").unwrap();
+        let large = ImageHeader::from_str("width=2,height=1,color=gray8").unwrap();
+        let a = decode(&compose(&small, &[0]).unwrap()).unwrap();
+        let b = decode(&compose(&large, &[0, 0]).unwrap()).unwrap();
+
+        assert!(PixelDiff::between(&a, &b).is_err());
+    }
+
+    #[test]
+    fn test_filter_row() {
+        let prior = [10, 20, 30, 40];
+        let row = [11, 22, 33, 44];
+        assert_eq!(filter_row(0, &row, &prior, 1), vec![11, 22, 33, 44]);
+        assert_eq!(filter_row(1, &row, &prior, 1), vec![11, 11, 11, 11]);
+        assert_eq!(filter_row(2, &row, &prior, 1), vec![1, 2, 3, 4]);
+        assert_eq!(filter_row(3, &row, &prior, 1), vec![6, 7, 7, 8]);
+    }
+}
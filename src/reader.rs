@@ -0,0 +1,315 @@
+use std::io::{self, ErrorKind, Read};
+use std::ops::Range;
+
+use crate::png::{Layout, ParseWarning, Png};
+use crate::{chunk::Chunk, chunk_type::ChunkType, diagnostics, limits, PngChunkError, Result};
+
+/// Reads chunks one at a time from any `Read`, so only the current chunk is held in memory.
+/// The signature is checked on the first call to `next`. Iteration ends cleanly at the end of
+/// the input; a chunk cut short is an error, after which the reader yields nothing more.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: usize,
+    chunks_read: usize,
+    done: bool,
+    layout: Layout,
+    warn_on_layout: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(reader: R) -> Self {
+        ChunkReader {
+            reader,
+            offset: 0,
+            chunks_read: 0,
+            done: false,
+            layout: Layout::default(),
+            warn_on_layout: false,
+        }
+    }
+
+    /// Reports the oddities in the chunk layout found by `layout_warnings` through
+    /// `diagnostics::warn` once the input has been read to the end, as commands that parse the
+    /// whole file do
+    pub fn warn_on_layout(mut self) -> Self {
+        self.warn_on_layout = true;
+        self
+    }
+
+    /// Oddities in the layout of the chunks read so far, see `Png::layout_warnings`. Only
+    /// complete once the input has been read to the end.
+    pub fn layout_warnings(&self) -> Vec<ParseWarning> {
+        self.layout.warnings()
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        if self.warn_on_layout {
+            self.layout_warnings().iter().for_each(diagnostics::warn);
+        }
+    }
+
+    /// The byte offset of the next chunk in the input
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Reads `range` of the data of every remaining chunk of type `chunk_type`, returning the
+    /// full data length of each chunk along with the bytes read. Everything else is skipped
+    /// without being held in memory, so the range of a huge chunk can be read cheaply. Ranges
+    /// are cut short at the end of a chunk's data. CRCs are not checked, as they cover data
+    /// that is never read.
+    pub fn data_ranges(
+        self,
+        chunk_type: &ChunkType,
+        range: Range<usize>,
+    ) -> Result<Vec<(usize, Vec<u8>)>> {
+        self.data_ranges_matching(|t| t == chunk_type, range)
+    }
+
+    /// Like `data_ranges`, for every remaining chunk whose type is `selected`
+    pub fn data_ranges_matching<F: Fn(&ChunkType) -> bool>(
+        mut self,
+        selected: F,
+        range: Range<usize>,
+    ) -> Result<Vec<(usize, Vec<u8>)>> {
+        if self.offset == 0 {
+            self.read_signature()?;
+        }
+        let mut found = vec![];
+        loop {
+            let mut header = [0u8; 8];
+            match read_up_to(&mut self.reader, &mut header)? {
+                0 => {
+                    self.finish();
+                    return Ok(found);
+                }
+                8 => {}
+                _ => return Err(self.truncated(&[])),
+            }
+            let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let mut rest = length + 4;
+            let chunk_type = ChunkType::new([header[4], header[5], header[6], header[7]]);
+            self.layout.add(&chunk_type);
+            if selected(&chunk_type) {
+                let start = range.start.min(length);
+                let end = range.end.clamp(start, length);
+                self.skip_bytes(start as u64)?;
+                let mut bytes = vec![];
+                (&mut self.reader)
+                    .take((end - start) as u64)
+                    .read_to_end(&mut bytes)?;
+                if bytes.len() != end - start {
+                    return Err(self.truncated(&[]));
+                }
+                found.push((length, bytes));
+                rest -= end;
+            }
+            self.skip_bytes(rest as u64)?;
+            self.offset += 8 + length + 4;
+        }
+    }
+
+    fn skip_bytes(&mut self, count: u64) -> Result<()> {
+        if io::copy(&mut (&mut self.reader).take(count), &mut io::sink())? != count {
+            return Err(self.truncated(&[]));
+        }
+        Ok(())
+    }
+
+    fn read_signature(&mut self) -> Result<()> {
+        let mut signature = [0u8; 8];
+        let read = read_up_to(&mut self.reader, &mut signature)?;
+        Png::check_signature(&signature[..read])?;
+        self.offset = signature.len();
+        Ok(())
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let mut header = [0u8; 8];
+        match read_up_to(&mut self.reader, &mut header)? {
+            0 => return Ok(None),
+            8 => {}
+            read => return Err(self.truncated(&header[..read])),
+        }
+        limits::get().check_chunk_count(self.chunks_read + 1)?;
+
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if length > Chunk::MAX_DATA_LENGTH {
+            let e = PngChunkError::Parse(format!(
+                "Chunk declares {} bytes of data, more than the {} allowed by the PNG spec",
+                length,
+                Chunk::MAX_DATA_LENGTH
+            ));
+            return Err(e.in_chunk(&header, self.offset, self.offset, self.chunks_read));
+        }
+
+        // Grow the buffer as data arrives rather than trusting the declared length up front
+        let mut bytes = header.to_vec();
+        let wanted = (length + 4) as u64;
+        if (&mut self.reader).take(wanted).read_to_end(&mut bytes)? as u64 != wanted {
+            return Err(self.truncated(&bytes));
+        }
+
+        let chunk = Chunk::try_from(&bytes[..])
+            .map_err(|e| e.in_chunk(&bytes, self.offset, self.offset, self.chunks_read))?;
+        self.offset += bytes.len();
+        self.chunks_read += 1;
+        self.layout.add(chunk.chunk_type());
+        Ok(Some(chunk))
+    }
+
+    /// The error for a chunk cut short after `read`, the bytes of it that could be read
+    fn truncated(&self, read: &[u8]) -> PngChunkError {
+        PngChunkError::Parse("Truncated chunk".to_string()).in_chunk(
+            read,
+            self.offset,
+            self.offset,
+            self.chunks_read,
+        )
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.offset == 0 {
+            if let Err(e) = self.read_signature() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        match self.read_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.finish();
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Fills as much of `buf` as the input allows, returning how many bytes were read
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        testing::png_from(&[
+            ("IHDR", &[0; 13]),
+            ("tEXt", b"Title\0Hello"),
+            ("IDAT", &[1, 2, 3]),
+            ("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_chunk_reader_matches_try_from() {
+        let bytes = testing_png().as_bytes();
+        let png = Png::try_from(&bytes[..]).unwrap();
+        let chunks: Vec<Chunk> = ChunkReader::new(&bytes[..])
+            .collect::<Result<Vec<Chunk>>>()
+            .unwrap();
+        assert_eq!(chunks.len(), png.chunks().len());
+        for (read, parsed) in chunks.iter().zip(png.chunks()) {
+            assert_eq!(read.as_bytes(), parsed.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_png_from_reader() {
+        let bytes = testing_png().as_bytes();
+        let png = Png::from_reader(&bytes[..]).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_data_ranges() {
+        let bytes = testing_png().as_bytes();
+        let text = ChunkType::from_str("tEXt").unwrap();
+        let ranges = ChunkReader::new(&bytes[..])
+            .data_ranges(&text, 6..8)
+            .unwrap();
+        assert_eq!(ranges, vec![(11, b"He".to_vec())]);
+
+        let ranges = ChunkReader::new(&bytes[..])
+            .data_ranges(&ChunkType::from_str("IDAT").unwrap(), 1..100)
+            .unwrap();
+        assert_eq!(ranges, vec![(3, vec![2, 3])]);
+
+        let ranges = ChunkReader::new(&bytes[..])
+            .data_ranges(&ChunkType::from_str("zzZz").unwrap(), 0..4)
+            .unwrap();
+        assert!(ranges.is_empty());
+
+        // The bytes after a truncated chunk are missing, even though the range itself is intact
+        assert!(ChunkReader::new(&bytes[..50])
+            .data_ranges(&text, 0..1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_chunk_reader_truncated() {
+        let bytes = testing_png().as_bytes();
+        let mut reader = ChunkReader::new(&bytes[..bytes.len() - 1]);
+        assert!(reader.by_ref().any(|chunk| chunk.is_err()));
+        assert!(reader.next().is_none());
+
+        assert!(ChunkReader::new(&bytes[..bytes.len() - 12 + 2]).any(|chunk| chunk.is_err()));
+    }
+
+    #[test]
+    fn test_chunk_reader_bad_signature() {
+        let mut reader = ChunkReader::new(&b"GIF89a"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_chunk_reader_layout_warnings() {
+        let mut png = testing_png();
+        png.push_chunk(testing::chunk("tEXt", &[]));
+        let bytes = png.as_bytes();
+        let mut reader = ChunkReader::new(&bytes[..]);
+        reader.by_ref().for_each(drop);
+        assert_eq!(reader.layout_warnings(), png.layout_warnings());
+        assert_eq!(
+            reader.layout_warnings(),
+            vec![ParseWarning::AfterIend { count: 1 }]
+        );
+        assert!(ChunkReader::new(&testing_png().as_bytes()[..])
+            .layout_warnings()
+            .contains(&ParseWarning::MissingIhdr));
+    }
+
+    #[test]
+    fn test_chunk_reader_huge_length() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend([0x7f, 0xff, 0xff, 0xff, b'I', b'D', b'A', b'T', 0, 0]);
+        let mut reader = ChunkReader::new(&bytes[..]);
+        assert!(reader.next().unwrap().is_err());
+    }
+}
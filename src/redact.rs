@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use crate::{
+    raster::{ColorType, Image},
+    Error, Result,
+};
+
+/// Size of the blocks a pixelated region is divided into
+pub const PIXELATE_BLOCK: u32 = 8;
+
+/// A rectangle in pixel coordinates, parsed from `x,y,width,height`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Rect {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<std::result::Result<Vec<u32>, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(Rect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!("Expected x,y,width,height, got '{}'", s).into()),
+        }
+    }
+}
+
+/// How a redacted region is filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// Opaque black
+    Black,
+    /// Blocks of `PIXELATE_BLOCK` pixels averaged to a single color
+    Blur,
+}
+
+impl FromStr for Fill {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "black" => Ok(Fill::Black),
+            "blur" => Ok(Fill::Blur),
+            _ => Err(format!("Unknown fill '{}', expected black or blur", s).into()),
+        }
+    }
+}
+
+/// Blanks or pixelates `rect` in `image`. The rectangle is clipped to the image, and an error is
+/// returned if nothing is left of it. Indexed images keep their palette: black uses the darkest
+/// entry and pixelated blocks take the index of their first pixel.
+pub fn redact(image: &mut Image, rect: Rect, fill: Fill) -> Result<()> {
+    let right = rect.x.saturating_add(rect.width).min(image.header.width);
+    let bottom = rect.y.saturating_add(rect.height).min(image.header.height);
+    if rect.x >= right || rect.y >= bottom {
+        return Err("Redaction rectangle lies outside the image".into());
+    }
+
+    match fill {
+        Fill::Black => {
+            let black = black_pixel(image);
+            for y in rect.y..bottom {
+                for x in rect.x..right {
+                    pixel_mut(image, x, y).copy_from_slice(&black);
+                }
+            }
+        }
+        Fill::Blur => {
+            let channels = image.header.color_type.channels();
+            for top in (rect.y..bottom).step_by(PIXELATE_BLOCK as usize) {
+                for left in (rect.x..right).step_by(PIXELATE_BLOCK as usize) {
+                    let xs = left..(left + PIXELATE_BLOCK).min(right);
+                    let ys = top..(top + PIXELATE_BLOCK).min(bottom);
+
+                    let block = if image.header.color_type == ColorType::Indexed {
+                        image.pixel(left, top).to_vec()
+                    } else {
+                        let mut sums = vec![0u64; channels];
+                        for y in ys.clone() {
+                            for x in xs.clone() {
+                                for (sum, &s) in sums.iter_mut().zip(image.pixel(x, y)) {
+                                    *sum += s as u64;
+                                }
+                            }
+                        }
+                        let count = xs.len() as u64 * ys.len() as u64;
+                        sums.iter().map(|&sum| (sum / count) as u16).collect()
+                    };
+
+                    for y in ys.clone() {
+                        for x in xs.clone() {
+                            pixel_mut(image, x, y).copy_from_slice(&block);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pixel_mut(image: &mut Image, x: u32, y: u32) -> &mut [u16] {
+    let channels = image.header.color_type.channels();
+    let start = (y as usize * image.header.width as usize + x as usize) * channels;
+    &mut image.samples[start..start + channels]
+}
+
+fn black_pixel(image: &Image) -> Vec<u16> {
+    let opaque = ((1u32 << image.header.bit_depth) - 1) as u16;
+    match image.header.color_type {
+        ColorType::Gray => vec![0],
+        ColorType::GrayAlpha => vec![0, opaque],
+        ColorType::Rgb => vec![0, 0, 0],
+        ColorType::Rgba => vec![0, 0, 0, opaque],
+        ColorType::Indexed => {
+            let darkest = image
+                .palette
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, [r, g, b])| *r as u32 + *g as u32 + *b as u32)
+                .map_or(0, |(index, _)| index);
+            vec![darkest as u16]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{compose, decode, ImageHeader};
+
+    fn testing_image() -> Image {
+        let header = ImageHeader::from_str("width=4,height=2,color=rgb8").unwrap();
+        let pixels: Vec<u8> = (0..24).map(|i| i * 10).collect();
+        decode(&compose(&header, &pixels).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_rect_from_str() {
+        let rect = Rect::from_str("10, 20,30,40").unwrap();
+        assert_eq!(
+            rect,
+            Rect {
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 40
+            }
+        );
+        assert!(Rect::from_str("1,2,3").is_err());
+        assert!(Rect::from_str("1,2,3,-4").is_err());
+    }
+
+    #[test]
+    fn test_redact_black() {
+        let mut image = testing_image();
+        let rect = Rect::from_str("1,0,2,1").unwrap();
+        redact(&mut image, rect, Fill::Black).unwrap();
+
+        assert_eq!(image.pixel(0, 0), &[0, 10, 20]);
+        assert_eq!(image.pixel(1, 0), &[0, 0, 0]);
+        assert_eq!(image.pixel(2, 0), &[0, 0, 0]);
+        assert_eq!(image.pixel(3, 0), &[90, 100, 110]);
+        assert_eq!(image.pixel(1, 1), &[150, 160, 170]);
+    }
+
+    #[test]
+    fn test_redact_blur() {
+        let mut image = testing_image();
+        let rect = Rect::from_str("0,0,2,2").unwrap();
+        redact(&mut image, rect, Fill::Blur).unwrap();
+
+        // Average of (0,0), (1,0), (0,1) and (1,1)
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            assert_eq!(image.pixel(x, y), &[75, 85, 95]);
+        }
+        assert_eq!(image.pixel(2, 0), &[60, 70, 80]);
+    }
+
+    #[test]
+    fn test_redact_clips_rect() {
+        let mut image = testing_image();
+        redact(
+            &mut image,
+            Rect::from_str("3,1,100,100").unwrap(),
+            Fill::Black,
+        )
+        .unwrap();
+        assert_eq!(image.pixel(3, 1), &[0, 0, 0]);
+
+        assert!(redact(&mut image, Rect::from_str("4,0,1,1").unwrap(), Fill::Black).is_err());
+    }
+}
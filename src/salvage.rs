@@ -0,0 +1,285 @@
+use std::str::FromStr;
+
+use crate::png::{ParseMode, ParseWarning, Png};
+use crate::{chunk::Chunk, chunk_type::ChunkType, Result};
+
+/// The result of salvaging a damaged PNG
+#[derive(Debug)]
+pub struct Salvaged {
+    pub png: Png,
+    /// Number of bytes that could not be turned into chunks
+    pub dropped_bytes: usize,
+    /// Number of separate runs of unrecoverable bytes
+    pub gaps: usize,
+    /// Whether an IEND chunk had to be added
+    pub synthesized_iend: bool,
+    /// Whether a truncated IDAT chunk was kept
+    pub partial_idat: bool,
+    /// What lenient parsing found, for `salvage`. Empty for `salvage_resync`.
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Recovers as much of a truncated or damaged PNG as possible by parsing it leniently. Every
+/// chunk up to the first that is truncated or has a malformed type is kept (with its CRC
+/// recomputed), a missing IEND is added if `add_iend` is set, and if `keep_partial_idat` is set
+/// the data of a truncated IDAT chunk is kept so decoders can still render the top of the image.
+pub fn salvage(bytes: &[u8], keep_partial_idat: bool, add_iend: bool) -> Result<Salvaged> {
+    let (mut png, warnings) = Png::parse(bytes, ParseMode::Lenient)?;
+    png.fix_crcs();
+    let mut chunks = png.chunks().to_vec();
+    let mut consumed = Png::STANDARD_HEADER.len()
+        + chunks
+            .iter()
+            .map(|c| Chunk::MIN_CHUNK_LENGTH + c.data().len())
+            .sum::<usize>();
+
+    let truncated_at = warnings.iter().find_map(|warning| match warning {
+        ParseWarning::LengthMismatch { offset, .. } | ParseWarning::Truncated { offset, .. } => {
+            Some(*offset)
+        }
+        _ => None,
+    });
+    let partial_idat = keep_partial_idat
+        && truncated_at.is_some_and(|offset| bytes.get(offset + 4..offset + 8) == Some(b"IDAT"));
+    if let (true, Some(offset)) = (partial_idat, truncated_at) {
+        let length = u32::from_be_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ]);
+        let data_start = offset + 8;
+        let data_end = data_start + (bytes.len() - data_start).min(length as usize);
+        chunks.push(Chunk::new(
+            ChunkType::new(*b"IDAT"),
+            bytes[data_start..data_end].to_vec(),
+        )?);
+        consumed = bytes.len();
+    }
+
+    let dropped_bytes = bytes.len() - consumed;
+    let mut salvaged = finish(
+        chunks,
+        dropped_bytes,
+        (dropped_bytes > 0) as usize,
+        partial_idat,
+        add_iend,
+    )?;
+    salvaged.warnings = warnings;
+    Ok(salvaged)
+}
+
+/// A piece of a damaged file, as read by `resync`
+#[derive(Debug)]
+pub enum Segment {
+    Chunk(Chunk),
+    /// A run of bytes, of this length, that is not part of any valid chunk
+    Gap(usize),
+}
+
+/// Reads every valid chunk in a damaged PNG. Where a chunk is corrupt, this scans ahead for the
+/// next position holding a chunk with a valid type and CRC, and records the bytes skipped as a
+/// gap. Anything after IEND is also a gap.
+pub fn resync(bytes: &[u8]) -> Result<Vec<Segment>> {
+    Png::check_signature(bytes)?;
+
+    let mut segments = vec![];
+    let mut i = Png::STANDARD_HEADER.len();
+    while i < bytes.len() {
+        match valid_chunk_at(bytes, i) {
+            Some(size) => {
+                let chunk = Chunk::try_from(&bytes[i..i + size])?;
+                let is_iend = chunk.chunk_type().to_string() == "IEND";
+                segments.push(Segment::Chunk(chunk));
+                i += size;
+                if is_iend {
+                    break;
+                }
+            }
+            None => {
+                let next = next_chunk(bytes, i + 1).unwrap_or(bytes.len());
+                segments.push(Segment::Gap(next - i));
+                i = next;
+            }
+        }
+    }
+    if i < bytes.len() {
+        segments.push(Segment::Gap(bytes.len() - i));
+    }
+    Ok(segments)
+}
+
+/// Like `salvage`, but skips over corrupt chunks with `resync` instead of stopping at the first
+/// one, so every valid chunk after the damage is kept too
+pub fn salvage_resync(bytes: &[u8]) -> Result<Salvaged> {
+    let mut chunks = vec![];
+    let (mut dropped_bytes, mut gaps) = (0, 0);
+    for segment in resync(bytes)? {
+        match segment {
+            Segment::Chunk(chunk) => chunks.push(chunk),
+            Segment::Gap(length) => {
+                dropped_bytes += length;
+                gaps += 1;
+            }
+        }
+    }
+    finish(chunks, dropped_bytes, gaps, false, true)
+}
+
+/// The total size of the chunk at `offset` if it fits in `bytes` and has a valid type and CRC
+pub(crate) fn valid_chunk_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    let size = Chunk::size_at(bytes, offset).ok()?;
+    let chunk_type = &bytes[offset + 4..offset + 8];
+    let crc_offset = offset + size - 4;
+    let crc = u32::from_be_bytes([
+        bytes[crc_offset],
+        bytes[crc_offset + 1],
+        bytes[crc_offset + 2],
+        bytes[crc_offset + 3],
+    ]);
+    (chunk_type.iter().all(u8::is_ascii_alphabetic)
+        && Chunk::calculate_crc(chunk_type, &bytes[offset + 8..crc_offset]) == crc)
+        .then_some(size)
+}
+
+/// The offset of the first valid chunk at or after `from`
+pub(crate) fn next_chunk(bytes: &[u8], from: usize) -> Option<usize> {
+    (from..bytes.len()).find(|&offset| valid_chunk_at(bytes, offset).is_some())
+}
+
+fn finish(
+    mut chunks: Vec<Chunk>,
+    dropped_bytes: usize,
+    gaps: usize,
+    partial_idat: bool,
+    add_iend: bool,
+) -> Result<Salvaged> {
+    let synthesized_iend = add_iend
+        && chunks
+            .last()
+            .is_none_or(|c| c.chunk_type().to_string() != "IEND");
+    if synthesized_iend {
+        chunks.push(Chunk::new(ChunkType::from_str("IEND")?, vec![])?);
+    }
+
+    Ok(Salvaged {
+        png: Png::from_chunks(chunks),
+        dropped_bytes,
+        gaps,
+        synthesized_iend,
+        partial_idat,
+        warnings: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn testing_bytes() -> Vec<u8> {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[1; 32]),
+            chunk("IEND", &[]),
+        ])
+        .as_bytes()
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_salvage_intact() {
+        let bytes = testing_bytes();
+        let salvaged = salvage(&bytes, false, true).unwrap();
+
+        assert_eq!(salvaged.png.as_bytes(), bytes);
+        assert_eq!(salvaged.dropped_bytes, 0);
+        assert!(!salvaged.synthesized_iend);
+    }
+
+    #[test]
+    fn test_salvage_truncated_idat() {
+        let bytes = testing_bytes();
+        // Cut the file in the middle of the IDAT data
+        let truncated = &bytes[..8 + 25 + 8 + 10];
+
+        let salvaged = salvage(truncated, false, true).unwrap();
+        assert_eq!(types(&salvaged.png), vec!["IHDR", "IEND"]);
+        assert_eq!(salvaged.dropped_bytes, 18);
+        assert!(salvaged.synthesized_iend);
+
+        let salvaged = salvage(truncated, true, true).unwrap();
+        assert_eq!(types(&salvaged.png), vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(salvaged.png.chunks()[1].data(), &[1; 10]);
+        assert!(salvaged.partial_idat);
+        assert_eq!(salvaged.dropped_bytes, 0);
+    }
+
+    #[test]
+    fn test_salvage_fixes_crc() {
+        let mut bytes = testing_bytes();
+        bytes[8 + 25 - 1] ^= 0xff;
+
+        let salvaged = salvage(&bytes, false, true).unwrap();
+        assert_eq!(salvaged.png.as_bytes(), testing_bytes());
+        assert!(matches!(
+            salvaged.warnings[..],
+            [ParseWarning::BadCrc { offset: 8, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_salvage_invalid_type() {
+        let mut bytes = testing_bytes();
+        // A corrupted type cannot be told apart from a misplaced chunk boundary, so the chunk
+        // is dropped rather than given a CRC that would hide the damage
+        bytes[8 + 25 + 4] = b'1';
+
+        let salvaged = salvage(&bytes, false, false).unwrap();
+        assert_eq!(types(&salvaged.png), vec!["IHDR"]);
+        assert_eq!(salvaged.dropped_bytes, bytes.len() - 8 - 25);
+        assert!(!salvaged.synthesized_iend);
+        assert!(salvage(&bytes, false, true).unwrap().synthesized_iend);
+    }
+
+    #[test]
+    fn test_resync_skips_corrupt_chunk() {
+        let mut bytes = testing_bytes();
+        // Corrupt the IDAT length so the chunk no longer lines up with its CRC
+        bytes[8 + 25 + 3] = 40;
+
+        let segments = resync(&bytes).unwrap();
+        assert!(matches!(&segments[0], Segment::Chunk(c) if c.chunk_type().to_string() == "IHDR"));
+        assert!(matches!(segments[1], Segment::Gap(44)));
+        assert!(matches!(&segments[2], Segment::Chunk(c) if c.chunk_type().to_string() == "IEND"));
+        assert_eq!(segments.len(), 3);
+
+        let salvaged = salvage_resync(&bytes).unwrap();
+        assert_eq!(types(&salvaged.png), vec!["IHDR", "IEND"]);
+        assert_eq!(salvaged.dropped_bytes, 44);
+        assert_eq!(salvaged.gaps, 1);
+        assert!(!salvaged.synthesized_iend);
+
+        // Without resynchronizing, salvage stops at the damage and has to add IEND
+        assert!(salvage(&bytes, false, true).unwrap().synthesized_iend);
+    }
+
+    #[test]
+    fn test_resync_trailing_garbage() {
+        let mut bytes = testing_bytes();
+        bytes.extend(b"garbage");
+        let segments = resync(&bytes).unwrap();
+        assert!(matches!(segments.last(), Some(Segment::Gap(7))));
+    }
+
+    #[test]
+    fn test_salvage_bad_signature() {
+        assert!(salvage(b"not a png", false, true).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::ops::Range;
+
+use memchr::memmem;
+
+use crate::{chunk::Chunk, png::Png};
+
+/// A complete IEND chunk: zero length, type and its (always identical) CRC
+const IEND_CHUNK: [u8; 12] = [0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130];
+
+/// Returns the offset of every PNG signature in `haystack`
+pub fn signatures(haystack: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    memmem::find_iter(haystack, &Png::STANDARD_HEADER)
+}
+
+/// Finds the end of the PNG whose signature starts at `start`. Chunks are followed using their
+/// declared lengths; if that runs into garbage the next complete IEND chunk is used instead.
+/// Returns the offset just past the IEND chunk, or `None` if the PNG is never terminated.
+pub fn png_end(haystack: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + Png::STANDARD_HEADER.len();
+    while haystack.len().saturating_sub(i) >= Chunk::MIN_CHUNK_LENGTH {
+        let length = u32::from_be_bytes([
+            haystack[i],
+            haystack[i + 1],
+            haystack[i + 2],
+            haystack[i + 3],
+        ]) as usize;
+        let chunk_type = &haystack[i + 4..i + 8];
+        if !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+            break;
+        }
+
+        let end = i.checked_add(Chunk::MIN_CHUNK_LENGTH + length)?;
+        if end > haystack.len() {
+            break;
+        }
+        if chunk_type == b"IEND" {
+            return Some(end);
+        }
+        i = end;
+    }
+
+    memmem::find(&haystack[start..], &IEND_CHUNK).map(|offset| start + offset + IEND_CHUNK.len())
+}
+
+/// Locates every signature-to-IEND span in `haystack`
+pub fn find_pngs(haystack: &[u8]) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = vec![];
+    for start in signatures(haystack) {
+        if spans.last().is_some_and(|span| start < span.end) {
+            continue;
+        }
+        if let Some(end) = png_end(haystack, start) {
+            spans.push(start..end);
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn testing_png() -> Vec<u8> {
+        testing::png_from(&[("IHDR", &[0; 13]), ("IDAT", &[1; 32]), ("IEND", &[])]).as_bytes()
+    }
+
+    #[test]
+    fn test_iend_chunk_constant() {
+        assert_eq!(testing::chunk("IEND", &[]).as_bytes(), IEND_CHUNK);
+    }
+
+    #[test]
+    fn test_find_pngs() {
+        let png = testing_png();
+        let mut haystack = b"some leading garbage".to_vec();
+        let first = haystack.len();
+        haystack.extend(&png);
+        haystack.extend(b"more garbage in between");
+        let second = haystack.len();
+        haystack.extend(&png);
+        haystack.extend(b"trailing");
+
+        assert_eq!(
+            find_pngs(&haystack),
+            vec![first..first + png.len(), second..second + png.len()]
+        );
+    }
+
+    #[test]
+    fn test_find_pngs_corrupt_length() {
+        let mut png = testing_png();
+        // Declare an IDAT length far beyond the end of the data
+        png[8 + 25] = 0xff;
+
+        assert_eq!(find_pngs(&png), vec![0..png.len()]);
+    }
+
+    #[test]
+    fn test_find_pngs_unterminated() {
+        let png = testing_png();
+        assert!(find_pngs(&png[..png.len() - 12]).is_empty());
+    }
+}
@@ -0,0 +1,699 @@
+use serde_json::{json, Value};
+
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+/// How many bytes a string or byte field takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Size {
+    Fixed(usize),
+    /// The value of an earlier unsigned integer field
+    Field(String),
+    /// Up to, and consuming, the first occurrence of this byte
+    Terminator(u8),
+    /// Everything up to the end of the data
+    Eos,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    /// An unsigned integer of this many bytes
+    Unsigned(usize),
+    /// A two's complement integer of this many bytes
+    Signed(usize),
+    /// An IEEE 754 float of this many bytes
+    Float(usize),
+    Str(Size, Encoding),
+    Bytes(Size),
+    /// Fixed bytes that must be present, such as a magic number
+    Contents(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub field_type: FieldType,
+    pub endian: Endian,
+}
+
+/// The layout of a chunk's data as a sequence of fields
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+
+impl Schema {
+    /// Reads the subset of a Kaitai Struct `.ksy` file that describes a flat sequence of fields:
+    /// `meta/endian` and `seq` entries with `id`, `type` (integers, floats, `str` and `strz`),
+    /// `size`, `size-eos`, `terminator`, `encoding` and `contents`. Nested types, instances,
+    /// repetition and conditions are rejected rather than silently ignored.
+    pub fn from_ksy(bytes: &[u8]) -> Result<Schema> {
+        let ksy: Value =
+            serde_yaml::from_slice(bytes).map_err(|e| format!("Invalid .ksy file: {}", e))?;
+        let object = ksy.as_object().ok_or(".ksy file must be a YAML mapping")?;
+        if let Some(key) = object
+            .keys()
+            .find(|k| !["meta", "seq", "doc", "doc-ref"].contains(&k.as_str()))
+        {
+            return Err(format!("Schema uses '{}', which is not supported", key).into());
+        }
+        let endian = match ksy["meta"]["endian"].as_str() {
+            None | Some("be") => Endian::Big,
+            Some("le") => Endian::Little,
+            Some(other) => return Err(format!("Unknown endianness '{}'", other).into()),
+        };
+
+        let mut schema = Schema::default();
+        let seq = match &ksy["seq"] {
+            Value::Null => return Ok(schema),
+            Value::Array(seq) => seq,
+            _ => return Err("seq must be a list of fields".into()),
+        };
+        for attribute in seq {
+            let attribute = attribute
+                .as_object()
+                .ok_or("seq entries must be mappings")?;
+            let name = attribute
+                .get("id")
+                .and_then(Value::as_str)
+                .ok_or("seq entry is missing its id")?
+                .to_string();
+            if let Some(key) = attribute.keys().find(|k| {
+                ![
+                    "id",
+                    "type",
+                    "size",
+                    "size-eos",
+                    "terminator",
+                    "encoding",
+                    "contents",
+                    "doc",
+                ]
+                .contains(&k.as_str())
+            }) {
+                return Err(
+                    format!("Field '{}' uses '{}', which is not supported", name, key).into(),
+                );
+            }
+
+            let size = || -> Result<Size> {
+                if attribute.get("size-eos").and_then(Value::as_bool) == Some(true) {
+                    return Ok(Size::Eos);
+                }
+                if let Some(terminator) = attribute.get("terminator") {
+                    let byte = terminator
+                        .as_u64()
+                        .and_then(|t| u8::try_from(t).ok())
+                        .ok_or(format!("Field '{}' terminator must be a byte", name))?;
+                    return Ok(Size::Terminator(byte));
+                }
+                match attribute.get("size") {
+                    Some(Value::Number(n)) => Ok(Size::Fixed(
+                        n.as_u64()
+                            .ok_or(format!("Field '{}' has an invalid size", name))?
+                            as usize,
+                    )),
+                    Some(Value::String(field)) => Ok(Size::Field(field.clone())),
+                    _ => {
+                        Err(format!("Field '{}' needs a size, size-eos or terminator", name).into())
+                    }
+                }
+            };
+            let encoding = match attribute.get("encoding").and_then(Value::as_str) {
+                None => Encoding::Utf8,
+                Some(e) if e.eq_ignore_ascii_case("utf-8") || e.eq_ignore_ascii_case("ascii") => {
+                    Encoding::Utf8
+                }
+                Some(e)
+                    if e.eq_ignore_ascii_case("iso-8859-1") || e.eq_ignore_ascii_case("latin1") =>
+                {
+                    Encoding::Latin1
+                }
+                Some(e) => return Err(format!("Unsupported encoding '{}'", e).into()),
+            };
+
+            let type_name = attribute.get("type").and_then(Value::as_str);
+            let (field_type, field_endian) = match (type_name, attribute.get("contents")) {
+                (None, Some(contents)) => (FieldType::Contents(contents_bytes(contents)?), endian),
+                (None, None) => (FieldType::Bytes(size()?), endian),
+                (Some("str"), _) => (FieldType::Str(size()?, encoding), endian),
+                (Some("strz"), _) => (FieldType::Str(Size::Terminator(0), encoding), endian),
+                (Some(type_name), _) => primitive(type_name, endian).ok_or(format!(
+                    "Field '{}' has unsupported type '{}'",
+                    name, type_name
+                ))?,
+            };
+            if let FieldType::Str(Size::Field(other), _) | FieldType::Bytes(Size::Field(other)) =
+                &field_type
+            {
+                if !schema.fields.iter().any(|f| &f.name == other) {
+                    return Err(format!(
+                        "Field '{}' takes its size from '{}', which is not an earlier field",
+                        name, other
+                    )
+                    .into());
+                }
+            }
+            schema.fields.push(Field {
+                name,
+                field_type,
+                endian: field_endian,
+            });
+        }
+        Ok(schema)
+    }
+
+    /// Reads a schema from a TOML table such as
+    /// `{fields = [{name = "version", type = "u16"}, {name = "label", type = "str"}]}`. Types are
+    /// `u8` to `u64`, `i8` to `i64`, `f32`, `f64`, `str` and `bytes`. Strings and bytes take a
+    /// `size` (a number or the name of an earlier field) or a `terminator` byte, and otherwise
+    /// run to the end of the data. Integers are big-endian, as in PNG itself, unless the table
+    /// or the field sets `endian = "little"`.
+    pub fn from_toml(table: &Value) -> Result<Schema> {
+        let endian = |value: &Value, default: Endian| match value.as_str() {
+            None => Ok(default),
+            Some("big") => Ok(Endian::Big),
+            Some("little") => Ok(Endian::Little),
+            Some(other) => Err(format!("endian must be big or little, got '{}'", other)),
+        };
+        let default_endian = endian(&table["endian"], Endian::Big)?;
+        let fields = table["fields"]
+            .as_array()
+            .ok_or("Schema is missing its fields array")?;
+
+        let mut schema = Schema::default();
+        for field in fields {
+            let name = field["name"]
+                .as_str()
+                .ok_or("Schema field is missing its name")?
+                .to_string();
+            let type_name = field["type"]
+                .as_str()
+                .ok_or(format!("Field '{}' is missing its type", name))?;
+            let size = match (&field["size"], &field["terminator"]) {
+                (Value::Null, Value::Null) => Size::Eos,
+                (Value::Number(n), Value::Null) => Size::Fixed(
+                    n.as_u64()
+                        .ok_or(format!("Field '{}' has an invalid size", name))?
+                        as usize,
+                ),
+                (Value::String(other), Value::Null) => {
+                    if !schema.fields.iter().any(|f| &f.name == other) {
+                        return Err(format!(
+                            "Field '{}' takes its size from '{}', which is not an earlier field",
+                            name, other
+                        )
+                        .into());
+                    }
+                    Size::Field(other.clone())
+                }
+                (Value::Null, terminator) => Size::Terminator(
+                    terminator
+                        .as_u64()
+                        .and_then(|t| u8::try_from(t).ok())
+                        .ok_or(format!("Field '{}' terminator must be a byte", name))?,
+                ),
+                _ => {
+                    return Err(format!(
+                        "Field '{}' cannot have both a size and a terminator",
+                        name
+                    )
+                    .into())
+                }
+            };
+            let field_type = match type_name {
+                "str" => FieldType::Str(size, Encoding::Utf8),
+                "bytes" => FieldType::Bytes(size),
+                _ => {
+                    let bits = type_name
+                        .get(1..)
+                        .and_then(|bits| bits.parse::<usize>().ok());
+                    match (type_name.chars().next(), bits) {
+                        (Some('u'), Some(b @ (8 | 16 | 32 | 64))) => FieldType::Unsigned(b / 8),
+                        (Some('i'), Some(b @ (8 | 16 | 32 | 64))) => FieldType::Signed(b / 8),
+                        (Some('f'), Some(b @ (32 | 64))) => FieldType::Float(b / 8),
+                        _ => {
+                            return Err(format!(
+                                "Field '{}' has unsupported type '{}'",
+                                name, type_name
+                            )
+                            .into())
+                        }
+                    }
+                }
+            };
+            schema.fields.push(Field {
+                name,
+                field_type,
+                endian: endian(&field["endian"], default_endian)?,
+            });
+        }
+        Ok(schema)
+    }
+
+    /// Builds chunk data from field values given as strings, the reverse of `parse`. Numbers
+    /// are parsed according to the field type and bytes are given as hex. A field that gives
+    /// the size of a later field may be left out, and is then filled in from that field.
+    pub fn build(&self, values: &[(String, String)]) -> Result<Vec<u8>> {
+        if let Some((name, _)) = values
+            .iter()
+            .find(|(name, _)| !self.fields.iter().any(|f| &f.name == name))
+        {
+            return Err(format!("Schema has no field '{}'", name).into());
+        }
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let bytes_of = |field: &Field| -> Result<Option<Vec<u8>>> {
+            let Some(value) = value(&field.name) else {
+                return Ok(None);
+            };
+            Ok(match &field.field_type {
+                FieldType::Str(_, Encoding::Utf8) => Some(value.as_bytes().to_vec()),
+                FieldType::Str(_, Encoding::Latin1) => Some(
+                    value
+                        .chars()
+                        .map(|c| u8::try_from(c).map_err(|_| format!("'{}' is not Latin-1", c)))
+                        .collect::<std::result::Result<_, _>>()?,
+                ),
+                FieldType::Bytes(_) => Some(
+                    unhex(value).ok_or(format!("Field '{}' must be given as hex", field.name))?,
+                ),
+                _ => None,
+            })
+        };
+
+        let mut data = vec![];
+        for field in self.fields.iter() {
+            let invalid = || format!("Invalid value for field '{}'", field.name);
+            match &field.field_type {
+                FieldType::Unsigned(n) | FieldType::Signed(n) => {
+                    let number = match value(&field.name) {
+                        Some(v) if matches!(field.field_type, FieldType::Signed(_)) => {
+                            v.parse::<i64>().map_err(|_| invalid())? as u64
+                        }
+                        Some(v) => v.parse::<u64>().map_err(|_| invalid())?,
+                        // Fill in the size of a later field that refers to this one
+                        None => {
+                            let sized = self.fields.iter().find(|f| {
+                                matches!(&f.field_type,
+                                    FieldType::Str(Size::Field(s), _) | FieldType::Bytes(Size::Field(s))
+                                    if s == &field.name)
+                            });
+                            let bytes = match sized {
+                                Some(sized) => bytes_of(sized)?,
+                                None => None,
+                            };
+                            bytes
+                                .ok_or(format!("Field '{}' needs a value", field.name))?
+                                .len() as u64
+                        }
+                    };
+                    let fits = match field.field_type {
+                        _ if *n == 8 => true,
+                        FieldType::Signed(_) => {
+                            let shift = 64 - 8 * *n as u32;
+                            ((number << shift) as i64 >> shift) as u64 == number
+                        }
+                        _ => number >> (8 * n) == 0,
+                    };
+                    if !fits {
+                        return Err(
+                            format!("Field '{}' does not fit in {} bytes", field.name, n).into(),
+                        );
+                    }
+                    write_uint(&mut data, number, *n, field.endian);
+                }
+                FieldType::Float(n) => {
+                    let number: f64 = value(&field.name)
+                        .ok_or(format!("Field '{}' needs a value", field.name))?
+                        .parse()
+                        .map_err(|_| invalid())?;
+                    let bits = if *n == 4 {
+                        (number as f32).to_bits() as u64
+                    } else {
+                        number.to_bits()
+                    };
+                    write_uint(&mut data, bits, *n, field.endian);
+                }
+                FieldType::Str(size, _) | FieldType::Bytes(size) => {
+                    let bytes =
+                        bytes_of(field)?.ok_or(format!("Field '{}' needs a value", field.name))?;
+                    match size {
+                        Size::Fixed(n) if bytes.len() != *n => {
+                            return Err(format!(
+                                "Field '{}' must be {} bytes long, got {}",
+                                field.name,
+                                n,
+                                bytes.len()
+                            )
+                            .into())
+                        }
+                        Size::Terminator(byte) if bytes.contains(byte) => {
+                            return Err(format!(
+                                "Field '{}' cannot contain its terminator",
+                                field.name
+                            )
+                            .into())
+                        }
+                        _ => {}
+                    }
+                    data.extend(&bytes);
+                    if let Size::Terminator(byte) = size {
+                        data.push(*byte);
+                    }
+                }
+                FieldType::Contents(expected) => data.extend(expected),
+            }
+        }
+        Ok(data)
+    }
+
+    /// Reads every field from `data` in order, returning each field's name and value. Integers
+    /// and floats become numbers, strings become strings and bytes become lowercase hex.
+    pub fn parse(&self, data: &[u8]) -> Result<Vec<(String, Value)>> {
+        let mut values: Vec<(String, Value)> = vec![];
+        let mut pos = 0;
+        for field in self.fields.iter() {
+            let remaining = &data[pos..];
+            let take = |n: usize| -> Result<&[u8]> {
+                remaining.get(..n).ok_or_else(|| {
+                    format!(
+                        "Field '{}' needs {} bytes but only {} remain",
+                        field.name,
+                        n,
+                        remaining.len()
+                    )
+                    .into()
+                })
+            };
+            let size = |size: &Size| -> Result<(usize, usize)> {
+                // The length of the value and the number of bytes it consumes
+                match size {
+                    Size::Fixed(n) => Ok((*n, *n)),
+                    Size::Eos => Ok((remaining.len(), remaining.len())),
+                    Size::Terminator(byte) => {
+                        let end = remaining.iter().position(|b| b == byte).ok_or(format!(
+                            "Field '{}' is missing its terminator {:#04x}",
+                            field.name, byte
+                        ))?;
+                        Ok((end, end + 1))
+                    }
+                    Size::Field(other) => {
+                        let n = values
+                            .iter()
+                            .find(|(name, _)| name == other)
+                            .and_then(|(_, value)| value.as_u64())
+                            .ok_or(format!("Size field '{}' is not an unsigned integer", other))?;
+                        Ok((n as usize, n as usize))
+                    }
+                }
+            };
+
+            let (value, consumed) = match &field.field_type {
+                FieldType::Unsigned(n) => (json!(read_uint(take(*n)?, field.endian)), *n),
+                FieldType::Signed(n) => {
+                    let unsigned = read_uint(take(*n)?, field.endian);
+                    let shift = 64 - 8 * *n as u32;
+                    (json!(((unsigned << shift) as i64) >> shift), *n)
+                }
+                FieldType::Float(4) => {
+                    let bits = read_uint(take(4)?, field.endian) as u32;
+                    (json!(f32::from_bits(bits)), 4)
+                }
+                FieldType::Float(n) => (
+                    json!(f64::from_bits(read_uint(take(*n)?, field.endian))),
+                    *n,
+                ),
+                FieldType::Str(s, encoding) => {
+                    let (length, consumed) = size(s)?;
+                    let bytes = take(consumed)?;
+                    let text = match encoding {
+                        Encoding::Utf8 => String::from_utf8_lossy(&bytes[..length]).into_owned(),
+                        Encoding::Latin1 => bytes[..length].iter().map(|&b| b as char).collect(),
+                    };
+                    (json!(text), consumed)
+                }
+                FieldType::Bytes(s) => {
+                    let (length, consumed) = size(s)?;
+                    (json!(hex(&take(consumed)?[..length])), consumed)
+                }
+                FieldType::Contents(expected) => {
+                    let actual = take(expected.len())?;
+                    if actual != &expected[..] {
+                        return Err(format!(
+                            "Field '{}' should be {} but is {}",
+                            field.name,
+                            hex(expected),
+                            hex(actual)
+                        )
+                        .into());
+                    }
+                    (json!(hex(actual)), expected.len())
+                }
+            };
+            values.push((field.name.clone(), value));
+            pos += consumed;
+        }
+        Ok(values)
+    }
+}
+
+/// Formats parsed fields on one line, e.g. `version: 1, name: "logo"`
+pub fn describe(values: &[(String, Value)]) -> String {
+    values
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses Kaitai primitive type names such as `u4`, `s2le` or `f8be`
+fn primitive(name: &str, endian: Endian) -> Option<(FieldType, Endian)> {
+    let (name, endian) = match name {
+        _ if name.ends_with("be") => (&name[..name.len() - 2], Endian::Big),
+        _ if name.ends_with("le") => (&name[..name.len() - 2], Endian::Little),
+        _ => (name, endian),
+    };
+    let (kind, width) = name.split_at_checked(1)?;
+    let width: usize = width.parse().ok()?;
+    let field_type = match (kind, width) {
+        ("u", 1 | 2 | 4 | 8) => FieldType::Unsigned(width),
+        ("s", 1 | 2 | 4 | 8) => FieldType::Signed(width),
+        ("f", 4 | 8) => FieldType::Float(width),
+        _ => return None,
+    };
+    Some((field_type, endian))
+}
+
+fn contents_bytes(contents: &Value) -> Result<Vec<u8>> {
+    match contents {
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) if s.len() == 1 => Ok(s.as_bytes()[0]),
+                _ => item
+                    .as_u64()
+                    .and_then(|b| u8::try_from(b).ok())
+                    .ok_or_else(|| "contents must list bytes".into()),
+            })
+            .collect(),
+        _ => Err("contents must be a string or a list of bytes".into()),
+    }
+}
+
+fn read_uint(bytes: &[u8], endian: Endian) -> u64 {
+    let fold = |value: u64, &byte: &u8| (value << 8) | byte as u64;
+    match endian {
+        Endian::Big => bytes.iter().fold(0, fold),
+        Endian::Little => bytes.iter().rev().fold(0, fold),
+    }
+}
+
+fn write_uint(data: &mut Vec<u8>, value: u64, width: usize, endian: Endian) {
+    let bytes = value.to_be_bytes();
+    let bytes = &bytes[8 - width..];
+    match endian {
+        Endian::Big => data.extend(bytes),
+        Endian::Little => data.extend(bytes.iter().rev()),
+    }
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KSY: &str = "
+meta:
+  id: rust_chunk
+  endian: le
+seq:
+  - id: magic
+    contents: [0x52, 0x53]
+  - id: version
+    type: u2
+  - id: offset
+    type: s4be
+  - id: name_length
+    type: u1
+  - id: name
+    type: str
+    size: name_length
+  - id: comment
+    type: strz
+    encoding: ISO-8859-1
+  - id: rest
+    size-eos: true
+";
+
+    #[test]
+    fn test_from_ksy() {
+        let schema = Schema::from_ksy(KSY.as_bytes()).unwrap();
+        assert_eq!(schema.fields.len(), 7);
+        assert_eq!(schema.fields[1].field_type, FieldType::Unsigned(2));
+        assert_eq!(schema.fields[1].endian, Endian::Little);
+        assert_eq!(schema.fields[2].endian, Endian::Big);
+        assert_eq!(
+            schema.fields[5].field_type,
+            FieldType::Str(Size::Terminator(0), Encoding::Latin1)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let schema = Schema::from_ksy(KSY.as_bytes()).unwrap();
+        let mut data = vec![b'R', b'S', 2, 1, 0xff, 0xff, 0xff, 0xfe, 4];
+        data.extend(b"logoM\xfcller\0\x01\x02");
+
+        let values = schema.parse(&data).unwrap();
+        assert_eq!(
+            describe(&values),
+            r#"magic: "5253", version: 258, offset: -2, name_length: 4, name: "logo", comment: "Müller", rest: "0102""#
+        );
+
+        data[0] = b'X';
+        assert!(schema
+            .parse(&data)
+            .unwrap_err()
+            .to_string()
+            .contains("magic"));
+        assert!(schema.parse(&data[..5]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_ksy() {
+        assert!(Schema::from_ksy(b"seq:\n  - id: a\n    type: u4\n    repeat: eos\n").is_err());
+        assert!(Schema::from_ksy(b"types: {}\n").is_err());
+        assert!(Schema::from_ksy(b"seq:\n  - id: a\n    type: b12\n").is_err());
+        assert!(Schema::from_ksy(b"seq:\n  - id: a\n    type: be\n").is_err());
+        assert!(Schema::from_ksy("seq:\n  - id: a\n    type: ü4\n".as_bytes()).is_err());
+        assert!(Schema::from_ksy(b"seq:\n  - id: a\n    type: str\n    size: len\n").is_err());
+    }
+
+    const TOML_SCHEMA: &str = r#"{
+        "fields": [
+            {"name": "version", "type": "u16"},
+            {"name": "delta", "type": "i8"},
+            {"name": "label_length", "type": "u8"},
+            {"name": "label", "type": "str", "size": "label_length"},
+            {"name": "note", "type": "str", "terminator": 0, "endian": "little"},
+            {"name": "rest", "type": "bytes"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_from_toml() {
+        let schema = Schema::from_toml(&serde_json::from_str(TOML_SCHEMA).unwrap()).unwrap();
+        assert_eq!(schema.fields[0].field_type, FieldType::Unsigned(2));
+        assert_eq!(schema.fields[1].field_type, FieldType::Signed(1));
+        assert_eq!(
+            schema.fields[3].field_type,
+            FieldType::Str(Size::Field("label_length".to_string()), Encoding::Utf8)
+        );
+        assert_eq!(schema.fields[4].endian, Endian::Little);
+        assert_eq!(schema.fields[5].field_type, FieldType::Bytes(Size::Eos));
+
+        let invalid = |fields: &str| {
+            Schema::from_toml(
+                &serde_json::from_str(&format!(r#"{{"fields": {}}}"#, fields)).unwrap(),
+            )
+            .is_err()
+        };
+        assert!(invalid(r#"[{"name": "a", "type": "u12"}]"#));
+        assert!(invalid(r#"[{"name": "a", "type": ""}]"#));
+        assert!(invalid(r#"[{"name": "a", "type": "str", "size": "b"}]"#));
+        assert!(invalid(
+            r#"[{"name": "a", "type": "str", "size": 1, "terminator": 0}]"#
+        ));
+        assert!(invalid(r#"[{"type": "u8"}]"#));
+    }
+
+    #[test]
+    fn test_build() {
+        let schema = Schema::from_toml(&serde_json::from_str(TOML_SCHEMA).unwrap()).unwrap();
+        let values = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect()
+        };
+        let data = schema
+            .build(&values(&[
+                ("version", "258"),
+                ("delta", "-2"),
+                ("label", "logo"),
+                ("note", "hi"),
+                ("rest", "0aff"),
+            ]))
+            .unwrap();
+        assert_eq!(data, b"\x01\x02\xfe\x04logohi\0\x0a\xff");
+        assert_eq!(
+            describe(&schema.parse(&data).unwrap()),
+            r#"version: 258, delta: -2, label_length: 4, label: "logo", note: "hi", rest: "0aff""#
+        );
+
+        let fails = |pairs: &[(&str, &str)]| schema.build(&values(pairs)).is_err();
+        let base = [
+            ("version", "1"),
+            ("delta", "0"),
+            ("label", ""),
+            ("note", ""),
+            ("rest", ""),
+        ];
+        assert!(!fails(&base));
+        assert!(fails(&[&base[..], &[("other", "1")]].concat()));
+        assert!(fails(&[("version", "65536")]));
+        assert!(fails(&[("version", "1"), ("delta", "-129")]));
+        assert!(fails(&base[..4]));
+        assert!(fails(&[&base[..4], &[("rest", "0")]].concat()));
+    }
+}
@@ -0,0 +1,176 @@
+//! Buffers for payloads put together from several chunks or inflated, which move to a
+//! temporary file once they outgrow `--max-memory` so huge payloads work on small machines.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::sync::OnceLock;
+
+use crate::temp::TempFile;
+use crate::Result;
+
+static MAX_MEMORY: OnceLock<usize> = OnceLock::new();
+
+/// Sets how many bytes a buffer may hold in memory before it spills. Only the first call has
+/// an effect.
+pub fn set_max_memory(bytes: usize) {
+    let _ = MAX_MEMORY.set(bytes);
+}
+
+/// The `--max-memory` cap, unlimited unless `set_max_memory` was called
+pub fn max_memory() -> Option<usize> {
+    MAX_MEMORY.get().copied()
+}
+
+/// Bytes kept in memory until there are more than the cap, then in a temporary file
+#[derive(Debug)]
+pub struct Spill {
+    cap: Option<usize>,
+    memory: Vec<u8>,
+    file: Option<TempFile>,
+    len: usize,
+}
+
+impl Default for Spill {
+    fn default() -> Self {
+        Spill::with_cap(max_memory())
+    }
+}
+
+impl Spill {
+    /// A buffer that spills once it holds more than `cap` bytes, or never for `None`
+    pub fn with_cap(cap: Option<usize>) -> Spill {
+        Spill {
+            cap,
+            memory: vec![],
+            file: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the contents have moved to a temporary file
+    pub fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Reads the contents from `offset` on
+    pub fn reader_at(&self, offset: usize) -> Result<Box<dyn Read + '_>> {
+        let offset = offset.min(self.len);
+        Ok(match &self.file {
+            Some(temp) => {
+                let mut file = File::open(temp.path())?;
+                file.seek(SeekFrom::Start(offset as u64))?;
+                Box::new(BufReader::new(file))
+            }
+            None => Box::new(&self.memory[offset..]),
+        })
+    }
+
+    /// Up to `length` bytes from `offset` on, for peeking at headers
+    pub fn read_at(&self, offset: usize, length: usize) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.reader_at(offset)?
+            .take(length as u64)
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// The contents from `offset` on, read back into memory
+    pub fn to_vec_at(&self, offset: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.len - offset.min(self.len));
+        self.reader_at(offset)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Copies the contents from `offset` on to `writer`
+    pub fn copy_to<W: Write>(&self, offset: usize, writer: &mut W) -> Result<()> {
+        io::copy(&mut self.reader_at(offset)?, writer)?;
+        Ok(())
+    }
+}
+
+impl Write for Spill {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let spill = self.file.is_none() && self.cap.is_some_and(|cap| self.len + buf.len() > cap);
+        if spill {
+            let _span = tracing::info_span!("spill", bytes = self.len).entered();
+            let mut temp = TempFile::new().map_err(io::Error::other)?;
+            temp.write_all(&self.memory).map_err(io::Error::other)?;
+            self.memory = vec![];
+            self.file = Some(temp);
+        }
+        match &mut self.file {
+            Some(temp) => temp.write_all(buf).map_err(io::Error::other)?,
+            None => self.memory.extend_from_slice(buf),
+        }
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use crate::payload;
+
+    #[test]
+    fn test_spill() {
+        let mut buffer = Spill::with_cap(Some(8));
+        buffer.write_all(b"small").unwrap();
+        assert!(!buffer.is_spilled());
+        buffer.write_all(b" then larger").unwrap();
+        assert!(buffer.is_spilled());
+        assert_eq!(buffer.len(), 17);
+        assert_eq!(buffer.to_vec_at(0).unwrap(), b"small then larger");
+        assert_eq!(buffer.read_at(6, 4).unwrap(), b"then");
+
+        let mut copied = vec![];
+        buffer.copy_to(11, &mut copied).unwrap();
+        assert_eq!(copied, b"larger");
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut buffer = Spill::with_cap(None);
+        buffer.write_all(&[0; 4096]).unwrap();
+        assert!(!buffer.is_spilled());
+        assert_eq!(buffer.to_vec_at(4000).unwrap().len(), 96);
+    }
+
+    #[test]
+    fn test_split_payload_spills() {
+        let message = "hidden message ".repeat(1000).into_bytes();
+        let compressed = payload::compress(&message).unwrap();
+        let parts = payload::split(&compressed, 64).unwrap();
+        let parts: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+
+        let mut joined = Spill::with_cap(Some(16));
+        payload::join_into(&parts, &mut joined).unwrap();
+        assert!(joined.is_spilled());
+        assert_eq!(joined.to_vec_at(0).unwrap(), compressed);
+
+        let magic = payload::COMPRESSED_MAGIC.len();
+        let mut inflated = Spill::with_cap(Some(100));
+        Limits::default()
+            .inflate_into(
+                joined.reader_at(magic).unwrap(),
+                joined.len() - magic,
+                &mut inflated,
+            )
+            .unwrap();
+        assert!(inflated.is_spilled());
+        assert_eq!(inflated.to_vec_at(0).unwrap(), message);
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{limits, Result};
+
+/// The path standing for stdin when reading and stdout when writing
+pub const PATH: &str = "-";
+
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(PATH)
+}
+
+/// Reads all of stdin, failing as soon as it passes the file size limit
+pub fn read_stdin() -> Result<Vec<u8>> {
+    read_limited(io::stdin().lock())
+}
+
+fn read_limited<R: Read>(reader: R) -> Result<Vec<u8>> {
+    let limits = limits::get();
+    let cap = limits.max_file_size.map_or(u64::MAX, |max| max as u64 + 1);
+    let mut contents = vec![];
+    reader.take(cap).read_to_end(&mut contents)?;
+    limits.check_file_size(contents.len())?;
+    Ok(contents)
+}
+
+/// Writes `contents` to stdout. From then on stdout carries data, so status messages belong on
+/// stderr, see `stdout_taken`.
+pub fn write_stdout(contents: &[u8]) -> Result<()> {
+    copy_to_stdout(contents)
+}
+
+/// Copies `reader` to stdout without reading it all into memory first, see `write_stdout`
+pub fn copy_to_stdout<R: Read>(mut reader: R) -> Result<()> {
+    STDOUT_TAKEN.store(true, Ordering::SeqCst);
+    let mut stdout = io::stdout().lock();
+    io::copy(&mut reader, &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Whether a file has been written to stdout
+pub fn stdout_taken() -> bool {
+    STDOUT_TAKEN.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stdio() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("./-")));
+        assert!(!is_stdio(Path::new("image.png")));
+    }
+
+    #[test]
+    fn test_read_limited() {
+        assert_eq!(read_limited(&b"\x89PNG"[..]).unwrap(), b"\x89PNG");
+    }
+}
@@ -0,0 +1,261 @@
+//! Checks which ways of hiding a payload in a PNG survive the transformations files commonly go
+//! through on their way to an audience, such as metadata stripping and lossless optimization.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    payload,
+    png::Png,
+    profile::Profile,
+    raster,
+    text::{self, TextChunk},
+    watermark, Error, Result,
+};
+
+/// The payload embedded by every method
+pub const PAYLOAD: &str = "pngchunk stego test";
+/// Private, ancillary and safe to copy, so editors that follow the spec keep it
+const CHUNK_TYPE: &str = "stEg";
+const KEYWORD: &str = "Comment";
+const WATERMARK_ID: u32 = 0x5e9a_7e57;
+
+/// A way of embedding a payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// An enveloped payload in a private chunk, as written by `encode`
+    Chunk,
+    /// A tEXt chunk
+    Text,
+    /// A watermark in the pixel data, as written by `watermark embed`
+    Watermark,
+}
+
+pub const METHODS: [Method; 3] = [Method::Chunk, Method::Text, Method::Watermark];
+
+impl Method {
+    fn embed(&self, png: &Png) -> Result<Png> {
+        let chunk = match self {
+            Method::Chunk => Chunk::new(
+                ChunkType::from_str(CHUNK_TYPE)?,
+                payload::seal(PAYLOAD.as_bytes()),
+            )?,
+            Method::Text => TextChunk {
+                keyword: KEYWORD.to_string(),
+                text: PAYLOAD.to_string(),
+            }
+            .to_chunk()?,
+            Method::Watermark => {
+                let mut image = raster::decode(png)?;
+                watermark::embed(&mut image, WATERMARK_ID, PAYLOAD, 2.0)?;
+                return raster::reencode(png, &image);
+            }
+        };
+        let mut png = png.clone();
+        png.insert_before_iend(chunk);
+        Ok(png)
+    }
+
+    /// Whether the payload can still be read back from `png`
+    fn detect(&self, png: &Png) -> bool {
+        match self {
+            Method::Chunk => png.chunks().iter().any(|chunk| {
+                chunk.chunk_type().to_string() == CHUNK_TYPE
+                    && payload::open(chunk.data())
+                        .is_ok_and(|payload| payload.message() == PAYLOAD.as_bytes())
+            }),
+            Method::Text => png.chunks().iter().any(|chunk| {
+                matches!(text::keyword_text(chunk), Ok(Some((keyword, text)))
+                    if keyword == KEYWORD && text == PAYLOAD)
+            }),
+            Method::Watermark => raster::decode(png)
+                .and_then(|image| watermark::detect(&image, PAYLOAD))
+                .is_ok_and(|detection| detection.is_present() && detection.id == WATERMARK_ID),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Method::Chunk => "chunk",
+            Method::Text => "text",
+            Method::Watermark => "watermark",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A transformation a file might go through after the payload is embedded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Removes every ancillary chunk, like `strip` without a profile
+    Strip,
+    /// Keeps only the chunks that affect how the image looks and recompresses the image data,
+    /// like lossless optimizers do by default
+    Optimize,
+    /// Decodes and re-encodes the image data. Unknown chunks that are not safe to copy are
+    /// dropped, as the PNG spec asks of editors that change the image.
+    Recompress,
+}
+
+pub const TRANSFORMS: [Transform; 3] =
+    [Transform::Strip, Transform::Optimize, Transform::Recompress];
+
+impl Transform {
+    pub fn apply(&self, png: &Png) -> Result<Png> {
+        match self {
+            Transform::Strip => Ok(Profile::default().strip(png).0),
+            Transform::Optimize => {
+                let web = Profile::built_in("web").unwrap_or_default();
+                Transform::Recompress.apply(&web.strip(png).0)
+            }
+            Transform::Recompress => {
+                let reencoded = raster::reencode(png, &raster::decode(png)?)?;
+                let kept = reencoded
+                    .chunks()
+                    .iter()
+                    .filter(|chunk| {
+                        let chunk_type = chunk.chunk_type();
+                        chunk_type.is_critical()
+                            || chunk_type.is_safe_to_copy()
+                            || chunk_type.description().is_some()
+                    })
+                    .cloned()
+                    .collect();
+                Ok(Png::from_chunks(kept))
+            }
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strip" => Ok(Transform::Strip),
+            "optimize" => Ok(Transform::Optimize),
+            "recompress" => Ok(Transform::Recompress),
+            _ => Err(format!(
+                "Unknown transform '{}', expected strip, optimize or recompress",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Transform::Strip => "strip",
+            Transform::Optimize => "optimize",
+            Transform::Recompress => "recompress",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How a method fared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Survived,
+    Lost,
+    /// The method cannot be used on this image, e.g. watermarking an indexed image
+    Unsupported,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Outcome::Survived => "survived",
+            Outcome::Lost => "lost",
+            Outcome::Unsupported => "unsupported",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Embeds the test payload in `png` with each method, applies each of `transforms` to the
+/// result separately and reports whether the payload could still be read back
+pub fn survival(png: &Png, transforms: &[Transform]) -> Result<Vec<(Method, Vec<Outcome>)>> {
+    METHODS
+        .iter()
+        .map(|&method| {
+            let embedded = match method.embed(png) {
+                Ok(embedded) if method.detect(&embedded) => embedded,
+                _ => return Ok((method, vec![Outcome::Unsupported; transforms.len()])),
+            };
+            let outcomes = transforms
+                .iter()
+                .map(|transform| {
+                    Ok(match method.detect(&transform.apply(&embedded)?) {
+                        true => Outcome::Survived,
+                        false => Outcome::Lost,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Ok((method, outcomes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{compose, ColorType, ImageHeader};
+    use crate::testing::chunk;
+
+    fn testing_png(header: ImageHeader) -> Png {
+        let pixels: Vec<u8> = (0..header.raw_size().unwrap())
+            .map(|i| (i / 3 % 128 + i / 384 / 2) as u8)
+            .collect();
+        compose(&header, &pixels).unwrap()
+    }
+
+    #[test]
+    fn test_survival() {
+        let header = ImageHeader::from_str("width=128,height=128,color=rgb8").unwrap();
+        let outcomes = survival(&testing_png(header), &TRANSFORMS).unwrap();
+        let [(Method::Chunk, chunk), (Method::Text, text), (Method::Watermark, watermark)] =
+            &outcomes[..]
+        else {
+            panic!("unexpected methods: {:?}", outcomes);
+        };
+        use Outcome::*;
+        assert_eq!(chunk, &[Lost, Lost, Survived]);
+        assert_eq!(text, &[Lost, Lost, Survived]);
+        assert_eq!(watermark, &[Survived, Survived, Survived]);
+    }
+
+    #[test]
+    fn test_survival_indexed() {
+        let header = ImageHeader {
+            color_type: ColorType::Indexed,
+            ..ImageHeader::from_str("width=128,height=128,color=gray8").unwrap()
+        };
+        let mut png = testing_png(header);
+        png.insert_before_iend(chunk("PLTE", &[0; 256 * 3]));
+        let outcomes = survival(&png, &[Transform::Strip]).unwrap();
+        assert_eq!(
+            outcomes,
+            [
+                (Method::Chunk, vec![Outcome::Lost]),
+                (Method::Text, vec![Outcome::Lost]),
+                (Method::Watermark, vec![Outcome::Unsupported]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transform_from_str() {
+        assert_eq!(
+            Transform::from_str("recompress").unwrap(),
+            Transform::Recompress
+        );
+        assert!(Transform::from_str("resize").is_err());
+    }
+}
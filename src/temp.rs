@@ -0,0 +1,235 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use crate::{cancel, Result};
+
+static WORK_DIR: OnceLock<PathBuf> = OnceLock::new();
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Overrides the directory temporary files are created in. Only the first call has an effect.
+pub fn set_work_dir(dir: PathBuf) {
+    let _ = WORK_DIR.set(dir);
+}
+
+/// The directory temporary files are created in: the `--work-dir` override if given, otherwise
+/// the system temp directory, which honours `TMPDIR`
+pub fn work_dir() -> PathBuf {
+    WORK_DIR.get().cloned().unwrap_or_else(system_temp_dir)
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn system_temp_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+/// WASI has no system temp directory, only the directories preopened by the host, so this is
+/// `TMPDIR` if set and the working directory otherwise
+#[cfg(target_os = "wasi")]
+fn system_temp_dir() -> PathBuf {
+    std::env::var_os("TMPDIR").map_or_else(|| PathBuf::from("."), PathBuf::from)
+}
+
+/// Distinguishes the temporary files of concurrent runs. WASI has no process ids, so there
+/// `create_new` alone keeps names from clashing.
+pub(crate) fn process_id() -> u32 {
+    #[cfg(not(target_os = "wasi"))]
+    return std::process::id();
+    #[cfg(target_os = "wasi")]
+    return 0;
+}
+
+/// A uniquely named file that is removed when dropped, including while unwinding from a panic,
+/// unless it has been persisted
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl TempFile {
+    /// Creates a temporary file in the work directory
+    pub fn new() -> Result<TempFile> {
+        TempFile::new_in(&work_dir())
+    }
+
+    /// Creates a temporary file in `dir`
+    pub fn new_in(dir: &Path) -> Result<TempFile> {
+        loop {
+            let name = format!(
+                ".pngchunk-{}-{}.tmp",
+                process_id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = dir.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(TempFile {
+                        path,
+                        file: Some(file),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("Cannot create {}: {}", path.display(), e).into()),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_all(&mut self, contents: &[u8]) -> Result<()> {
+        let file = self
+            .file
+            .as_mut()
+            .ok_or("Temporary file is already closed")?;
+        file.write_all(contents)?;
+        Ok(())
+    }
+
+    /// Moves the file to `target`, replacing it atomically. If `target` is on another filesystem
+    /// the file is first copied next to it so the final rename is still atomic. A replaced file
+    /// keeps its permissions.
+    pub fn persist(mut self, target: &Path) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        if let Ok(metadata) = fs::metadata(target) {
+            fs::set_permissions(&self.path, metadata.permissions())?;
+        }
+        if fs::rename(&self.path, target).is_ok() {
+            // Nothing left to clean up
+            self.path = PathBuf::new();
+            return Ok(());
+        }
+
+        let dir = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let sibling = TempFile::new_in(dir)?;
+        fs::copy(self.path(), sibling.path())?;
+        sibling.persist(target)
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other("Temporary file is already closed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Writes `contents` to `target` through a temporary file, so readers never see a partial file
+/// and a failed or cancelled write leaves the original untouched
+pub fn write_atomic(target: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp = TempFile::new()?;
+    temp.write_all(contents)?;
+    cancel::check()?;
+    temp.persist(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn testing_dir(name: &str) -> PathBuf {
+        let dir = work_dir().join(format!("pngchunk-test-{}-{}", name, process_id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_temp_file_removed_on_drop() {
+        let dir = testing_dir("drop");
+        let path = {
+            let mut temp = TempFile::new_in(&dir).unwrap();
+            temp.write_all(b"partial").unwrap();
+            temp.path().to_path_buf()
+        };
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "wasi", ignore = "panics abort on wasm32")]
+    fn test_temp_file_removed_on_panic() {
+        let dir = testing_dir("panic");
+        let inner = dir.clone();
+        let result = std::panic::catch_unwind(move || {
+            let _temp = TempFile::new_in(&inner).unwrap();
+            panic!("interrupted");
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_persist() {
+        let dir = testing_dir("persist");
+        let target = dir.join("out.png");
+        fs::write(&target, b"old").unwrap();
+
+        let mut temp = TempFile::new_in(&dir).unwrap();
+        temp.write_all(b"new").unwrap();
+        temp.persist(&target).unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        use crate::png::Png;
+
+        let dir = testing_dir("atomic");
+        let target = dir.join("out.png");
+        let png = Png::from_chunks(vec![chunk("IEND", &[])]);
+        write_atomic(&target, &png.as_bytes()).unwrap();
+
+        let read = Png::from_reader(File::open(&target).unwrap()).unwrap();
+        assert_eq!(read.as_bytes(), png.as_bytes());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = testing_dir("permissions");
+        let target = dir.join("out.png");
+        fs::write(&target, b"old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(&target, b"new").unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
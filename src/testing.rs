@@ -0,0 +1,291 @@
+//! Builders for PNG fixtures, so tests can create the files they need instead of checking in
+//! binary files. Everything here is deterministic: the same calls always give the same bytes.
+
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::raster::{self, ColorType, ImageHeader};
+use crate::Result;
+
+/// A 1x1 black grayscale PNG, the smallest valid image
+pub fn minimal_png() -> Png {
+    PngBuilder::new(1, 1)
+        .pixels(vec![0])
+        .build()
+        .expect("a 1x1 image is always valid")
+}
+
+/// A chunk of the given type and data. Panics if the type is not a valid chunk type.
+pub fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(
+        ChunkType::from_str(chunk_type).expect("a valid chunk type"),
+        data.to_vec(),
+    )
+    .expect("data short enough for a chunk")
+}
+
+/// A PNG holding exactly these chunks, in order. Nothing checks that they make a valid image,
+/// so this suits tests that only care about the chunk layout.
+pub fn png_from(chunks: &[(&str, &[u8])]) -> Png {
+    Png::from_chunks(
+        chunks
+            .iter()
+            .map(|&(chunk_type, data)| chunk(chunk_type, data))
+            .collect(),
+    )
+}
+
+/// Builds a valid PNG or APNG. Unless pixels are given, every frame is filled with a pattern
+/// that differs from frame to frame.
+#[derive(Debug, Clone)]
+pub struct PngBuilder {
+    header: ImageHeader,
+    pixels: Option<Vec<u8>>,
+    frames: u32,
+    chunks: Vec<Chunk>,
+}
+
+impl PngBuilder {
+    /// An 8-bit grayscale image of the given size
+    pub fn new(width: u32, height: u32) -> PngBuilder {
+        PngBuilder {
+            header: ImageHeader {
+                width,
+                height,
+                bit_depth: 8,
+                color_type: ColorType::Gray,
+                interlaced: false,
+            },
+            pixels: None,
+            frames: 1,
+            chunks: vec![],
+        }
+    }
+
+    pub fn color(mut self, color_type: ColorType, bit_depth: u8) -> PngBuilder {
+        self.header.color_type = color_type;
+        self.header.bit_depth = bit_depth;
+        self
+    }
+
+    /// The pixels of the image, laid out as `raster::compose` expects
+    pub fn pixels(mut self, pixels: Vec<u8>) -> PngBuilder {
+        self.pixels = Some(pixels);
+        self
+    }
+
+    /// Makes an APNG with `frames` frames, the first being the default image
+    pub fn frames(mut self, frames: u32) -> PngBuilder {
+        self.frames = frames.max(1);
+        self
+    }
+
+    /// Adds a chunk just before IEND
+    pub fn chunk(mut self, chunk_type: &str, data: &[u8]) -> Result<PngBuilder> {
+        self.chunks
+            .push(Chunk::new(ChunkType::from_str(chunk_type)?, data.to_vec())?);
+        Ok(self)
+    }
+
+    /// Adds a `tEXt` chunk
+    pub fn text(self, keyword: &str, text: &str) -> Result<PngBuilder> {
+        self.chunk("tEXt", format!("{}\0{}", keyword, text).as_bytes())
+    }
+
+    pub fn build(&self) -> Result<Png> {
+        let frame_pixels = |frame: u32| -> Result<Vec<u8>> {
+            Ok(match (&self.pixels, frame) {
+                (Some(pixels), 0) => pixels.clone(),
+                _ => (0..self.header.raw_size()?)
+                    .map(|i| (i as u32 * 7 + frame * 31) as u8)
+                    .collect(),
+            })
+        };
+        let mut png = raster::compose(&self.header, &frame_pixels(0)?)?;
+        if self.frames > 1 {
+            let mut sequence = 0;
+            let mut chunks = png.chunks().to_vec();
+            let iend = chunks.pop().ok_or("Missing IEND chunk")?;
+            let actl = [self.frames.to_be_bytes(), 0u32.to_be_bytes()].concat();
+            chunks.insert(1, Chunk::new(ChunkType::from_str("acTL")?, actl)?);
+            chunks.insert(2, fctl(&mut sequence, &self.header)?);
+            for frame in 1..self.frames {
+                chunks.push(fctl(&mut sequence, &self.header)?);
+                let frame_png = raster::compose(&self.header, &frame_pixels(frame)?)?;
+                let idat = frame_png
+                    .chunk_by_type("IDAT")
+                    .ok_or("Missing IDAT chunk")?;
+                let fdat = [&sequence.to_be_bytes()[..], idat.data()].concat();
+                sequence += 1;
+                chunks.push(Chunk::new(ChunkType::from_str("fdAT")?, fdat)?);
+            }
+            chunks.push(iend);
+            png = Png::from_chunks(chunks);
+        }
+        for chunk in self.chunks.iter() {
+            png.insert_before_iend(chunk.clone());
+        }
+        Ok(png)
+    }
+}
+
+/// An fcTL chunk for a full frame shown for 1/10 s, taking the next sequence number
+fn fctl(sequence: &mut u32, header: &ImageHeader) -> Result<Chunk> {
+    let mut data = vec![];
+    data.extend(sequence.to_be_bytes());
+    *sequence += 1;
+    data.extend(header.width.to_be_bytes());
+    data.extend(header.height.to_be_bytes());
+    data.extend([0; 8]);
+    data.extend(1u16.to_be_bytes());
+    data.extend(10u16.to_be_bytes());
+    data.extend([0, 0]);
+    Chunk::new(ChunkType::from_str("fcTL")?, data)
+}
+
+/// Ways of damaging a PNG, for testing how damaged files are handled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Corruption {
+    /// Flips the bits of the CRC of the chunk at this index
+    BadCrc(usize),
+    /// Flips a bit of the data of the chunk at this index, leaving its CRC as it was
+    BadData(usize),
+    /// Replaces the type of the chunk at this index with bytes that are not letters
+    InvalidType(usize),
+    /// Makes the chunk at this index claim more data than the file holds
+    LengthOverflow(usize),
+    /// Cuts this many bytes off the end of the file
+    Truncate(usize),
+    /// Appends these bytes after IEND
+    TrailingData(Vec<u8>),
+    /// Leaves out the IEND chunk
+    MissingIend,
+    /// Damages the signature
+    BadSignature,
+}
+
+/// The bytes of `png` damaged as described. Chunk indices past the last chunk are ignored.
+pub fn corrupt(png: &Png, corruption: Corruption) -> Vec<u8> {
+    let mut bytes = png.as_bytes();
+    let offset = |index: usize| -> Option<usize> {
+        let chunks = png.chunks();
+        (index < chunks.len()).then(|| {
+            Png::STANDARD_HEADER.len()
+                + chunks[..index]
+                    .iter()
+                    .map(|chunk| Chunk::MIN_CHUNK_LENGTH + chunk.data().len())
+                    .sum::<usize>()
+        })
+    };
+    match corruption {
+        Corruption::BadCrc(index) => {
+            if let Some(start) = offset(index) {
+                let crc = start + 8 + png.chunks()[index].data().len();
+                bytes[crc..crc + 4].iter_mut().for_each(|b| *b = !*b);
+            }
+        }
+        Corruption::BadData(index) => {
+            if let Some(start) = offset(index).filter(|_| !png.chunks()[index].data().is_empty()) {
+                bytes[start + 8] ^= 1;
+            }
+        }
+        Corruption::InvalidType(index) => {
+            if let Some(start) = offset(index) {
+                bytes[start + 4..start + 8].copy_from_slice(b"1234");
+            }
+        }
+        Corruption::LengthOverflow(index) => {
+            if let Some(start) = offset(index) {
+                let length = (bytes.len() - start) as u32;
+                bytes[start..start + 4].copy_from_slice(&length.to_be_bytes());
+            }
+        }
+        Corruption::Truncate(count) => bytes.truncate(bytes.len().saturating_sub(count)),
+        Corruption::TrailingData(data) => bytes.extend(data),
+        Corruption::MissingIend => {
+            if png
+                .chunks()
+                .last()
+                .is_some_and(|chunk| chunk.chunk_type().to_string() == "IEND")
+            {
+                bytes.truncate(bytes.len() - Chunk::MIN_CHUNK_LENGTH);
+            }
+        }
+        Corruption::BadSignature => bytes[1] = b'J',
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::ParseMode;
+    use crate::validate;
+
+    #[test]
+    fn test_minimal_png() {
+        let png = minimal_png();
+        assert!(validate::report(&png.as_bytes()).is_empty());
+        assert_eq!(raster::decode(&png).unwrap().samples, [0]);
+        assert_eq!(minimal_png().as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_builder() {
+        let png = PngBuilder::new(3, 2)
+            .color(ColorType::Rgb, 8)
+            .text("Title", "fixture")
+            .unwrap()
+            .build()
+            .unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert!(validate::report(&png.as_bytes()).is_empty());
+        assert_eq!(raster::decode(&png).unwrap().samples.len(), 18);
+    }
+
+    #[test]
+    fn test_apng() {
+        let png = PngBuilder::new(2, 2).frames(3).build().unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]
+        );
+        let sequence = |index: usize| png.chunks()[index].read_u32_be(0).unwrap();
+        assert_eq!(
+            [2, 4, 5, 6, 7].map(sequence),
+            [0, 1, 2, 3, 4],
+            "sequence numbers run across fcTL and fdAT"
+        );
+        assert!(validate::report(&png.as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let png = minimal_png();
+        let parse = |corruption| Png::try_from(&corrupt(&png, corruption)[..]);
+        assert!(parse(Corruption::BadCrc(0)).is_err());
+        assert!(parse(Corruption::BadData(1)).is_err());
+        assert!(parse(Corruption::InvalidType(1)).is_err());
+        assert!(parse(Corruption::LengthOverflow(1)).is_err());
+        assert!(parse(Corruption::Truncate(3)).is_err());
+        assert!(parse(Corruption::BadSignature).is_err());
+        assert!(parse(Corruption::MissingIend).is_ok());
+        assert_eq!(corrupt(&png, Corruption::BadCrc(9)), png.as_bytes());
+
+        let bytes = corrupt(&png, Corruption::TrailingData(vec![1, 2]));
+        let (_, warnings) = Png::parse(&bytes, ParseMode::Lenient).unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -0,0 +1,741 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, limits, png::Png, Error, Result};
+
+/// Keywords must be between 1 and 79 bytes long
+pub const MAX_KEYWORD_LENGTH: usize = 79;
+
+/// The contents of an iTXt chunk: UTF-8 text tagged with a keyword and optionally a language
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    pub keyword: String,
+    /// An RFC 3066/BCP-47 language tag, empty if the language is unspecified
+    pub language: String,
+    /// The keyword translated into `language`
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl InternationalTextChunk {
+    /// Builds the chunk with uncompressed text. Valid language tags are written in canonical
+    /// case; invalid ones are written unchanged, see `language_warning`.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        check_keyword(&self.keyword)?;
+
+        let mut data = self.keyword.as_bytes().to_vec();
+        // Null separator, then the compression flag and method
+        data.extend([0, 0, 0]);
+        let language =
+            canonicalize_language_tag(&self.language).unwrap_or_else(|| self.language.clone());
+        data.extend(language.as_bytes());
+        data.push(0);
+        data.extend(self.translated_keyword.as_bytes());
+        data.push(0);
+        data.extend(self.text.as_bytes());
+        Chunk::new(ChunkType::from_str("iTXt")?, data)
+    }
+
+    /// Describes what is wrong with the language tag, if anything. An empty tag is allowed.
+    pub fn language_warning(&self) -> Option<String> {
+        if self.language.is_empty() || is_valid_language_tag(&self.language) {
+            None
+        } else {
+            Some(format!(
+                "iTXt chunk '{}' has malformed language tag '{}'",
+                self.keyword, self.language
+            ))
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "iTXt" {
+            return Err(format!("Expected an iTXt chunk, got {}", chunk.chunk_type()).into());
+        }
+
+        let data = chunk.data();
+        let (keyword, rest) = split_null(data).ok_or("iTXt chunk is missing its keyword")?;
+        let (flag, method, rest) = match rest {
+            [flag, method, rest @ ..] => (*flag, *method, rest),
+            _ => return Err("iTXt chunk is missing its compression flag".into()),
+        };
+        let (language, rest) = split_null(rest).ok_or("iTXt chunk is missing its language")?;
+        let (translated_keyword, text) =
+            split_null(rest).ok_or("iTXt chunk is missing its translated keyword")?;
+
+        let text = match (flag, method) {
+            (0, _) => text.to_vec(),
+            (1, 0) => limits::get().inflate(text)?,
+            _ => return Err(format!("Unsupported iTXt compression {}/{}", flag, method).into()),
+        };
+
+        Ok(InternationalTextChunk {
+            keyword: latin1(keyword),
+            language: String::from_utf8(language.to_vec())?,
+            translated_keyword: String::from_utf8(translated_keyword.to_vec())?,
+            text: String::from_utf8(text)?,
+        })
+    }
+}
+
+/// The contents of a tEXt chunk: Latin-1 text tagged with a keyword
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl TextChunk {
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        check_keyword(&self.keyword)?;
+        let mut data = to_latin1(&self.keyword)?;
+        data.push(0);
+        data.extend(to_latin1(&self.text)?);
+        Chunk::new(ChunkType::from_str("tEXt")?, data)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "tEXt" {
+            return Err(format!("Expected a tEXt chunk, got {}", chunk.chunk_type()).into());
+        }
+        let (keyword, text) =
+            split_null(chunk.data()).ok_or("tEXt chunk is missing its keyword")?;
+        Ok(TextChunk {
+            keyword: latin1(keyword),
+            text: latin1(text),
+        })
+    }
+}
+
+/// The contents of a zTXt chunk: zlib-compressed Latin-1 text tagged with a keyword
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl CompressedTextChunk {
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        check_keyword(&self.keyword)?;
+        let mut data = to_latin1(&self.keyword)?;
+        // Null separator, then compression method 0 (zlib)
+        data.extend([0, 0]);
+        let mut encoder = ZlibEncoder::new(data, Compression::default());
+        encoder.write_all(&to_latin1(&self.text)?)?;
+        Chunk::new(ChunkType::from_str("zTXt")?, encoder.finish()?)
+    }
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "zTXt" {
+            return Err(format!("Expected a zTXt chunk, got {}", chunk.chunk_type()).into());
+        }
+        let (keyword, rest) =
+            split_null(chunk.data()).ok_or("zTXt chunk is missing its keyword")?;
+        match rest {
+            [0, compressed @ ..] => Ok(CompressedTextChunk {
+                keyword: latin1(keyword),
+                text: latin1(&limits::get().inflate(compressed)?),
+            }),
+            _ => Err("Unsupported zTXt compression method".into()),
+        }
+    }
+}
+
+/// Reads the keyword and text of any tEXt, zTXt or iTXt chunk, or `None` for other chunks
+pub fn keyword_text(chunk: &Chunk) -> Result<Option<(String, String)>> {
+    match chunk.chunk_type().to_string().as_str() {
+        "tEXt" => {
+            let text = TextChunk::try_from(chunk)?;
+            Ok(Some((text.keyword, text.text)))
+        }
+        "zTXt" => {
+            let text = CompressedTextChunk::try_from(chunk)?;
+            Ok(Some((text.keyword, text.text)))
+        }
+        "iTXt" => {
+            let itxt = InternationalTextChunk::try_from(chunk)?;
+            Ok(Some((itxt.keyword, itxt.text)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Removes every tEXt, zTXt and iTXt chunk with `keyword`, returning the number removed
+pub fn remove_text(png: &Png, keyword: &str) -> (Png, usize) {
+    let mut chunks = png.chunks().to_vec();
+    let before = chunks.len();
+    chunks.retain(|chunk| !matches!(keyword_text(chunk), Ok(Some((k, _))) if k == keyword));
+    let removed = before - chunks.len();
+    (Png::from_chunks(chunks), removed)
+}
+
+/// The chunk type `set_text_as` stores text in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    /// tEXt: uncompressed Latin-1 text
+    Plain,
+    /// zTXt: compressed Latin-1 text
+    Compressed,
+    /// iTXt: uncompressed UTF-8 text with an optional language
+    International,
+}
+
+impl TextKind {
+    /// Builds a chunk of this kind holding `text`. Only iTXt can carry a language or a
+    /// translated keyword.
+    pub fn to_chunk(&self, text: &InternationalTextChunk) -> Result<Chunk> {
+        if *self != TextKind::International
+            && !(text.language.is_empty() && text.translated_keyword.is_empty())
+        {
+            return Err("Only iTXt chunks can store a language".into());
+        }
+        match self {
+            TextKind::Plain => TextChunk {
+                keyword: text.keyword.clone(),
+                text: text.text.clone(),
+            }
+            .to_chunk(),
+            TextKind::Compressed => CompressedTextChunk {
+                keyword: text.keyword.clone(),
+                text: text.text.clone(),
+            }
+            .to_chunk(),
+            TextKind::International => text.to_chunk(),
+        }
+    }
+}
+
+impl FromStr for TextKind {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tEXt" => Ok(TextKind::Plain),
+            "zTXt" => Ok(TextKind::Compressed),
+            "iTXt" => Ok(TextKind::International),
+            _ => Err(format!(
+                "Unknown text chunk type '{}', expected tEXt, zTXt or iTXt",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+/// What `set_text` does when the keyword is already present
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Overwrite the existing text, removing any further duplicates
+    Replace,
+    /// Add the new text to the end of the existing text on a new line
+    Append,
+    /// Fail and leave the file untouched
+    Error,
+    /// Add another chunk with the same keyword
+    KeepBoth,
+}
+
+impl FromStr for OnConflict {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "replace" => Ok(OnConflict::Replace),
+            "append" => Ok(OnConflict::Append),
+            "error" => Ok(OnConflict::Error),
+            "keep-both" => Ok(OnConflict::KeepBoth),
+            _ => Err(format!(
+                "Unknown conflict policy '{}', expected replace, append, error or keep-both",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+/// Sets `text` on `png`. An existing tEXt, zTXt or iTXt chunk with the same keyword is a
+/// conflict, resolved according to `on_conflict`; for iTXt the language must match as well, and
+/// tEXt and zTXt count as having no language. New chunks are added just before IEND.
+pub fn set_text(png: &Png, text: InternationalTextChunk, on_conflict: OnConflict) -> Result<Png> {
+    set_text_as(png, text, TextKind::International, on_conflict)
+}
+
+/// `set_text` storing new text in a chunk of `kind`. Appending to existing text keeps the type
+/// of the existing chunk.
+pub fn set_text_as(
+    png: &Png,
+    text: InternationalTextChunk,
+    kind: TextKind,
+    on_conflict: OnConflict,
+) -> Result<Png> {
+    let same_key = |chunk: &Chunk| match chunk.chunk_type().to_string().as_str() {
+        "iTXt" => InternationalTextChunk::try_from(chunk).is_ok_and(|existing| {
+            existing.keyword == text.keyword
+                && existing.language.eq_ignore_ascii_case(&text.language)
+        }),
+        _ => {
+            text.language.is_empty()
+                && matches!(keyword_text(chunk), Ok(Some((keyword, _))) if keyword == text.keyword)
+        }
+    };
+
+    let mut chunks = png.chunks().to_vec();
+    let Some(first) = chunks.iter().position(same_key) else {
+        let mut png = png.clone();
+        png.insert_before_iend(kind.to_chunk(&text)?);
+        return Ok(png);
+    };
+
+    match on_conflict {
+        OnConflict::Error => {
+            return Err(format!("Keyword '{}' is already set", text.keyword).into());
+        }
+        OnConflict::KeepBoth => {
+            let mut png = png.clone();
+            png.insert_before_iend(kind.to_chunk(&text)?);
+            return Ok(png);
+        }
+        OnConflict::Replace => {
+            chunks[first] = kind.to_chunk(&text)?;
+            let mut index = 0;
+            chunks.retain(|chunk| {
+                let keep = index <= first || !same_key(chunk);
+                index += 1;
+                keep
+            });
+        }
+        OnConflict::Append => {
+            chunks[first] = append_text(&chunks[first], &text.text)?;
+        }
+    }
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Rebuilds a tEXt, zTXt or iTXt chunk with `text` added to its text on a new line, keeping
+/// its type
+fn append_text(chunk: &Chunk, text: &str) -> Result<Chunk> {
+    match chunk.chunk_type().to_string().as_str() {
+        "tEXt" => {
+            let mut existing = TextChunk::try_from(chunk)?;
+            existing.text = format!("{}\n{}", existing.text, text);
+            existing.to_chunk()
+        }
+        "zTXt" => {
+            let mut existing = CompressedTextChunk::try_from(chunk)?;
+            existing.text = format!("{}\n{}", existing.text, text);
+            existing.to_chunk()
+        }
+        _ => {
+            let mut existing = InternationalTextChunk::try_from(chunk)?;
+            existing.text = format!("{}\n{}", existing.text, text);
+            existing.to_chunk()
+        }
+    }
+}
+
+/// Checks the syntax of a BCP-47 language tag: a 2-3 or 5-8 letter primary language followed by
+/// alphanumeric subtags of up to 8 characters, or a private-use tag starting with `x-`
+pub fn is_valid_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let primary_valid = primary.eq_ignore_ascii_case("x")
+        || primary.eq_ignore_ascii_case("i")
+        || (matches!(primary.len(), 2..=3 | 5..=8)
+            && primary.bytes().all(|b| b.is_ascii_alphabetic()));
+
+    let mut count = 0;
+    let rest_valid = subtags.all(|s| {
+        count += 1;
+        (1..=8).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_alphanumeric())
+    });
+
+    primary_valid && rest_valid && (primary.len() > 1 || count > 0)
+}
+
+/// Returns `tag` in the case recommended by BCP-47, e.g. `en-US`, `zh-Hant-TW` or `x-klingon`,
+/// or `None` if it is not a valid tag. Subtags after a private-use `x` are left lowercase.
+pub fn canonicalize_language_tag(tag: &str) -> Option<String> {
+    if !is_valid_language_tag(tag) {
+        return None;
+    }
+
+    let mut private = false;
+    let subtags: Vec<String> = tag
+        .split('-')
+        .enumerate()
+        .map(|(index, subtag)| {
+            let lower = subtag.to_ascii_lowercase();
+            if index == 0 || private {
+                private |= lower == "x";
+                return lower;
+            }
+            private = lower == "x";
+            match subtag.len() {
+                2 if subtag.bytes().all(|b| b.is_ascii_alphabetic()) => subtag.to_ascii_uppercase(),
+                4 if subtag.bytes().all(|b| b.is_ascii_alphabetic()) => {
+                    lower[..1].to_ascii_uppercase() + &lower[1..]
+                }
+                _ => lower,
+            }
+        })
+        .collect();
+    Some(subtags.join("-"))
+}
+
+pub(crate) fn check_keyword(keyword: &str) -> Result<()> {
+    if keyword.is_empty() || keyword.len() > MAX_KEYWORD_LENGTH || keyword.contains('\0') {
+        return Err(format!(
+            "Keyword '{}' must be 1 to {} bytes without null characters",
+            keyword, MAX_KEYWORD_LENGTH
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub(crate) fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+/// Keywords are Latin-1, which maps directly onto the first 256 code points
+pub(crate) fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+pub(crate) fn to_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| format!("'{}' cannot be stored as Latin-1", c).into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chunk;
+
+    fn testing_text() -> InternationalTextChunk {
+        InternationalTextChunk {
+            keyword: "Title".to_string(),
+            language: "fr-CA".to_string(),
+            translated_keyword: "Titre".to_string(),
+            text: "Bonjour à tous".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_itxt_round_trip() {
+        let chunk = testing_text().to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"Title\0\0\0fr-CA\0Titre\0"));
+        assert_eq!(
+            InternationalTextChunk::try_from(&chunk).unwrap(),
+            testing_text()
+        );
+    }
+
+    #[test]
+    fn test_itxt_compressed() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all("Bonjour".as_bytes()).unwrap();
+        let mut data = b"Title\0\x01\x00fr\0\0".to_vec();
+        data.extend(encoder.finish().unwrap());
+        let chunk = chunk("iTXt", &data);
+
+        let text = InternationalTextChunk::try_from(&chunk).unwrap();
+        assert_eq!(text.language, "fr");
+        assert_eq!(text.text, "Bonjour");
+    }
+
+    #[test]
+    fn test_keyword_text() {
+        let text = chunk("tEXt", b"Author\0M\xfcller");
+        assert_eq!(
+            keyword_text(&text).unwrap(),
+            Some(("Author".to_string(), "Müller".to_string()))
+        );
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(encoder.finish().unwrap());
+        let ztxt = chunk("zTXt", &data);
+        assert_eq!(keyword_text(&ztxt).unwrap().unwrap().1, "hello");
+
+        let idat = chunk("IDAT", &[]);
+        assert_eq!(keyword_text(&idat).unwrap(), None);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let text = TextChunk {
+            keyword: "Author".to_string(),
+            text: "Müller".to_string(),
+        };
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Author\0M\xfcller");
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+
+        let ztxt = CompressedTextChunk {
+            keyword: "Comment".to_string(),
+            text: "hello ".repeat(20),
+        };
+        let chunk = ztxt.to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"Comment\0\0"));
+        assert_eq!(CompressedTextChunk::try_from(&chunk).unwrap(), ztxt);
+        assert!(TextChunk::try_from(&chunk).is_err());
+
+        let emoji = TextChunk {
+            keyword: "Title".to_string(),
+            text: "🦀".to_string(),
+        };
+        assert!(emoji.to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_remove_text() {
+        let mut png = testing_png();
+        let author = TextChunk {
+            keyword: "Author".to_string(),
+            text: "me".to_string(),
+        };
+        png.append_chunk(author.to_chunk().unwrap());
+
+        let (removed, count) = remove_text(&png, "Title");
+        assert_eq!(count, 3);
+        assert_eq!(removed.chunks().len(), 3);
+        assert_eq!(remove_text(&png, "Missing").1, 0);
+    }
+
+    #[test]
+    fn test_itxt_invalid() {
+        let chunk = chunk("iTXt", b"Title");
+        assert!(InternationalTextChunk::try_from(&chunk).is_err());
+
+        let mut text = testing_text();
+        text.keyword = String::new();
+        assert!(text.to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_itxt_canonical_language() {
+        let mut text = testing_text();
+        text.language = "FR-ca".to_string();
+        assert!(text.language_warning().is_none());
+        let parsed = InternationalTextChunk::try_from(&text.to_chunk().unwrap()).unwrap();
+        assert_eq!(parsed.language, "fr-CA");
+
+        text.language = "fr_CA".to_string();
+        assert!(text.language_warning().is_some());
+    }
+
+    fn testing_png() -> Png {
+        let mut other = testing_text();
+        other.language = "de".to_string();
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            testing_text().to_chunk().unwrap(),
+            other.to_chunk().unwrap(),
+            testing_text().to_chunk().unwrap(),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    fn texts(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .filter_map(|c| InternationalTextChunk::try_from(c).ok())
+            .map(|t| format!("{}:{}", t.language, t.text))
+            .collect()
+    }
+
+    fn new_text() -> InternationalTextChunk {
+        InternationalTextChunk {
+            text: "Salut".to_string(),
+            ..testing_text()
+        }
+    }
+
+    #[test]
+    fn test_set_text_without_conflict() {
+        let text = InternationalTextChunk {
+            keyword: "Author".to_string(),
+            ..testing_text()
+        };
+        let png = set_text(&testing_png(), text, OnConflict::Error).unwrap();
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(png.chunks()[4].chunk_type().to_string(), "iTXt");
+    }
+
+    #[test]
+    fn test_set_text_conflicts() {
+        let png = testing_png();
+        assert!(set_text(&png, new_text(), OnConflict::Error).is_err());
+
+        let replaced = set_text(&png, new_text(), OnConflict::Replace).unwrap();
+        assert_eq!(texts(&replaced), vec!["fr-CA:Salut", "de:Bonjour à tous"]);
+
+        let appended = set_text(&png, new_text(), OnConflict::Append).unwrap();
+        assert_eq!(
+            texts(&appended)[0],
+            "fr-CA:Bonjour à tous\nSalut".to_string()
+        );
+        assert_eq!(texts(&appended).len(), 3);
+
+        let both = set_text(&png, new_text(), OnConflict::KeepBoth).unwrap();
+        assert_eq!(texts(&both).len(), 4);
+    }
+
+    #[test]
+    fn test_set_text_conflicts_with_latin1_text() {
+        let comment = || InternationalTextChunk {
+            keyword: "Comment".to_string(),
+            language: String::new(),
+            translated_keyword: String::new(),
+            text: "new".to_string(),
+        };
+        for old in [
+            TextChunk {
+                keyword: "Comment".to_string(),
+                text: "old".to_string(),
+            }
+            .to_chunk()
+            .unwrap(),
+            CompressedTextChunk {
+                keyword: "Comment".to_string(),
+                text: "old".to_string(),
+            }
+            .to_chunk()
+            .unwrap(),
+        ] {
+            let old_type = old.chunk_type().to_string();
+            let png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), old, chunk("IEND", &[])]);
+            let all_texts = |png: &Png| -> Vec<(String, String)> {
+                png.chunks()
+                    .iter()
+                    .filter_map(|c| keyword_text(c).unwrap())
+                    .collect()
+            };
+
+            assert!(set_text(&png, comment(), OnConflict::Error).is_err());
+
+            let replaced = set_text(&png, comment(), OnConflict::Replace).unwrap();
+            assert_eq!(
+                all_texts(&replaced),
+                [("Comment".to_string(), "new".to_string())]
+            );
+            assert_eq!(replaced.chunks()[1].chunk_type().to_string(), "iTXt");
+
+            let appended = set_text(&png, comment(), OnConflict::Append).unwrap();
+            assert_eq!(
+                all_texts(&appended),
+                [("Comment".to_string(), "old\nnew".to_string())]
+            );
+            assert_eq!(appended.chunks()[1].chunk_type().to_string(), old_type);
+
+            let both = set_text(&png, comment(), OnConflict::KeepBoth).unwrap();
+            assert_eq!(all_texts(&both).len(), 2);
+
+            // tEXt and zTXt have no language, so text in a given language is not a conflict
+            let german = InternationalTextChunk {
+                language: "de".to_string(),
+                ..comment()
+            };
+            assert!(set_text(&png, german, OnConflict::Error).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_set_text_as_round_trip() {
+        let bare = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        let comment = InternationalTextChunk {
+            keyword: "Comment".to_string(),
+            language: String::new(),
+            translated_keyword: String::new(),
+            text: "café au lait".to_string(),
+        };
+        for (name, kind) in [
+            ("tEXt", TextKind::Plain),
+            ("zTXt", TextKind::Compressed),
+            ("iTXt", TextKind::International),
+        ] {
+            assert_eq!(TextKind::from_str(name).unwrap(), kind);
+            let png = set_text_as(&bare, comment.clone(), kind, OnConflict::Error).unwrap();
+            let written = &png.chunks()[1];
+            assert_eq!(written.chunk_type().to_string(), name);
+            assert_eq!(
+                keyword_text(written).unwrap().unwrap(),
+                ("Comment".to_string(), "café au lait".to_string())
+            );
+        }
+
+        let german = InternationalTextChunk {
+            language: "de".to_string(),
+            ..comment.clone()
+        };
+        assert!(set_text_as(&bare, german, TextKind::Plain, OnConflict::Error).is_err());
+        let greek = InternationalTextChunk {
+            text: "καλημέρα".to_string(),
+            ..comment
+        };
+        assert!(set_text_as(&bare, greek, TextKind::Compressed, OnConflict::Error).is_err());
+        assert!(TextKind::from_str("text").is_err());
+    }
+
+    #[test]
+    fn test_on_conflict_from_str() {
+        assert_eq!(
+            OnConflict::from_str("keep-both").unwrap(),
+            OnConflict::KeepBoth
+        );
+        assert!(OnConflict::from_str("merge").is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_language_tag() {
+        for (tag, canonical) in [
+            ("EN-us", "en-US"),
+            ("zh-hant-tw", "zh-Hant-TW"),
+            ("de-ch-1901", "de-CH-1901"),
+            ("X-Private-AB", "x-private-ab"),
+            ("en-x-US", "en-x-us"),
+        ] {
+            assert_eq!(canonicalize_language_tag(tag).unwrap(), canonical);
+        }
+        assert!(canonicalize_language_tag("en_US").is_none());
+    }
+
+    #[test]
+    fn test_language_tags() {
+        for tag in [
+            "en",
+            "en-US",
+            "zh-Hant-TW",
+            "de-CH-1901",
+            "x-klingon",
+            "haw",
+        ] {
+            assert!(is_valid_language_tag(tag), "{}", tag);
+        }
+        for tag in [
+            "",
+            "e",
+            "en_US",
+            "en--US",
+            "en-",
+            "x",
+            "1en",
+            "en-toolongsubtag",
+        ] {
+            assert!(!is_valid_language_tag(tag), "{}", tag);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#[cfg(not(target_os = "wasi"))]
+use std::fs::File;
+#[cfg(not(target_os = "wasi"))]
+use std::path::Path;
+
+#[cfg(not(target_os = "wasi"))]
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::Result;
+
+/// A layer receiving the spans around parsing, serializing, compression and each file of a batch
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// A layer recording spans to `path` in the Chrome trace event format, viewable in
+/// `about:tracing` or Perfetto. The trace is complete once the returned guard is dropped.
+#[cfg(not(target_os = "wasi"))]
+pub fn chrome(path: &Path) -> Result<(BoxedLayer, FlushGuard)> {
+    let file =
+        File::create(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .writer(file)
+        .include_args(true)
+        .build();
+    Ok((Box::new(layer), guard))
+}
+
+/// Sends spans to `layers` for the rest of the process. Without any, spans cost next to nothing.
+pub fn install(layers: Vec<BoxedLayer>) -> Result<()> {
+    if layers.is_empty() {
+        return Ok(());
+    }
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layers))
+        .map_err(|e| format!("Cannot start tracing: {}", e).into())
+}
@@ -0,0 +1,148 @@
+use core::fmt;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{chunk_type::ChunkType, Result};
+
+/// Selects chunks by type for quick one-off filters on the command line. Regular expressions
+/// have to match the whole type and, like prefixes, are compiled once up front.
+#[derive(Debug, Clone)]
+pub enum TypeFilter {
+    Exact {
+        chunk_type: ChunkType,
+        ignore_case: bool,
+    },
+    Prefix {
+        prefix: String,
+        ignore_case: bool,
+    },
+    Regex {
+        pattern: String,
+        regex: Regex,
+    },
+}
+
+impl TypeFilter {
+    /// The filter given by the exact type, `--type` pattern or `--type-prefix` arguments of a
+    /// command, or `None` if none of them were given. At most one should be.
+    pub fn from_args(
+        chunk_type: Option<&ChunkType>,
+        pattern: Option<&str>,
+        prefix: Option<&str>,
+        ignore_case: bool,
+    ) -> Result<Option<TypeFilter>> {
+        Ok(match (chunk_type, pattern, prefix) {
+            (Some(chunk_type), _, _) => Some(TypeFilter::Exact {
+                chunk_type: chunk_type.clone(),
+                ignore_case,
+            }),
+            (_, Some(pattern), _) => Some(TypeFilter::regex(pattern, ignore_case)?),
+            (_, _, Some(prefix)) => Some(TypeFilter::prefix(prefix, ignore_case)?),
+            _ => None,
+        })
+    }
+
+    pub fn regex(pattern: &str, ignore_case: bool) -> Result<TypeFilter> {
+        let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| format!("Invalid chunk type pattern '{}': {}", pattern, e))?;
+        Ok(TypeFilter::Regex {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn prefix(prefix: &str, ignore_case: bool) -> Result<TypeFilter> {
+        if prefix.is_empty() || prefix.len() > 4 || !prefix.bytes().all(|b| b.is_ascii_alphabetic())
+        {
+            return Err(format!("Chunk type prefix '{}' must be 1 to 4 letters", prefix).into());
+        }
+        Ok(TypeFilter::Prefix {
+            prefix: prefix.to_string(),
+            ignore_case,
+        })
+    }
+
+    /// Whether this selects a single chunk type rather than a pattern of them
+    pub fn is_exact(&self) -> bool {
+        matches!(self, TypeFilter::Exact { .. })
+    }
+
+    pub fn matches(&self, chunk_type: &ChunkType) -> bool {
+        match self {
+            TypeFilter::Exact {
+                chunk_type: expected,
+                ignore_case,
+            } => chunk_type.matches(expected, *ignore_case),
+            TypeFilter::Prefix {
+                prefix,
+                ignore_case,
+            } => {
+                let start = &chunk_type.bytes()[..prefix.len()];
+                match ignore_case {
+                    true => start.eq_ignore_ascii_case(prefix.as_bytes()),
+                    false => start == prefix.as_bytes(),
+                }
+            }
+            TypeFilter::Regex { regex, .. } => regex.is_match(&chunk_type.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for TypeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeFilter::Exact { chunk_type, .. } => write!(f, "{}", chunk_type),
+            TypeFilter::Prefix { prefix, .. } => write!(f, "{}*", prefix),
+            TypeFilter::Regex { pattern, .. } => write!(f, "/{}/", pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn matches(filter: &TypeFilter, chunk_type: &str) -> bool {
+        filter.matches(&ChunkType::from_str(chunk_type).unwrap())
+    }
+
+    #[test]
+    fn test_regex() {
+        let filter = TypeFilter::regex("r[a-z][A-Z]t", false).unwrap();
+        assert!(matches(&filter, "ruSt"));
+        assert!(!matches(&filter, "RuSt"));
+        assert_eq!(filter.to_string(), "/r[a-z][A-Z]t/");
+
+        let partial = TypeFilter::regex("uS", false).unwrap();
+        assert!(!matches(&partial, "ruSt"), "patterns match the whole type");
+        assert!(matches(&TypeFilter::regex("RUST", true).unwrap(), "ruSt"));
+        assert!(TypeFilter::regex("(", false).is_err());
+    }
+
+    #[test]
+    fn test_prefix() {
+        let filter = TypeFilter::prefix("tE", false).unwrap();
+        assert!(matches(&filter, "tEXt"));
+        assert!(!matches(&filter, "tIME"));
+        assert!(!matches(&filter, "TEXt"));
+        assert!(matches(&TypeFilter::prefix("te", true).unwrap(), "tEXt"));
+        assert!(TypeFilter::prefix("", false).is_err());
+        assert!(TypeFilter::prefix("tEXtt", false).is_err());
+    }
+
+    #[test]
+    fn test_from_args() {
+        let exact = ChunkType::from_str("ruSt").unwrap();
+        let filter = TypeFilter::from_args(Some(&exact), None, None, true)
+            .unwrap()
+            .unwrap();
+        assert!(filter.is_exact());
+        assert!(matches(&filter, "RUST"));
+        assert!(TypeFilter::from_args(None, None, None, false)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,794 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+use serde_json::Value;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    edit::SINGLE_CHUNKS,
+    known_chunks::Ihdr,
+    limits::{self, Limits, BOMB_RATIO, RATIO_FLOOR},
+    png::Png,
+    raster::{ColorType, ImageHeader},
+    salvage::{self, Segment},
+    text::InternationalTextChunk,
+    Result,
+};
+
+/// A problem found in a PNG file, located by the byte offset of the chunk it concerns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x}: {}", self.offset, self.message)
+    }
+}
+
+/// Runs every rule against `png`, returning the findings in file order
+pub fn validate(png: &Png) -> Vec<Finding> {
+    validate_with(png, &limits::get())
+}
+
+/// `validate` with the given decompression limits instead of the global ones
+pub fn validate_with(png: &Png, limits: &Limits) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut expected_idat = None;
+    let mut idat: Option<(usize, Vec<u8>)> = None;
+
+    let mut offset = Png::STANDARD_HEADER.len();
+    for chunk in png.chunks() {
+        // A header that parses can still describe an image too large to lay out
+        if let Some(ihdr) = (chunk.chunk_type().to_string() == "IHDR")
+            .then(|| Ihdr::parse(chunk.data()).ok())
+            .flatten()
+        {
+            match ImageHeader::from(ihdr).filtered_size() {
+                Ok(size) => expected_idat = Some(size),
+                Err(e) => findings.push(Finding {
+                    offset,
+                    message: format!("IHDR chunk: {}", e),
+                }),
+            }
+        }
+        if chunk.chunk_type().to_string() == "IDAT" {
+            idat.get_or_insert_with(|| (offset, vec![]))
+                .1
+                .extend(chunk.data());
+        }
+        if let Some(compressed) = compressed_payload(chunk) {
+            if let Some(message) = check_inflate(compressed, limits) {
+                findings.push(Finding {
+                    offset,
+                    message: format!("{} chunk: {}", chunk.chunk_type(), message),
+                });
+            }
+        }
+        if chunk.chunk_type().to_string() == "iTXt" {
+            match InternationalTextChunk::try_from(chunk) {
+                Ok(text) => {
+                    if let Some(message) = text.language_warning() {
+                        findings.push(Finding { offset, message });
+                    }
+                }
+                Err(e) => findings.push(Finding {
+                    offset,
+                    message: format!("malformed iTXt chunk: {}", e),
+                }),
+            }
+        }
+        offset += chunk.as_bytes().len();
+    }
+
+    if let (Some((offset, compressed)), Some(expected)) = (idat, expected_idat) {
+        let mut inflated = 0;
+        let mut buf = [0u8; 1 << 16];
+        let mut decoder = ZlibDecoder::new(&compressed[..]).take(expected as u64 + 1);
+        while let Ok(n @ 1..) = decoder.read(&mut buf) {
+            inflated += n;
+        }
+        if inflated > expected {
+            findings.push(Finding {
+                offset,
+                message: format!(
+                    "Possible decompression bomb: IDAT inflates past the {} bytes the image needs",
+                    expected
+                ),
+            });
+        }
+    }
+    findings.sort_by_key(|f| f.offset);
+
+    findings
+}
+
+/// Checks everything that can be wrong with a PNG file: its signature, chunk lengths and CRCs,
+/// the ordering and multiplicity rules of the spec, data after IEND, and, once the structure
+/// is sound, the content rules of `validate`. Unlike `validate` this works on files that fail
+/// to parse, so every problem is reported with its byte offset rather than just the first.
+pub fn report(bytes: &[u8]) -> Vec<Finding> {
+    if let Err(e) = Png::check_signature(bytes) {
+        return vec![Finding {
+            offset: 0,
+            message: e.to_string(),
+        }];
+    }
+    let mut findings = diagnose_layout(bytes);
+
+    let mut chunks = vec![];
+    let mut offset = Png::STANDARD_HEADER.len();
+    for segment in salvage::resync(bytes).unwrap_or_default() {
+        match segment {
+            Segment::Chunk(chunk) => {
+                let size = Chunk::MIN_CHUNK_LENGTH + chunk.data().len();
+                chunks.push((offset, chunk));
+                offset += size;
+            }
+            Segment::Gap(length) => offset += length,
+        }
+    }
+    if let Some((iend, _)) = chunks
+        .iter()
+        .find(|(_, c)| c.chunk_type().to_string() == "IEND")
+    {
+        let end = iend + Chunk::MIN_CHUNK_LENGTH;
+        if end < bytes.len() {
+            findings.push(Finding {
+                offset: end,
+                message: format!("{} byte(s) of trailing data after IEND", bytes.len() - end),
+            });
+        }
+    }
+    findings.extend(order_findings(&chunks, bytes.len()));
+
+    if findings.is_empty() {
+        let png = Png::from_chunks(chunks.into_iter().map(|(_, chunk)| chunk).collect());
+        findings.extend(validate(&png));
+    }
+    findings.sort_by_key(|f| f.offset);
+    findings
+}
+
+/// Checks chunk ordering and multiplicity like `edit::check_order`, but reports every
+/// violation at the offset of the chunk concerned. `chunks` holds each chunk with its offset.
+fn order_findings(chunks: &[(usize, Chunk)], file_length: usize) -> Vec<Finding> {
+    let mut findings = vec![];
+    let types: Vec<String> = chunks
+        .iter()
+        .map(|(_, c)| c.chunk_type().to_string())
+        .collect();
+    let position = |chunk_type: &str| types.iter().position(|t| t == chunk_type);
+    let mut push = |offset: usize, message: String| findings.push(Finding { offset, message });
+
+    match types.first() {
+        None => {
+            push(Png::STANDARD_HEADER.len(), "File has no chunks".to_string());
+            return findings;
+        }
+        Some(first) if first != "IHDR" => push(
+            chunks[0].0,
+            format!("IHDR must be the first chunk, found {}", first),
+        ),
+        _ => {}
+    }
+    if position("IEND").is_none() {
+        push(file_length, "IEND chunk is missing".to_string());
+    }
+
+    let idats: Vec<usize> = (0..types.len()).filter(|&i| types[i] == "IDAT").collect();
+    if idats.is_empty() {
+        push(
+            chunks[0].0,
+            "At least one IDAT chunk is required".to_string(),
+        );
+    }
+    if let Some(pair) = idats.windows(2).find(|pair| pair[1] != pair[0] + 1) {
+        push(
+            chunks[pair[1]].0,
+            "IDAT chunks must be consecutive".to_string(),
+        );
+    }
+
+    for (chunk_type, before) in SINGLE_CHUNKS.iter() {
+        let Some(first) = position(chunk_type) else {
+            continue;
+        };
+        for (index, _) in types
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t == chunk_type)
+            .skip(1)
+        {
+            push(
+                chunks[index].0,
+                format!(
+                    "Only one {} chunk is allowed, the first is at {:#010x}",
+                    chunk_type, chunks[first].0
+                ),
+            );
+        }
+        for other in before.iter() {
+            if position(other).is_some_and(|other_index| other_index < first) {
+                push(
+                    chunks[first].0,
+                    format!("{} must come before {}", chunk_type, other),
+                );
+            }
+        }
+    }
+
+    let plte = position("PLTE");
+    if let Some(plte) = plte {
+        for after in ["bKGD", "hIST", "tRNS"] {
+            if let Some(index) = position(after).filter(|&index| index < plte) {
+                push(chunks[index].0, format!("{} must come after PLTE", after));
+            }
+        }
+    }
+    let ihdr = position("IHDR").and_then(|i| Some((i, Ihdr::parse(chunks[i].1.data()).ok()?)));
+    match (ihdr, plte) {
+        (Some((i, ihdr)), None) if ihdr.color_type == ColorType::Indexed => push(
+            chunks[i].0,
+            "Indexed-color images need a PLTE chunk".to_string(),
+        ),
+        (Some((_, ihdr)), Some(plte))
+            if matches!(ihdr.color_type, ColorType::Gray | ColorType::GrayAlpha) =>
+        {
+            push(
+                chunks[plte].0,
+                "PLTE is not allowed in grayscale images".to_string(),
+            )
+        }
+        _ => {}
+    }
+    findings
+}
+
+/// Checks the chunk layout of a file that failed to parse. Where a chunk's CRC is not found
+/// after its declared length, this resynchronizes by scanning forward for the length whose CRC
+/// does match, or failing that for the next chunk header with a valid CRC, and reports the
+/// likely true length. Walking continues from the resynchronized position.
+pub fn diagnose_layout(bytes: &[u8]) -> Vec<Finding> {
+    let mut findings = vec![];
+    if !Png::has_valid_signature(bytes) {
+        return findings;
+    }
+    let mut offset = Png::STANDARD_HEADER.len();
+    while bytes.len().saturating_sub(offset) >= Chunk::MIN_CHUNK_LENGTH {
+        let declared = read_u32(bytes, offset) as usize;
+        let chunk_type = &bytes[offset + 4..offset + 8];
+        if !is_chunk_type(chunk_type) {
+            findings.push(Finding {
+                offset,
+                message: "No valid chunk header here; the previous chunk's length is likely wrong"
+                    .to_string(),
+            });
+            break;
+        }
+        let name = String::from_utf8_lossy(chunk_type);
+        if crc_matches(bytes, offset, declared) {
+            offset += Chunk::MIN_CHUNK_LENGTH + declared;
+            if chunk_type == b"IEND" {
+                break;
+            }
+            continue;
+        }
+
+        let (actual, message) = if let Some(actual) = matching_length(bytes, offset) {
+            let message = format!(
+                "{} chunk declares {} bytes of data but its CRC matches after {}; the length \
+                 field is likely corrupt",
+                name, declared, actual
+            );
+            (actual, message)
+        } else if let Some(next) = salvage::next_chunk(bytes, offset + Chunk::MIN_CHUNK_LENGTH) {
+            let actual = next - offset - Chunk::MIN_CHUNK_LENGTH;
+            let message = format!(
+                "{} chunk declares {} bytes of data but the next valid chunk starts at {:#010x}, \
+                 so it likely holds {} bytes and a corrupt CRC",
+                name, declared, next, actual
+            );
+            (actual, message)
+        } else {
+            findings.push(Finding {
+                offset,
+                message: format!(
+                    "{} chunk declares {} bytes of data and no valid chunk follows it",
+                    name, declared
+                ),
+            });
+            break;
+        };
+        if actual == declared {
+            findings.push(Finding {
+                offset,
+                message: format!("{} chunk has a corrupt CRC", name),
+            });
+        } else {
+            findings.push(Finding { offset, message });
+        }
+        offset += Chunk::MIN_CHUNK_LENGTH + actual;
+    }
+    findings
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn is_chunk_type(bytes: &[u8]) -> bool {
+    bytes.iter().all(u8::is_ascii_alphabetic)
+}
+
+/// Whether the chunk at `offset` has a valid CRC when its data is `length` bytes long
+fn crc_matches(bytes: &[u8], offset: usize, length: usize) -> bool {
+    let Some(end) = (offset + 8)
+        .checked_add(length)
+        .filter(|end| end + 4 <= bytes.len())
+    else {
+        return false;
+    };
+    Chunk::calculate_crc(&bytes[offset + 4..offset + 8], &bytes[offset + 8..end])
+        == read_u32(bytes, end)
+}
+
+/// The shortest data length for the chunk at `offset` after which its CRC matches and a chunk
+/// header or the end of the file follows
+fn matching_length(bytes: &[u8], offset: usize) -> Option<usize> {
+    let mut crc = flate2::Crc::new();
+    crc.update(&bytes[offset + 4..offset + 8]);
+    let mut end = offset + 8;
+    while end + 4 <= bytes.len() {
+        if crc.sum() == read_u32(bytes, end) {
+            let next = end + 4;
+            if next == bytes.len()
+                || (next + 8 <= bytes.len() && is_chunk_type(&bytes[next + 4..next + 8]))
+            {
+                return Some(end - offset - 8);
+            }
+        }
+        crc.update(&bytes[end..end + 1]);
+        end += 1;
+    }
+    None
+}
+
+/// Thresholds for the advisory rules, which flag things that are legal but suspicious
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// Largest tEXt chunk, in bytes
+    pub max_text_size: Option<usize>,
+    /// Most text chunks (tEXt, zTXt and iTXt) a file may contain
+    pub max_text_chunks: Option<usize>,
+    /// Flag private chunks that are larger than all the IDAT data together
+    pub private_larger_than_image: bool,
+    /// Flag ancillary chunks with no data
+    pub zero_length_ancillary: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            max_text_size: Some(1 << 20),
+            max_text_chunks: Some(64),
+            private_larger_than_image: true,
+            zero_length_ancillary: true,
+        }
+    }
+}
+
+impl Policy {
+    /// Reads a policy file, a JSON object whose keys override the defaults. A `null`
+    /// threshold or a `false` flag turns that rule off.
+    pub fn parse(bytes: &[u8]) -> Result<Policy> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        let object = value
+            .as_object()
+            .ok_or("Policy file must be a JSON object")?;
+        let mut policy = Policy::default();
+        for (key, value) in object {
+            let threshold = || -> Result<Option<usize>> {
+                match value {
+                    Value::Null => Ok(None),
+                    _ => Ok(Some(
+                        value.as_u64().ok_or(format!("{} must be a number", key))? as usize,
+                    )),
+                }
+            };
+            let flag = || {
+                value
+                    .as_bool()
+                    .ok_or(format!("{} must be true or false", key))
+            };
+            match key.as_str() {
+                "max_text_size" => policy.max_text_size = threshold()?,
+                "max_text_chunks" => policy.max_text_chunks = threshold()?,
+                "private_larger_than_image" => policy.private_larger_than_image = flag()?,
+                "zero_length_ancillary" => policy.zero_length_ancillary = flag()?,
+                _ => return Err(format!("Unknown policy setting '{}'", key).into()),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Checks a chunk against the rules that need only its type and size. `text_chunks` counts
+    /// the text chunks so far, including this one if it is one.
+    pub(crate) fn check_chunk(
+        &self,
+        chunk_type: &ChunkType,
+        size: usize,
+        text_chunks: usize,
+    ) -> Vec<String> {
+        let name = chunk_type.to_string();
+        let mut messages = vec![];
+        if let Some(max) = self.max_text_chunks {
+            if is_text(chunk_type) && text_chunks == max + 1 {
+                messages.push(format!("more than {} text chunks", max));
+            }
+        }
+        if name == "tEXt" && self.max_text_size.is_some_and(|max| size > max) {
+            messages.push(format!("tEXt chunk is unusually large ({} bytes)", size));
+        }
+        if self.zero_length_ancillary && !chunk_type.is_critical() && size == 0 {
+            messages.push(format!("{} chunk is empty", name));
+        }
+        messages
+    }
+
+    /// Checks a chunk against the size of the image data, once that is known
+    pub(crate) fn check_private(
+        &self,
+        chunk_type: &ChunkType,
+        size: usize,
+        image_size: usize,
+    ) -> Option<String> {
+        (self.private_larger_than_image && !chunk_type.is_public() && size > image_size).then(
+            || {
+                format!(
+                    "private {} chunk ({} bytes) is larger than the image data ({} bytes)",
+                    chunk_type, size, image_size
+                )
+            },
+        )
+    }
+}
+
+/// Whether `chunk_type` is one of the three text chunk types
+pub(crate) fn is_text(chunk_type: &ChunkType) -> bool {
+    matches!(chunk_type.to_string().as_str(), "tEXt" | "zTXt" | "iTXt")
+}
+
+/// Runs the advisory rules in `policy` against `png`. These are not spec violations, so
+/// callers should report them as warnings.
+pub fn advise(png: &Png, policy: &Policy) -> Vec<Finding> {
+    let mut findings = vec![];
+    let image_size: usize = png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type().to_string() == "IDAT")
+        .map(|c| c.data().len())
+        .sum();
+
+    let mut text_chunks = 0;
+    let mut offset = Png::STANDARD_HEADER.len();
+    for chunk in png.chunks() {
+        let (chunk_type, size) = (chunk.chunk_type(), chunk.data().len());
+        if is_text(chunk_type) {
+            text_chunks += 1;
+        }
+        let messages = policy
+            .check_chunk(chunk_type, size, text_chunks)
+            .into_iter()
+            .chain(policy.check_private(chunk_type, size, image_size));
+        findings.extend(messages.map(|message| Finding { offset, message }));
+        offset += chunk.as_bytes().len();
+    }
+
+    findings
+}
+
+/// The zlib stream inside zTXt, compressed iTXt and iCCP chunks
+/// Inflates `compressed` without keeping the output, describing it if the limits reject it or
+/// it inflates past `BOMB_RATIO` whatever ratio the limits allow
+fn check_inflate(compressed: &[u8], limits: &Limits) -> Option<String> {
+    let inflated = match limits.inflate_into(compressed, compressed.len(), &mut io::sink()) {
+        Ok(inflated) => inflated,
+        Err(e) => return Some(e.to_string()),
+    };
+    (inflated > RATIO_FLOOR && inflated > compressed.len().saturating_mul(BOMB_RATIO)).then(|| {
+        format!(
+            "Possible decompression bomb: {} compressed bytes inflate to {}, more than {}:1",
+            compressed.len(),
+            inflated,
+            BOMB_RATIO
+        )
+    })
+}
+
+fn compressed_payload(chunk: &Chunk) -> Option<&[u8]> {
+    let data = chunk.data();
+    let name_end = data.iter().position(|&b| b == 0)?;
+    match (
+        chunk.chunk_type().to_string().as_str(),
+        &data[name_end + 1..],
+    ) {
+        ("zTXt" | "iCCP", [0, stream @ ..]) => Some(stream),
+        ("iTXt", [1, 0, rest @ ..]) => {
+            // Skip the language tag and translated keyword
+            let language_end = rest.iter().position(|&b| b == 0)?;
+            let rest = &rest[language_end + 1..];
+            let keyword_end = rest.iter().position(|&b| b == 0)?;
+            Some(&rest[keyword_end + 1..])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::testing::chunk;
+    use std::str::FromStr;
+
+    fn itxt(language: &str) -> Chunk {
+        let data = format!("Title\0\0\0{}\0\0text", language);
+        chunk("iTXt", data.as_bytes())
+    }
+
+    #[test]
+    fn test_validate_language_tags() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            itxt("en-US"),
+            itxt(""),
+            itxt("en_US"),
+            chunk("IEND", &[]),
+        ]);
+
+        let findings = validate(&png);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].offset, 8 + 25 + 31 + 26);
+        assert!(findings[0].message.contains("en_US"));
+    }
+
+    fn compressed(len: usize) -> Vec<u8> {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b'a'; len]).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn layout() -> Vec<u8> {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", b"hidden message"),
+            chunk("IEND", &[]),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_diagnose_wrong_length() {
+        let mut bytes = layout();
+        assert!(diagnose_layout(&bytes).is_empty());
+
+        // The ruSt chunk starts at 33; claim 20 bytes of data instead of 14
+        bytes[36] = 20;
+        assert!(Png::try_from(&bytes[..]).is_err());
+        let findings = diagnose_layout(&bytes);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].offset, 33);
+        assert!(findings[0].message.contains("declares 20"));
+        assert!(findings[0].message.contains("matches after 14"));
+    }
+
+    #[test]
+    fn test_diagnose_wrong_length_and_crc() {
+        let mut bytes = layout();
+        bytes[36] = 3;
+        bytes[33 + 8 + 14] ^= 0xff;
+        let findings = diagnose_layout(&bytes);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0]
+            .message
+            .contains("next valid chunk starts at 0x0000003b"));
+        assert!(findings[0].message.contains("likely holds 14 bytes"));
+
+        let mut bytes = layout();
+        bytes[33 + 8 + 14] ^= 0xff;
+        assert!(diagnose_layout(&bytes)[0].message.contains("corrupt CRC"));
+    }
+
+    fn minimal(types: &[&str]) -> Vec<u8> {
+        let chunks = types
+            .iter()
+            .map(|t| {
+                let data = match *t {
+                    // 1x1 RGB
+                    "IHDR" => vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0],
+                    "PLTE" => vec![0; 3],
+                    _ => vec![],
+                };
+                chunk(t, &data)
+            })
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    fn messages(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|f| f.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_report_clean() {
+        assert!(report(&minimal(&["IHDR", "PLTE", "IDAT", "IEND"])).is_empty());
+    }
+
+    #[test]
+    fn test_report_signature() {
+        let findings = report(b"GIF89a...");
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].offset, 0);
+    }
+
+    #[test]
+    fn test_report_ordering() {
+        let findings = report(&minimal(&["IHDR", "IDAT", "PLTE", "IHDR", "IEND"]));
+        assert_eq!(
+            messages(&findings),
+            vec![
+                "PLTE must come before IDAT",
+                "Only one IHDR chunk is allowed, the first is at 0x00000008"
+            ]
+        );
+        assert_eq!(findings[0].offset, 8 + 25 + 12);
+
+        let findings = report(&minimal(&["IDAT", "IHDR", "IDAT"]));
+        assert_eq!(
+            messages(&findings),
+            vec![
+                "IHDR must be the first chunk, found IDAT",
+                "IDAT chunks must be consecutive",
+                "IEND chunk is missing"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_trailing_data_and_crc() {
+        let mut bytes = minimal(&["IHDR", "IDAT", "IEND"]);
+        let end = bytes.len();
+        bytes.extend(b"junk");
+        let findings = report(&bytes);
+        assert_eq!(
+            messages(&findings),
+            vec!["4 byte(s) of trailing data after IEND"]
+        );
+        assert_eq!(findings[0].offset, end);
+
+        bytes[8 + 20] ^= 1;
+        let findings = report(&bytes);
+        assert!(findings[0].message.contains("corrupt CRC"));
+    }
+
+    #[test]
+    fn test_validate_ztxt_bomb() {
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(compressed((1 << 16) + 1));
+        let png = Png::from_chunks(vec![chunk("zTXt", &data)]);
+        let limits = Limits {
+            max_inflated: Some(1 << 16),
+            ..Default::default()
+        };
+
+        assert!(validate(&png).is_empty());
+        let findings = validate_with(&png, &limits);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.contains("decompression bomb"));
+    }
+
+    #[test]
+    fn test_validate_ztxt_ratio() {
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(compressed(RATIO_FLOOR * 4));
+        let png = Png::from_chunks(vec![chunk("zTXt", &data)]);
+
+        for max_ratio in [Some(BOMB_RATIO), Some(10_000), None] {
+            let limits = Limits {
+                max_ratio,
+                ..Default::default()
+            };
+            let findings = validate_with(&png, &limits);
+            assert_eq!(findings.len(), 1, "{:?}", max_ratio);
+            assert!(findings[0].message.contains("decompression bomb"));
+        }
+    }
+
+    #[test]
+    fn test_validate_oversized_header() {
+        let mut ihdr = Ihdr::MAX_DIMENSION.to_be_bytes().repeat(2);
+        ihdr.extend([16, 6, 0, 0, 0]);
+        let png = Png::from_chunks(vec![chunk("IHDR", &ihdr), chunk("IDAT", &compressed(16))]);
+        let findings = validate(&png);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.starts_with("IHDR chunk: Image size"));
+    }
+
+    #[test]
+    fn test_validate_idat_bomb() {
+        use crate::raster::compose;
+
+        let header = ImageHeader::from_str("width=2,height=2,color=gray8").unwrap();
+        let mut chunks = compose(&header, &[0; 4]).unwrap().chunks().to_vec();
+        assert!(validate(&Png::from_chunks(chunks.clone())).is_empty());
+
+        chunks[1] = chunk("IDAT", &compressed(1 << 16));
+        let findings = validate(&Png::from_chunks(chunks));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].offset, 8 + 25);
+    }
+
+    fn filled(chunk_type: &str, size: usize) -> Chunk {
+        chunk(chunk_type, &vec![b'a'; size])
+    }
+
+    #[test]
+    fn test_advise() {
+        let png = Png::from_chunks(vec![
+            filled("IDAT", 16),
+            filled("tEXt", 8),
+            filled("tEXt", 8),
+            filled("prVt", 17),
+            filled("gAMA", 0),
+            filled("IEND", 0),
+        ]);
+        let policy = Policy {
+            max_text_size: Some(4),
+            max_text_chunks: Some(1),
+            ..Default::default()
+        };
+
+        let messages: Vec<String> = advise(&png, &policy)
+            .into_iter()
+            .map(|f| f.message)
+            .collect();
+        assert_eq!(messages.len(), 5);
+        assert!(messages[0].contains("unusually large"));
+        assert!(messages[1].contains("more than 1 text chunks"));
+        assert!(messages[3].contains("private prVt"));
+        assert!(messages[4].contains("gAMA chunk is empty"));
+
+        assert_eq!(advise(&png, &Policy::default()).len(), 2);
+    }
+
+    #[test]
+    fn test_policy_parse() {
+        let policy = Policy::parse(br#"{"max_text_size": 10, "max_text_chunks": null}"#).unwrap();
+        assert_eq!(policy.max_text_size, Some(10));
+        assert_eq!(policy.max_text_chunks, None);
+        assert!(policy.zero_length_ancillary);
+
+        assert!(Policy::parse(br#"{"max_text_size": "big"}"#).is_err());
+        assert!(Policy::parse(br#"{"colour": true}"#).is_err());
+    }
+
+    #[test]
+    fn test_validate_clean() {
+        let png = Png::from_chunks(vec![itxt("de-CH")]);
+        assert!(validate(&png).is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+#![allow(dead_code)]
+
+use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+/// What a `ChunkVisitor` wants the parser to do next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitAction {
+    /// Keep going, parsing the body of the current chunk if it has not been parsed yet
+    Continue,
+    /// Move on to the next chunk without parsing the body of the current one
+    SkipBody,
+    /// Stop parsing altogether
+    Stop,
+}
+
+/// Callbacks fired by `Png::parse_with_visitor` as it walks through a PNG file. Both methods
+/// default to `VisitAction::Continue`, so implementors only need to override what they use.
+pub trait ChunkVisitor {
+    /// Called with the byte offset, declared length and type of each chunk before its data is read
+    fn visit_header(
+        &mut self,
+        _offset: usize,
+        _length: u32,
+        _chunk_type: &ChunkType,
+    ) -> VisitAction {
+        VisitAction::Continue
+    }
+
+    /// Called with each chunk whose body was parsed, after its CRC has been checked
+    fn visit_body(&mut self, _chunk: &Chunk) -> VisitAction {
+        VisitAction::Continue
+    }
+}
@@ -0,0 +1,202 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    raster::{ColorType, Image},
+    Result,
+};
+
+/// Number of ID bits carried by a watermark
+pub const ID_BITS: usize = 32;
+/// Detection score above which a watermark is reported as present. The score is a z-score of
+/// the correlation with the pilot pattern, so unmarked images stay well below this.
+pub const DETECTION_THRESHOLD: f64 = 6.0;
+
+/// A keyed ±1 pattern per pixel for each ID bit plus a pilot, drawn from a SplitMix64 stream
+/// seeded with the SHA-256 of the key. The same key always yields the same patterns.
+struct Patterns {
+    state: u64,
+}
+
+impl Patterns {
+    fn new(key: &str) -> Patterns {
+        let digest = Sha256::digest(key.as_bytes());
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&digest[..8]);
+        Patterns {
+            state: u64::from_le_bytes(seed),
+        }
+    }
+
+    /// The signs for the next pixel: bit `b` of the result is the pattern of ID bit `b`, and
+    /// bit `ID_BITS` is the pilot
+    fn next_pixel(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+fn sign(signs: u64, bit: usize) -> i32 {
+    if signs >> bit & 1 == 1 {
+        1
+    } else {
+        -1
+    }
+}
+
+/// The result of looking for a watermark
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Strength of the pilot correlation; above `DETECTION_THRESHOLD` means a watermark is present
+    pub score: f64,
+    pub id: u32,
+}
+
+impl Detection {
+    pub fn is_present(&self) -> bool {
+        self.score > DETECTION_THRESHOLD
+    }
+}
+
+/// Adds a spread-spectrum watermark carrying `id` to the brightness of `image`. Every pixel
+/// is shifted by the sum of keyed ±1 patterns, one per ID bit, scaled so the shift has a
+/// standard deviation of about `strength` levels at 8 bits.
+pub fn embed(image: &mut Image, id: u32, key: &str, strength: f64) -> Result<()> {
+    let color_channels = color_channels(image)?;
+    let channels = image.header.color_type.channels();
+    let max = ((1u32 << image.header.bit_depth) - 1) as i32;
+    let scale = strength * max as f64 / 255.0 / ((ID_BITS + 1) as f64).sqrt();
+
+    let mut patterns = Patterns::new(key);
+    for pixel in image.samples.chunks_mut(channels) {
+        let signs = patterns.next_pixel();
+        let mut sum = sign(signs, ID_BITS);
+        for bit in 0..ID_BITS {
+            let message = if id >> bit & 1 == 1 { 1 } else { -1 };
+            sum += message * sign(signs, bit);
+        }
+        let delta = (sum as f64 * scale).round() as i32;
+        for sample in pixel[..color_channels].iter_mut() {
+            *sample = (*sample as i32 + delta).clamp(0, max) as u16;
+        }
+    }
+    Ok(())
+}
+
+/// Looks for a watermark made with `key` without needing the original image. The image is
+/// high-pass filtered to suppress its own content before correlating with each pattern.
+pub fn detect(image: &Image, key: &str) -> Result<Detection> {
+    let color_channels = color_channels(image)?;
+    let channels = image.header.color_type.channels();
+    let (width, height) = (image.header.width as usize, image.header.height as usize);
+
+    let brightness: Vec<f64> = image
+        .samples
+        .chunks(channels)
+        .map(|p| p[..color_channels].iter().map(|&s| s as f64).sum::<f64>() / color_channels as f64)
+        .collect();
+    let residual = high_pass(&brightness, width, height);
+
+    let mut correlations = [0f64; ID_BITS + 1];
+    let mut patterns = Patterns::new(key);
+    for &value in residual.iter() {
+        let signs = patterns.next_pixel();
+        for (bit, correlation) in correlations.iter_mut().enumerate() {
+            *correlation += value * sign(signs, bit) as f64;
+        }
+    }
+
+    let energy = residual.iter().map(|v| v * v).sum::<f64>();
+    let score = if energy > 0.0 {
+        correlations[ID_BITS] / energy.sqrt()
+    } else {
+        0.0
+    };
+    let id = (0..ID_BITS)
+        .filter(|&bit| correlations[bit] > 0.0)
+        .fold(0u32, |id, bit| id | 1 << bit);
+
+    Ok(Detection { score, id })
+}
+
+/// Subtracts the 3x3 local mean from every value
+fn high_pass(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    let mut residual = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (mut sum, mut count) = (0.0, 0.0);
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    sum += values[ny * width + nx];
+                    count += 1.0;
+                }
+            }
+            residual.push(values[y * width + x] - sum / count);
+        }
+    }
+    residual
+}
+
+/// The number of leading channels that carry brightness
+fn color_channels(image: &Image) -> Result<usize> {
+    match image.header.color_type {
+        ColorType::Gray | ColorType::GrayAlpha => Ok(1),
+        ColorType::Rgb | ColorType::Rgba => Ok(3),
+        ColorType::Indexed => Err("Watermarking indexed images is not supported".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use crate::raster::{compose, decode, reencode, ImageHeader};
+    use std::str::FromStr;
+
+    fn testing_image() -> Image {
+        let header = ImageHeader::from_str("width=128,height=128,color=rgb8").unwrap();
+        let pixels: Vec<u8> = (0..128 * 128 * 3)
+            .map(|i| ((i / 3 % 128) + (i / 384) / 2) as u8)
+            .collect();
+        decode(&compose(&header, &pixels).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_embed_detect() {
+        let mut image = testing_image();
+        embed(&mut image, 12345, "secret", 2.0).unwrap();
+
+        let detection = detect(&image, "secret").unwrap();
+        assert!(detection.is_present(), "score {}", detection.score);
+        assert_eq!(detection.id, 12345);
+    }
+
+    #[test]
+    fn test_detect_unmarked_or_wrong_key() {
+        assert!(!detect(&testing_image(), "secret").unwrap().is_present());
+
+        let mut image = testing_image();
+        embed(&mut image, 12345, "secret", 2.0).unwrap();
+        assert!(!detect(&image, "other key").unwrap().is_present());
+    }
+
+    #[test]
+    fn test_watermark_survives_reencoding() {
+        let header = ImageHeader::from_str("width=128,height=128,color=rgb8").unwrap();
+        let png: Png = compose(&header, &testing_image().to_raw().unwrap()).unwrap();
+        let mut image = decode(&png).unwrap();
+        embed(&mut image, 0xdead_beef, "secret", 2.0).unwrap();
+
+        let marked = decode(&reencode(&png, &image).unwrap()).unwrap();
+        assert_eq!(detect(&marked, "secret").unwrap().id, 0xdead_beef);
+    }
+
+    #[test]
+    fn test_watermark_indexed() {
+        let mut image = testing_image();
+        image.header.color_type = ColorType::Indexed;
+        assert!(embed(&mut image, 1, "secret", 2.0).is_err());
+    }
+}
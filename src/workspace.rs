@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{compat, png::Png, validate, Result};
+
+/// Name of the database file inside a workspace directory
+pub const DATABASE: &str = "workspace.json";
+
+/// A file tracked by a workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// SHA-256 of the file when it was added or last analyzed
+    pub sha256: String,
+    /// The result of the last `analyze`, if any
+    pub analysis: Option<Value>,
+}
+
+/// A set of files investigated together, along with their analysis results
+#[derive(Debug, Default, PartialEq)]
+pub struct Workspace {
+    pub entries: Vec<Entry>,
+}
+
+impl Workspace {
+    pub fn database(dir: &Path) -> PathBuf {
+        dir.join(DATABASE)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Workspace> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        let entries = value["files"]
+            .as_array()
+            .ok_or("Workspace database is missing its files array")?
+            .iter()
+            .map(|file| {
+                Ok(Entry {
+                    path: PathBuf::from(file["path"].as_str().ok_or("File is missing its path")?),
+                    sha256: file["sha256"].as_str().unwrap_or_default().to_string(),
+                    analysis: Some(file["analysis"].clone()).filter(|a| !a.is_null()),
+                })
+            })
+            .collect::<Result<Vec<Entry>>>()?;
+        Ok(Workspace { entries })
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let files: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "path": entry.path.to_string_lossy(),
+                    "sha256": entry.sha256,
+                    "analysis": entry.analysis,
+                })
+            })
+            .collect();
+        Ok(serde_json::to_vec_pretty(&json!({ "files": files }))?)
+    }
+
+    /// Starts tracking `path`, whose current contents are `contents`. Returns false if the file
+    /// was already tracked.
+    pub fn add(&mut self, path: PathBuf, contents: &[u8]) -> bool {
+        if self.entries.iter().any(|entry| entry.path == path) {
+            return false;
+        }
+        self.entries.push(Entry {
+            path,
+            sha256: sha256_hex(contents),
+            analysis: None,
+        });
+        true
+    }
+}
+
+/// Runs the structural analyses on `png`: chunk inventory, compatibility issues and
+/// validation findings
+pub fn analyze(png: &Png) -> Value {
+    let types: Vec<String> = png
+        .chunks()
+        .iter()
+        .map(|c| c.chunk_type().to_string())
+        .collect();
+    let compat: Vec<String> = compat::check(png).iter().map(|i| i.to_string()).collect();
+    let findings: Vec<String> = validate::validate(png)
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+    json!({
+        "chunks": types,
+        "compat": compat,
+        "findings": findings,
+    })
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn testing_png() -> Png {
+        testing::png_from(&[("IHDR", &[]), ("ruSt", &[]), ("IEND", &[])])
+    }
+
+    #[test]
+    fn test_workspace_round_trip() {
+        let mut workspace = Workspace::default();
+        assert!(workspace.add(PathBuf::from("a.png"), b"first"));
+        assert!(workspace.add(PathBuf::from("b.png"), b"second"));
+        assert!(!workspace.add(PathBuf::from("a.png"), b"first"));
+        workspace.entries[0].analysis = Some(analyze(&testing_png()));
+
+        let parsed = Workspace::parse(&workspace.as_bytes().unwrap()).unwrap();
+        assert_eq!(parsed, workspace);
+        assert!(parsed.entries[1].analysis.is_none());
+    }
+
+    #[test]
+    fn test_analyze() {
+        let analysis = analyze(&testing_png());
+        assert_eq!(analysis["chunks"], json!(["IHDR", "ruSt", "IEND"]));
+        assert_eq!(analysis["findings"], json!([]));
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
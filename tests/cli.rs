@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::str::FromStr;
+
+use pngchunk::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+
+fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).unwrap()
+}
+
+/// Writes a small PNG with a `ruSt` chunk holding `message` to a file unique to `name`
+fn testing_file(name: &str, message: &[u8]) -> PathBuf {
+    let png = Png::from_chunks(vec![
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+        chunk(
+            "IDAT",
+            &[0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01],
+        ),
+        chunk("ruSt", message),
+        chunk("IEND", &[]),
+    ]);
+    let path =
+        std::env::temp_dir().join(format!("pngchunk-cli-{}-{}.png", name, std::process::id()));
+    fs::write(&path, png.as_bytes()).unwrap();
+    path
+}
+
+fn pngchunk(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngchunk"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_exists_status() {
+    let path = testing_file("exists", b"hello");
+    let file = path.to_str().unwrap();
+    let status = |args: &[&str]| pngchunk(args).status.code();
+
+    assert_eq!(status(&["exists", file, "ruSt"]), Some(0));
+    assert_eq!(status(&["exists", file, "RUST", "--ignore-case"]), Some(0));
+    assert_eq!(status(&["exists", file, "tEXt"]), Some(1));
+    // A file that cannot be read and a command line that does not parse are not absence
+    assert_eq!(status(&["exists", "missing.png", "ruSt"]), Some(2));
+    assert_eq!(status(&["exists", file, "ab1c"]), Some(2));
+    assert_eq!(status(&["exists", file]), Some(2));
+    assert_eq!(status(&["--format", "json", "exists"]), Some(2));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_textconv() {
+    let path = testing_file("textconv", b"hello");
+    let output = pngchunk(&["textconv", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(lines.len(), 5, "{}", stdout);
+    assert!(lines[0].starts_with("   0 IHDR length=13 crc="));
+    assert_eq!(
+        lines[1],
+        "       width=1 height=1 bit_depth=8 color_type=0 interlace=0"
+    );
+    assert!(lines[2].starts_with("   1 IDAT length=10 "));
+    assert!(lines[3].starts_with("   2 ruSt length=5 "));
+    assert!(lines[3]
+        .ends_with(" sha256=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"));
+    assert!(lines[4].starts_with("   3 IEND length=0 crc=ae426082 "));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_decode_porcelain() {
+    let message = b"\xffline one\nline two\n";
+    let path = testing_file("porcelain", message);
+    let path = path.to_str().unwrap();
+    for args in [
+        &["decode", path, "ruSt", "--porcelain"][..],
+        &["--format", "json", "decode", path, "ruSt", "--porcelain"],
+    ] {
+        let output = pngchunk(args);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(output.stdout, message);
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_text_set_type() {
+    let path = testing_file("text-set", b"hello");
+    let file = path.to_str().unwrap();
+    for (chunk_type, keyword) in [("tEXt", "Author"), ("zTXt", "Comment"), ("iTXt", "Title")] {
+        let output = pngchunk(&[
+            "text",
+            "set",
+            file,
+            keyword,
+            "caf\u{e9}",
+            "--type",
+            chunk_type,
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let output = pngchunk(&["--format", "csv", "text", "list", file]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "type,keyword,language,text\n\
+         tEXt,Author,,caf\u{e9}\n\
+         zTXt,Comment,,caf\u{e9}\n\
+         iTXt,Title,,caf\u{e9}\n"
+    );
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_print_detailed_exif() {
+    // A little-endian TIFF whose only IFD holds a Make tag of "Foo"
+    let mut exif = b"II*\0\x08\0\0\0\x01\0".to_vec();
+    exif.extend([0x0f, 0x01, 2, 0, 4, 0, 0, 0]);
+    exif.extend(b"Foo\0\0\0\0\0");
+    let png = Png::from_chunks(vec![
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+        chunk("eXIf", &exif),
+        chunk(
+            "IDAT",
+            &[0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01],
+        ),
+        chunk("IEND", &[]),
+    ]);
+    let path = std::env::temp_dir().join(format!("pngchunk-cli-exif-{}.png", std::process::id()));
+    fs::write(&path, png.as_bytes()).unwrap();
+
+    let output = pngchunk(&["print", path.to_str().unwrap(), "--detailed"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("EXIF (little-endian)"), "{}", stdout);
+    assert!(stdout.contains("Make: Foo"), "{}", stdout);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_validate_batch_csv() {
+    let (a, b) = (
+        testing_file("validate-a", b"a"),
+        testing_file("validate-b", b"b"),
+    );
+    let mut bytes = fs::read(&b).unwrap();
+    bytes.extend(b"junk");
+    fs::write(&b, bytes).unwrap();
+
+    let output = pngchunk(&[
+        "--format",
+        "csv",
+        "validate",
+        a.to_str().unwrap(),
+        b.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "file,offset,severity,message");
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[1].starts_with(&format!("{},", b.display())));
+    fs::remove_file(a).unwrap();
+    fs::remove_file(b).unwrap();
+}